@@ -0,0 +1,11 @@
+use std::env;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Bundled protoc, so building doesn't need one installed
+    env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::configure()
+        .build_client(env::var_os("CARGO_FEATURE_CLIENT").is_some())
+        .build_server(env::var_os("CARGO_FEATURE_SERVER").is_some())
+        .compile_protos(&["proto/ormox.proto"], &["proto"])?;
+    Ok(())
+}
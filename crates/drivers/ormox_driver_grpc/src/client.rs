@@ -0,0 +1,443 @@
+use std::any::Any;
+
+use async_trait::async_trait;
+use ormox_core::{
+    bson,
+    core::driver::OperationCount,
+    core::remote::{context_header, ServerInfo, CONTEXT_HEADER},
+    CollectionStats, DatabaseDriver, DriverCapabilities, Find, HealthReport, Index, IndexStats,
+    OResult, OrmoxError, Query, QueryPlan, WriteOptions,
+};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Channel, Endpoint},
+    Request, Status,
+};
+use uuid::Uuid;
+
+use crate::{
+    convert::{
+        from_bson, from_bson_all, from_capabilities, from_health, from_index_stats, from_plan,
+        from_stats, from_status, parse_uuid, to_bson, to_bson_all, to_count, to_find, to_index,
+        to_query, to_write_options, DRIVER_NAME,
+    },
+    proto::{self, database_client::DatabaseClient},
+};
+
+/// Adds the bearer token & the current `OperationContext` to every request
+#[derive(Clone)]
+struct Authorization(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Authorization {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        if let Some(context) = context_header().and_then(|context| context.try_into().ok()) {
+            request.metadata_mut().insert(CONTEXT_HEADER, context);
+        }
+        Ok(request)
+    }
+}
+
+/// Driver for a database served by `GrpcServer`
+#[derive(Clone)]
+pub struct GrpcDriver {
+    client: DatabaseClient<InterceptedService<Channel, Authorization>>,
+    info: ServerInfo,
+}
+
+impl GrpcDriver {
+    /// Connects to the `GrpcServer` at `url` (ie "http://127.0.0.1:50051"), authenticating with `token` if the server
+    /// requires one
+    pub async fn connect(url: impl AsRef<str>, token: Option<String>) -> OResult<Self> {
+        let endpoint = Endpoint::from_shared(url.as_ref().to_string())
+            .map_err(|e| OrmoxError::driver(DRIVER_NAME, e))?;
+        let channel = endpoint
+            .connect()
+            .await
+            .map_err(|e| OrmoxError::transient(DRIVER_NAME, e))?;
+        Self::with_channel(channel, token).await
+    }
+
+    /// Like `connect`, over a preconfigured channel (ie with TLS or timeouts)
+    pub async fn with_channel(channel: Channel, token: Option<String>) -> OResult<Self> {
+        let token = token
+            .map(|token| format!("Bearer {token}").parse::<MetadataValue<Ascii>>())
+            .transpose()
+            .map_err(|e| OrmoxError::driver(DRIVER_NAME, e))?;
+        let mut client = DatabaseClient::with_interceptor(channel, Authorization(token));
+        let info = client
+            .info(proto::Empty {})
+            .await
+            .map_err(from_status)?
+            .into_inner();
+        Ok(Self {
+            client,
+            info: ServerInfo {
+                driver_name: info.driver_name,
+                capabilities: from_capabilities(info.capabilities),
+            },
+        })
+    }
+
+    /// Driver name & capabilities of the remote database, as of connecting
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.info
+    }
+
+    fn client(&self) -> DatabaseClient<InterceptedService<Channel, Authorization>> {
+        self.client.clone()
+    }
+
+    fn find_request(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<proto::FindRequest> {
+        Ok(proto::FindRequest {
+            collection,
+            query: Some(to_query(query)?),
+            options: Some(to_find(options)?),
+        })
+    }
+
+    fn write_request(
+        &self,
+        collection: String,
+        query: Query,
+        document: &bson::Document,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<proto::WriteRequest> {
+        Ok(proto::WriteRequest {
+            collection,
+            query: Some(to_query(query)?),
+            document: to_bson(document)?,
+            count: to_count(count),
+            options: Some(to_write_options(options)),
+        })
+    }
+}
+
+#[async_trait]
+impl DatabaseDriver for GrpcDriver {
+    fn driver_name(&self) -> String {
+        String::from(DRIVER_NAME)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        // Documents are still decoded from messages, so there's nothing to gain, changes aren't streamed over gRPC
+        // and upsert batches are sent one document per request
+        self.info.capabilities.difference(
+            DriverCapabilities::RAW_DOCUMENTS
+                | DriverCapabilities::CHANGE_STREAMS
+                | DriverCapabilities::BULK_UPSERTS,
+        )
+    }
+
+    async fn collections(&self) -> OResult<Vec<String>> {
+        let names = self
+            .client()
+            .collections(proto::Empty {})
+            .await
+            .map_err(from_status)?;
+        Ok(names.into_inner().names)
+    }
+
+    async fn insert(
+        &self,
+        collection: String,
+        documents: Vec<bson::Document>,
+        options: WriteOptions,
+    ) -> OResult<Vec<Uuid>> {
+        let request = proto::InsertRequest {
+            collection,
+            documents: to_bson_all(&documents)?,
+            options: Some(to_write_options(options)),
+        };
+        let result = self.client().insert(request).await.map_err(from_status)?;
+        result
+            .into_inner()
+            .inserted_ids
+            .iter()
+            .map(|id| parse_uuid(id))
+            .collect()
+    }
+
+    async fn update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let request = self.write_request(collection, query, &update, count, options)?;
+        self.client()
+            .update(request)
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn delete(
+        &self,
+        collection: String,
+        query: Query,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let request = proto::DeleteRequest {
+            collection,
+            query: Some(to_query(query)?),
+            count: to_count(count),
+            options: Some(to_write_options(options)),
+        };
+        self.client()
+            .delete(request)
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn find(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        let request = self.find_request(collection, query, options)?;
+        let found = self.client().find(request).await.map_err(from_status)?;
+        from_bson_all(&found.into_inner().documents)
+    }
+
+    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
+        let request = self.find_request(collection, Query::new(), options)?;
+        let found = self.client().all(request).await.map_err(from_status)?;
+        from_bson_all(&found.into_inner().documents)
+    }
+
+    async fn upsert(
+        &self,
+        collection: String,
+        query: Query,
+        document: bson::Document,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let request = self.write_request(collection, query, &document, count, options)?;
+        self.client()
+            .upsert(request)
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn count(&self, collection: String, query: Query, options: Find) -> OResult<u64> {
+        let request = self.find_request(collection, query, options)?;
+        let result = self.client().count(request).await.map_err(from_status)?;
+        Ok(result.into_inner().count)
+    }
+
+    async fn find_with_count(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<(Vec<bson::Document>, u64)> {
+        let request = self.find_request(collection, query, options)?;
+        let found = self
+            .client()
+            .find_with_count(request)
+            .await
+            .map_err(from_status)?
+            .into_inner();
+        Ok((
+            from_bson_all(&found.documents)?,
+            found.count.unwrap_or_default(),
+        ))
+    }
+
+    async fn aggregate(
+        &self,
+        collection: String,
+        pipeline: Vec<bson::Document>,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        let request = proto::AggregateRequest {
+            collection,
+            pipeline: to_bson_all(&pipeline)?,
+            options: Some(to_find(options)?),
+        };
+        let found = self
+            .client()
+            .aggregate(request)
+            .await
+            .map_err(from_status)?;
+        from_bson_all(&found.into_inner().documents)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        upsert: bool,
+        options: WriteOptions,
+    ) -> OResult<Option<bson::Document>> {
+        let request = proto::FindOneAndUpdateRequest {
+            collection,
+            query: Some(to_query(query)?),
+            update: to_bson(&update)?,
+            upsert,
+            options: Some(to_write_options(options)),
+        };
+        let found = self
+            .client()
+            .find_one_and_update(request)
+            .await
+            .map_err(from_status)?;
+        found
+            .into_inner()
+            .documents
+            .first()
+            .map(|d| from_bson(d))
+            .transpose()
+    }
+
+    async fn create_index(&self, collection: String, index: Index) -> OResult<()> {
+        let request = proto::IndexRequest {
+            collection,
+            index: Some(to_index(index)?),
+        };
+        self.client()
+            .create_index(request)
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
+        self.client()
+            .drop_index(proto::DropIndexRequest { collection, name })
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        let stats = self
+            .client()
+            .stats(proto::CollectionRequest { collection })
+            .await
+            .map_err(from_status)?;
+        Ok(from_stats(stats.into_inner()))
+    }
+
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        let stats = self
+            .client()
+            .index_stats(proto::CollectionRequest { collection })
+            .await
+            .map_err(from_status)?;
+        Ok(stats
+            .into_inner()
+            .indexes
+            .into_iter()
+            .map(from_index_stats)
+            .collect())
+    }
+
+    async fn explain(&self, collection: String, query: Query, options: Find) -> OResult<QueryPlan> {
+        let request = self.find_request(collection, query, options)?;
+        let plan = self.client().explain(request).await.map_err(from_status)?;
+        from_plan(plan.into_inner())
+    }
+
+    async fn start_session(&self) -> OResult<Uuid> {
+        let session = self
+            .client()
+            .start_session(proto::Empty {})
+            .await
+            .map_err(from_status)?;
+        parse_uuid(&session.into_inner().id)
+    }
+
+    async fn end_session(&self, session: Uuid) -> OResult<()> {
+        self.client()
+            .end_session(proto::Session {
+                id: session.to_string(),
+            })
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn start_transaction(&self, session: Uuid) -> OResult<()> {
+        self.client()
+            .start_transaction(proto::Session {
+                id: session.to_string(),
+            })
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn commit_transaction(&self, session: Uuid) -> OResult<()> {
+        self.client()
+            .commit_transaction(proto::Session {
+                id: session.to_string(),
+            })
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn abort_transaction(&self, session: Uuid) -> OResult<()> {
+        self.client()
+            .abort_transaction(proto::Session {
+                id: session.to_string(),
+            })
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn maintain(&self) -> OResult<()> {
+        self.client()
+            .maintain(proto::Empty {})
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    async fn ping(&self) -> OResult<()> {
+        self.client()
+            .ping(proto::Empty {})
+            .await
+            .map_err(from_status)
+            .and(Ok(()))
+    }
+
+    /// Reports the server's driver as a component, so a reachable server with an unreachable database is unhealthy
+    async fn health(&self) -> HealthReport {
+        HealthReport::measure(self.driver_name(), async {
+            let report = self
+                .client()
+                .health(proto::Empty {})
+                .await
+                .map_err(from_status)?;
+            Ok(vec![from_health(report.into_inner())])
+        })
+        .await
+    }
+}
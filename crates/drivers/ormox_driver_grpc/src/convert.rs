@@ -0,0 +1,354 @@
+//! Conversions between ormox's driver types & their protobuf messages
+
+use std::time::Duration;
+
+use ormox_core::{
+    bson, core::driver::OperationCount, Acknowledgment, Collation, CollationStrength,
+    CollectionStats, DriverCapabilities, ErrorKind, ErrorPolicy, Find, HealthReport, Index,
+    IndexKind, IndexStats, OResult, OrmoxError, Query, QueryPlan, ReadPreference, Sorting,
+    WriteConcern, WriteOptions,
+};
+use tonic::{Code, Status};
+use uuid::Uuid;
+
+use crate::proto;
+
+pub(crate) const DRIVER_NAME: &str = "remote::grpc";
+
+pub(crate) fn to_bson(document: &bson::Document) -> OResult<Vec<u8>> {
+    bson::to_vec(document).map_err(OrmoxError::serialization)
+}
+
+pub(crate) fn from_bson(bytes: &[u8]) -> OResult<bson::Document> {
+    bson::from_slice(bytes).map_err(OrmoxError::deserialization)
+}
+
+pub(crate) fn to_bson_all(documents: &[bson::Document]) -> OResult<Vec<Vec<u8>>> {
+    documents.iter().map(to_bson).collect()
+}
+
+pub(crate) fn from_bson_all(documents: &[Vec<u8>]) -> OResult<Vec<bson::Document>> {
+    documents.iter().map(|d| from_bson(d)).collect()
+}
+
+pub(crate) fn to_query(query: Query) -> OResult<proto::Query> {
+    Ok(proto::Query {
+        bson: to_bson(&query.try_into()?)?,
+    })
+}
+
+pub(crate) fn from_query(query: Option<proto::Query>) -> OResult<Query> {
+    match query {
+        Some(query) => Query::try_from(from_bson(&query.bson)?),
+        None => Ok(Query::new()),
+    }
+}
+
+pub(crate) fn parse_uuid(id: &str) -> OResult<Uuid> {
+    Uuid::parse_str(id).map_err(|_| OrmoxError::Id {
+        provided: id.to_string(),
+    })
+}
+
+fn to_usize(value: u64) -> OResult<usize> {
+    usize::try_from(value).map_err(|_| OrmoxError::compaibility(format!("{value} is out of range")))
+}
+
+pub(crate) fn to_count(count: OperationCount) -> i32 {
+    match count {
+        OperationCount::One => proto::OperationCount::One,
+        OperationCount::Many => proto::OperationCount::Many,
+    }
+    .into()
+}
+
+pub(crate) fn from_count(count: i32) -> OperationCount {
+    match proto::OperationCount::try_from(count) {
+        Ok(proto::OperationCount::One) => OperationCount::One,
+        _ => OperationCount::Many,
+    }
+}
+
+fn to_collation(collation: Collation) -> proto::Collation {
+    let strength = match collation.strength {
+        None => proto::CollationStrength::Unspecified,
+        Some(CollationStrength::Primary) => proto::CollationStrength::Primary,
+        Some(CollationStrength::Secondary) => proto::CollationStrength::Secondary,
+        Some(CollationStrength::Tertiary) => proto::CollationStrength::Tertiary,
+        Some(CollationStrength::Quaternary) => proto::CollationStrength::Quaternary,
+        Some(CollationStrength::Identical) => proto::CollationStrength::Identical,
+    };
+
+    proto::Collation {
+        locale: collation.locale,
+        strength: strength.into(),
+        case_insensitive: collation.case_insensitive,
+    }
+}
+
+fn from_collation(collation: proto::Collation) -> Collation {
+    Collation {
+        locale: collation.locale,
+        strength: match proto::CollationStrength::try_from(collation.strength) {
+            Ok(proto::CollationStrength::Primary) => Some(CollationStrength::Primary),
+            Ok(proto::CollationStrength::Secondary) => Some(CollationStrength::Secondary),
+            Ok(proto::CollationStrength::Tertiary) => Some(CollationStrength::Tertiary),
+            Ok(proto::CollationStrength::Quaternary) => Some(CollationStrength::Quaternary),
+            Ok(proto::CollationStrength::Identical) => Some(CollationStrength::Identical),
+            _ => None,
+        },
+        case_insensitive: collation.case_insensitive,
+    }
+}
+
+pub(crate) fn to_find(find: Find) -> OResult<proto::Find> {
+    let read_preference = match find.read_preference {
+        None => proto::ReadPreference::Unspecified,
+        Some(ReadPreference::Primary) => proto::ReadPreference::Primary,
+        Some(ReadPreference::PrimaryPreferred) => proto::ReadPreference::PrimaryPreferred,
+        Some(ReadPreference::Secondary) => proto::ReadPreference::Secondary,
+        Some(ReadPreference::SecondaryPreferred) => proto::ReadPreference::SecondaryPreferred,
+        Some(ReadPreference::Nearest) => proto::ReadPreference::Nearest,
+    };
+    let error_policy = match find.error_policy {
+        ErrorPolicy::FailFast => proto::ErrorPolicy::FailFast,
+        ErrorPolicy::Skip => proto::ErrorPolicy::Skip,
+        ErrorPolicy::Collect => proto::ErrorPolicy::Collect,
+    };
+
+    Ok(proto::Find {
+        operation: to_count(find.operation),
+        offset: find.offset.map(|o| o as u64),
+        limit: find.limit.map(|l| l as u64),
+        sort: find.sort.map(|sort| match sort {
+            Sorting::Ascending(field) => proto::Sorting {
+                field,
+                descending: false,
+            },
+            Sorting::Descending(field) => proto::Sorting {
+                field,
+                descending: true,
+            },
+        }),
+        read_preference: read_preference.into(),
+        session: find.session.map(|s| s.to_string()),
+        error_policy: error_policy.into(),
+        projection: find.projection.as_ref().map(to_bson).transpose()?,
+        batch_size: find.batch_size.map(|b| b as u64),
+        collation: find.collation.map(to_collation),
+        hint: find.hint,
+    })
+}
+
+pub(crate) fn from_find(find: Option<proto::Find>) -> OResult<Find> {
+    let Some(find) = find else {
+        return Ok(Find::many());
+    };
+
+    Ok(Find {
+        operation: from_count(find.operation),
+        offset: find.offset.map(to_usize).transpose()?,
+        limit: find.limit.map(to_usize).transpose()?,
+        sort: find.sort.map(|sort| match sort.descending {
+            true => Sorting::Descending(sort.field),
+            false => Sorting::Ascending(sort.field),
+        }),
+        read_preference: match proto::ReadPreference::try_from(find.read_preference) {
+            Ok(proto::ReadPreference::Primary) => Some(ReadPreference::Primary),
+            Ok(proto::ReadPreference::PrimaryPreferred) => Some(ReadPreference::PrimaryPreferred),
+            Ok(proto::ReadPreference::Secondary) => Some(ReadPreference::Secondary),
+            Ok(proto::ReadPreference::SecondaryPreferred) => {
+                Some(ReadPreference::SecondaryPreferred)
+            }
+            Ok(proto::ReadPreference::Nearest) => Some(ReadPreference::Nearest),
+            _ => None,
+        },
+        session: find.session.as_deref().map(parse_uuid).transpose()?,
+        error_policy: match proto::ErrorPolicy::try_from(find.error_policy) {
+            Ok(proto::ErrorPolicy::Skip) => ErrorPolicy::Skip,
+            Ok(proto::ErrorPolicy::Collect) => ErrorPolicy::Collect,
+            _ => ErrorPolicy::FailFast,
+        },
+        projection: find.projection.as_deref().map(from_bson).transpose()?,
+        batch_size: find.batch_size.map(to_usize).transpose()?,
+        collation: find.collation.map(from_collation),
+        hint: find.hint,
+    })
+}
+
+pub(crate) fn to_write_options(options: WriteOptions) -> proto::WriteOptions {
+    proto::WriteOptions {
+        write_concern: options.write_concern.map(|concern| proto::WriteConcern {
+            w: concern.w.map(|w| match w {
+                Acknowledgment::Nodes(nodes) => proto::write_concern::W::Nodes(nodes),
+                Acknowledgment::Majority => proto::write_concern::W::Majority(true),
+                Acknowledgment::Custom(custom) => proto::write_concern::W::Custom(custom),
+            }),
+            journal: concern.journal,
+            timeout_ms: concern.timeout.map(|t| t.as_millis() as u64),
+        }),
+        session: options.session.map(|s| s.to_string()),
+    }
+}
+
+pub(crate) fn from_write_options(options: Option<proto::WriteOptions>) -> OResult<WriteOptions> {
+    let Some(options) = options else {
+        return Ok(WriteOptions::default());
+    };
+
+    Ok(WriteOptions {
+        write_concern: options.write_concern.map(|concern| WriteConcern {
+            w: concern.w.map(|w| match w {
+                proto::write_concern::W::Nodes(nodes) => Acknowledgment::Nodes(nodes),
+                proto::write_concern::W::Majority(_) => Acknowledgment::Majority,
+                proto::write_concern::W::Custom(custom) => Acknowledgment::Custom(custom),
+            }),
+            journal: concern.journal,
+            timeout: concern.timeout_ms.map(Duration::from_millis),
+        }),
+        session: options.session.as_deref().map(parse_uuid).transpose()?,
+    })
+}
+
+pub(crate) fn to_index(index: Index) -> OResult<proto::Index> {
+    let kind = match index.kind {
+        IndexKind::BTree => proto::IndexKind::BTree,
+        IndexKind::Hashed => proto::IndexKind::Hashed,
+        IndexKind::Text => proto::IndexKind::Text,
+        IndexKind::Geo => proto::IndexKind::Geo,
+    };
+    Ok(proto::Index {
+        fields: index.fields,
+        name: index.name,
+        unique: index.unique,
+        collation: index.collation.map(to_collation),
+        background: index.background,
+        kind: kind.into(),
+        partial_filter: index.partial_filter.map(to_query).transpose()?,
+    })
+}
+
+pub(crate) fn from_index(index: Option<proto::Index>) -> OResult<Index> {
+    let index = index.ok_or_else(|| OrmoxError::compaibility("Missing index"))?;
+    Ok(Index {
+        fields: index.fields,
+        name: index.name,
+        unique: index.unique,
+        collation: index.collation.map(from_collation),
+        background: index.background,
+        kind: match proto::IndexKind::try_from(index.kind) {
+            Ok(proto::IndexKind::Hashed) => IndexKind::Hashed,
+            Ok(proto::IndexKind::Text) => IndexKind::Text,
+            Ok(proto::IndexKind::Geo) => IndexKind::Geo,
+            _ => IndexKind::BTree,
+        },
+        partial_filter: index
+            .partial_filter
+            .map(|filter| from_query(Some(filter)))
+            .transpose()?,
+    })
+}
+
+pub(crate) fn to_stats(stats: CollectionStats) -> proto::CollectionStats {
+    proto::CollectionStats {
+        count: stats.count,
+        size: stats.size,
+        index_sizes: stats.index_sizes,
+    }
+}
+
+pub(crate) fn from_stats(stats: proto::CollectionStats) -> CollectionStats {
+    CollectionStats {
+        count: stats.count,
+        size: stats.size,
+        index_sizes: stats.index_sizes,
+    }
+}
+
+pub(crate) fn to_index_stats(stats: IndexStats) -> proto::IndexStats {
+    let millis = |time| bson::DateTime::from_chrono(time).timestamp_millis();
+    proto::IndexStats {
+        name: stats.name,
+        accesses: stats.accesses,
+        since: stats.since.map(millis),
+        last_used: stats.last_used.map(millis),
+    }
+}
+
+pub(crate) fn from_index_stats(stats: proto::IndexStats) -> IndexStats {
+    let time = |millis| bson::DateTime::from_millis(millis).to_chrono();
+    IndexStats {
+        name: stats.name,
+        accesses: stats.accesses,
+        since: stats.since.map(time),
+        last_used: stats.last_used.map(time),
+    }
+}
+
+pub(crate) fn to_health(report: HealthReport) -> proto::HealthReport {
+    proto::HealthReport {
+        driver: report.driver,
+        healthy: report.healthy,
+        latency_us: report.latency.as_micros() as u64,
+        error: report.error,
+        components: report.components.into_iter().map(to_health).collect(),
+    }
+}
+
+pub(crate) fn from_health(report: proto::HealthReport) -> HealthReport {
+    HealthReport {
+        driver: report.driver,
+        healthy: report.healthy,
+        latency: Duration::from_micros(report.latency_us),
+        error: report.error,
+        components: report.components.into_iter().map(from_health).collect(),
+    }
+}
+
+pub(crate) fn to_plan(plan: QueryPlan) -> OResult<proto::QueryPlan> {
+    Ok(proto::QueryPlan {
+        indexes: plan.indexes,
+        collection_scan: plan.collection_scan,
+        raw: plan.raw.as_ref().map(to_bson).transpose()?,
+    })
+}
+
+pub(crate) fn from_plan(plan: proto::QueryPlan) -> OResult<QueryPlan> {
+    Ok(QueryPlan {
+        indexes: plan.indexes,
+        collection_scan: plan.collection_scan,
+        raw: plan.raw.as_deref().map(from_bson).transpose()?,
+    })
+}
+
+pub(crate) fn from_capabilities(bits: u32) -> DriverCapabilities {
+    DriverCapabilities::from_bits_truncate(bits)
+}
+
+/// Converts an error to a status with a matching code, carrying the `OrmoxError` as JSON in its details
+pub(crate) fn to_status(error: OrmoxError) -> Status {
+    let code = match error.kind() {
+        ErrorKind::NotFound => Code::NotFound,
+        ErrorKind::Conflict => Code::AlreadyExists,
+        ErrorKind::Unsupported => Code::Unimplemented,
+        ErrorKind::InvalidInput | ErrorKind::Serialization | ErrorKind::Uninitialized => {
+            Code::InvalidArgument
+        }
+        ErrorKind::Transient => Code::Unavailable,
+        ErrorKind::Driver => Code::Internal,
+    };
+    let details = serde_json::to_vec(&error).unwrap_or_default();
+    Status::with_details(code, error.to_string(), details.into())
+}
+
+/// Recovers the `OrmoxError` sent by the server, or converts a transport failure
+pub(crate) fn from_status(status: Status) -> OrmoxError {
+    if let Ok(error) = serde_json::from_slice::<OrmoxError>(status.details()) {
+        return error;
+    }
+
+    match status.code() {
+        Code::Unavailable | Code::DeadlineExceeded => OrmoxError::transient(DRIVER_NAME, status),
+        _ => OrmoxError::driver(DRIVER_NAME, status),
+    }
+}
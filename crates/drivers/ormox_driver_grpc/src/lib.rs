@@ -0,0 +1,19 @@
+//! gRPC access to a remote database: `GrpcServer` serves any `DatabaseDriver` and `GrpcDriver` connects to it. The
+//! protocol is defined in `proto/ormox.proto`, so clients can be generated for other languages.
+
+mod convert;
+
+/// Messages & services generated from `proto/ormox.proto`
+pub mod proto {
+    tonic::include_proto!("ormox.v1");
+}
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+pub use client::GrpcDriver;
+
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+pub use server::{Authorize, Contextualize, GrpcServer};
@@ -0,0 +1,548 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use ormox_core::{
+    core::{
+        context::{self, Scoped},
+        remote::{parse_context_header, CONTEXT_HEADER},
+    },
+    Client, DatabaseDriver, OResult,
+};
+use tonic::{
+    codegen::{http, Service},
+    server::NamedService,
+    service::{interceptor::InterceptedService, Interceptor},
+    transport, Request, Response, Status,
+};
+
+use crate::{
+    convert::{
+        from_bson, from_bson_all, from_count, from_find, from_index, from_query,
+        from_write_options, parse_uuid, to_bson, to_bson_all, to_health, to_index_stats, to_plan,
+        to_stats, to_status,
+    },
+    proto::{
+        self,
+        database_server::{Database, DatabaseServer},
+    },
+};
+
+// `Status` is what tonic's generated handlers return, large or not
+#[allow(clippy::result_large_err)]
+fn respond<T>(result: OResult<T>) -> Result<Response<T>, Status> {
+    result.map(Response::new).map_err(to_status)
+}
+
+/// Rejects requests without the server's bearer token
+#[derive(Clone)]
+pub struct Authorize(Option<Arc<str>>);
+
+impl Interceptor for Authorize {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(token) = &self.0 else {
+            return Ok(request);
+        };
+
+        let provided = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if provided.is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes()))
+        {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("Unauthorized"))
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Runs each request in the `OperationContext` of its `ormox-context` metadata, sent by `GrpcDriver`
+#[derive(Clone)]
+pub struct Contextualize<S>(S);
+
+impl<S: Service<http::Request<B>>, B> Service<http::Request<B>> for Contextualize<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Scoped<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let context = request
+            .headers()
+            .get(CONTEXT_HEADER)
+            .and_then(|value| parse_context_header(value.as_bytes()));
+        context::scope(context, self.0.call(request))
+    }
+}
+
+impl<S: NamedService> NamedService for Contextualize<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+/// Serves any `DatabaseDriver` over gRPC, for `GrpcDriver` clients
+#[derive(Clone)]
+pub struct GrpcServer {
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+    token: Option<Arc<str>>,
+}
+
+impl GrpcServer {
+    pub fn new(driver: impl DatabaseDriver + Send + Sync + 'static) -> Self {
+        Self {
+            driver: Arc::new(driver),
+            token: None,
+        }
+    }
+
+    /// Serves the driver of an existing client
+    pub fn from_client(client: &Client) -> Self {
+        Self {
+            driver: client.driver(),
+            token: None,
+        }
+    }
+
+    /// Requires every request to carry `authorization: Bearer <token>` metadata
+    pub fn with_token(mut self, token: impl AsRef<str>) -> Self {
+        self.token = Some(Arc::from(token.as_ref()));
+        self
+    }
+
+    /// The service, for adding to a `tonic::transport::Server` alongside others
+    pub fn service(self) -> Contextualize<InterceptedService<DatabaseServer<Self>, Authorize>> {
+        let authorize = Authorize(self.token.clone());
+        Contextualize(DatabaseServer::with_interceptor(self, authorize))
+    }
+
+    /// Listens on `address` until the process exits
+    pub async fn serve(self, address: SocketAddr) -> Result<(), transport::Error> {
+        transport::Server::builder()
+            .add_service(self.service())
+            .serve(address)
+            .await
+    }
+}
+
+#[tonic::async_trait]
+impl Database for GrpcServer {
+    async fn info(&self, _: Request<proto::Empty>) -> Result<Response<proto::ServerInfo>, Status> {
+        respond(Ok(proto::ServerInfo {
+            driver_name: self.driver.driver_name(),
+            capabilities: self.driver.capabilities().bits(),
+        }))
+    }
+
+    async fn collections(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::CollectionNames>, Status> {
+        respond(
+            self.driver
+                .collections()
+                .await
+                .map(|names| proto::CollectionNames { names }),
+        )
+    }
+
+    async fn insert(
+        &self,
+        request: Request<proto::InsertRequest>,
+    ) -> Result<Response<proto::WriteResult>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let ids = self
+                    .driver
+                    .insert(
+                        request.collection,
+                        from_bson_all(&request.documents)?,
+                        from_write_options(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::WriteResult {
+                    inserted_ids: ids.iter().map(ToString::to_string).collect(),
+                })
+            }
+            .await,
+        )
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::WriteRequest>,
+    ) -> Result<Response<proto::WriteResult>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                self.driver
+                    .update(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_bson(&request.document)?,
+                        from_count(request.count),
+                        from_write_options(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::WriteResult::default())
+            }
+            .await,
+        )
+    }
+
+    async fn upsert(
+        &self,
+        request: Request<proto::WriteRequest>,
+    ) -> Result<Response<proto::WriteResult>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                self.driver
+                    .upsert(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_bson(&request.document)?,
+                        from_count(request.count),
+                        from_write_options(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::WriteResult::default())
+            }
+            .await,
+        )
+    }
+
+    async fn delete(
+        &self,
+        request: Request<proto::DeleteRequest>,
+    ) -> Result<Response<proto::WriteResult>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                self.driver
+                    .delete(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_count(request.count),
+                        from_write_options(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::WriteResult::default())
+            }
+            .await,
+        )
+    }
+
+    async fn find(
+        &self,
+        request: Request<proto::FindRequest>,
+    ) -> Result<Response<proto::Documents>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let found = self
+                    .driver
+                    .find(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_find(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::Documents {
+                    documents: to_bson_all(&found)?,
+                    count: None,
+                })
+            }
+            .await,
+        )
+    }
+
+    async fn all(
+        &self,
+        request: Request<proto::FindRequest>,
+    ) -> Result<Response<proto::Documents>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let found = self
+                    .driver
+                    .all(request.collection, from_find(request.options)?)
+                    .await?;
+                Ok(proto::Documents {
+                    documents: to_bson_all(&found)?,
+                    count: None,
+                })
+            }
+            .await,
+        )
+    }
+
+    async fn count(
+        &self,
+        request: Request<proto::FindRequest>,
+    ) -> Result<Response<proto::CountResult>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let count = self
+                    .driver
+                    .count(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_find(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::CountResult { count })
+            }
+            .await,
+        )
+    }
+
+    async fn find_with_count(
+        &self,
+        request: Request<proto::FindRequest>,
+    ) -> Result<Response<proto::Documents>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let (found, count) = self
+                    .driver
+                    .find_with_count(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_find(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::Documents {
+                    documents: to_bson_all(&found)?,
+                    count: Some(count),
+                })
+            }
+            .await,
+        )
+    }
+
+    async fn aggregate(
+        &self,
+        request: Request<proto::AggregateRequest>,
+    ) -> Result<Response<proto::Documents>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let found = self
+                    .driver
+                    .aggregate(
+                        request.collection,
+                        from_bson_all(&request.pipeline)?,
+                        from_find(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::Documents {
+                    documents: to_bson_all(&found)?,
+                    count: None,
+                })
+            }
+            .await,
+        )
+    }
+
+    async fn find_one_and_update(
+        &self,
+        request: Request<proto::FindOneAndUpdateRequest>,
+    ) -> Result<Response<proto::Documents>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let found = self
+                    .driver
+                    .find_one_and_update(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_bson(&request.update)?,
+                        request.upsert,
+                        from_write_options(request.options)?,
+                    )
+                    .await?;
+                Ok(proto::Documents {
+                    documents: found
+                        .as_ref()
+                        .map(to_bson)
+                        .transpose()?
+                        .into_iter()
+                        .collect(),
+                    count: None,
+                })
+            }
+            .await,
+        )
+    }
+
+    async fn explain(
+        &self,
+        request: Request<proto::FindRequest>,
+    ) -> Result<Response<proto::QueryPlan>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                let plan = self
+                    .driver
+                    .explain(
+                        request.collection,
+                        from_query(request.query)?,
+                        from_find(request.options)?,
+                    )
+                    .await?;
+                to_plan(plan)
+            }
+            .await,
+        )
+    }
+
+    async fn stats(
+        &self,
+        request: Request<proto::CollectionRequest>,
+    ) -> Result<Response<proto::CollectionStats>, Status> {
+        respond(
+            self.driver
+                .collection_stats(request.into_inner().collection)
+                .await
+                .map(to_stats),
+        )
+    }
+
+    async fn index_stats(
+        &self,
+        request: Request<proto::CollectionRequest>,
+    ) -> Result<Response<proto::IndexStatsList>, Status> {
+        respond(
+            self.driver
+                .index_stats(request.into_inner().collection)
+                .await
+                .map(|stats| proto::IndexStatsList {
+                    indexes: stats.into_iter().map(to_index_stats).collect(),
+                }),
+        )
+    }
+
+    async fn create_index(
+        &self,
+        request: Request<proto::IndexRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        respond(
+            async {
+                self.driver
+                    .create_index(request.collection, from_index(request.index)?)
+                    .await?;
+                Ok(proto::Empty {})
+            }
+            .await,
+        )
+    }
+
+    async fn drop_index(
+        &self,
+        request: Request<proto::DropIndexRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        respond(
+            self.driver
+                .drop_index(request.collection, request.name)
+                .await
+                .map(|_| proto::Empty {}),
+        )
+    }
+
+    async fn start_session(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::Session>, Status> {
+        respond(
+            self.driver
+                .start_session()
+                .await
+                .map(|id| proto::Session { id: id.to_string() }),
+        )
+    }
+
+    async fn end_session(
+        &self,
+        request: Request<proto::Session>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        respond(
+            async {
+                let session = parse_uuid(&request.into_inner().id)?;
+                self.driver.end_session(session).await?;
+                Ok(proto::Empty {})
+            }
+            .await,
+        )
+    }
+
+    async fn start_transaction(
+        &self,
+        request: Request<proto::Session>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        respond(
+            async {
+                let session = parse_uuid(&request.into_inner().id)?;
+                self.driver.start_transaction(session).await?;
+                Ok(proto::Empty {})
+            }
+            .await,
+        )
+    }
+
+    async fn commit_transaction(
+        &self,
+        request: Request<proto::Session>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        respond(
+            async {
+                let session = parse_uuid(&request.into_inner().id)?;
+                self.driver.commit_transaction(session).await?;
+                Ok(proto::Empty {})
+            }
+            .await,
+        )
+    }
+
+    async fn abort_transaction(
+        &self,
+        request: Request<proto::Session>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        respond(
+            async {
+                let session = parse_uuid(&request.into_inner().id)?;
+                self.driver.abort_transaction(session).await?;
+                Ok(proto::Empty {})
+            }
+            .await,
+        )
+    }
+
+    async fn maintain(&self, _: Request<proto::Empty>) -> Result<Response<proto::Empty>, Status> {
+        respond(self.driver.maintain().await.map(|_| proto::Empty {}))
+    }
+
+    async fn ping(&self, _: Request<proto::Empty>) -> Result<Response<proto::Empty>, Status> {
+        respond(self.driver.ping().await.map(|_| proto::Empty {}))
+    }
+
+    async fn health(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::HealthReport>, Status> {
+        respond(Ok(to_health(self.driver.health().await)))
+    }
+}
@@ -0,0 +1,424 @@
+use std::any::Any;
+
+use async_trait::async_trait;
+use ormox_core::{
+    bson,
+    core::{
+        driver::OperationCount,
+        remote::{
+            context_header, unwire, wire, AggregateRequest, CountedDocuments, DeleteRequest,
+            FindOneAndUpdateRequest, FindRequest, InsertRequest, ServerInfo, WireDocument,
+            WriteRequest, CONTEXT_HEADER,
+        },
+    },
+    CollectionStats, DatabaseDriver, DriverCapabilities, Find, HealthReport, Index, IndexStats,
+    OResult, OrmoxError, Query, QueryPlan, WriteOptions,
+};
+use reqwest::{IntoUrl, Method, Url};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+const DRIVER_NAME: &str = "remote::http";
+
+/// Converts a request error, marking connection failures & timeouts as transient
+fn http_error(error: reqwest::Error) -> OrmoxError {
+    if error.is_connect() || error.is_timeout() {
+        OrmoxError::transient(DRIVER_NAME, error)
+    } else {
+        OrmoxError::driver(DRIVER_NAME, error)
+    }
+}
+
+/// Driver for a database served by `ormox_server`
+#[derive(Clone, Debug)]
+pub struct HttpDriver {
+    client: reqwest::Client,
+    url: Url,
+    token: Option<String>,
+    info: ServerInfo,
+}
+
+impl HttpDriver {
+    /// Connects to the `ormox_server` at `url`, authenticating with `token` if the server requires one
+    pub async fn connect(url: impl IntoUrl, token: Option<String>) -> OResult<Self> {
+        Self::with_client(reqwest::Client::new(), url, token).await
+    }
+
+    /// Like `connect`, with a preconfigured HTTP client (ie for timeouts or proxies)
+    pub async fn with_client(
+        client: reqwest::Client,
+        url: impl IntoUrl,
+        token: Option<String>,
+    ) -> OResult<Self> {
+        let url = url
+            .into_url()
+            .map_err(|e| OrmoxError::driver(DRIVER_NAME, e))?;
+        if url.cannot_be_a_base() {
+            return Err(OrmoxError::compaibility(format!(
+                "Invalid server URL: {url}"
+            )));
+        }
+
+        let info = ServerInfo {
+            driver_name: String::new(),
+            capabilities: DriverCapabilities::empty(),
+        };
+        let mut driver = Self {
+            client,
+            url,
+            token,
+            info,
+        };
+        driver.info = driver.send(Method::GET, &[], None::<&()>).await?;
+        Ok(driver)
+    }
+
+    /// Driver name & capabilities of the remote database, as of connecting
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.info
+    }
+
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.url.clone();
+        // Checked in `with_client`
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    async fn send<R: DeserializeOwned>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: Option<&impl Serialize>,
+    ) -> OResult<R> {
+        let mut request = self.client.request(method, self.endpoint(segments));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(context) = context_header() {
+            request = request.header(CONTEXT_HEADER, context);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().await.map_err(http_error)?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(http_error)?;
+        if status.is_success() {
+            serde_json::from_slice(&body).map_err(OrmoxError::deserialization)
+        } else {
+            Err(
+                serde_json::from_slice::<OrmoxError>(&body).unwrap_or_else(|_| {
+                    OrmoxError::Driver {
+                        driver_name: String::from(DRIVER_NAME),
+                        error: format!("{status}: {}", String::from_utf8_lossy(&body)),
+                    }
+                }),
+            )
+        }
+    }
+
+    async fn collection_op<R: DeserializeOwned>(
+        &self,
+        collection: &str,
+        operation: &str,
+        body: &impl Serialize,
+    ) -> OResult<R> {
+        self.send(
+            Method::POST,
+            &["collections", collection, operation],
+            Some(body),
+        )
+        .await
+    }
+
+    async fn session_op(&self, session: Uuid, operation: &str) -> OResult<()> {
+        self.send(
+            Method::POST,
+            &["sessions", &session.to_string(), operation],
+            None::<&()>,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl DatabaseDriver for HttpDriver {
+    fn driver_name(&self) -> String {
+        String::from(DRIVER_NAME)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        // Raw documents are still parsed from JSON, so there's nothing to gain, changes aren't streamed over HTTP
+        // and upsert batches are sent one document per request
+        self.info.capabilities.difference(
+            DriverCapabilities::RAW_DOCUMENTS
+                | DriverCapabilities::CHANGE_STREAMS
+                | DriverCapabilities::BULK_UPSERTS,
+        )
+    }
+
+    async fn collections(&self) -> OResult<Vec<String>> {
+        self.send(Method::GET, &["collections"], None::<&()>).await
+    }
+
+    async fn insert(
+        &self,
+        collection: String,
+        documents: Vec<bson::Document>,
+        options: WriteOptions,
+    ) -> OResult<Vec<Uuid>> {
+        self.collection_op(
+            &collection,
+            "insert",
+            &InsertRequest {
+                documents: wire(documents),
+                options,
+            },
+        )
+        .await
+    }
+
+    async fn update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let request = WriteRequest {
+            query: WireDocument::try_from(query)?,
+            document: WireDocument(update),
+            count,
+            options,
+        };
+        self.collection_op(&collection, "update", &request).await
+    }
+
+    async fn delete(
+        &self,
+        collection: String,
+        query: Query,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        self.collection_op(
+            &collection,
+            "delete",
+            &DeleteRequest {
+                query: WireDocument::try_from(query)?,
+                count,
+                options,
+            },
+        )
+        .await
+    }
+
+    async fn find(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        self.collection_op(
+            &collection,
+            "find",
+            &FindRequest {
+                query: WireDocument::try_from(query)?,
+                options,
+            },
+        )
+        .await
+        .map(unwire)
+    }
+
+    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
+        self.collection_op(
+            &collection,
+            "all",
+            &FindRequest {
+                query: WireDocument::default(),
+                options,
+            },
+        )
+        .await
+        .map(unwire)
+    }
+
+    async fn upsert(
+        &self,
+        collection: String,
+        query: Query,
+        document: bson::Document,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let request = WriteRequest {
+            query: WireDocument::try_from(query)?,
+            document: WireDocument(document),
+            count,
+            options,
+        };
+        self.collection_op(&collection, "upsert", &request).await
+    }
+
+    async fn count(&self, collection: String, query: Query, options: Find) -> OResult<u64> {
+        self.collection_op(
+            &collection,
+            "count",
+            &FindRequest {
+                query: WireDocument::try_from(query)?,
+                options,
+            },
+        )
+        .await
+    }
+
+    async fn find_with_count(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<(Vec<bson::Document>, u64)> {
+        let request = FindRequest {
+            query: WireDocument::try_from(query)?,
+            options,
+        };
+        let CountedDocuments { documents, count } = self
+            .collection_op(&collection, "find_with_count", &request)
+            .await?;
+        Ok((unwire(documents), count))
+    }
+
+    async fn aggregate(
+        &self,
+        collection: String,
+        pipeline: Vec<bson::Document>,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        self.collection_op(
+            &collection,
+            "aggregate",
+            &AggregateRequest {
+                pipeline: wire(pipeline),
+                options,
+            },
+        )
+        .await
+        .map(unwire)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        upsert: bool,
+        options: WriteOptions,
+    ) -> OResult<Option<bson::Document>> {
+        let request = FindOneAndUpdateRequest {
+            query: WireDocument::try_from(query)?,
+            update: WireDocument(update),
+            upsert,
+            options,
+        };
+        let document: Option<WireDocument> = self
+            .collection_op(&collection, "find_one_and_update", &request)
+            .await?;
+        Ok(document.map(|WireDocument(d)| d))
+    }
+
+    async fn create_index(&self, collection: String, index: Index) -> OResult<()> {
+        self.collection_op(&collection, "indexes", &index).await
+    }
+
+    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
+        self.send(
+            Method::DELETE,
+            &["collections", &collection, "indexes", &name],
+            None::<&()>,
+        )
+        .await
+    }
+
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        self.send(
+            Method::GET,
+            &["collections", &collection, "stats"],
+            None::<&()>,
+        )
+        .await
+    }
+
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        self.send(
+            Method::GET,
+            &["collections", &collection, "indexes"],
+            None::<&()>,
+        )
+        .await
+    }
+
+    async fn explain(&self, collection: String, query: Query, options: Find) -> OResult<QueryPlan> {
+        self.collection_op(
+            &collection,
+            "explain",
+            &FindRequest {
+                query: WireDocument::try_from(query)?,
+                options,
+            },
+        )
+        .await
+    }
+
+    async fn start_session(&self) -> OResult<Uuid> {
+        self.send(Method::POST, &["sessions"], None::<&()>).await
+    }
+
+    async fn end_session(&self, session: Uuid) -> OResult<()> {
+        self.send(
+            Method::DELETE,
+            &["sessions", &session.to_string()],
+            None::<&()>,
+        )
+        .await
+    }
+
+    async fn start_transaction(&self, session: Uuid) -> OResult<()> {
+        self.session_op(session, "start_transaction").await
+    }
+
+    async fn commit_transaction(&self, session: Uuid) -> OResult<()> {
+        self.session_op(session, "commit_transaction").await
+    }
+
+    async fn abort_transaction(&self, session: Uuid) -> OResult<()> {
+        self.session_op(session, "abort_transaction").await
+    }
+
+    async fn maintain(&self) -> OResult<()> {
+        self.send(Method::POST, &["maintain"], None::<&()>).await
+    }
+
+    async fn ping(&self) -> OResult<()> {
+        self.send(Method::GET, &["ping"], None::<&()>).await
+    }
+
+    /// Reports the server's driver as a component, so a reachable server with an unreachable database is unhealthy
+    async fn health(&self) -> HealthReport {
+        HealthReport::measure(self.driver_name(), async {
+            Ok(vec![
+                self.send(Method::GET, &["health"], None::<&()>).await?,
+            ])
+        })
+        .await
+    }
+}
@@ -0,0 +1,115 @@
+//! Futures over IndexedDB's event-based requests & transactions
+
+use js_sys::{Function, Promise};
+use ormox_core::{OResult, OrmoxError};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbFactory, IdbRequest, IdbTransaction, WorkerGlobalScope};
+
+pub(crate) const DRIVER_NAME: &str = "browser::indexeddb";
+
+/// Converts a rejected request or transaction, using the `DOMException` message where there is one
+pub(crate) fn js_error(error: JsValue) -> OrmoxError {
+    let error = match error.dyn_ref::<web_sys::DomException>() {
+        Some(exception) => format!("{}: {}", exception.name(), exception.message()),
+        None => error.as_string().unwrap_or_else(|| format!("{error:?}")),
+    };
+    OrmoxError::Driver {
+        driver_name: String::from(DRIVER_NAME),
+        error,
+    }
+}
+
+/// The IndexedDB factory of the current window or worker
+pub(crate) fn factory() -> OResult<IdbFactory> {
+    let global = js_sys::global();
+    let factory = if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+        window.indexed_db()
+    } else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+        worker.indexed_db()
+    } else {
+        return Err(OrmoxError::unsupported(
+            "IndexedDB outside of a window or worker",
+        ));
+    };
+
+    factory
+        .map_err(js_error)?
+        .ok_or_else(|| OrmoxError::unsupported("IndexedDB is disabled in this browser"))
+}
+
+type Callback = Closure<dyn FnMut()>;
+
+/// Waits for one of the callbacks installed by `install` to settle the promise. The callbacks are kept alive until then
+/// and uninstalled afterwards.
+async fn settle(
+    install: impl FnOnce(Function, Function) -> Vec<Callback>,
+    uninstall: impl FnOnce(),
+) -> OResult<JsValue> {
+    let mut install = Some(install);
+    let mut callbacks = Vec::new();
+    let promise = Promise::new(&mut |resolve, reject| {
+        if let Some(install) = install.take() {
+            callbacks = install(resolve, reject);
+        }
+    });
+
+    let result = JsFuture::from(promise).await;
+    uninstall();
+    drop(callbacks);
+    result.map_err(js_error)
+}
+
+/// Resolves with the result of `request` once it succeeds
+pub(crate) async fn request(request: &IdbRequest) -> OResult<JsValue> {
+    let (succeeded, failed) = (request.clone(), request.clone());
+    settle(
+        |resolve, reject| {
+            let onsuccess = Closure::<dyn FnMut()>::new(move || {
+                let _ = resolve.call1(&JsValue::UNDEFINED, &succeeded.result().unwrap_or_default());
+            });
+            let onerror = Closure::<dyn FnMut()>::new(move || {
+                let error = failed.error().ok().flatten().map(JsValue::from);
+                let _ = reject.call1(&JsValue::UNDEFINED, &error.unwrap_or_default());
+            });
+            request.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+            request.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+            vec![onsuccess, onerror]
+        },
+        || {
+            request.set_onsuccess(None);
+            request.set_onerror(None);
+        },
+    )
+    .await
+}
+
+/// Resolves once `transaction` commits, or fails if it errors or is aborted
+pub(crate) async fn commit(transaction: &IdbTransaction) -> OResult<()> {
+    let failed = transaction.clone();
+    settle(
+        |resolve, reject| {
+            let oncomplete = Closure::<dyn FnMut()>::new(move || {
+                let _ = resolve.call0(&JsValue::UNDEFINED);
+            });
+            let onerror = Closure::<dyn FnMut()>::new(move || {
+                let error = failed.error().map(JsValue::from);
+                let _ = reject.call1(
+                    &JsValue::UNDEFINED,
+                    &error.unwrap_or_else(|| "Transaction aborted".into()),
+                );
+            });
+            transaction.set_oncomplete(Some(oncomplete.as_ref().unchecked_ref()));
+            transaction.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+            transaction.set_onabort(Some(onerror.as_ref().unchecked_ref()));
+            vec![oncomplete, onerror]
+        },
+        || {
+            transaction.set_oncomplete(None);
+            transaction.set_onerror(None);
+            transaction.set_onabort(None);
+        },
+    )
+    .await
+    .map(|_| ())
+}
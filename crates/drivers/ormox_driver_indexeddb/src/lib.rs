@@ -0,0 +1,642 @@
+//! Driver storing ormox collections in the browser's IndexedDB, for wasm-bindgen front ends sharing document models
+//! with a server. IndexedDB can only look documents up by key, so queries & updates are evaluated in memory by
+//! `ormox_core::core::memory`; it suits the amounts of data kept client-side, not large collections.
+//!
+//! Everything lives in one database with three object stores: `documents` (records keyed by collection & `_id`, holding
+//! the document encoded by the driver's `Codec`),
+//! `indexes` (declared indexes, enforced by the driver when unique) and `collections` (known collection names).
+
+mod idb;
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use ormox_core::{
+    bson::{self, doc, oid::ObjectId, Bson},
+    core::{driver::OperationCount, memory},
+    BsonCodec, Codec, CollectionStats, DatabaseDriver, DriverCapabilities, ErrorPolicy, Find,
+    Index, OResult, OrmoxError, PartialResult, Query, WriteOptions,
+};
+use send_wrapper::SendWrapper;
+use uuid::Uuid;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{
+    IdbDatabase, IdbObjectStore, IdbObjectStoreParameters, IdbTransaction, IdbTransactionMode,
+};
+
+use idb::{commit, factory, js_error, request, DRIVER_NAME};
+
+const VERSION: u32 = 1;
+const DOCUMENTS: &str = "documents";
+const INDEXES: &str = "indexes";
+const COLLECTIONS: &str = "collections";
+const BY_COLLECTION: &str = "collection";
+
+/// Runs a future holding JS values, which aren't `Send`, where the driver trait needs a `Send` future. Browsers run
+/// wasm on a single thread, so it never actually moves.
+fn local<F: Future>(future: F) -> SendWrapper<F> {
+    SendWrapper::new(future)
+}
+
+/// Key of a document id, distinguishing types (ie `"1"` and `1`)
+fn id_key(id: &Bson) -> String {
+    id.clone().into_canonical_extjson().to_string()
+}
+
+fn key_of(document: &bson::Document) -> String {
+    id_key(document.get("_id").unwrap_or(&Bson::Null))
+}
+
+fn primary_key(collection: &str, key: &str) -> JsValue {
+    Array::of2(&collection.into(), &key.into()).into()
+}
+
+/// Gives documents without an `_id` an `ObjectId`, like MongoDB does
+fn with_id(document: &mut bson::Document) {
+    if !document.contains_key("_id") {
+        document.insert("_id", ObjectId::new());
+    }
+}
+
+fn set(record: &Object, key: &str, value: &JsValue) -> OResult<()> {
+    Reflect::set(record, &key.into(), value)
+        .map(|_| ())
+        .map_err(js_error)
+}
+
+fn get(record: &JsValue, key: &str) -> OResult<JsValue> {
+    Reflect::get(record, &key.into()).map_err(js_error)
+}
+
+fn to_record(codec: &dyn Codec, collection: &str, document: &bson::Document) -> OResult<JsValue> {
+    let bytes = codec.encode(document)?;
+    let record = Object::new();
+    set(&record, "collection", &collection.into())?;
+    set(&record, "key", &key_of(document).into())?;
+    set(&record, "codec", &codec.name().into())?;
+    set(&record, "data", &Uint8Array::from(bytes.as_slice()))?;
+    Ok(record.into())
+}
+
+fn from_record(codec: &dyn Codec, record: &JsValue) -> OResult<bson::Document> {
+    let written_with = get(record, "codec")?.as_string().unwrap_or_default();
+    if written_with != codec.name() {
+        return Err(OrmoxError::deserialization(format!(
+            "Record was written with the {written_with:?} codec, not {:?}",
+            codec.name()
+        )));
+    }
+
+    let bytes = get(record, "data")?
+        .dyn_into::<Uint8Array>()
+        .map_err(|_| OrmoxError::deserialization("Record has no data"))?;
+    codec.decode(&bytes.to_vec())
+}
+
+/// Decodes records, handling ones that fail according to `policy`
+fn decode(
+    codec: &dyn Codec,
+    records: Array,
+    policy: ErrorPolicy,
+) -> OResult<PartialResult<bson::Document>> {
+    let mut result = PartialResult::from(Vec::new());
+    for record in records.iter() {
+        match from_record(codec, &record) {
+            Ok(document) => result.items.push(document),
+            Err(e) if policy != ErrorPolicy::FailFast => result.errors.push(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(result)
+}
+
+fn create_stores(database: &IdbDatabase) -> Result<(), JsValue> {
+    let keyed_by = |field: &str| {
+        let parameters = IdbObjectStoreParameters::new();
+        parameters.set_key_path(&Array::of2(&BY_COLLECTION.into(), &field.into()));
+        parameters
+    };
+
+    database
+        .create_object_store_with_optional_parameters(DOCUMENTS, &keyed_by("key"))?
+        .create_index_with_str(BY_COLLECTION, BY_COLLECTION)?;
+    database
+        .create_object_store_with_optional_parameters(INDEXES, &keyed_by("name"))?
+        .create_index_with_str(BY_COLLECTION, BY_COLLECTION)?;
+    database.create_object_store(COLLECTIONS)?;
+    Ok(())
+}
+
+fn index_name(index: &Index) -> String {
+    index.name.clone().unwrap_or_else(|| {
+        index
+            .fields
+            .iter()
+            .map(|field| format!("{field}_1"))
+            .collect::<Vec<_>>()
+            .join("_")
+    })
+}
+
+/// Checks that no two documents share an `_id` or the fields of a unique index. Missing fields count as null.
+fn check_unique(collection: &str, documents: &[bson::Document], indexes: &[Index]) -> OResult<()> {
+    let mut ids = HashSet::new();
+    for document in documents {
+        let key = key_of(document);
+        if !ids.insert(key.clone()) {
+            return Err(OrmoxError::duplicate_key(collection, "_id_", key));
+        }
+    }
+
+    for index in indexes.iter().filter(|index| index.unique) {
+        let filter: Option<bson::Document> = index
+            .partial_filter
+            .clone()
+            .map(|filter| filter.try_into())
+            .transpose()?;
+        let mut seen = HashSet::new();
+        for document in documents {
+            // Documents outside a partial index aren't in it, so they can't conflict
+            if let Some(filter) = &filter {
+                if !memory::matches(filter, document)? {
+                    continue;
+                }
+            }
+            let values = index
+                .fields
+                .iter()
+                .map(|field| {
+                    memory::get_path(document, field)
+                        .cloned()
+                        .unwrap_or(Bson::Null)
+                })
+                .collect::<Vec<_>>();
+            let key = Bson::Array(values);
+            if !seen.insert(id_key(&key)) {
+                return Err(OrmoxError::duplicate_key(
+                    collection,
+                    index_name(index),
+                    key.to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies `update` to the documents matching `query`, or inserts one built from the query if none match & `upsert`
+/// is set. Returns the first document updated or inserted.
+fn update_documents(
+    documents: &mut Vec<bson::Document>,
+    query: &bson::Document,
+    update: &bson::Document,
+    count: OperationCount,
+    upsert: bool,
+) -> OResult<Option<bson::Document>> {
+    let mut first = None;
+    for document in documents.iter_mut() {
+        if memory::matches(query, document)? {
+            memory::apply_update(document, update)?;
+            first.get_or_insert_with(|| document.clone());
+            if matches!(count, OperationCount::One) {
+                break;
+            }
+        }
+    }
+
+    if first.is_none() && upsert {
+        let mut document = memory::upserted(query, update)?;
+        with_id(&mut document);
+        documents.push(document.clone());
+        first = Some(document);
+    }
+    Ok(first)
+}
+
+/// Driver for an IndexedDB database in the current window or worker
+#[derive(Clone)]
+pub struct IndexedDbDriver {
+    database: SendWrapper<IdbDatabase>,
+    name: String,
+    codec: Arc<dyn Codec>,
+}
+
+impl IndexedDbDriver {
+    /// Opens (or creates) the IndexedDB database `name`, storing documents as BSON
+    pub async fn open(name: impl AsRef<str>) -> OResult<Self> {
+        Self::open_with_codec(name, BsonCodec).await
+    }
+
+    /// Opens (or creates) the IndexedDB database `name`, storing documents with `codec`. A database must always be
+    /// opened with the same codec: documents written with another one fail to decode.
+    pub async fn open_with_codec(
+        name: impl AsRef<str>,
+        codec: impl Codec + 'static,
+    ) -> OResult<Self> {
+        let name = name.as_ref().to_string();
+        local(async move {
+            let opening = factory()?.open_with_u32(&name, VERSION).map_err(js_error)?;
+            let upgrading = opening.clone();
+            let onupgradeneeded = Closure::<dyn FnMut()>::new(move || {
+                if let Ok(database) = upgrading.result().and_then(|d| d.dyn_into::<IdbDatabase>()) {
+                    // A failure here aborts the upgrade, which fails the open request below
+                    let _ = create_stores(&database);
+                }
+            });
+            opening.set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
+            let database = request(&opening).await;
+            opening.set_onupgradeneeded(None);
+            drop(onupgradeneeded);
+
+            let database = database?.dyn_into::<IdbDatabase>().map_err(js_error)?;
+            Ok(Self {
+                database: SendWrapper::new(database),
+                name,
+                codec: Arc::new(codec),
+            })
+        })
+        .await
+    }
+
+    /// Name of the IndexedDB database
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Codec documents are stored with
+    pub fn codec(&self) -> &dyn Codec {
+        self.codec.as_ref()
+    }
+
+    /// The underlying IndexedDB database, for running operations ormox doesn't cover
+    pub fn database(&self) -> &IdbDatabase {
+        &self.database
+    }
+
+    fn transaction(&self, stores: &[&str], mode: IdbTransactionMode) -> OResult<IdbTransaction> {
+        let stores = stores
+            .iter()
+            .map(|s| JsValue::from_str(s))
+            .collect::<Array>();
+        self.database
+            .transaction_with_str_sequence_and_mode(&stores, mode)
+            .map_err(js_error)
+    }
+
+    /// Every record in `collection` from `store`
+    async fn records(
+        transaction: &IdbTransaction,
+        store: &str,
+        collection: &str,
+    ) -> OResult<Array> {
+        let index = Self::store(transaction, store)?
+            .index(BY_COLLECTION)
+            .map_err(js_error)?;
+        let found = request(
+            &index
+                .get_all_with_key(&collection.into())
+                .map_err(js_error)?,
+        )
+        .await?;
+        Ok(found.unchecked_into())
+    }
+
+    fn store(transaction: &IdbTransaction, name: &str) -> OResult<IdbObjectStore> {
+        transaction.object_store(name).map_err(js_error)
+    }
+
+    async fn indexes(transaction: &IdbTransaction, collection: &str) -> OResult<Vec<Index>> {
+        let mut indexes = Vec::new();
+        for record in Self::records(transaction, INDEXES, collection)
+            .await?
+            .iter()
+        {
+            let index = get(&record, "index")?.as_string().unwrap_or_default();
+            indexes.push(serde_json::from_str(&index).map_err(OrmoxError::deserialization)?);
+        }
+        Ok(indexes)
+    }
+
+    /// Documents in `collection`, handling ones that fail to decode according to `policy`
+    async fn read(
+        &self,
+        collection: &str,
+        policy: ErrorPolicy,
+    ) -> OResult<PartialResult<bson::Document>> {
+        local(async move {
+            let transaction = self.transaction(&[DOCUMENTS], IdbTransactionMode::Readonly)?;
+            decode(
+                self.codec.as_ref(),
+                Self::records(&transaction, DOCUMENTS, collection).await?,
+                policy,
+            )
+        })
+        .await
+    }
+
+    /// Runs `change` over the documents of `collection` in one read-write transaction, then stores the documents it
+    /// added or changed & deletes the ones it removed. Nothing is written if `change` fails or breaks a unique index.
+    async fn write<T>(
+        &self,
+        collection: &str,
+        change: impl FnOnce(&mut Vec<bson::Document>) -> OResult<T>,
+    ) -> OResult<T> {
+        local(async move {
+            let transaction = self.transaction(
+                &[DOCUMENTS, INDEXES, COLLECTIONS],
+                IdbTransactionMode::Readwrite,
+            )?;
+            let result = async {
+                let records = Self::records(&transaction, DOCUMENTS, collection).await?;
+                let mut documents =
+                    decode(self.codec.as_ref(), records, ErrorPolicy::FailFast)?.items;
+                let indexes = Self::indexes(&transaction, collection).await?;
+                let before = documents
+                    .iter()
+                    .map(|document| (key_of(document), document.clone()))
+                    .collect::<HashMap<_, _>>();
+
+                let result = change(&mut documents)?;
+                check_unique(collection, &documents, &indexes)?;
+
+                let store = Self::store(&transaction, DOCUMENTS)?;
+                let mut kept = HashSet::new();
+                for document in &documents {
+                    let key = key_of(document);
+                    if before.get(&key) != Some(document) {
+                        store
+                            .put(&to_record(self.codec.as_ref(), collection, document)?)
+                            .map_err(js_error)?;
+                    }
+                    kept.insert(key);
+                }
+                for key in before.keys().filter(|key| !kept.contains(*key)) {
+                    store
+                        .delete(&primary_key(collection, key))
+                        .map_err(js_error)?;
+                }
+                if !documents.is_empty() {
+                    Self::store(&transaction, COLLECTIONS)?
+                        .put_with_key(&collection.into(), &collection.into())
+                        .map_err(js_error)?;
+                }
+
+                commit(&transaction).await?;
+                Ok(result)
+            }
+            .await;
+
+            if result.is_err() {
+                // Fails harmlessly if the transaction already finished
+                let _ = transaction.abort();
+            }
+            result
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DatabaseDriver for IndexedDbDriver {
+    fn driver_name(&self) -> String {
+        String::from(DRIVER_NAME)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities::INDEXES
+            | DriverCapabilities::UNIQUE_INDEXES
+            | DriverCapabilities::REGEX
+            | DriverCapabilities::COLLECTION_STATS
+            | DriverCapabilities::HASHED_INDEXES
+            | DriverCapabilities::PARTIAL_INDEXES
+    }
+
+    async fn collections(&self) -> OResult<Vec<String>> {
+        local(async move {
+            let transaction = self.transaction(&[COLLECTIONS], IdbTransactionMode::Readonly)?;
+            let store = Self::store(&transaction, COLLECTIONS)?;
+            let names = request(&store.get_all_keys().map_err(js_error)?).await?;
+            Ok(names
+                .unchecked_into::<Array>()
+                .iter()
+                .filter_map(|name| name.as_string())
+                .collect())
+        })
+        .await
+    }
+
+    async fn insert(
+        &self,
+        collection: String,
+        documents: Vec<bson::Document>,
+        _options: WriteOptions,
+    ) -> OResult<Vec<Uuid>> {
+        let mut documents = documents;
+        let mut ids = Vec::new();
+        for document in documents.iter_mut() {
+            with_id(document);
+            if let Some(Ok(id)) = document
+                .get("_id")
+                .map(|id| bson::from_bson::<Uuid>(id.clone()))
+            {
+                ids.push(id);
+            }
+        }
+
+        self.write(&collection, move |existing| {
+            existing.extend(documents);
+            Ok(())
+        })
+        .await?;
+        Ok(ids)
+    }
+
+    async fn update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        count: OperationCount,
+        _options: WriteOptions,
+    ) -> OResult<()> {
+        let query: bson::Document = query.try_into()?;
+        self.write(&collection, move |documents| {
+            update_documents(documents, &query, &update, count, false).map(|_| ())
+        })
+        .await
+    }
+
+    async fn delete(
+        &self,
+        collection: String,
+        query: Query,
+        count: OperationCount,
+        _options: WriteOptions,
+    ) -> OResult<()> {
+        let query: bson::Document = query.try_into()?;
+        self.write(&collection, move |documents| {
+            let mut kept = Vec::with_capacity(documents.len());
+            let mut deleting = true;
+            for document in documents.drain(..) {
+                if deleting && memory::matches(&query, &document)? {
+                    deleting = matches!(count, OperationCount::Many);
+                } else {
+                    kept.push(document);
+                }
+            }
+            *documents = kept;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn find(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        // Errors are only collected by `find_partial`
+        Ok(self.find_partial(collection, query, options).await?.items)
+    }
+
+    async fn find_partial(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<PartialResult<bson::Document>> {
+        let query: bson::Document = query.try_into()?;
+        let PartialResult { items, errors } = self.read(&collection, options.error_policy).await?;
+        Ok(PartialResult {
+            items: memory::find(items, &query, &options)?,
+            errors,
+        })
+    }
+
+    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
+        self.find(collection, Query::new(), options).await
+    }
+
+    async fn upsert(
+        &self,
+        collection: String,
+        query: Query,
+        document: bson::Document,
+        count: OperationCount,
+        _options: WriteOptions,
+    ) -> OResult<()> {
+        let query: bson::Document = query.try_into()?;
+        let update = doc! {"$set": document};
+        self.write(&collection, move |documents| {
+            update_documents(documents, &query, &update, count, true).map(|_| ())
+        })
+        .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        upsert: bool,
+        _options: WriteOptions,
+    ) -> OResult<Option<bson::Document>> {
+        let query: bson::Document = query.try_into()?;
+        self.write(&collection, move |documents| {
+            update_documents(documents, &query, &update, OperationCount::One, upsert)
+        })
+        .await
+    }
+
+    /// Indexes only enforce uniqueness, so hashed ones are the same as regular ones
+    async fn create_index(&self, collection: String, index: Index) -> OResult<()> {
+        self.capabilities().require(index.required_capabilities())?;
+        let name = index_name(&index);
+        let encoded = serde_json::to_string(&index).map_err(OrmoxError::serialization)?;
+        local(async move {
+            let transaction = self.transaction(
+                &[DOCUMENTS, INDEXES, COLLECTIONS],
+                IdbTransactionMode::Readwrite,
+            )?;
+            let result = async {
+                if index.unique {
+                    let records = Self::records(&transaction, DOCUMENTS, &collection).await?;
+                    check_unique(
+                        &collection,
+                        &decode(self.codec.as_ref(), records, ErrorPolicy::FailFast)?.items,
+                        &[index],
+                    )?;
+                }
+
+                let record = Object::new();
+                set(&record, "collection", &collection.as_str().into())?;
+                set(&record, "name", &name.as_str().into())?;
+                set(&record, "index", &encoded.as_str().into())?;
+                Self::store(&transaction, INDEXES)?
+                    .put(&record)
+                    .map_err(js_error)?;
+                Self::store(&transaction, COLLECTIONS)?
+                    .put_with_key(&collection.as_str().into(), &collection.as_str().into())
+                    .map_err(js_error)?;
+                commit(&transaction).await
+            }
+            .await;
+
+            if result.is_err() {
+                let _ = transaction.abort();
+            }
+            result
+        })
+        .await
+    }
+
+    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
+        local(async move {
+            let transaction = self.transaction(&[INDEXES], IdbTransactionMode::Readwrite)?;
+            Self::store(&transaction, INDEXES)?
+                .delete(&primary_key(&collection, &name))
+                .map_err(js_error)?;
+            commit(&transaction).await
+        })
+        .await
+    }
+
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        local(async move {
+            let transaction = self.transaction(&[DOCUMENTS], IdbTransactionMode::Readonly)?;
+            let records = Self::records(&transaction, DOCUMENTS, &collection).await?;
+            let mut size = 0;
+            for record in records.iter() {
+                size += get(&record, "data")?
+                    .dyn_into::<Uint8Array>()
+                    .map(|bytes| bytes.length() as u64)
+                    .unwrap_or_default();
+            }
+
+            Ok(CollectionStats {
+                count: records.length() as u64,
+                size: Some(size),
+                // Declared indexes aren't materialized, so they take no space
+                index_sizes: HashMap::new(),
+            })
+        })
+        .await
+    }
+
+    /// Closes the connection; IndexedDB lets pending transactions finish first
+    async fn shutdown(&self) -> OResult<()> {
+        self.database.close();
+        Ok(())
+    }
+}
@@ -1,114 +1,382 @@
-use futures::stream::TryStreamExt;
-use std::{error::Error, sync::Arc};
+use futures::{
+    lock::Mutex as AsyncMutex,
+    stream::{StreamExt, TryStreamExt},
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    error::Error,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use mongodb::{
-    bson::{self, doc},
-    options::IndexOptions,
-    Collection, Database, IndexModel,
+    bson::{self, doc, RawDocumentBuf},
+    change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken},
+    error::{
+        ErrorKind, InsertManyError, WriteFailure, RETRYABLE_WRITE_ERROR,
+        TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT,
+    },
+    options::{
+        Acknowledgment, ClientOptions, Collation, CollationStrength, DeleteOptions,
+        FindOneAndUpdateOptions, FullDocumentBeforeChangeType, FullDocumentType, Hint,
+        IndexOptions, InsertManyOptions, ReadPreference, ReturnDocument, SelectionCriteria, Tls,
+        TlsOptions, UpdateOptions, WriteConcern,
+    },
+    Client, ClientSession, Collection, Database, IndexModel,
 };
 use ormox_core::{
-    core::driver::OperationCount, DatabaseDriver, Find, OResult, OrmoxError, Query, Sorting,
+    core::{
+        change::{Change, ChangeKind, ChangeStream},
+        driver::OperationCount,
+    },
+    CollectionStats, DatabaseDriver, DriverCapabilities, Find, IndexProgress, IndexStats, OResult,
+    OrmoxError, Query, QueryPlan, Sorting, WriteOptions,
 };
+use serde::de::DeserializeOwned;
 use uuid::Uuid;
 
 #[allow(dead_code)]
-fn wrap<T, E: Error>(result: Result<T, E>) -> OResult<T> {
-    match result {
-        Ok(r) => Ok(r),
-        Err(e) => Err(OrmoxError::driver("base::mongodb", e)),
+fn wrap<T, E: Error + 'static>(result: Result<T, E>) -> OResult<T> {
+    result.map_err(driver_error)
+}
+
+/// Converts a driver error, marking errors that are safe to retry as transient
+fn driver_error<E: Error + 'static>(error: E) -> OrmoxError {
+    match (&error as &dyn Any).downcast_ref::<mongodb::error::Error>() {
+        Some(e) if is_transient(e) => OrmoxError::transient("base::mongodb", error),
+        _ => OrmoxError::driver("base::mongodb", error),
     }
 }
 
-#[allow(dead_code)]
-pub struct MongoDriver(Arc<Database>);
+/// Whether the server or driver reports that retrying may succeed
+fn is_transient(error: &mongodb::error::Error) -> bool {
+    [
+        RETRYABLE_WRITE_ERROR,
+        TRANSIENT_TRANSACTION_ERROR,
+        UNKNOWN_TRANSACTION_COMMIT_RESULT,
+    ]
+    .iter()
+    .any(|label| error.contains_label(label))
+        || matches!(
+            error.kind.as_ref(),
+            ErrorKind::Io(_)
+                | ErrorKind::ConnectionPoolCleared { .. }
+                | ErrorKind::ServerSelection { .. }
+        )
+}
 
-#[allow(dead_code)]
-impl MongoDriver {
-    fn collection(&self, name: String) -> Collection<bson::Document> {
-        self.0.collection(name.as_str())
-    }
+/// MongoDB's error code for unique index violations
+const DUPLICATE_KEY: i32 = 11000;
 
-    pub fn new(db: Database) -> Self {
-        Self(Arc::new(db))
+/// Like `wrap`, but normalizes duplicate key errors into `OrmoxError::DuplicateKey`
+fn wrap_write<T>(collection: &str, result: mongodb::error::Result<T>) -> OResult<T> {
+    result.map_err(|e| {
+        let message = match e.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(error)) if error.code == DUPLICATE_KEY => {
+                Some(error.message.clone())
+            }
+            ErrorKind::InsertMany(InsertManyError {
+                write_errors: Some(errors),
+                ..
+            }) => errors
+                .iter()
+                .find(|error| error.code == DUPLICATE_KEY)
+                .map(|error| error.message.clone()),
+            ErrorKind::Command(error) if error.code == DUPLICATE_KEY => Some(error.message.clone()),
+            _ => None,
+        };
+
+        match message {
+            Some(message) => duplicate_key(collection, &message),
+            None => driver_error(e),
+        }
+    })
+}
+
+/// Builds an `OrmoxError::DuplicateKey` from a server message, ie
+/// "E11000 duplicate key error collection: db.users index: email_1 dup key: { email: \"a@b.c\" }"
+fn duplicate_key(collection: &str, message: &str) -> OrmoxError {
+    OrmoxError::duplicate_key(
+        collection,
+        message
+            .split_once("index: ")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .unwrap_or_default(),
+        message
+            .split_once("dup key: ")
+            .map(|(_, key)| key)
+            .unwrap_or_default(),
+    )
+}
+
+fn bson_u64(value: Option<&bson::Bson>) -> Option<u64> {
+    match value? {
+        bson::Bson::Int32(v) => u64::try_from(*v).ok(),
+        bson::Bson::Int64(v) => u64::try_from(*v).ok(),
+        bson::Bson::Double(v) if *v >= 0.0 => Some(*v as u64),
+        _ => None,
     }
 }
 
-#[async_trait]
-impl DatabaseDriver for MongoDriver {
-    fn driver_name(&self) -> String {
-        String::from("base::mongodb")
+fn plan_stages(stage: &bson::Document, plan: &mut QueryPlan) {
+    match stage.get_str("stage") {
+        Ok("IXSCAN") | Ok("IDHACK") | Ok("EXPRESS_IXSCAN") => plan
+            .indexes
+            .push(stage.get_str("indexName").unwrap_or("_id_").to_string()),
+        Ok("COLLSCAN") => plan.collection_scan = true,
+        _ => {}
     }
 
-    async fn collections(&self) -> OResult<Vec<String>> {
-        wrap(self.0.list_collection_names().await)
+    for key in ["queryPlan", "inputStage"] {
+        if let Ok(child) = stage.get_document(key) {
+            plan_stages(child, plan);
+        }
     }
 
-    async fn insert(
-        &self,
-        collection: String,
-        documents: Vec<bson::Document>,
-    ) -> OResult<Vec<Uuid>> {
-        let result = wrap(self.collection(collection).insert_many(documents).await)?;
-        let mut ids: Vec<Uuid> = Vec::new();
-        for id in result.inserted_ids.values() {
-            ids.push(wrap(bson::from_bson::<Uuid>(id.clone()))?);
+    if let Ok(children) = stage.get_array("inputStages") {
+        for child in children.iter().filter_map(|c| c.as_document()) {
+            plan_stages(child, plan);
         }
-        Ok(ids)
     }
+}
 
-    async fn update(
-        &self,
-        collection: String,
-        query: Query,
-        update: bson::Document,
-        count: OperationCount,
-    ) -> OResult<()> {
-        wrap(match count {
-            OperationCount::One => {
-                self.collection(collection)
-                    .update_one(wrap(query.try_into())?, update)
-                    .await
-            }
-            OperationCount::Many => {
-                self.collection(collection)
-                    .update_many(wrap(query.try_into())?, update)
-                    .await
+/// Connection options for [`MongoDriver::connect`]. Unset fields use the connection string or MongoDB defaults.
+#[derive(Clone, Debug, Default)]
+pub struct MongoOptions {
+    pub app_name: Option<String>,
+    pub min_pool_size: Option<u32>,
+    pub max_pool_size: Option<u32>,
+    pub max_idle_time: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub server_selection_timeout: Option<Duration>,
+
+    /// Enables or disables TLS. Leave unset to follow the connection string.
+    pub tls: Option<bool>,
+    pub tls_ca_file: Option<PathBuf>,
+    pub tls_allow_invalid_certificates: Option<bool>,
+
+    /// Default read preference for all reads made through this client
+    pub read_preference: Option<ormox_core::ReadPreference>,
+
+    /// Default write concern for all writes made through this client
+    pub write_concern: Option<ormox_core::WriteConcern>,
+}
+
+impl MongoOptions {
+    fn apply(self, options: &mut ClientOptions) {
+        if self.app_name.is_some() {
+            options.app_name = self.app_name;
+        }
+        if self.min_pool_size.is_some() {
+            options.min_pool_size = self.min_pool_size;
+        }
+        if self.max_pool_size.is_some() {
+            options.max_pool_size = self.max_pool_size;
+        }
+        if self.max_idle_time.is_some() {
+            options.max_idle_time = self.max_idle_time;
+        }
+        if self.connect_timeout.is_some() {
+            options.connect_timeout = self.connect_timeout;
+        }
+        if self.server_selection_timeout.is_some() {
+            options.server_selection_timeout = self.server_selection_timeout;
+        }
+
+        if let Some(preference) = self.read_preference {
+            options.selection_criteria = Some(read_preference(preference));
+        }
+        if self.write_concern.is_some() {
+            options.write_concern = write_concern(self.write_concern);
+        }
+
+        match self.tls {
+            Some(false) => options.tls = Some(Tls::Disabled),
+            Some(true) => {
+                options.tls = Some(Tls::Enabled(
+                    TlsOptions::builder()
+                        .ca_file_path(self.tls_ca_file)
+                        .allow_invalid_certificates(self.tls_allow_invalid_certificates)
+                        .build(),
+                ))
             }
-        })?;
-        Ok(())
+            None => {}
+        }
     }
+}
 
-    async fn delete(&self, collection: String, query: Query, count: OperationCount) -> OResult<()> {
-        wrap(match count {
-            OperationCount::One => {
-                self.collection(collection)
-                    .delete_one(wrap(query.try_into())?)
-                    .await
+fn read_preference(preference: ormox_core::ReadPreference) -> SelectionCriteria {
+    SelectionCriteria::ReadPreference(match preference {
+        ormox_core::ReadPreference::Primary => ReadPreference::Primary,
+        ormox_core::ReadPreference::PrimaryPreferred => {
+            ReadPreference::PrimaryPreferred { options: None }
+        }
+        ormox_core::ReadPreference::Secondary => ReadPreference::Secondary { options: None },
+        ormox_core::ReadPreference::SecondaryPreferred => {
+            ReadPreference::SecondaryPreferred { options: None }
+        }
+        ormox_core::ReadPreference::Nearest => ReadPreference::Nearest { options: None },
+    })
+}
+
+fn write_concern(concern: Option<ormox_core::WriteConcern>) -> Option<WriteConcern> {
+    let concern = concern?;
+    Some(
+        WriteConcern::builder()
+            .w(concern.w.map(|w| match w {
+                ormox_core::Acknowledgment::Nodes(n) => Acknowledgment::Nodes(n),
+                ormox_core::Acknowledgment::Majority => Acknowledgment::Majority,
+                ormox_core::Acknowledgment::Custom(c) => Acknowledgment::Custom(c),
+            }))
+            .journal(concern.journal)
+            .w_timeout(concern.timeout)
+            .build(),
+    )
+}
+
+fn collation(collation: ormox_core::Collation) -> Collation {
+    let strength = match collation.effective_strength() {
+        ormox_core::CollationStrength::Primary => CollationStrength::Primary,
+        ormox_core::CollationStrength::Secondary => CollationStrength::Secondary,
+        ormox_core::CollationStrength::Tertiary => CollationStrength::Tertiary,
+        ormox_core::CollationStrength::Quaternary => CollationStrength::Quaternary,
+        ormox_core::CollationStrength::Identical => CollationStrength::Identical,
+    };
+    Collation::builder()
+        .locale(collation.locale)
+        .strength(strength)
+        .build()
+}
+
+fn to_change(event: ChangeStreamEvent<bson::Document>) -> OResult<Change> {
+    let token = match wrap(bson::to_bson(&event.id))? {
+        bson::Bson::Document(token) => token,
+        token => doc! {"_data": token},
+    };
+    let (updated_fields, removed_fields) = match event.update_description {
+        Some(description) => (Some(description.updated_fields), description.removed_fields),
+        None => (None, Vec::new()),
+    };
+
+    Ok(Change {
+        token: ormox_core::ResumeToken(token),
+        kind: match event.operation_type {
+            OperationType::Insert => ChangeKind::Insert,
+            OperationType::Update => ChangeKind::Update,
+            OperationType::Replace => ChangeKind::Replace,
+            OperationType::Delete => ChangeKind::Delete,
+            OperationType::Drop => ChangeKind::Other("drop".to_string()),
+            OperationType::Rename => ChangeKind::Other("rename".to_string()),
+            OperationType::DropDatabase => ChangeKind::Other("dropDatabase".to_string()),
+            OperationType::Invalidate => ChangeKind::Other("invalidate".to_string()),
+            OperationType::Other(other) => ChangeKind::Other(other),
+            _ => ChangeKind::Other("unknown".to_string()),
+        },
+        key: event.document_key,
+        document: event.full_document,
+        previous: event.full_document_before_change,
+        updated_fields,
+        removed_fields,
+    })
+}
+
+/// Runs a MongoDB action, attaching the given ormox session if present
+macro_rules! in_session {
+    ($driver:expr, $session:expr, $action:expr) => {
+        match $driver.session($session)? {
+            Some(session) => {
+                let mut session = session.lock().await;
+                $action.session(&mut *session).await
             }
-            OperationCount::Many => {
-                self.collection(collection)
-                    .delete_many(wrap(query.try_into())?)
-                    .await
+            None => $action.await,
+        }
+    };
+}
+
+type Sessions = Arc<Mutex<HashMap<Uuid, Arc<AsyncMutex<ClientSession>>>>>;
+
+#[allow(dead_code)]
+pub struct MongoDriver(Arc<Database>, Sessions);
+
+#[allow(dead_code)]
+impl MongoDriver {
+    fn collection(&self, name: String) -> Collection<bson::Document> {
+        self.0.collection(name.as_str())
+    }
+
+    fn session(&self, id: Option<Uuid>) -> OResult<Option<Arc<AsyncMutex<ClientSession>>>> {
+        match id {
+            Some(id) => match self.1.lock().unwrap().get(&id) {
+                Some(session) => Ok(Some(session.clone())),
+                None => Err(OrmoxError::Driver {
+                    driver_name: String::from("base::mongodb"),
+                    error: format!("Unknown or ended session {id}"),
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    async fn run_find<T: DeserializeOwned + Send + Sync + Unpin>(
+        &self,
+        find: mongodb::action::Find<'_, T>,
+        session: Option<Uuid>,
+    ) -> OResult<Vec<T>> {
+        match self.session(session)? {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let mut cursor = wrap(find.session(&mut *session).await)?;
+                wrap(cursor.stream(&mut session).try_collect().await)
             }
-        })?;
-        Ok(())
+            None => wrap(wrap(find.await)?.try_collect().await),
+        }
     }
 
-    async fn find(
+    /// Finds documents, deserializing them as `T` straight from the wire
+    async fn find_documents<T: DeserializeOwned + Send + Sync + Unpin>(
         &self,
         collection: String,
         query: Query,
         options: Find,
-    ) -> OResult<Vec<bson::Document>> {
-        let cl = self.collection(collection);
+    ) -> OResult<Vec<T>> {
+        let cl = self.0.collection::<T>(collection.as_str());
         let results = match options.operation {
-            OperationCount::One => wrap(cl.find_one(wrap(query.try_into())?).await)?
-                .and_then(|d| Some(vec![d]))
-                .or(Some(Vec::<bson::Document>::new()))
-                .unwrap(),
+            OperationCount::One => {
+                let mut find = cl.find_one(wrap(query.try_into())?);
+                if let Some(preference) = options.read_preference {
+                    find = find.selection_criteria(read_preference(preference));
+                }
+
+                if let Some(projection) = options.projection {
+                    find = find.projection(projection);
+                }
+
+                if let Some(options) = options.collation {
+                    find = find.collation(collation(options));
+                }
+
+                if let Some(hint) = options.hint {
+                    find = find.hint(Hint::Name(hint));
+                }
+
+                wrap(in_session!(self, options.session, find))?
+                    .and_then(|d| Some(vec![d]))
+                    .or(Some(Vec::<T>::new()))
+                    .unwrap()
+            }
             OperationCount::Many => {
                 let mut find = cl.find(wrap(query.try_into())?);
+                if let Some(preference) = options.read_preference {
+                    find = find.selection_criteria(read_preference(preference));
+                }
+
+                if let Some(projection) = options.projection {
+                    find = find.projection(projection);
+                }
+
                 if let Some(sort) = options.sort {
                     find = find.sort(match sort {
                         Sorting::Ascending(field) => doc! {field: 1},
@@ -117,23 +385,47 @@ impl DatabaseDriver for MongoDriver {
                 }
 
                 if let Some(skip) = options.offset {
-                    find = find.skip(skip.try_into().unwrap());
+                    find = find.skip(skip.try_into().map_err(OrmoxError::compaibility)?);
                 }
 
                 if let Some(limit) = options.limit {
-                    find = find.limit(limit.try_into().unwrap());
+                    find = find.limit(limit.try_into().map_err(OrmoxError::compaibility)?);
+                }
+
+                if let Some(batch_size) = options.batch_size {
+                    find = find.batch_size(batch_size.try_into().unwrap_or(u32::MAX));
+                }
+
+                if let Some(options) = options.collation {
+                    find = find.collation(collation(options));
+                }
+
+                if let Some(hint) = options.hint {
+                    find = find.hint(Hint::Name(hint));
                 }
 
-                wrap(wrap(find.await)?.try_collect::<Vec<bson::Document>>().await)?
+                self.run_find(find, options.session).await?
             }
         };
 
         Ok(results)
     }
 
-    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
-        let cl = self.collection(collection);
+    async fn all_documents<T: DeserializeOwned + Send + Sync + Unpin>(
+        &self,
+        collection: String,
+        options: Find,
+    ) -> OResult<Vec<T>> {
+        let cl = self.0.collection::<T>(collection.as_str());
         let mut find = cl.find(doc! {});
+        if let Some(preference) = options.read_preference {
+            find = find.selection_criteria(read_preference(preference));
+        }
+
+        if let Some(projection) = options.projection {
+            find = find.projection(projection);
+        }
+
         if let Some(sort) = options.sort {
             find = find.sort(match sort {
                 Sorting::Ascending(field) => doc! {field: 1},
@@ -142,21 +434,308 @@ impl DatabaseDriver for MongoDriver {
         }
 
         if let Some(skip) = options.offset {
-            find = find.skip(skip.try_into().unwrap());
+            find = find.skip(skip.try_into().map_err(OrmoxError::compaibility)?);
         }
 
         if let Some(limit) = options.limit {
-            find = find.limit(limit.try_into().unwrap());
+            find = find.limit(limit.try_into().map_err(OrmoxError::compaibility)?);
+        }
+
+        if let Some(batch_size) = options.batch_size {
+            find = find.batch_size(batch_size.try_into().unwrap_or(u32::MAX));
+        }
+
+        if let Some(options) = options.collation {
+            find = find.collation(collation(options));
+        }
+
+        if let Some(hint) = options.hint {
+            find = find.hint(Hint::Name(hint));
+        }
+
+        self.run_find(find, options.session).await
+    }
+
+    pub fn new(db: Database) -> Self {
+        Self(Arc::new(db), Default::default())
+    }
+
+    /// Connects to a MongoDB deployment and selects the named database
+    pub async fn connect(
+        uri: impl AsRef<str>,
+        db_name: impl AsRef<str>,
+        options: MongoOptions,
+    ) -> OResult<Self> {
+        let mut client_options = wrap(ClientOptions::parse(uri.as_ref()).await)?;
+        options.apply(&mut client_options);
+        let client = wrap(Client::with_options(client_options))?;
+        Ok(Self::new(client.database(db_name.as_ref())))
+    }
+
+    /// Major version of the server, ie 7
+    async fn server_version(&self) -> OResult<i32> {
+        let info = wrap(self.0.run_command(doc! {"buildInfo": 1}).await)?;
+        Ok(info
+            .get_array("versionArray")
+            .ok()
+            .and_then(|version| version.first())
+            .and_then(|major| major.as_i32())
+            .unwrap_or(0))
+    }
+
+    /// The underlying MongoDB database, for running commands ormox doesn't cover
+    pub fn database(&self) -> &Database {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl DatabaseDriver for MongoDriver {
+    fn driver_name(&self) -> String {
+        String::from("base::mongodb")
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities::all()
+    }
+
+    async fn collections(&self) -> OResult<Vec<String>> {
+        wrap(self.0.list_collection_names().await)
+    }
+
+    async fn start_session(&self) -> OResult<Uuid> {
+        let session = wrap(
+            self.0
+                .client()
+                .start_session()
+                .causal_consistency(true)
+                .await,
+        )?;
+        let id = Uuid::new_v4();
+        self.1
+            .lock()
+            .unwrap()
+            .insert(id, Arc::new(AsyncMutex::new(session)));
+        Ok(id)
+    }
+
+    async fn end_session(&self, session: Uuid) -> OResult<()> {
+        self.1.lock().unwrap().remove(&session);
+        Ok(())
+    }
+
+    async fn start_transaction(&self, session: Uuid) -> OResult<()> {
+        if let Some(session) = self.session(Some(session))? {
+            wrap(session.lock().await.start_transaction().await)?;
+        }
+        Ok(())
+    }
+
+    async fn commit_transaction(&self, session: Uuid) -> OResult<()> {
+        if let Some(session) = self.session(Some(session))? {
+            wrap(session.lock().await.commit_transaction().await)?;
         }
+        Ok(())
+    }
+
+    async fn abort_transaction(&self, session: Uuid) -> OResult<()> {
+        if let Some(session) = self.session(Some(session))? {
+            wrap(session.lock().await.abort_transaction().await)?;
+        }
+        Ok(())
+    }
+
+    /// Watches with the full document looked up for updates, and the previous document for collections with pre-images
+    /// enabled (MongoDB 6.0+)
+    async fn watch(
+        &self,
+        collection: String,
+        resume_after: Option<ormox_core::ResumeToken>,
+    ) -> OResult<ChangeStream> {
+        let cl = self.collection(collection);
+        let mut watch = cl.watch().full_document(FullDocumentType::UpdateLookup);
+        if self.server_version().await? >= 6 {
+            watch = watch.full_document_before_change(FullDocumentBeforeChangeType::WhenAvailable);
+        }
+
+        if let Some(token) = resume_after {
+            let token: ResumeToken = wrap(bson::from_bson(bson::Bson::Document(token.0)))?;
+            watch = watch.resume_after(token);
+        }
+
+        let changes = wrap(watch.await)?;
+        Ok(changes.map(|event| to_change(wrap(event)?)).boxed())
+    }
+
+    /// Ends open sessions, aborting their transactions, and closes the connection pool. Open cursors & change streams
+    /// are cut off rather than waited for.
+    async fn shutdown(&self) -> OResult<()> {
+        let sessions: Vec<_> = self.1.lock().unwrap().drain().collect();
+        drop(sessions);
+        self.0.client().clone().shutdown().immediate(true).await;
+        Ok(())
+    }
+
+    async fn ping(&self) -> OResult<()> {
+        wrap(self.0.run_command(doc! {"ping": 1}).await).and(Ok(()))
+    }
+
+    async fn insert(
+        &self,
+        collection: String,
+        documents: Vec<bson::Document>,
+        options: WriteOptions,
+    ) -> OResult<Vec<Uuid>> {
+        let cl = self.collection(collection);
+        let insert_options = InsertManyOptions::builder()
+            .write_concern(write_concern(options.write_concern))
+            .build();
+        let result = wrap_write(
+            cl.name(),
+            in_session!(
+                self,
+                options.session,
+                cl.insert_many(documents)
+                    .with_options(insert_options.clone())
+            ),
+        )?;
+        let mut ids: Vec<Uuid> = Vec::new();
+        for id in result.inserted_ids.values() {
+            if let Ok(id) = bson::from_bson::<Uuid>(id.clone()) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
 
-        wrap(wrap(find.await)?.try_collect::<Vec<bson::Document>>().await)
+    async fn update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let cl = self.collection(collection);
+        let query: bson::Document = wrap(query.try_into())?;
+        let update_options = UpdateOptions::builder()
+            .write_concern(write_concern(options.write_concern))
+            .build();
+        wrap_write(
+            cl.name(),
+            match count {
+                OperationCount::One => in_session!(
+                    self,
+                    options.session,
+                    cl.update_one(query, update).with_options(update_options)
+                ),
+                OperationCount::Many => in_session!(
+                    self,
+                    options.session,
+                    cl.update_many(query, update).with_options(update_options)
+                ),
+            },
+        )?;
+        Ok(())
+    }
+
+    async fn find_one_and_update(
+        &self,
+        collection: String,
+        query: Query,
+        update: bson::Document,
+        upsert: bool,
+        options: WriteOptions,
+    ) -> OResult<Option<bson::Document>> {
+        let cl = self.collection(collection);
+        let query: bson::Document = wrap(query.try_into())?;
+        let update_options = FindOneAndUpdateOptions::builder()
+            .upsert(upsert)
+            .return_document(ReturnDocument::After)
+            .write_concern(write_concern(options.write_concern))
+            .build();
+        wrap_write(
+            cl.name(),
+            in_session!(
+                self,
+                options.session,
+                cl.find_one_and_update(query, update)
+                    .with_options(update_options)
+            ),
+        )
+    }
+
+    async fn delete(
+        &self,
+        collection: String,
+        query: Query,
+        count: OperationCount,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        let cl = self.collection(collection);
+        let query: bson::Document = wrap(query.try_into())?;
+        let delete_options = DeleteOptions::builder()
+            .write_concern(write_concern(options.write_concern))
+            .build();
+        wrap(match count {
+            OperationCount::One => in_session!(
+                self,
+                options.session,
+                cl.delete_one(query).with_options(delete_options)
+            ),
+            OperationCount::Many => in_session!(
+                self,
+                options.session,
+                cl.delete_many(query).with_options(delete_options)
+            ),
+        })?;
+        Ok(())
+    }
+
+    async fn find(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        self.find_documents(collection, query, options).await
+    }
+
+    async fn find_raw(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<Vec<RawDocumentBuf>> {
+        self.find_documents(collection, query, options).await
+    }
+
+    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
+        self.all_documents(collection, options).await
+    }
+
+    async fn all_raw(&self, collection: String, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        self.all_documents(collection, options).await
     }
 
     async fn create_index(&self, collection: String, index: ormox_core::Index) -> OResult<()> {
         let mut keys: bson::Document = bson::Document::new();
+        let value = index.key_value();
         for key in index.fields {
-            keys.insert(key, 1);
+            keys.insert(key, value.clone());
         }
+        let partial_filter: Option<bson::Document> = wrap(
+            index
+                .partial_filter
+                .map(|filter| filter.try_into())
+                .transpose(),
+        )?;
+
+        // `index.background` needs nothing: MongoDB 4.2+ builds every index online & ignores the old option
         wrap(
             self.collection(collection)
                 .create_index(
@@ -166,6 +745,8 @@ impl DatabaseDriver for MongoDriver {
                             IndexOptions::builder()
                                 .unique(Some(index.unique))
                                 .name(index.name)
+                                .collation(index.collation.map(collation))
+                                .partial_filter_expression(partial_filter)
                                 .build(),
                         ))
                         .build(),
@@ -179,27 +760,323 @@ impl DatabaseDriver for MongoDriver {
         wrap(self.collection(collection).drop_index(name).await)
     }
 
+    // Sharded collections report each index once per shard, so their counts are summed
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        let entries: Vec<bson::Document> = wrap(
+            wrap(
+                self.collection(collection)
+                    .aggregate(vec![doc! {"$indexStats": {}}])
+                    .await,
+            )?
+            .try_collect()
+            .await,
+        )?;
+        let mut stats: Vec<IndexStats> = Vec::new();
+        for entry in entries {
+            let name = wrap(entry.get_str("name"))?.to_string();
+            let accesses = entry.get_document("accesses").ok();
+            let ops = bson_u64(accesses.and_then(|a| a.get("ops"))).unwrap_or_default();
+            let since = accesses
+                .and_then(|a| a.get_datetime("since").ok())
+                .map(|since| since.to_chrono());
+            match stats.iter_mut().find(|s| s.name == name) {
+                Some(existing) => {
+                    existing.accesses += ops;
+                    existing.since = match (existing.since, since) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+                None => stats.push(IndexStats {
+                    name,
+                    accesses: ops,
+                    since,
+                    last_used: None,
+                }),
+            }
+        }
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(stats)
+    }
+
+    // Index builds show up in `$currentOp` with the documents scanned so far
+    async fn index_build_progress(
+        &self,
+        collection: String,
+        name: String,
+    ) -> OResult<Option<IndexProgress>> {
+        let pipeline = vec![
+            doc! {"$currentOp": {"allUsers": true, "idleConnections": false}},
+            doc! {"$match": {
+                "ns": format!("{}.{}", self.0.name(), collection),
+                "command.createIndexes": &collection,
+                "command.indexes.name": &name,
+                "progress": {"$exists": true},
+            }},
+        ];
+        let operations: Vec<bson::Document> = wrap(
+            wrap(self.0.client().database("admin").aggregate(pipeline).await)?
+                .try_collect()
+                .await,
+        )?;
+        Ok(operations.iter().find_map(|operation| {
+            let progress = operation.get_document("progress").ok()?;
+            Some(IndexProgress {
+                done: bson_u64(progress.get("done"))?,
+                total: bson_u64(progress.get("total"))?,
+            })
+        }))
+    }
+
+    async fn count(&self, collection: String, query: Query, options: Find) -> OResult<u64> {
+        let cl = self.collection(collection);
+        let mut count = cl.count_documents(wrap(query.try_into())?);
+        if let Some(preference) = options.read_preference {
+            count = count.selection_criteria(read_preference(preference));
+        }
+
+        if let Some(options) = options.collation {
+            count = count.collation(collation(options));
+        }
+
+        if let Some(hint) = options.hint {
+            count = count.hint(Hint::Name(hint));
+        }
+
+        wrap(in_session!(self, options.session, count))
+    }
+
+    /// Runs the page & the count in a single `$facet` aggregation
+    async fn find_with_count(
+        &self,
+        collection: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<(Vec<bson::Document>, u64)> {
+        let mut page: Vec<bson::Document> = Vec::new();
+        if let Some(sort) = options.sort.clone() {
+            page.push(doc! {"$sort": match sort {
+                Sorting::Ascending(field) => doc! {field: 1},
+                Sorting::Descending(field) => doc! {field: -1},
+            }});
+        }
+
+        if let Some(skip) = options.offset {
+            page.push(doc! {"$skip": i64::try_from(skip).map_err(OrmoxError::compaibility)?});
+        }
+
+        match (options.operation.clone(), options.limit) {
+            (OperationCount::One, _) => page.push(doc! {"$limit": 1}),
+            (OperationCount::Many, Some(limit)) => {
+                page.push(doc! {"$limit": i64::try_from(limit).map_err(OrmoxError::compaibility)?})
+            }
+            (OperationCount::Many, None) => {}
+        }
+
+        if let Some(projection) = options.projection.clone() {
+            page.push(doc! {"$project": projection});
+        }
+
+        let pipeline = vec![
+            doc! {"$match": wrap(TryInto::<bson::Document>::try_into(query))?},
+            doc! {"$facet": {"results": page, "total": [{"$count": "count"}]}},
+        ];
+        let result = self.aggregate(collection, pipeline, options).await?;
+        let Some(facets) = result.first() else {
+            return Ok((Vec::new(), 0));
+        };
+
+        let results = wrap(facets.get_array("results"))?
+            .iter()
+            .filter_map(|d| d.as_document().cloned())
+            .collect();
+        let total = facets
+            .get_array("total")
+            .ok()
+            .and_then(|t| t.first())
+            .and_then(|t| t.as_document())
+            .and_then(|t| bson_u64(t.get("count")))
+            .unwrap_or(0);
+        Ok((results, total))
+    }
+
+    async fn aggregate(
+        &self,
+        collection: String,
+        pipeline: Vec<bson::Document>,
+        options: Find,
+    ) -> OResult<Vec<bson::Document>> {
+        let cl = self.collection(collection);
+        let mut aggregate = cl.aggregate(pipeline);
+        if let Some(preference) = options.read_preference {
+            aggregate = aggregate.selection_criteria(read_preference(preference));
+        }
+
+        if let Some(batch_size) = options.batch_size {
+            aggregate = aggregate.batch_size(batch_size.try_into().unwrap_or(u32::MAX));
+        }
+
+        if let Some(options) = options.collation {
+            aggregate = aggregate.collation(collation(options));
+        }
+
+        if let Some(hint) = options.hint {
+            aggregate = aggregate.hint(Hint::Name(hint));
+        }
+
+        match self.session(options.session)? {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let mut cursor = wrap(aggregate.session(&mut *session).await)?;
+                wrap(cursor.stream(&mut session).try_collect().await)
+            }
+            None => wrap(wrap(aggregate.await)?.try_collect().await),
+        }
+    }
+
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        let result = wrap(self.0.run_command(doc! {"collStats": collection}).await)?;
+        let mut index_sizes: HashMap<String, u64> = HashMap::new();
+        if let Ok(sizes) = result.get_document("indexSizes") {
+            for (name, size) in sizes {
+                if let Some(size) = bson_u64(Some(size)) {
+                    index_sizes.insert(name.clone(), size);
+                }
+            }
+        }
+
+        Ok(CollectionStats {
+            count: bson_u64(result.get("count")).unwrap_or(0),
+            size: bson_u64(result.get("storageSize")).or(bson_u64(result.get("size"))),
+            index_sizes,
+        })
+    }
+
+    async fn explain(&self, collection: String, query: Query, options: Find) -> OResult<QueryPlan> {
+        let mut command =
+            doc! {"find": collection, "filter": wrap(TryInto::<bson::Document>::try_into(query))?};
+        if let Some(sort) = options.sort {
+            command.insert(
+                "sort",
+                match sort {
+                    Sorting::Ascending(field) => doc! {field: 1},
+                    Sorting::Descending(field) => doc! {field: -1},
+                },
+            );
+        }
+
+        if let Some(skip) = options.offset {
+            command.insert(
+                "skip",
+                i64::try_from(skip).map_err(OrmoxError::compaibility)?,
+            );
+        }
+
+        if let Some(limit) = options.limit {
+            command.insert(
+                "limit",
+                i64::try_from(limit).map_err(OrmoxError::compaibility)?,
+            );
+        }
+
+        if let Some(options) = options.collation {
+            command.insert("collation", wrap(bson::to_document(&collation(options)))?);
+        }
+
+        if let Some(hint) = options.hint {
+            command.insert("hint", hint);
+        }
+
+        let result = wrap(
+            self.0
+                .run_command(doc! {"explain": command, "verbosity": "queryPlanner"})
+                .await,
+        )?;
+        let mut plan = QueryPlan::default();
+        if let Ok(winning) = result
+            .get_document("queryPlanner")
+            .and_then(|p| p.get_document("winningPlan"))
+        {
+            plan_stages(winning, &mut plan);
+        }
+        plan.raw = Some(result);
+        Ok(plan)
+    }
+
+    async fn upsert_many(
+        &self,
+        collection: String,
+        upserts: Vec<(Query, bson::Document)>,
+        options: WriteOptions,
+    ) -> OResult<()> {
+        if upserts.is_empty() {
+            return Ok(());
+        }
+
+        let mut updates = Vec::with_capacity(upserts.len());
+        for (query, document) in upserts {
+            let query: bson::Document = wrap(query.try_into())?;
+            updates.push(doc! {"q": query, "u": {"$set": document}, "upsert": true});
+        }
+        let mut command = doc! {"update": &collection, "updates": updates, "ordered": true};
+        if let Some(concern) = write_concern(options.write_concern) {
+            command.insert("writeConcern", wrap(bson::to_bson(&concern))?);
+        }
+
+        let result = wrap_write(
+            &collection,
+            in_session!(self, options.session, self.0.run_command(command)),
+        )?;
+        // Failed statements are reported in the reply rather than as an error
+        let error = result
+            .get_array("writeErrors")
+            .ok()
+            .and_then(|errors| errors.first())
+            .and_then(|error| error.as_document());
+        match error {
+            Some(error) if error.get_i32("code") == Ok(DUPLICATE_KEY) => Err(duplicate_key(
+                &collection,
+                error.get_str("errmsg").unwrap_or_default(),
+            )),
+            Some(error) => Err(OrmoxError::Driver {
+                driver_name: "base::mongodb".to_string(),
+                error: error.get_str("errmsg").unwrap_or_default().to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     async fn upsert(
         &self,
         collection: String,
         query: Query,
         document: bson::Document,
         count: OperationCount,
+        options: WriteOptions,
     ) -> OResult<()> {
-        wrap(match count {
-            OperationCount::One => {
-                self.collection(collection)
-                    .update_one(wrap(query.try_into())?, doc! {"$set": document})
-                    .upsert(true)
-                    .await
-            }
-            OperationCount::Many => {
-                self.collection(collection)
-                    .update_many(wrap(query.try_into())?, doc! {"$set": document})
-                    .upsert(true)
-                    .await
-            }
-        })?;
+        let cl = self.collection(collection);
+        let query: bson::Document = wrap(query.try_into())?;
+        let update_options = UpdateOptions::builder()
+            .upsert(true)
+            .write_concern(write_concern(options.write_concern))
+            .build();
+        wrap_write(
+            cl.name(),
+            match count {
+                OperationCount::One => in_session!(
+                    self,
+                    options.session,
+                    cl.update_one(query, doc! {"$set": document})
+                        .with_options(update_options)
+                ),
+                OperationCount::Many => in_session!(
+                    self,
+                    options.session,
+                    cl.update_many(query, doc! {"$set": document})
+                        .with_options(update_options)
+                ),
+            },
+        )?;
         Ok(())
     }
 }
@@ -1,12 +1,15 @@
-use std::{error::Error, sync::Arc};
+use std::{any::Any, collections::HashMap, error::Error, path::PathBuf, sync::{Arc, Mutex, RwLock}};
 
 use async_trait::async_trait;
 use ormox_core::bson::doc;
-use ormox_core::core::driver::OperationCount;
-use ormox_core::{bson, Find, Sorting};
+use ormox_core::core::{driver::OperationCount, memory};
+use ormox_core::{
+    bson, CollectionStats, DriverCapabilities, ErrorPolicy, Find, IndexStats, PartialResult, Sorting, WriteOptions,
+};
 use ormox_core::{DatabaseDriver, OResult, OrmoxError, Query};
 use polodb_core::options::UpdateOptions;
 use polodb_core::{Collection, CollectionT, Database, IndexModel, IndexOptions};
+pub use polodb_core::{Config, ConfigBuilder};
 use uuid::Uuid;
 
 #[allow(dead_code)]
@@ -17,18 +20,142 @@ fn wrap<T, E: Error>(result: Result<T, E>) -> OResult<T> {
     }
 }
 
+/// Like `wrap`, but normalizes unique index violations into `OrmoxError::DuplicateKey`
+fn wrap_write<T>(result: polodb_core::Result<T>) -> OResult<T> {
+    match result {
+        Ok(r) => Ok(r),
+        Err(polodb_core::Error::DuplicateKey(e)) => Err(OrmoxError::duplicate_key(&e.ns, &e.name, &e.key)),
+        Err(e) => Err(OrmoxError::driver("base::polodb", e))
+    }
+}
+
+/// Directory removed when the owning driver is dropped
+struct TemporaryPath(PathBuf);
+
+impl Drop for TemporaryPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// An index created through the driver. PoloDB keeps no usage statistics, so the driver counts the reads PoloDB answers
+/// through the index.
+struct TrackedIndex {
+    field: String,
+    stats: IndexStats
+}
+
+// Field order matters: the database must be dropped before its temporary directory is removed. It's taken out by
+// `shutdown`, closing it once running operations let go of it.
 #[allow(dead_code)]
-pub struct PoloDriver(Arc<Database>);
+pub struct PoloDriver(RwLock<Option<Arc<Database>>>, Option<TemporaryPath>, Mutex<HashMap<String, Vec<TrackedIndex>>>);
+
+fn collection(db: &Database, name: &str) -> Collection<bson::Document> {
+    db.collection(name)
+}
+
+/// Runs a find, handling per-item errors according to the options' error policy
+fn run_find(cl: &Collection<bson::Document>, query: bson::Document, options: Find) -> OResult<PartialResult<bson::Document>> {
+    let mut result = PartialResult::from(Vec::new());
+    match options.operation {
+        OperationCount::One => match wrap(cl.find_one(query)) {
+            Ok(Some(document)) => result.items.push(document),
+            Ok(None) => {},
+            Err(e) if options.error_policy != ErrorPolicy::FailFast => result.errors.push(e),
+            Err(e) => return Err(e)
+        },
+        OperationCount::Many => {
+            // PoloDB compares strings by code point, so collated sorts (and the paging after them) happen in memory
+            let collated = options.collation.is_some() && options.sort.is_some();
+            let mut find = cl.find(query);
+            if let Some(sort) = options.sort.clone().filter(|_| !collated) {
+                find = find.sort(match sort {
+                    Sorting::Ascending(field) => doc! {field: 1},
+                    Sorting::Descending(field) => doc! {field: -1},
+                });
+            }
+
+            if let Some(skip) = options.offset.filter(|_| !collated) {
+                find = find.skip(skip.try_into().map_err(OrmoxError::compaibility)?);
+            }
+
+            if let Some(limit) = options.limit.filter(|_| !collated) {
+                find = find.limit(limit.try_into().map_err(OrmoxError::compaibility)?);
+            }
+
+            for item in wrap(find.run())? {
+                match wrap(item) {
+                    Ok(document) => result.items.push(document),
+                    Err(e) if options.error_policy != ErrorPolicy::FailFast => result.errors.push(e),
+                    Err(e) => return Err(e)
+                }
+            }
+
+            if let (true, Some(sort)) = (collated, &options.sort) {
+                memory::sort(&mut result.items, sort, options.collation.as_ref());
+                result.items = result.items.into_iter().skip(options.offset.unwrap_or(0)).take(options.limit.unwrap_or(usize::MAX)).collect();
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Logs & drops collected errors, for operations that can't return them
+fn skip_errors(result: PartialResult<bson::Document>, policy: ErrorPolicy) -> OResult<Vec<bson::Document>> {
+    for error in result.errors {
+        log::warn!("Skipping result that failed to load ({policy:?}): {error}");
+    }
+    Ok(result.items)
+}
 
 #[allow(dead_code)]
 impl PoloDriver {
-    fn collection(&self, name: String) -> Collection<bson::Document> {
-        self.0.collection(&name)
+    /// Runs a synchronous PoloDB operation on the `blocking` crate's thread pool, keeping the async executor free on any
+    /// runtime
+    async fn blocking<T, F>(&self, operation: F) -> OResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> OResult<T> + Send + 'static,
+    {
+        let db = self.database().ok_or(OrmoxError::Closed)?;
+        blocking::unblock(move || operation(&db)).await
     }
 
     pub fn new(database_path: impl AsRef<str>) -> OResult<Self> {
         let db = wrap(Database::open_path(database_path.as_ref().to_string()))?;
-        Ok(Self(Arc::new(db)))
+        Ok(Self(RwLock::new(Some(Arc::new(db))), None, Default::default()))
+    }
+
+    /// Opens a database with a custom PoloDB configuration
+    pub fn open_with_options(database_path: impl AsRef<str>, config: Config) -> OResult<Self> {
+        let db = wrap(Database::open_path_with_config(database_path.as_ref().to_string(), config))?;
+        Ok(Self(RwLock::new(Some(Arc::new(db))), None, Default::default()))
+    }
+
+    /// Opens an ephemeral database for tests and scratch tooling. PoloDB 5 has no memory backend, so this
+    /// uses a unique directory under the system temp dir that is deleted when the driver is dropped.
+    pub fn new_memory() -> OResult<Self> {
+        let path = std::env::temp_dir().join(format!("ormox-polodb-{}", Uuid::new_v4()));
+        let db = wrap(Database::open_path(&path))?;
+        Ok(Self(RwLock::new(Some(Arc::new(db))), Some(TemporaryPath(path)), Default::default()))
+    }
+
+    /// The underlying PoloDB database, for running operations ormox doesn't cover. `None` once the driver's shut down.
+    pub fn database(&self) -> Option<Arc<Database>> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Counts a read against the index PoloDB would use for it: the first one on a field the query matches by value
+    fn record_usage(&self, collection: &str, query: &bson::Document) {
+        let mut tracked = self.2.lock().unwrap();
+        let index = tracked.get_mut(collection).and_then(|indexes| {
+            indexes.iter_mut().find(|index| query.get(&index.field).is_some_and(|value| value.as_document().is_none()))
+        });
+        if let Some(index) = index {
+            index.stats.accesses += 1;
+            index.stats.last_used = Some(bson::DateTime::now().to_chrono());
+        }
     }
 }
 
@@ -38,156 +165,251 @@ impl DatabaseDriver for PoloDriver {
         String::from("base::polodb")
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities::INDEXES
+            | DriverCapabilities::UNIQUE_INDEXES
+            | DriverCapabilities::REGEX
+            | DriverCapabilities::COLLECTION_STATS
+            | DriverCapabilities::INDEX_STATS
+            | DriverCapabilities::HASHED_INDEXES
+    }
+
     async fn collections(&self) -> OResult<Vec<String>> {
-        wrap(self.0.list_collection_names())
+        self.blocking(|db| wrap(db.list_collection_names())).await
     }
 
     async fn insert(
         &self,
-        collection: String,
+        name: String,
         documents: Vec<bson::Document>,
+        _options: WriteOptions,
     ) -> OResult<Vec<Uuid>> {
-        let result = wrap(self.collection(collection).insert_many(documents))?;
-        let mut ids: Vec<Uuid> = Vec::new();
-        for id in result.inserted_ids.values() {
-            ids.push(wrap(bson::from_bson::<Uuid>(id.clone()))?);
-        }
+        self.blocking(move |db| {
+            let result = wrap_write(collection(db, &name).insert_many(documents))?;
+            let mut ids: Vec<Uuid> = Vec::new();
+            for id in result.inserted_ids.values() {
+                if let Ok(id) = bson::from_bson::<Uuid>(id.clone()) {
+                    ids.push(id);
+                }
+            }
 
-        Ok(ids)
+            Ok(ids)
+        }).await
     }
 
     async fn update(
         &self,
-        collection: String,
+        name: String,
         query: Query,
         update: bson::Document,
-        count: OperationCount
+        count: OperationCount,
+        _options: WriteOptions
     ) -> OResult<()> {
-        wrap(match count {
-            OperationCount::One => self.collection(collection).update_one(
-                wrap(query.try_into())?,
-                update
-            ),
-            OperationCount::Many => self.collection(collection).update_many(
-                wrap(query.try_into())?,
-                update
-            ),
-        })?;
-        Ok(())
+        let query: bson::Document = wrap(query.try_into())?;
+        self.blocking(move |db| {
+            wrap_write(match count {
+                OperationCount::One => collection(db, &name).update_one(query, update),
+                OperationCount::Many => collection(db, &name).update_many(query, update),
+            })?;
+            Ok(())
+        }).await
     }
 
-    async fn delete(&self, collection: String, query: Query, count: OperationCount) -> OResult<()> {
-        wrap(match count {
-            OperationCount::One => self
-                .collection(collection)
-                .delete_one(wrap(query.try_into())?),
-            OperationCount::Many => self
-                .collection(collection)
-                .delete_many(wrap(query.try_into())?),
-        })?;
-        Ok(())
+    async fn find_one_and_update(
+        &self,
+        name: String,
+        query: Query,
+        update: bson::Document,
+        upsert: bool,
+        _options: WriteOptions
+    ) -> OResult<Option<bson::Document>> {
+        let query: bson::Document = wrap(query.try_into())?;
+        self.record_usage(&name, &query);
+        self.blocking(move |db| {
+            // PoloDB has no find-and-modify, so the read, write & re-read share a transaction
+            let txn = wrap(db.start_transaction())?;
+            let cl = txn.collection::<bson::Document>(&name);
+            let result = match wrap(cl.find_one(query.clone())) {
+                Ok(Some(existing)) => {
+                    let by_id = doc! {"_id": existing.get("_id").cloned().unwrap_or(bson::Bson::Null)};
+                    wrap_write(cl.update_one(by_id.clone(), update)).and_then(|_| wrap(cl.find_one(by_id)))
+                },
+                // PoloDB only upserts with `$set`, so the inserted document is built like the in-memory drivers do
+                Ok(None) if upsert => memory::upserted(&query, &update).and_then(|document| {
+                    let inserted = wrap_write(cl.insert_one(document))?;
+                    wrap(cl.find_one(doc! {"_id": inserted.inserted_id}))
+                }),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e)
+            };
+
+            match result {
+                Ok(document) => {
+                    wrap(txn.commit())?;
+                    Ok(document)
+                },
+                Err(e) => {
+                    let _ = txn.rollback();
+                    Err(e)
+                }
+            }
+        }).await
+    }
+
+    async fn delete(&self, name: String, query: Query, count: OperationCount, _options: WriteOptions) -> OResult<()> {
+        let query: bson::Document = wrap(query.try_into())?;
+        self.blocking(move |db| {
+            wrap(match count {
+                OperationCount::One => collection(db, &name).delete_one(query),
+                OperationCount::Many => collection(db, &name).delete_many(query),
+            })?;
+            Ok(())
+        }).await
     }
 
     async fn find(
         &self,
-        collection: String,
+        name: String,
         query: Query,
         options: Find,
     ) -> OResult<Vec<bson::Document>> {
-        let cl = self.collection(collection);
-        let results = match options.operation {
-            OperationCount::One => wrap(cl.find_one(wrap(query.try_into())?))?
-                .and_then(|d| Some(vec![d]))
-                .or(Some(Vec::<bson::Document>::new()))
-                .unwrap(),
-            OperationCount::Many => {
-                let mut find = cl.find(wrap(query.try_into())?);
-                if let Some(sort) = options.sort {
-                    find = find.sort(match sort {
-                        Sorting::Ascending(field) => doc! {field: 1},
-                        Sorting::Descending(field) => doc! {field: -1},
-                    });
-                }
-
-                if let Some(skip) = options.offset {
-                    find = find.skip(skip.try_into().unwrap());
-                }
+        let query: bson::Document = wrap(query.try_into())?;
+        self.record_usage(&name, &query);
+        self.blocking(move |db| {
+            let policy = options.error_policy;
+            skip_errors(run_find(&collection(db, &name), query, options)?, policy)
+        }).await
+    }
 
-                if let Some(limit) = options.limit {
-                    find = find.limit(limit.try_into().unwrap());
-                }
+    async fn find_partial(
+        &self,
+        name: String,
+        query: Query,
+        options: Find,
+    ) -> OResult<PartialResult<bson::Document>> {
+        let query: bson::Document = wrap(query.try_into())?;
+        self.record_usage(&name, &query);
+        self.blocking(move |db| run_find(&collection(db, &name), query, options)).await
+    }
 
-                wrap(find.run())?
-                    .filter(|r| r.is_ok())
-                    .map(|r| r.unwrap())
-                    .collect()
-            }
-        };
+    async fn all(&self, name: String, options: Find) -> OResult<Vec<bson::Document>> {
+        self.blocking(move |db| {
+            let policy = options.error_policy;
+            let options = Find { operation: OperationCount::Many, ..options };
+            skip_errors(run_find(&collection(db, &name), doc! {}, options)?, policy)
+        }).await
+    }
 
-        Ok(results)
+    async fn count(&self, name: String, query: Query, _options: Find) -> OResult<u64> {
+        let query: bson::Document = wrap(query.try_into())?;
+        self.record_usage(&name, &query);
+        self.blocking(move |db| Ok(wrap(collection(db, &name).find(query).run())?.count() as u64)).await
     }
 
-    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
-        let cl = self.collection(collection);
-        let mut find = cl.find(doc! {});
-        if let Some(sort) = options.sort {
-            find = find.sort(match sort {
-                Sorting::Ascending(field) => doc! {field: 1},
-                Sorting::Descending(field) => doc! {field: -1},
-            });
+    /// Hashed indexes are emulated with regular ones, which answer the same equality queries
+    async fn create_index(&self, name: String, index: ormox_core::Index) -> OResult<()> {
+        self.capabilities().require(index.required_capabilities())?;
+        let (index_name, field) = (index.resolved_name(), index.fields.first().cloned().unwrap_or_default());
+        let mut keys: bson::Document = bson::Document::new();
+        for key in index.fields {
+            keys.insert(key, 1);
         }
+        let created = name.clone();
+        self.blocking(move |db| {
+            wrap(collection(db, &created).create_index(IndexModel {
+                keys,
+                options: Some(IndexOptions {
+                    name: index.name,
+                    unique: if index.unique { Some(true) } else { None },
+                }),
+            }))
+        }).await?;
 
-        if let Some(skip) = options.offset {
-            find = find.skip(skip.try_into().unwrap());
+        let mut tracked = self.2.lock().unwrap();
+        let indexes = tracked.entry(name).or_default();
+        if !indexes.iter().any(|index| index.stats.name == index_name) {
+            indexes.push(TrackedIndex {
+                field,
+                stats: IndexStats { name: index_name, accesses: 0, since: Some(bson::DateTime::now().to_chrono()), last_used: None }
+            });
         }
+        Ok(())
+    }
 
-        if let Some(limit) = options.limit {
-            find = find.limit(limit.try_into().unwrap());
+    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
+        let (dropped, index_name) = (collection.clone(), name.clone());
+        self.blocking(move |db| wrap(db.collection::<bson::Document>(&dropped).drop_index(index_name))).await?;
+        if let Some(indexes) = self.2.lock().unwrap().get_mut(&collection) {
+            indexes.retain(|index| index.stats.name != name);
         }
+        Ok(())
+    }
 
-        Ok(wrap(find.run())?
-            .filter(|r| r.is_ok())
-            .map(|r| r.unwrap())
-            .collect())
+    /// Only covers indexes created since the driver was opened (ie by `register_indices` at startup), counting from then
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        let tracked = self.2.lock().unwrap();
+        Ok(tracked.get(&collection).map(|indexes| indexes.iter().map(|index| index.stats.clone()).collect()).unwrap_or_default())
     }
 
-    async fn create_index(&self, collection: String, index: ormox_core::Index) -> OResult<()> {
-        let mut keys: bson::Document = bson::Document::new();
-        for key in index.fields {
-            keys.insert(key, 1);
-        }
-        wrap(self.collection(collection).create_index(IndexModel {
-            keys,
-            options: Some(IndexOptions {
-                name: index.name,
-                unique: if index.unique { Some(true) } else { None },
-            }),
-        }))
+    async fn collection_stats(&self, name: String) -> OResult<CollectionStats> {
+        self.blocking(move |db| {
+            let cl = collection(db, &name);
+            let mut size: u64 = 0;
+            for document in wrap(cl.find(doc! {}).run())? {
+                size += wrap(bson::to_vec(&wrap(document)?))?.len() as u64;
+            }
+
+            Ok(CollectionStats {
+                count: wrap(cl.count_documents())?,
+                size: Some(size),
+                index_sizes: Default::default(),
+            })
+        }).await
     }
 
-    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
-        wrap(self.collection(collection).drop_index(name))
+    async fn maintain(&self) -> OResult<()> {
+        // RocksDB compacts in the background and polodb_core does not expose a manual trigger,
+        // so the best we can do is check that the database is still readable.
+        self.blocking(|db| wrap(db.list_collection_names()).and(Ok(()))).await
+    }
+
+    /// Closes the database once operations still running on it finish. The temporary directory of a `new_memory`
+    /// database is removed when the driver is dropped.
+    async fn shutdown(&self) -> OResult<()> {
+        let db = self.0.write().unwrap().take();
+        if let Some(db) = db {
+            blocking::unblock(move || drop(db)).await;
+        }
+        Ok(())
     }
 
     async fn upsert(
         &self,
-        collection: String,
+        name: String,
         query: Query,
         document: bson::Document,
-        count: OperationCount
+        count: OperationCount,
+        _options: WriteOptions
     ) -> OResult<()> {
-        wrap(match count {
-            OperationCount::One => self.collection(collection).update_one_with_options(
-                wrap(query.try_into())?,
-                doc! {"$set": document},
-                UpdateOptions::builder().upsert(true).build()
-            ),
-            OperationCount::Many => self.collection(collection).update_many_with_options(
-                wrap(query.try_into())?,
-                doc! {"$set": document},
-                UpdateOptions::builder().upsert(true).build()
-            ),
-        })?;
-        Ok(())
+        let query: bson::Document = wrap(query.try_into())?;
+        self.blocking(move |db| {
+            wrap_write(match count {
+                OperationCount::One => collection(db, &name).update_one_with_options(
+                    query,
+                    doc! {"$set": document},
+                    UpdateOptions::builder().upsert(true).build()
+                ),
+                OperationCount::Many => collection(db, &name).update_many_with_options(
+                    query,
+                    doc! {"$set": document},
+                    UpdateOptions::builder().upsert(true).build()
+                ),
+            })?;
+            Ok(())
+        }).await
     }
 }
@@ -1,23 +1,107 @@
 pub use ormox_core::{
-    client::{Client, Collection, self},
+    client::{Client, ClientOptions, ClientOptionsBuilder, Collection, DedupeReport, JobStatus, KeepStrategy, ScheduledJob, Session, self},
     core::{
-        document::{Document, Index},
-        driver::{DatabaseDriver, Find, Sorting},
-        error::OrmoxError as Error,
-        query::{Query, QueryKey, QueryValue, SimpleQuery},
+        change::{ChangeEvent, ChangeKind, ResumeToken},
+        codec::{BsonCodec, Codec},
+        context::{self, OperationContext},
+        diff::{diff, DocumentDiff, FieldChange},
+        document::{DecodeMode, Document, Index, IndexKind, Projection, Variant},
+        driver::{
+            Acknowledgment, Collation, CollationStrength, CollectionStats, DatabaseDriver, DriverCapabilities, ErrorPolicy, Find,
+            HealthReport, IndexProgress, IndexStats, PartialResult, QueryPlan,
+            ReadPreference, Sorting, WriteConcern, WriteOptions,
+        },
+        error::{ErrorKind, OrmoxError as Error},
+        dry_run::{DryRunDriver, PlannedWrite, WritePlan},
+        field::{Field, FieldPaths},
+        limit::LimitedDriver,
+        merge::{merge, MergePolicy},
+        numeric::NumericPolicy,
+        observer::Observer,
+        outbox::OutboxEvent,
+        query::{Query, QueryKey, QueryMut, QueryValue, SimpleQuery},
+        relation::{CounterCache, ManyToMany, Ref},
+        runtime::{Runtime, Task},
+        sanitization::SanitizationPolicy,
+        update::Update,
+        validation::{FieldError, ValidateFields},
         self
     },
+    lock::LockGuard,
+    partition::{Partition, PartitionPeriod, Partitioned},
+    queue::{self, Queue, QueueItem},
+    rate_limit::{RateLimit, RateLimitStrategy, RateLimiter},
+    unit_of_work::UnitOfWork,
+    view::{View, ViewDefinition, ViewHandle, ViewRefresh},
 };
 
 pub use ormox_core;
 
+#[cfg(feature = "encryption")]
+pub use ormox_core::core::encryption::{EncryptionMode, KeyProvider, StaticKeyProvider};
+
+#[cfg(feature = "cbor")]
+pub use ormox_core::core::codec::CborCodec;
+
+#[cfg(feature = "csv")]
+pub use ormox_core::core::import::{ColumnMapping, ColumnType, CsvMapping, ImportFailure, ImportReport};
+
+#[cfg(feature = "msgpack")]
+pub use ormox_core::core::codec::MessagePackCodec;
+
+#[cfg(feature = "tokio")]
+pub use ormox_core::core::runtime::TokioRuntime;
+
+#[cfg(feature = "async-std")]
+pub use ormox_core::core::runtime::AsyncStdRuntime;
+
+#[cfg(feature = "smol")]
+pub use ormox_core::core::runtime::SmolRuntime;
+
+#[cfg(feature = "ormox_axum")]
+pub mod axum {
+    pub use ormox_core::axum::{status_code, DocId, Ormox, RouterExt};
+}
+
+#[cfg(feature = "graphql")]
+pub mod graphql {
+    pub use ormox_core::graphql::{Filter, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+}
+
+#[cfg(feature = "sessions")]
+pub mod sessions {
+    pub use ormox_core::sessions::{OrmoxStore, SESSIONS_COLLECTION};
+}
+
+#[cfg(feature = "proptest")]
+pub mod testing {
+    pub use ormox_core::testing::{document_round_trip, equivalent, field, query, round_trip, value, wire_round_trip};
+}
+
+#[cfg(feature = "blocking")]
+pub mod blocking {
+    pub use ormox_core::blocking::{BlockingClient, BlockingCollection, BlockingSession};
+}
+
 #[cfg(feature = "derive")]
-pub use ormox_derive::{ormox_document, Document};
+pub use ormox_derive::{ormox_document, Document, EmbeddedDocument, Projection};
 
 pub mod drivers {
     #[cfg(feature = "polodb")]
     pub use ormox_driver_polodb::PoloDriver;
 
     #[cfg(feature = "mongodb")]
-    pub use ormox_driver_mongodb::MongoDriver;
-}
\ No newline at end of file
+    pub use ormox_driver_mongodb::{MongoDriver, MongoOptions};
+
+    #[cfg(feature = "http")]
+    pub use ormox_driver_http::HttpDriver;
+
+    #[cfg(feature = "grpc")]
+    pub use ormox_driver_grpc::{GrpcDriver, GrpcServer};
+
+    #[cfg(feature = "indexeddb")]
+    pub use ormox_driver_indexeddb::IndexedDbDriver;
+}
+
+#[cfg(feature = "server")]
+pub use ormox_server::Server;
\ No newline at end of file
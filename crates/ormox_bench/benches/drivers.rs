@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use ormox_bench::{bench_driver, Workload};
+use tokio::runtime::Runtime;
+
+#[cfg(feature = "polodb")]
+fn polodb(c: &mut Criterion, runtime: &Runtime, workload: &Workload) {
+    use ormox_driver_polodb::PoloDriver;
+    use std::sync::Arc;
+
+    let memory = PoloDriver::new_memory().expect("Failed to open an in-memory PoloDB database");
+    bench_driver(c, "polodb-memory", runtime, Arc::new(memory), workload);
+
+    let path = std::env::temp_dir().join(format!("ormox-bench-{}.db", std::process::id()));
+    let file = PoloDriver::new(path.to_string_lossy()).expect("Failed to open a PoloDB database");
+    bench_driver(c, "polodb-file", runtime, Arc::new(file), workload);
+    let _ = std::fs::remove_dir_all(&path).or_else(|_| std::fs::remove_file(&path));
+}
+
+/// Runs against the deployment at `ORMOX_BENCH_MONGODB_URI`, in the `ormox_bench` database, if it's set
+#[cfg(feature = "mongodb")]
+fn mongodb(c: &mut Criterion, runtime: &Runtime, workload: &Workload) {
+    use ormox_driver_mongodb::{MongoDriver, MongoOptions};
+    use std::sync::Arc;
+
+    let Ok(uri) = std::env::var("ORMOX_BENCH_MONGODB_URI") else {
+        eprintln!("Skipping MongoDB: ORMOX_BENCH_MONGODB_URI isn't set");
+        return;
+    };
+    let driver = runtime
+        .block_on(MongoDriver::connect(
+            uri,
+            "ormox_bench",
+            MongoOptions::default(),
+        ))
+        .expect("Failed to connect to MongoDB");
+    bench_driver(c, "mongodb", runtime, Arc::new(driver), workload);
+}
+
+#[allow(unused_variables)]
+fn drivers(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("Failed to start the tokio runtime");
+    let workload = Workload::default();
+
+    #[cfg(feature = "polodb")]
+    polodb(c, &runtime, &workload);
+
+    #[cfg(feature = "mongodb")]
+    mongodb(c, &runtime, &workload);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = drivers
+}
+criterion_main!(benches);
@@ -0,0 +1,228 @@
+//! Driver benchmarks: workloads exercising `DatabaseDriver` end-to-end, shared so driver authors can run them against
+//! their own drivers. `benches/drivers.rs` runs them against PoloDB (in memory & on disk) and MongoDB:
+//!
+//! ```text
+//! ORMOX_BENCH_MONGODB_URI=mongodb://localhost:27017 cargo bench -p ormox_bench
+//! ```
+//!
+//! From another crate's criterion bench:
+//!
+//! ```ignore
+//! fn drivers(c: &mut Criterion) {
+//!     let runtime = tokio::runtime::Runtime::new().unwrap();
+//!     let driver = Arc::new(runtime.block_on(MyDriver::connect("...")).unwrap());
+//!     ormox_bench::bench_driver(c, "my-driver", &runtime, driver, &Workload::default());
+//! }
+//! ```
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use criterion::{black_box, Criterion};
+use ormox_core::{
+    bson::{doc, Document},
+    core::driver::OperationCount,
+    DatabaseDriver, DriverCapabilities, Find, Index, OResult, Query, WriteOptions,
+};
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+/// Sizes of the data the scenarios work on
+#[derive(Clone, Debug)]
+pub struct Workload {
+    /// Documents loaded before the read & update scenarios
+    pub documents: usize,
+
+    /// Documents per insert in `Scenario::BulkInsert`
+    pub batch_size: usize,
+
+    /// Documents matched by each query of `Scenario::RangeQuery`
+    pub range: usize,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self {
+            documents: 10_000,
+            batch_size: 1_000,
+            range: 100,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scenario {
+    /// Inserts `batch_size` documents into an empty collection
+    BulkInsert,
+
+    /// Finds one document by an indexed field
+    PointRead,
+
+    /// Finds `range` documents with a range query on an indexed field
+    RangeQuery,
+
+    /// Increments a field of one document, found by an indexed field
+    Update,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 4] = [
+        Self::BulkInsert,
+        Self::PointRead,
+        Self::RangeQuery,
+        Self::Update,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::BulkInsert => "bulk_insert",
+            Self::PointRead => "point_read",
+            Self::RangeQuery => "range_query",
+            Self::Update => "update",
+        }
+    }
+
+    /// Collection the scenario works in
+    pub fn collection(&self) -> String {
+        format!("bench_{}", self.name())
+    }
+
+    /// Empties the scenario's collection, then loads & indexes the documents it reads
+    pub async fn setup(
+        &self,
+        driver: &(dyn DatabaseDriver + Send + Sync),
+        workload: &Workload,
+    ) -> OResult<()> {
+        clear(driver, self.collection()).await?;
+        if *self == Self::BulkInsert {
+            return Ok(());
+        }
+        if driver.capabilities().contains(DriverCapabilities::INDEXES) {
+            driver
+                .create_index(self.collection(), Index::new("key"))
+                .await?;
+        }
+        for start in (0..workload.documents).step_by(workload.batch_size.max(1)) {
+            let end = (start + workload.batch_size).min(workload.documents);
+            driver
+                .insert(
+                    self.collection(),
+                    (start..end).map(document).collect(),
+                    WriteOptions::default(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Runs the scenario once. `iteration` picks the documents read or updated, spreading them over the collection.
+    pub async fn run(
+        &self,
+        driver: &(dyn DatabaseDriver + Send + Sync),
+        workload: &Workload,
+        iteration: usize,
+    ) -> OResult<()> {
+        // Stepping by a prime visits keys out of order, so consecutive reads don't hit the same pages
+        let key = (iteration * 7919) % workload.documents.max(1);
+        match self {
+            Self::BulkInsert => {
+                let documents = (0..workload.batch_size).map(document).collect();
+                driver
+                    .insert(self.collection(), documents, WriteOptions::default())
+                    .await?;
+            }
+            Self::PointRead => {
+                let query = Query::try_from(doc! {"key": key as i64})?;
+                black_box(driver.find(self.collection(), query, Find::one()).await?);
+            }
+            Self::RangeQuery => {
+                let start = key.min(workload.documents.saturating_sub(workload.range));
+                let end = start + workload.range;
+                let query =
+                    Query::try_from(doc! {"key": {"$gte": start as i64, "$lt": end as i64}})?;
+                black_box(driver.find(self.collection(), query, Find::many()).await?);
+            }
+            Self::Update => {
+                let query = Query::try_from(doc! {"key": key as i64})?;
+                driver
+                    .update(
+                        self.collection(),
+                        query,
+                        doc! {"$inc": {"score": 1}},
+                        OperationCount::One,
+                        WriteOptions::default(),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `key`th benchmark document
+pub fn document(key: usize) -> Document {
+    doc! {
+        "_docid": Uuid::new_v4().to_string(),
+        "key": key as i64,
+        "name": format!("user-{key}"),
+        "email": format!("user-{key}@example.com"),
+        "age": (key % 90) as i32,
+        "tags": ["alpha", "beta", "gamma"],
+        "score": 0
+    }
+}
+
+async fn clear(driver: &(dyn DatabaseDriver + Send + Sync), collection: String) -> OResult<()> {
+    driver
+        .delete(
+            collection,
+            Query::new(),
+            OperationCount::Many,
+            WriteOptions::default(),
+        )
+        .await
+}
+
+/// Runs every scenario against `driver` as a criterion group named `name`. The driver's scenario collections are
+/// emptied first, and left holding the benchmark data afterwards.
+pub fn bench_driver(
+    c: &mut Criterion,
+    name: &str,
+    runtime: &Runtime,
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+    workload: &Workload,
+) {
+    let mut group = c.benchmark_group(name);
+    for scenario in Scenario::ALL {
+        runtime
+            .block_on(scenario.setup(driver.as_ref(), workload))
+            .unwrap_or_else(|e| panic!("Setting up {} failed: {e}", scenario.name()));
+
+        group.bench_function(scenario.name(), |b| {
+            b.to_async(runtime).iter_custom(|iterations| {
+                let driver = driver.clone();
+                async move {
+                    let mut elapsed = Duration::ZERO;
+                    for iteration in 0..iterations as usize {
+                        // Inserts start from an empty collection each time, so their cost doesn't grow over the run
+                        if scenario == Scenario::BulkInsert {
+                            clear(driver.as_ref(), scenario.collection())
+                                .await
+                                .expect("Clearing the collection failed");
+                        }
+                        let start = Instant::now();
+                        scenario
+                            .run(driver.as_ref(), workload, iteration)
+                            .await
+                            .unwrap_or_else(|e| panic!("{} failed: {e}", scenario.name()));
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                }
+            })
+        });
+    }
+    group.finish();
+}
@@ -0,0 +1,144 @@
+//! `ormox.toml` loading. Relative paths in the file (the PoloDB database & the migrations directory) are resolved from
+//! the directory holding it, so the CLI behaves the same wherever it's run from.
+//!
+//! ```toml
+//! migrations = "migrations"
+//!
+//! [driver]
+//! kind = "mongodb"
+//! uri = "mongodb://localhost:27017"
+//! database = "app"
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use ormox_core::DatabaseDriver;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub driver: DriverConfig,
+
+    /// Directory of migration files, see `migrations`
+    #[serde(default = "default_migrations")]
+    pub migrations: PathBuf,
+}
+
+fn default_migrations() -> PathBuf {
+    PathBuf::from("migrations")
+}
+
+/// Driver to connect to, chosen by `kind`
+#[derive(Deserialize, Clone, Debug)]
+#[cfg_attr(
+    not(all(
+        feature = "polodb",
+        feature = "mongodb",
+        feature = "http",
+        feature = "grpc"
+    )),
+    allow(dead_code)
+)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DriverConfig {
+    /// Embedded PoloDB database at `path`, or in memory if there's no path
+    Polodb { path: Option<PathBuf> },
+
+    Mongodb {
+        uri: String,
+        database: String,
+        #[serde(default)]
+        app_name: Option<String>,
+    },
+
+    /// `ormox_server` instance
+    Http {
+        url: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+
+    /// `GrpcServer` instance
+    Grpc {
+        url: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        let mut config: Self =
+            toml::from_str(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        config.migrations = base.join(&config.migrations);
+        if let DriverConfig::Polodb {
+            path: Some(ref mut database),
+        } = config.driver
+        {
+            *database = base.join(&*database);
+        }
+        Ok(config)
+    }
+
+    /// Connects to the configured driver
+    pub async fn connect(&self) -> anyhow::Result<Arc<dyn DatabaseDriver + Send + Sync>> {
+        match &self.driver {
+            #[cfg(feature = "polodb")]
+            DriverConfig::Polodb { path } => {
+                let driver = match path {
+                    Some(path) => ormox_driver_polodb::PoloDriver::new(path.to_string_lossy())?,
+                    None => ormox_driver_polodb::PoloDriver::new_memory()?,
+                };
+                Ok(Arc::new(driver))
+            }
+            #[cfg(feature = "mongodb")]
+            DriverConfig::Mongodb {
+                uri,
+                database,
+                app_name,
+            } => {
+                let options = ormox_driver_mongodb::MongoOptions {
+                    app_name: app_name.clone(),
+                    ..Default::default()
+                };
+                let driver =
+                    ormox_driver_mongodb::MongoDriver::connect(uri, database, options).await?;
+                Ok(Arc::new(driver))
+            }
+            #[cfg(feature = "http")]
+            DriverConfig::Http { url, token } => Ok(Arc::new(
+                ormox_driver_http::HttpDriver::connect(url.as_str(), token.clone()).await?,
+            )),
+            #[cfg(feature = "grpc")]
+            DriverConfig::Grpc { url, token } => Ok(Arc::new(
+                ormox_driver_grpc::GrpcDriver::connect(url, token.clone()).await?,
+            )),
+            #[allow(unreachable_patterns)]
+            other => bail!(
+                "ormox-cli was built without the {} driver feature",
+                other.kind()
+            ),
+        }
+    }
+}
+
+impl DriverConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Polodb { .. } => "polodb",
+            Self::Mongodb { .. } => "mongodb",
+            Self::Http { .. } => "http",
+            Self::Grpc { .. } => "grpc",
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! `ormox-cli`: inspects & edits the data of any database ormox has a driver for, as configured in `ormox.toml` (see
+//! `config`). Documents, queries & updates are read in the relaxed JSON of `syntax` and printed as Extended JSON, one
+//! document per line; `repl` starts an interactive shell.
+
+mod config;
+mod migrations;
+mod repl;
+mod syntax;
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::Context;
+use bson::Bson;
+use clap::{Parser, Subcommand};
+use ormox_core::{
+    core::{driver::OperationCount, remote::WireDocument},
+    DatabaseDriver, DriverCapabilities, Find, Index, IndexKind, Query, Sorting, WriteOptions,
+};
+
+use crate::config::Config;
+
+/// Number of documents per insert when importing
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "ormox-cli",
+    version,
+    about = "Inspect and manipulate data in ormox databases"
+)]
+struct Cli {
+    /// Config file
+    #[arg(
+        short,
+        long,
+        env = "ORMOX_CONFIG",
+        default_value = "ormox.toml",
+        global = true
+    )]
+    config: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List collections
+    Collections,
+
+    /// Start an interactive shell
+    Repl {
+        /// Collection to start in
+        collection: Option<String>,
+    },
+
+    /// Count the documents matching a query
+    Count {
+        collection: String,
+
+        /// Query, in relaxed JSON
+        #[arg(default_value = "{}")]
+        query: String,
+    },
+
+    /// Print the documents matching a query
+    Find {
+        collection: String,
+
+        /// Query, in relaxed JSON
+        #[arg(default_value = "{}")]
+        query: String,
+
+        #[arg(long)]
+        limit: Option<usize>,
+
+        #[arg(long)]
+        skip: Option<usize>,
+
+        /// Field to sort by, prefixed with `-` for descending order
+        #[arg(long, allow_hyphen_values = true)]
+        sort: Option<String>,
+
+        /// Print documents as canonical rather than relaxed Extended JSON
+        #[arg(long)]
+        canonical: bool,
+    },
+
+    /// Insert a document, or an array of documents
+    Insert {
+        collection: String,
+
+        /// Document(s), in relaxed JSON
+        documents: String,
+    },
+
+    /// Delete the documents matching a query
+    Delete {
+        collection: String,
+
+        /// Query, in relaxed JSON; use `{}` to delete every document
+        query: String,
+
+        /// Only delete the first matching document
+        #[arg(long)]
+        one: bool,
+    },
+
+    /// Write a collection's documents as JSON Lines
+    Export {
+        collection: String,
+
+        /// File to write to, instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Insert the documents of a JSON Lines file
+    Import {
+        collection: String,
+
+        /// File to read from, instead of stdin
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+    },
+
+    /// Manage indexes
+    #[command(subcommand)]
+    Index(IndexCommand),
+
+    /// Apply pending migrations
+    Migrate {
+        /// Only list migrations and whether they've been applied
+        #[arg(long)]
+        status: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// List a collection's indexes, with their sizes
+    List { collection: String },
+
+    /// Show how often each of a collection's indexes is used, and since when
+    Usage { collection: String },
+
+    /// Create an index on one or more fields
+    Create {
+        collection: String,
+
+        #[arg(required = true)]
+        fields: Vec<String>,
+
+        #[arg(long)]
+        name: Option<String>,
+
+        #[arg(long)]
+        unique: bool,
+
+        /// Build the index without blocking writes, where the driver supports it
+        #[arg(long)]
+        background: bool,
+
+        /// "btree", "hashed", "text" or "geo"
+        #[arg(long, value_parser = parse_kind, default_value = "btree")]
+        kind: IndexKind,
+
+        /// Only index documents matching this query, ie '{"deleted": false}'
+        #[arg(long)]
+        partial: Option<String>,
+    },
+
+    /// Drop an index by name
+    Drop { collection: String, name: String },
+}
+
+fn parse_query(text: &str) -> anyhow::Result<Query> {
+    Ok(Query::try_from(syntax::parse_document(text)?)?)
+}
+
+fn parse_kind(text: &str) -> Result<IndexKind, String> {
+    match text {
+        "btree" => Ok(IndexKind::BTree),
+        "hashed" => Ok(IndexKind::Hashed),
+        "text" => Ok(IndexKind::Text),
+        "geo" => Ok(IndexKind::Geo),
+        _ => Err(format!("unknown index kind {text:?}")),
+    }
+}
+
+/// Sorting on `field`, descending if it starts with `-`
+fn sorting(field: &str) -> Sorting {
+    match field.strip_prefix('-') {
+        Some(field) => Sorting::desc(field),
+        None => Sorting::asc(field),
+    }
+}
+
+/// Names & sizes of a collection's indexes, as reported by `collection_stats`
+async fn list_indexes(
+    driver: &(dyn DatabaseDriver + Send + Sync),
+    collection: String,
+) -> anyhow::Result<Vec<(String, u64)>> {
+    driver
+        .capabilities()
+        .require(DriverCapabilities::COLLECTION_STATS)?;
+    let mut indexes: Vec<_> = driver
+        .collection_stats(collection)
+        .await?
+        .index_sizes
+        .into_iter()
+        .collect();
+    indexes.sort();
+    Ok(indexes)
+}
+
+fn print_document(
+    out: &mut impl Write,
+    document: bson::Document,
+    canonical: bool,
+) -> io::Result<()> {
+    let json = if canonical {
+        Bson::Document(document).into_canonical_extjson()
+    } else {
+        Bson::Document(document).into_relaxed_extjson()
+    };
+    writeln!(out, "{json}")
+}
+
+async fn run(
+    command: Command,
+    config: &Config,
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    match command {
+        Command::Repl { .. } => unreachable!("The shell is started by main"),
+        Command::Collections => {
+            let mut collections = driver.collections().await?;
+            collections.sort();
+            for collection in collections {
+                writeln!(out, "{collection}")?;
+            }
+        }
+        Command::Count { collection, query } => {
+            let count = driver
+                .count(collection, parse_query(&query)?, Find::many())
+                .await?;
+            writeln!(out, "{count}")?;
+        }
+        Command::Find {
+            collection,
+            query,
+            limit,
+            skip,
+            sort,
+            canonical,
+        } => {
+            let options = Find {
+                limit,
+                offset: skip,
+                sort: sort.as_deref().map(sorting),
+                ..Find::many()
+            };
+            for document in driver
+                .find(collection, parse_query(&query)?, options)
+                .await?
+            {
+                print_document(&mut out, document, canonical)?;
+            }
+        }
+        Command::Insert {
+            collection,
+            documents,
+        } => {
+            let documents = match syntax::parse_value(&documents)? {
+                serde_json::Value::Array(documents) => documents
+                    .into_iter()
+                    .map(syntax::to_document)
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+                document => vec![syntax::to_document(document)?],
+            };
+            let count = documents.len();
+            driver
+                .insert(collection, documents, WriteOptions::default())
+                .await?;
+            writeln!(out, "Inserted {count} document(s)")?;
+        }
+        Command::Delete {
+            collection,
+            query,
+            one,
+        } => {
+            let count = if one {
+                OperationCount::One
+            } else {
+                OperationCount::Many
+            };
+            driver
+                .delete(
+                    collection,
+                    parse_query(&query)?,
+                    count,
+                    WriteOptions::default(),
+                )
+                .await?;
+        }
+        Command::Export { collection, output } => {
+            let documents = driver.all(collection, Find::many()).await?;
+            let count = documents.len();
+            match output {
+                Some(path) => {
+                    let mut file = BufWriter::new(
+                        File::create(&path)
+                            .with_context(|| format!("Failed to create {}", path.display()))?,
+                    );
+                    for document in documents {
+                        print_document(&mut file, document, true)?;
+                    }
+                    file.flush()?;
+                    writeln!(out, "Exported {count} document(s) to {}", path.display())?;
+                }
+                None => {
+                    for document in documents {
+                        print_document(&mut out, document, true)?;
+                    }
+                }
+            }
+        }
+        Command::Import { collection, input } => {
+            let reader: Box<dyn BufRead> = match input {
+                Some(path) => Box::new(BufReader::new(
+                    File::open(&path)
+                        .with_context(|| format!("Failed to open {}", path.display()))?,
+                )),
+                None => Box::new(io::stdin().lock()),
+            };
+            let mut batch = Vec::new();
+            let mut count = 0;
+            for (number, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let WireDocument(document) = serde_json::from_str(&line)
+                    .with_context(|| format!("Invalid document on line {}", number + 1))?;
+                batch.push(document);
+                if batch.len() == IMPORT_BATCH_SIZE {
+                    count += batch.len();
+                    driver
+                        .insert(
+                            collection.clone(),
+                            std::mem::take(&mut batch),
+                            WriteOptions::default(),
+                        )
+                        .await?;
+                }
+            }
+            if !batch.is_empty() {
+                count += batch.len();
+                driver
+                    .insert(collection, batch, WriteOptions::default())
+                    .await?;
+            }
+            writeln!(out, "Imported {count} document(s)")?;
+        }
+        Command::Index(IndexCommand::List { collection }) => {
+            for (name, size) in list_indexes(driver.as_ref(), collection).await? {
+                writeln!(out, "{name}\t{size}")?;
+            }
+        }
+        Command::Index(IndexCommand::Usage { collection }) => {
+            driver
+                .capabilities()
+                .require(DriverCapabilities::INDEX_STATS)?;
+            for stats in driver.index_stats(collection).await? {
+                let [since, last_used] = [stats.since, stats.last_used]
+                    .map(|time| time.map_or_else(|| String::from("-"), |time| time.to_rfc3339()));
+                writeln!(
+                    out,
+                    "{}\t{}\t{since}\t{last_used}",
+                    stats.name, stats.accesses
+                )?;
+            }
+        }
+        Command::Index(IndexCommand::Create {
+            collection,
+            fields,
+            name,
+            unique,
+            background,
+            kind,
+            partial,
+        }) => {
+            let index = Index {
+                fields,
+                name,
+                unique,
+                collation: None,
+                background,
+                kind,
+                partial_filter: partial.as_deref().map(parse_query).transpose()?,
+            };
+            driver.create_index(collection, index).await?;
+        }
+        Command::Index(IndexCommand::Drop { collection, name }) => {
+            driver.drop_index(collection, name).await?;
+        }
+        Command::Migrate { status: true } => {
+            let applied = migrations::applied(driver.as_ref()).await?;
+            for (name, _) in migrations::files(&config.migrations)? {
+                let state = if applied.contains(&name) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                writeln!(out, "{name}\t{state}")?;
+            }
+        }
+        Command::Migrate { status: false } => {
+            let migrated = migrations::migrate(driver.as_ref(), &config.migrations).await?;
+            for name in &migrated {
+                writeln!(out, "Applied {name}")?;
+            }
+            if migrated.is_empty() {
+                writeln!(out, "No pending migrations")?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let config = Config::load(&cli.config)?;
+    let driver = config.connect().await?;
+    match cli.command {
+        Command::Repl { collection } => repl::repl(config, driver, collection).await,
+        command => run(command, &config, driver).await,
+    }
+}
@@ -0,0 +1,169 @@
+//! Migrations are JSON files in the configured directory, applied in file name order (ie `001_user_roles.json`) and
+//! recorded in the `_migrations` collection so each runs once. A file holds a list of steps, with documents, queries &
+//! updates as Extended JSON:
+//!
+//! ```json
+//! [
+//!     {"op": "create_index", "collection": "users", "index": {"fields": ["email"], "unique": true}},
+//!     {"op": "update", "collection": "users", "query": {"role": {"$exists": false}}, "update": {"$set": {"role": "member"}}}
+//! ]
+//! ```
+//!
+//! Steps aren't transactional: a migration that fails isn't recorded, but the steps before the failure stay applied.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use bson::doc;
+use ormox_core::{
+    core::{driver::OperationCount, remote::WireDocument},
+    DatabaseDriver, Find, Index, Query, Sorting, WriteOptions,
+};
+use serde::Deserialize;
+
+/// Collection recording applied migrations
+pub const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    Insert {
+        collection: String,
+        documents: Vec<WireDocument>,
+    },
+    Update {
+        collection: String,
+        query: WireDocument,
+        update: WireDocument,
+    },
+    Delete {
+        collection: String,
+        query: WireDocument,
+    },
+    CreateIndex {
+        collection: String,
+        index: Index,
+    },
+    DropIndex {
+        collection: String,
+        name: String,
+    },
+}
+
+impl Step {
+    async fn apply(self, driver: &(dyn DatabaseDriver + Send + Sync)) -> anyhow::Result<()> {
+        match self {
+            Self::Insert {
+                collection,
+                documents,
+            } => {
+                let documents = documents.into_iter().map(|WireDocument(d)| d).collect();
+                driver
+                    .insert(collection, documents, WriteOptions::default())
+                    .await?;
+            }
+            Self::Update {
+                collection,
+                query,
+                update,
+            } => {
+                driver
+                    .update(
+                        collection,
+                        Query::try_from(query)?,
+                        update.0,
+                        OperationCount::Many,
+                        WriteOptions::default(),
+                    )
+                    .await?
+            }
+            Self::Delete { collection, query } => {
+                driver
+                    .delete(
+                        collection,
+                        Query::try_from(query)?,
+                        OperationCount::Many,
+                        WriteOptions::default(),
+                    )
+                    .await?
+            }
+            Self::CreateIndex { collection, index } => {
+                driver.create_index(collection, index).await?
+            }
+            Self::DropIndex { collection, name } => driver.drop_index(collection, name).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Migration files in `directory`, in the order they're applied
+pub fn files(directory: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let entries = std::fs::read_dir(directory)
+        .with_context(|| format!("Failed to read migrations from {}", directory.display()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            if let Some(name) = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+            {
+                files.push((name, path));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Names of the applied migrations, in the order they were applied
+pub async fn applied(driver: &(dyn DatabaseDriver + Send + Sync)) -> anyhow::Result<Vec<String>> {
+    let options = Find {
+        sort: Some(Sorting::asc("applied_at")),
+        ..Find::many()
+    };
+    let records = driver
+        .all(MIGRATIONS_COLLECTION.to_string(), options)
+        .await?;
+    Ok(records
+        .iter()
+        .filter_map(|record| record.get_str("name").ok().map(String::from))
+        .collect())
+}
+
+/// Applies the migrations in `directory` that haven't been yet, returning their names
+pub async fn migrate(
+    driver: &(dyn DatabaseDriver + Send + Sync),
+    directory: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let applied = applied(driver).await?;
+    let mut migrated = Vec::new();
+    for (name, path) in files(directory)? {
+        if applied.contains(&name) {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let steps: Vec<Step> = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid migration {}", path.display()))?;
+        for (index, step) in steps.into_iter().enumerate() {
+            step.apply(driver)
+                .await
+                .with_context(|| format!("Migration {name} failed at step {}", index + 1))?;
+        }
+
+        let record = doc! {"name": &name, "applied_at": bson::DateTime::now()};
+        driver
+            .insert(
+                MIGRATIONS_COLLECTION.to_string(),
+                vec![record],
+                WriteOptions::default(),
+            )
+            .await?;
+        migrated.push(name);
+    }
+    Ok(migrated)
+}
@@ -0,0 +1,347 @@
+//! `ormox-cli repl`: an interactive shell over the configured driver, with line editing & history (kept in
+//! `~/.ormox_history`). Queries & documents use the relaxed syntax of `syntax`, ie `find {age: {$gte: 18}} limit 10`.
+
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use bson::Bson;
+use ormox_core::{core::driver::OperationCount, DatabaseDriver, Find, Query, WriteOptions};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    config::{Config, DriverConfig},
+    list_indexes, print_document, sorting, syntax,
+};
+
+const HELP: &str = "\
+collections                   List collections
+use <collection>              Switch to a collection
+find [query] [options]        Print matching documents; options are `limit N`, `skip N` & `sort [-]field`
+count [query]                 Count matching documents
+insert <document | [documents]>
+delete <query> [one]          Delete matching documents, or only the first
+indexes                       List the collection's indexes
+format table | json           Print documents as a table or as JSON lines
+connect <file>                Switch to the driver of a config file (.toml), or to a PoloDB database file
+help                          Show this help
+exit                          Leave the shell
+
+Queries & documents are JSON, with optional quotes around keys: {name: 'Ada', age: {$gte: 18}}";
+
+/// Longest value shown in a table cell
+const CELL_WIDTH: usize = 40;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Table,
+    Json,
+}
+
+struct Repl {
+    config: Config,
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+    collection: Option<String>,
+    format: Format,
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ormox_history"))
+}
+
+/// Splits `text` at its first word
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// Parses an optional query at the start of `text`, returning it with the rest of the text
+fn parse_query(text: &str) -> anyhow::Result<(Query, &str)> {
+    if !text.starts_with('{') {
+        return Ok((Query::new(), text));
+    }
+    let (value, rest) = syntax::parse_prefix(text)?;
+    Ok((Query::try_from(syntax::to_document(value)?)?, rest))
+}
+
+/// Parses `limit N`, `skip N` & `sort [-]field` options
+fn parse_find(mut text: &str) -> anyhow::Result<Find> {
+    let mut options = Find::many();
+    while !text.is_empty() {
+        let (option, rest) = split_word(text);
+        let (value, rest) = split_word(rest);
+        if value.is_empty() {
+            bail!("`{option}` needs a value");
+        }
+        match option {
+            "limit" => options.limit = Some(value.parse().context("Invalid limit")?),
+            "skip" => options.offset = Some(value.parse().context("Invalid skip")?),
+            "sort" => options.sort = Some(sorting(value)),
+            other => bail!("Unknown option `{other}`"),
+        }
+        text = rest;
+    }
+    Ok(options)
+}
+
+/// Text of a table cell: strings as-is, other values as relaxed Extended JSON, cut to `CELL_WIDTH` characters
+fn cell(value: Option<&Bson>) -> String {
+    let text = match value {
+        None => String::new(),
+        Some(Bson::String(string)) => string.replace(['\n', '\t'], " "),
+        Some(other) => other.clone().into_relaxed_extjson().to_string(),
+    };
+    if text.chars().count() > CELL_WIDTH {
+        let cut: String = text.chars().take(CELL_WIDTH - 1).collect();
+        format!("{cut}…")
+    } else {
+        text
+    }
+}
+
+/// Prints documents as a table with a column for each top-level field, in the order they first appear
+fn print_table(out: &mut impl Write, documents: &[bson::Document]) -> io::Result<()> {
+    let mut columns: Vec<&str> = Vec::new();
+    for document in documents {
+        for key in document.keys() {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+    let rows: Vec<Vec<String>> = documents
+        .iter()
+        .map(|document| {
+            columns
+                .iter()
+                .map(|column| cell(document.get(column)))
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            rows.iter()
+                .map(|row| row[index].chars().count())
+                .chain([column.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |cells: Vec<String>| -> String {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    writeln!(
+        out,
+        "{}",
+        line(columns.iter().map(|c| c.to_string()).collect())
+    )?;
+    writeln!(
+        out,
+        "{}",
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("-+-")
+    )?;
+    for row in rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+impl Repl {
+    fn prompt(&self) -> String {
+        format!(
+            "{}> ",
+            self.collection
+                .as_deref()
+                .unwrap_or(self.config.driver.kind())
+        )
+    }
+
+    fn collection(&self) -> anyhow::Result<String> {
+        self.collection
+            .clone()
+            .context("No collection selected: pick one with `use <collection>`")
+    }
+
+    fn print(&self, documents: Vec<bson::Document>) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        let count = documents.len();
+        match self.format {
+            Format::Table if count > 0 => print_table(&mut out, &documents)?,
+            Format::Table => {}
+            Format::Json => {
+                for document in documents {
+                    print_document(&mut out, document, false)?;
+                }
+            }
+        }
+        writeln!(
+            out,
+            "({count} document{})",
+            if count == 1 { "" } else { "s" }
+        )
+    }
+
+    /// Runs a line, returning false to leave the shell
+    async fn execute(&mut self, line: &str) -> anyhow::Result<bool> {
+        let (command, rest) = split_word(line);
+        match command {
+            "" => {}
+            "exit" | "quit" => return Ok(false),
+            "help" => println!("{HELP}"),
+            "collections" => {
+                let mut collections = self.driver.collections().await?;
+                collections.sort();
+                for collection in collections {
+                    println!("{collection}");
+                }
+            }
+            "use" => match split_word(rest) {
+                ("", _) => bail!("Usage: use <collection>"),
+                (collection, "") => self.collection = Some(collection.to_string()),
+                _ => bail!("Collection names can't contain spaces"),
+            },
+            "format" => {
+                self.format = match rest {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    _ => bail!("Usage: format table | json"),
+                }
+            }
+            "find" => {
+                let (query, rest) = parse_query(rest)?;
+                let options = parse_find(rest)?;
+                let documents = self.driver.find(self.collection()?, query, options).await?;
+                self.print(documents)?;
+            }
+            "count" => {
+                let (query, rest) = parse_query(rest)?;
+                if !rest.is_empty() {
+                    bail!("Unexpected input after the query: {rest}");
+                }
+                println!(
+                    "{}",
+                    self.driver
+                        .count(self.collection()?, query, Find::many())
+                        .await?
+                );
+            }
+            "insert" => {
+                let documents = match syntax::parse_value(rest)? {
+                    serde_json::Value::Array(documents) => documents
+                        .into_iter()
+                        .map(syntax::to_document)
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                    document => vec![syntax::to_document(document)?],
+                };
+                let count = documents.len();
+                self.driver
+                    .insert(self.collection()?, documents, WriteOptions::default())
+                    .await?;
+                println!("Inserted {count} document(s)");
+            }
+            "delete" => {
+                if !rest.starts_with('{') {
+                    bail!("Usage: delete <query> [one]; use {{}} to delete every document");
+                }
+                let (query, count) = match parse_query(rest)? {
+                    (query, "") => (query, OperationCount::Many),
+                    (query, "one") => (query, OperationCount::One),
+                    (_, rest) => bail!("Unexpected input after the query: {rest}"),
+                };
+                self.driver
+                    .delete(self.collection()?, query, count, WriteOptions::default())
+                    .await?;
+            }
+            "indexes" => {
+                for (name, size) in list_indexes(self.driver.as_ref(), self.collection()?).await? {
+                    println!("{name}\t{size}");
+                }
+            }
+            "connect" => {
+                if rest.is_empty() {
+                    bail!("Usage: connect <file>");
+                }
+                let path = PathBuf::from(rest);
+                let config = if path
+                    .extension()
+                    .is_some_and(|extension| extension == "toml")
+                {
+                    Config::load(&path)?
+                } else {
+                    Config {
+                        driver: DriverConfig::Polodb { path: Some(path) },
+                        migrations: self.config.migrations.clone(),
+                    }
+                };
+                self.driver = config.connect().await?;
+                self.config = config;
+                self.collection = None;
+                println!("Connected to {}", self.config.driver.kind());
+            }
+            other => bail!("Unknown command `{other}`, see `help`"),
+        }
+        Ok(true)
+    }
+}
+
+/// Runs the shell until `exit` or end of input
+pub async fn repl(
+    config: Config,
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+    collection: Option<String>,
+) -> anyhow::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    let mut repl = Repl {
+        config,
+        driver,
+        collection,
+        format: Format::Table,
+    };
+    loop {
+        let prompt = repl.prompt();
+        let line = match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        match repl.execute(&line).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("Error: {e:#}"),
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
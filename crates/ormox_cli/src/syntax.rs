@@ -0,0 +1,181 @@
+//! Relaxed JSON for queries & documents typed by hand, in the style of `bson::doc!`: keys may be left unquoted
+//! (including operators & dotted paths, ie `{address.city: "Oslo", age: {$gte: 18}}`), strings may use single quotes,
+//! and trailing commas are allowed. Plain JSON & Extended JSON (ie `{"$oid": "..."}`) parse as usual.
+
+use anyhow::{bail, Context};
+use bson::Bson;
+use serde_json::{Map, Number, Value};
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '$' | '.' | '-')
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => bail!(
+                "Expected `{expected}` at {}, found `{c}`",
+                self.position - c.len_utf8()
+            ),
+            None => bail!("Expected `{expected}`, found the end of input"),
+        }
+    }
+
+    /// Consumes `c` if it's next, ignoring whitespace
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self) -> &'a str {
+        let start = self.position;
+        while self.peek().is_some_and(is_key_char) {
+            self.next();
+        }
+        &self.text[start..self.position]
+    }
+
+    fn value(&mut self) -> anyhow::Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some(quote @ ('"' | '\'')) => self.string(quote).map(Value::String),
+            Some(_) => {
+                let start = self.position;
+                match self.word() {
+                    "" => bail!("Unexpected `{}` at {start}", self.peek().unwrap_or(' ')),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    word => serde_json::from_str::<Number>(word)
+                        .map(Value::Number)
+                        .with_context(|| format!("Invalid value `{word}` at {start}")),
+                }
+            }
+            None => bail!("Expected a value, found the end of input"),
+        }
+    }
+
+    fn string(&mut self, quote: char) -> anyhow::Result<String> {
+        self.next();
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('u') => {
+                        let start = self.position;
+                        let end = (start + 4).min(self.text.len());
+                        let code = self
+                            .text
+                            .get(start..end)
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                            .and_then(char::from_u32)
+                            .with_context(|| format!("Invalid unicode escape at {start}"))?;
+                        self.position = end;
+                        string.push(code);
+                    }
+                    Some(c) => string.push(c),
+                    None => bail!("Unterminated string"),
+                },
+                Some(c) => string.push(c),
+                None => bail!("Unterminated string"),
+            }
+        }
+    }
+
+    fn object(&mut self) -> anyhow::Result<Value> {
+        self.expect('{')?;
+        let mut object = Map::new();
+        while !self.eat('}') {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.string(quote)?,
+                _ => match self.word() {
+                    "" => bail!("Expected a key at {}", self.position),
+                    key => key.to_string(),
+                },
+            };
+            self.expect(':')?;
+            object.insert(key, self.value()?);
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        Ok(Value::Object(object))
+    }
+
+    fn array(&mut self) -> anyhow::Result<Value> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        while !self.eat(']') {
+            array.push(self.value()?);
+            if !self.eat(',') {
+                self.expect(']')?;
+                break;
+            }
+        }
+        Ok(Value::Array(array))
+    }
+}
+
+/// Parses a value in relaxed JSON at the start of `text`, returning it with the rest of the text
+pub fn parse_prefix(text: &str) -> anyhow::Result<(Value, &str)> {
+    let mut parser = Parser { text, position: 0 };
+    let value = parser.value()?;
+    Ok((value, text[parser.position..].trim_start()))
+}
+
+/// Parses a value in relaxed JSON
+pub fn parse_value(text: &str) -> anyhow::Result<Value> {
+    match parse_prefix(text)? {
+        (value, "") => Ok(value),
+        (_, rest) => bail!("Unexpected input after the value: {rest}"),
+    }
+}
+
+/// Converts a parsed value to a document, reading Extended JSON
+pub fn to_document(value: Value) -> anyhow::Result<bson::Document> {
+    match Bson::try_from(value).context("Invalid Extended JSON")? {
+        Bson::Document(document) => Ok(document),
+        other => bail!("Expected a document, got {other}"),
+    }
+}
+
+/// Parses a document in relaxed JSON
+pub fn parse_document(text: &str) -> anyhow::Result<bson::Document> {
+    to_document(parse_value(text)?)
+}
@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use ormox_core::bson::{self, serde_helpers::HumanReadable, RawDocumentBuf};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Row {
+    #[serde(rename = "_id")]
+    id: Uuid,
+    name: String,
+    email: String,
+    age: i32,
+    tags: Vec<String>,
+    scores: Vec<f64>,
+}
+
+fn rows(count: usize) -> Vec<RawDocumentBuf> {
+    (0..count)
+        .map(|i| {
+            let row = Row {
+                id: Uuid::new_v4(),
+                name: format!("user-{i}"),
+                email: format!("user-{i}@example.com"),
+                age: (i % 90) as i32,
+                tags: vec![
+                    String::from("alpha"),
+                    String::from("beta"),
+                    String::from("gamma"),
+                ],
+                scores: (0..16).map(|s| s as f64 * 1.5).collect(),
+            };
+            RawDocumentBuf::from_document(&bson::to_document(&row).unwrap()).unwrap()
+        })
+        .collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let raw = rows(10_000);
+    let mut group = c.benchmark_group("parse 10k rows");
+
+    // What drivers did before: build a bson::Document per row, then deserialize that tree
+    group.bench_function("via bson::Document", |b| {
+        b.iter_batched(
+            || raw.clone(),
+            |raw| {
+                for r in raw {
+                    let document = r.to_document().unwrap();
+                    black_box(bson::from_document::<Row>(document).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("via RawDocumentBuf", |b| {
+        b.iter_batched(
+            || raw.clone(),
+            |raw| {
+                for r in raw {
+                    black_box(bson::from_slice::<HumanReadable<Row>>(r.as_bytes()).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,133 @@
+//! Axum integration: `OrmoxError` responses, and extractors for the client & for documents loaded by the id in the
+//! request path.
+//!
+//! Extractors use the client added to the router with `RouterExt::with_client`, or the global client otherwise.
+
+use std::ops::{Deref, DerefMut};
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json, Router,
+};
+use serde_json::Value;
+
+use crate::{
+    client::Client,
+    core::{
+        document::Document,
+        error::{ErrorKind, OResult, OrmoxError},
+    },
+};
+
+/// Status code of a response reporting `error`
+pub fn status_code(error: &OrmoxError) -> StatusCode {
+    match error.kind() {
+        ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        ErrorKind::Conflict => StatusCode::CONFLICT,
+        ErrorKind::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        ErrorKind::InvalidInput | ErrorKind::Serialization | ErrorKind::Uninitialized => StatusCode::BAD_REQUEST,
+        ErrorKind::Transient => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::Driver => StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Responds with the error as JSON, with the status code of its `ErrorKind`
+impl IntoResponse for OrmoxError {
+    fn into_response(self) -> Response {
+        (status_code(&self), Json(self)).into_response()
+    }
+}
+
+pub trait RouterExt {
+    /// Makes `client` available to the ormox extractors of every route
+    fn with_client(self, client: Client) -> Self;
+}
+
+impl<S: Clone + Send + Sync + 'static> RouterExt for Router<S> {
+    fn with_client(self, client: Client) -> Self {
+        self.layer(Extension(client))
+    }
+}
+
+fn client(parts: &Parts) -> OResult<Client> {
+    parts
+        .extensions
+        .get::<Client>()
+        .cloned()
+        .or_else(|| Client::global().map(|client| client.as_ref().clone()))
+        .ok_or_else(|| OrmoxError::Driver {
+            driver_name: String::from("axum"),
+            error: String::from("No client: add one with RouterExt::with_client or Client::create_global")
+        })
+}
+
+/// Extracts the client
+#[derive(Clone)]
+pub struct Ormox(pub Client);
+
+impl<S: Send + Sync> FromRequestParts<S> for Ormox {
+    type Rejection = OrmoxError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> OResult<Self> {
+        client(parts).map(Self)
+    }
+}
+
+impl Deref for Ormox {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.0
+    }
+}
+
+/// Extracts the `T` whose id is the path parameter named `id`, or the last path parameter if there's none, ie
+/// `/users/{id}` or `/teams/{team}/users/{user}`. Responds with 404 if there's no such document, and 400 if the
+/// parameter isn't a valid id.
+#[derive(Clone, Debug)]
+pub struct DocId<T: Document>(pub T);
+
+impl<T: Document> DocId<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Parses an id path parameter, as JSON for non-string ids (ie numbers) and as a plain string otherwise
+fn parse_id<T: Document>(id: &str) -> OResult<T::Id> {
+    serde_json::from_str(id)
+        .or_else(|_| serde_json::from_value(Value::String(id.to_string())))
+        .map_err(|_| OrmoxError::id(id))
+}
+
+impl<T: Document, S: Send + Sync> FromRequestParts<S> for DocId<T> {
+    type Rejection = OrmoxError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> OResult<Self> {
+        let Path(parameters) = Path::<Vec<(String, String)>>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| OrmoxError::compaibility(e.body_text()))?;
+        let (_, id) = parameters
+            .iter()
+            .find(|(name, _)| name == "id")
+            .or(parameters.last())
+            .ok_or_else(|| OrmoxError::compaibility("Route has no path parameters"))?;
+        let id = parse_id::<T>(id)?;
+        Ok(Self(client(parts)?.collection::<T>().get(id).await?))
+    }
+}
+
+impl<T: Document> Deref for DocId<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Document> DerefMut for DocId<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
@@ -0,0 +1,561 @@
+//! Synchronous wrappers over `Client` & `Collection` for CLI tools, build scripts & other code without an async runtime.
+//!
+//! Each `BlockingClient` owns a tokio runtime that operations are run on, and that background tasks (outbox relays,
+//! scheduled jobs) are spawned on unless `ClientOptions::runtime` is set. Like other blocking APIs over tokio,
+//! these types panic when used from within an async runtime; use the async API there instead.
+
+use std::{collections::HashMap, error::Error, fmt::Display, future::Future, ops::Neg, sync::Arc, time::Duration};
+use futures::{future::BoxFuture, stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+use crate::{
+    client::{Client, ClientOptions, Collection, DedupeReport, KeepStrategy, MaintenanceHandle, OutboxRelay, ScheduledJob, Session},
+    core::{
+        document::{DecodeMode, Document, Index, Projection, Variant},
+        driver::{
+            CollectionStats, DatabaseDriver, DriverCapabilities, Find, HealthReport, IndexStats, OperationCount, PartialResult,
+            QueryPlan, ReadPreference, WriteConcern,
+        },
+        error::{OResult, OrmoxError},
+        merge::MergePolicy,
+        observer::Observer,
+        outbox::OutboxEvent,
+        query::Query,
+        relation::ManyToMany,
+        runtime::Runtime,
+    },
+    lock::LockGuard,
+    unit_of_work::UnitOfWork,
+    view::{View, ViewHandle, ViewRefresh},
+};
+#[cfg(feature = "csv")]
+use crate::core::import::{CsvMapping, ImportReport};
+
+/// Spawns background tasks on a `BlockingClient`'s own runtime, which keeps running them between blocking calls
+#[derive(Debug)]
+struct BlockingRuntime(Handle);
+
+impl Runtime for BlockingRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        self.0.spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+fn runtime() -> Arc<tokio::runtime::Runtime> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("ormox-blocking")
+        .enable_all()
+        .build()
+        .expect("Failed to start the runtime of a blocking client");
+    Arc::new(runtime)
+}
+
+/// Spawns background tasks on `runtime` unless the options name another runtime
+fn with_background_runtime(runtime: &tokio::runtime::Runtime, mut options: ClientOptions) -> ClientOptions {
+    if options.runtime.is_none() {
+        options.runtime = Some(Arc::new(BlockingRuntime(runtime.handle().clone())));
+    }
+    options
+}
+
+/// Blocking counterpart of `Client`
+#[derive(Clone)]
+pub struct BlockingClient {
+    client: Client,
+    runtime: Arc<tokio::runtime::Runtime>
+}
+
+impl BlockingClient {
+    pub fn create<D: DatabaseDriver + Send + Sync + 'static>(driver: D) -> Self {
+        Self::create_with_options(driver, ClientOptions::default())
+    }
+
+    pub fn create_with_options<D: DatabaseDriver + Send + Sync + 'static>(driver: D, options: ClientOptions) -> Self {
+        let runtime = runtime();
+        let options = with_background_runtime(&runtime, options);
+        Self::from_parts(runtime, driver, options)
+    }
+
+    /// Creates a client whose driver is set up asynchronously (ie `MongoDriver::connect`), running `connect` on the client's
+    /// runtime so drivers that spawn tasks while connecting keep them running
+    pub fn connect<D, F, Fut>(connect: F, options: ClientOptions) -> OResult<Self>
+    where
+        D: DatabaseDriver + Send + Sync + 'static,
+        F: FnOnce(&ClientOptions) -> Fut,
+        Fut: Future<Output = OResult<D>>,
+    {
+        let runtime = runtime();
+        let options = with_background_runtime(&runtime, options);
+        let driver = runtime.block_on(connect(&options))?;
+        Ok(Self::from_parts(runtime, driver, options))
+    }
+
+    fn from_parts<D: DatabaseDriver + Send + Sync + 'static>(runtime: Arc<tokio::runtime::Runtime>, driver: D, options: ClientOptions) -> Self {
+        Self { client: Client::create_with_options(driver, options).as_ref().clone(), runtime }
+    }
+
+    /// The wrapped async client, sharing this client's driver
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Runs a future to completion on this client's runtime, for async APIs without a blocking counterpart
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn options(&self) -> &ClientOptions {
+        self.client.options()
+    }
+
+    pub fn driver(&self) -> Arc<dyn DatabaseDriver + Send + Sync> {
+        self.client.driver()
+    }
+
+    pub fn downcast_driver<D: DatabaseDriver + 'static>(&self) -> Option<&D> {
+        self.client.downcast_driver()
+    }
+
+    pub fn capabilities(&self) -> DriverCapabilities {
+        self.client.capabilities()
+    }
+
+    pub fn supports(&self, capabilities: DriverCapabilities) -> bool {
+        self.client.supports(capabilities)
+    }
+
+    pub fn require(&self, capabilities: DriverCapabilities) -> OResult<()> {
+        self.client.require(capabilities)
+    }
+
+    pub fn collections(&self) -> OResult<Vec<String>> {
+        self.block_on(self.client.collections())
+    }
+
+    pub fn collection<D: Document>(&self) -> BlockingCollection<D> {
+        BlockingCollection { collection: self.client.collection(), runtime: self.runtime.clone() }
+    }
+
+    /// Runs `observer` after writes to its collection, see `Client::observe`. Observers run on the client's runtime.
+    pub fn observe(&self, observer: impl Observer + 'static) {
+        self.client.observe(observer)
+    }
+
+    /// See `Client::register_scope`
+    pub fn register_scope<D: Document>(&self, name: impl AsRef<str>, scope: impl Fn() -> Query + Send + Sync + 'static) {
+        self.client.register_scope::<D>(name, scope)
+    }
+
+    /// See `Client::session`
+    pub fn session(&self) -> OResult<BlockingSession> {
+        let session = self.block_on(self.client.session())?;
+        Ok(BlockingSession { session, runtime: self.runtime.clone() })
+    }
+
+    /// See `Client::next_sequence`
+    pub fn next_sequence(&self, name: impl AsRef<str>) -> OResult<i64> {
+        self.block_on(self.client.next_sequence(name))
+    }
+
+    pub fn unit_of_work(&self) -> UnitOfWork {
+        self.client.unit_of_work()
+    }
+
+    /// Commits a unit of work started with `unit_of_work`, see `UnitOfWork::commit`
+    pub fn commit(&self, work: UnitOfWork) -> OResult<()> {
+        self.block_on(work.commit())
+    }
+
+    pub fn pending_events(&self, limit: Option<usize>) -> OResult<Vec<OutboxEvent>> {
+        self.block_on(self.client.pending_events(limit))
+    }
+
+    /// See `Client::relay_outbox`
+    pub fn relay_outbox<E: Display>(&self, handler: impl Fn(OutboxEvent) -> Result<(), E>) -> OResult<u64> {
+        self.block_on(self.client.relay_outbox(&|event| std::future::ready(handler(event))))
+    }
+
+    /// Relays outbox events to `handler` in the background, see `Client::start_outbox_relay`. `handler` runs on the
+    /// client's runtime, so it should not block for long.
+    pub fn start_outbox_relay<E: Display + Send>(&self, handler: impl Fn(OutboxEvent) -> Result<(), E> + Send + Sync + 'static) -> OutboxRelay {
+        let _context = self.runtime.enter();
+        self.client.start_outbox_relay(move |event| std::future::ready(handler(event)))
+    }
+
+    pub fn purge_outbox(&self) -> OResult<()> {
+        self.block_on(self.client.purge_outbox())
+    }
+
+    pub fn maintain(&self) -> OResult<()> {
+        self.block_on(self.client.maintain())
+    }
+
+    /// See `Client::close`
+    pub fn close(&self) -> OResult<()> {
+        self.block_on(self.client.close())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.client.is_closed()
+    }
+
+    /// See `Client::health`
+    pub fn health(&self) -> HealthReport {
+        self.block_on(self.client.health())
+    }
+
+    pub fn try_lock(&self, name: impl AsRef<str>, ttl: Duration) -> OResult<Option<LockGuard>> {
+        self.block_on(self.client.try_lock(name, ttl))
+    }
+
+    pub fn lock(&self, name: impl AsRef<str>, ttl: Duration) -> OResult<LockGuard> {
+        self.block_on(self.client.lock(name, ttl))
+    }
+
+    /// Runs driver maintenance in the background, see `Client::schedule_maintenance`
+    pub fn schedule_maintenance(&self, interval: Duration) -> MaintenanceHandle {
+        let _context = self.runtime.enter();
+        self.client.schedule_maintenance(interval)
+    }
+
+    /// Runs `job` in the background every `interval`, see `Client::schedule`. `job` runs on the client's runtime, so it
+    /// should not block for long.
+    pub fn schedule(&self, name: impl AsRef<str>, interval: Duration, job: impl Fn() -> OResult<()> + Send + Sync + 'static) -> ScheduledJob {
+        let _context = self.runtime.enter();
+        self.client.schedule(name, interval, move || std::future::ready(job()))
+    }
+
+    /// Registers the materialized view `V`, refreshed in the background, see `Client::register_view`
+    pub fn register_view<V: View>(&self, refresh: ViewRefresh) -> OResult<ViewHandle> {
+        self.block_on(self.client.register_view::<V>(refresh))
+    }
+
+    pub fn refresh_view<V: View>(&self) -> OResult<()> {
+        self.block_on(self.client.refresh_view::<V>())
+    }
+}
+
+/// Blocking counterpart of `Session`
+#[derive(Clone)]
+pub struct BlockingSession {
+    session: Session,
+    runtime: Arc<tokio::runtime::Runtime>
+}
+
+impl BlockingSession {
+    pub fn id(&self) -> Option<Uuid> {
+        self.session.id()
+    }
+
+    /// Returns a collection handle whose operations run in this session
+    pub fn collection<D: Document>(&self) -> BlockingCollection<D> {
+        BlockingCollection { collection: self.session.collection(), runtime: self.runtime.clone() }
+    }
+
+    pub fn enqueue(&self, topic: impl AsRef<str>, payload: impl Serialize) -> OResult<Uuid> {
+        self.runtime.block_on(self.session.enqueue(topic, payload))
+    }
+
+    pub fn start_transaction(&self) -> OResult<()> {
+        self.runtime.block_on(self.session.start_transaction())
+    }
+
+    pub fn commit_transaction(&self) -> OResult<()> {
+        self.runtime.block_on(self.session.commit_transaction())
+    }
+
+    pub fn abort_transaction(&self) -> OResult<()> {
+        self.runtime.block_on(self.session.abort_transaction())
+    }
+
+    pub fn end(self) -> OResult<()> {
+        self.runtime.block_on(self.session.end())
+    }
+}
+
+/// Blocking counterpart of `Collection`. Every method runs its async namesake to completion.
+pub struct BlockingCollection<T> {
+    collection: Collection<T>,
+    runtime: Arc<tokio::runtime::Runtime>
+}
+
+impl<T> Clone for BlockingCollection<T> {
+    fn clone(&self) -> Self {
+        Self { collection: self.collection.clone(), runtime: self.runtime.clone() }
+    }
+}
+
+impl<T: Document> BlockingCollection<T> {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// The wrapped async collection
+    pub fn collection(&self) -> Collection<T> {
+        self.collection.clone()
+    }
+
+    pub fn name(&self) -> String {
+        self.collection.name()
+    }
+
+    pub fn with_write_concern(&self, concern: WriteConcern) -> Self {
+        Self { collection: self.collection.with_write_concern(concern), runtime: self.runtime.clone() }
+    }
+
+    pub fn with_read_preference(&self, preference: ReadPreference) -> Self {
+        Self { collection: self.collection.with_read_preference(preference), runtime: self.runtime.clone() }
+    }
+
+    pub fn with_decode_mode(&self, mode: DecodeMode) -> Self {
+        Self { collection: self.collection.with_decode_mode(mode), runtime: self.runtime.clone() }
+    }
+
+    pub fn scope(&self, name: impl AsRef<str>) -> Self {
+        Self { collection: self.collection.scope(name), runtime: self.runtime.clone() }
+    }
+
+    pub fn unscoped(&self) -> Self {
+        Self { collection: self.collection.unscoped(), runtime: self.runtime.clone() }
+    }
+
+    pub fn related<U: Document>(&self) -> BlockingCollection<U> {
+        BlockingCollection { collection: self.collection.related(), runtime: self.runtime.clone() }
+    }
+
+    pub fn register_indices(&self) -> OResult<()> {
+        self.block_on(self.collection.register_indices())
+    }
+
+    pub fn register_counter_caches(&self) {
+        self.collection.register_counter_caches()
+    }
+
+    pub fn recount_counter_caches(&self) -> OResult<()> {
+        self.block_on(self.collection.recount_counter_caches())
+    }
+
+    pub fn find(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<Vec<T>> {
+        self.block_on(self.collection.find(query, options))
+    }
+
+    pub fn count(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<u64> {
+        self.block_on(self.collection.count(query))
+    }
+
+    pub fn find_with_count(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<(Vec<T>, u64)> {
+        self.block_on(self.collection.find_with_count(query, options))
+    }
+
+    pub fn find_variant<V: Variant<Of = T>>(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<Vec<T>> {
+        self.block_on(self.collection.find_variant::<V>(query, options))
+    }
+
+    pub fn count_variant<V: Variant<Of = T>>(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<u64> {
+        self.block_on(self.collection.count_variant::<V>(query))
+    }
+
+    pub fn find_as<P: Projection<Of = T>>(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<Vec<P>> {
+        self.block_on(self.collection.find_as::<P>(query, options))
+    }
+
+    pub fn find_lossy(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>
+    ) -> OResult<Vec<Result<T, (bson::Document, OrmoxError)>>> {
+        self.block_on(self.collection.find_lossy(query, options))
+    }
+
+    pub fn find_partial(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<PartialResult<T>> {
+        self.block_on(self.collection.find_partial(query, options))
+    }
+
+    pub fn all(&self, options: Option<Find>) -> OResult<Vec<T>> {
+        self.block_on(self.collection.all(options))
+    }
+
+    /// Iterates over the documents matching `query`, fetched page by page, see `Collection::stream`
+    pub fn iter(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> impl Iterator<Item = OResult<T>> + '_ {
+        let mut documents = Box::pin(self.collection.stream(query, options));
+        std::iter::from_fn(move || self.block_on(documents.next()))
+    }
+
+    pub fn insert(&self, docs: Vec<T>) -> OResult<Vec<T::Id>> {
+        self.block_on(self.collection.insert(docs))
+    }
+
+    pub fn upsert_many(&self, docs: Vec<T>, key_fields: &[&str]) -> OResult<()> {
+        self.block_on(self.collection.upsert_many(docs, key_fields))
+    }
+
+    pub fn dedupe(&self, key_fields: &[&str], keep: KeepStrategy) -> OResult<DedupeReport> {
+        self.block_on(self.collection.dedupe(key_fields, keep))
+    }
+
+    #[cfg(feature = "csv")]
+    pub fn import_csv<R: std::io::Read + Send>(&self, reader: R, mapping: CsvMapping) -> OResult<ImportReport> {
+        self.block_on(self.collection.import_csv(reader, mapping))
+    }
+
+    pub fn enqueue(&self, topic: impl AsRef<str>, payload: impl Serialize) -> OResult<Uuid> {
+        self.block_on(self.collection.enqueue(topic, payload))
+    }
+
+    /// Inserts documents from an iterator in batches of `insert_batch_size`, see `Collection::insert_stream`
+    pub fn insert_iter(&self, docs: impl IntoIterator<Item = T>) -> OResult<Vec<T::Id>> {
+        self.block_on(self.collection.insert_stream(stream::iter(docs)))
+    }
+
+    pub fn update(&self, query: impl TryInto<Query, Error = impl Error>, update: impl Serialize, operations: OperationCount) -> OResult<()> {
+        self.block_on(self.collection.update(query, update, operations))
+    }
+
+    pub fn upsert(&self, query: impl TryInto<Query, Error = impl Error>, update: impl Serialize, operations: OperationCount) -> OResult<()> {
+        self.block_on(self.collection.upsert(query, update, operations))
+    }
+
+    pub fn delete(&self, query: impl TryInto<Query, Error = impl Error>, operations: OperationCount) -> OResult<()> {
+        self.block_on(self.collection.delete(query, operations))
+    }
+
+    pub fn find_one(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<T> {
+        self.block_on(self.collection.find_one(query))
+    }
+
+    pub fn find_many(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<Vec<T>> {
+        self.block_on(self.collection.find_many(query))
+    }
+
+    pub fn first(&self, query: impl TryInto<Query, Error = impl Error>, sort_field: impl AsRef<str>) -> OResult<Option<T>> {
+        self.block_on(self.collection.first(query, sort_field))
+    }
+
+    pub fn last(&self, query: impl TryInto<Query, Error = impl Error>, sort_field: impl AsRef<str>) -> OResult<Option<T>> {
+        self.block_on(self.collection.last(query, sort_field))
+    }
+
+    pub fn get(&self, id: impl Serialize) -> OResult<T> {
+        self.block_on(self.collection.get(id))
+    }
+
+    pub fn save(&self, document: T) -> OResult<()> {
+        self.block_on(self.collection.save(document))
+    }
+
+    pub fn save_merging(&self, document: T, policy: MergePolicy) -> OResult<T> {
+        self.block_on(self.collection.save_merging(document, policy))
+    }
+
+    pub fn update_by_id(&self, id: impl Serialize, update: impl Serialize) -> OResult<()> {
+        self.block_on(self.collection.update_by_id(id, update))
+    }
+
+    pub fn patch(&self, id: impl Serialize, patch: Value) -> OResult<()> {
+        self.block_on(self.collection.patch(id, patch))
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn rotate_keys(&self) -> OResult<u64> {
+        self.block_on(self.collection.rotate_keys())
+    }
+
+    pub fn increment<N: Serialize + DeserializeOwned>(&self, id: impl Serialize, field: impl AsRef<str>, delta: N) -> OResult<Option<N>> {
+        self.block_on(self.collection.increment(id, field, delta))
+    }
+
+    pub fn decrement<N: Serialize + DeserializeOwned + Neg<Output = N>>(&self, id: impl Serialize, field: impl AsRef<str>, delta: N) -> OResult<Option<N>> {
+        self.block_on(self.collection.decrement(id, field, delta))
+    }
+
+    pub fn check_references(&self, docs: &[T]) -> OResult<()> {
+        self.block_on(self.collection.check_references(docs))
+    }
+
+    pub fn link<U: Document>(&self, document: &T, other: &U) -> OResult<()>
+    where
+        T: ManyToMany<U>
+    {
+        self.block_on(self.collection.link(document, other))
+    }
+
+    pub fn unlink<U: Document>(&self, document: &T, other: &U) -> OResult<()>
+    where
+        T: ManyToMany<U>
+    {
+        self.block_on(self.collection.unlink(document, other))
+    }
+
+    pub fn linked<U: Document>(&self, document: &T) -> OResult<Vec<U>>
+    where
+        T: ManyToMany<U>
+    {
+        self.block_on(self.collection.linked(document))
+    }
+
+    pub fn delete_by_id(&self, id: impl Serialize) -> OResult<()> {
+        self.block_on(self.collection.delete_by_id(id))
+    }
+
+    pub fn delete_one(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<()> {
+        self.block_on(self.collection.delete_one(query))
+    }
+
+    pub fn delete_many(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<()> {
+        self.block_on(self.collection.delete_many(query))
+    }
+
+    pub fn create_index(&self, index: Index) -> OResult<()> {
+        self.block_on(self.collection.create_index(index))
+    }
+
+    pub fn drop_index(&self, index_name: impl AsRef<str>) -> OResult<()> {
+        self.block_on(self.collection.drop_index(index_name))
+    }
+
+    pub fn stats(&self) -> OResult<CollectionStats> {
+        self.block_on(self.collection.stats())
+    }
+
+    pub fn index_stats(&self) -> OResult<Vec<IndexStats>> {
+        self.block_on(self.collection.index_stats())
+    }
+
+    pub fn explain(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<QueryPlan> {
+        self.block_on(self.collection.explain(query, options))
+    }
+
+    /// Scans the whole collection in chunks, see `Collection::par_scan`. Chunks are fetched concurrently but `f` runs on
+    /// one chunk at a time.
+    pub fn par_scan(&self, partitions: usize, f: impl Fn(Vec<T>) -> OResult<()>) -> OResult<()> {
+        self.block_on(self.collection.par_scan(partitions, |chunk| std::future::ready(f(chunk))))
+    }
+
+    pub fn sum(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<f64> {
+        self.block_on(self.collection.sum(field, query))
+    }
+
+    pub fn avg(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<Option<f64>> {
+        self.block_on(self.collection.avg(field, query))
+    }
+
+    pub fn min(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<Option<Value>> {
+        self.block_on(self.collection.min(field, query))
+    }
+
+    pub fn max(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<Option<Value>> {
+        self.block_on(self.collection.max(field, query))
+    }
+
+    pub fn group_by(&self, field: impl AsRef<str>) -> OResult<HashMap<Value, u64>> {
+        self.block_on(self.collection.group_by(field))
+    }
+}
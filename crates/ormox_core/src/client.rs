@@ -1,99 +1,1450 @@
-use std::{error::Error, marker::PhantomData, sync::Arc};
-use serde::Serialize;
+use std::{cmp::Ordering, collections::{BTreeMap, HashMap, HashSet, VecDeque}, error::Error, fmt::{Debug, Display}, future::Future, marker::PhantomData, ops::Neg, pin::pin, sync::{atomic::{self, AtomicBool}, Arc, Mutex}, task::Poll};
+use bson::{bson, doc, serde_helpers::HumanReadable, Bson, RawDocumentBuf};
+use derive_builder::Builder;
+use futures::{channel::oneshot, future::{self, try_join_all, AbortHandle, BoxFuture}, stream, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 
 use uuid::Uuid;
 
 use crate::{
     core::{
-        document::{Document, Index},
-        driver::{DatabaseDriver, Find, OperationCount},
+        canonical::canonical,
+        change::{ChangeEvent, ResumeToken},
+        diff::diff,
+        document::{DecodeMode, Document, Index, Projection, Variant},
+        driver::{
+            CollectionStats, DatabaseDriver, DriverCapabilities, Find, HealthReport, IndexProgress, IndexStats, OperationCount, PartialResult, QueryPlan,
+            ReadPreference, Sorting, WriteConcern, WriteOptions,
+        },
         error::{OResult, OrmoxError},
+        dry_run::{DryRunDriver, WritePlan},
+        limit::LimitedDriver,
+        memory,
+        merge::{merge, MergePolicy, MERGE_ATTEMPTS},
+        numeric::{self, Lenient, NumericPolicy},
+        observer::Observer,
+        outbox::{OutboxEvent, OUTBOX_COLLECTION},
         query::Query,
+        redaction::redact_query,
+        relation::{CounterCacheObserver, ManyToMany, Reference},
+        runtime::{default_runtime, Runtime, Task},
+        sequence::SEQUENCES_COLLECTION,
+        update::Update,
     },
+    lock::LockGuard,
+    partition::{PartitionPeriod, Partitioned},
+    queue::Queue,
+    rate_limit::RateLimiter,
+    unit_of_work::UnitOfWork,
+    view::{self, View, ViewHandle, ViewRefresh},
     ORMOX,
 };
+#[cfg(feature = "encryption")]
+use crate::core::encryption::{self, decrypt_fields, KeyProvider};
+#[cfg(feature = "csv")]
+use crate::core::import::{row_document, ColumnType, CsvMapping, ImportFailure, ImportReport};
+
+#[derive(Clone, Debug, Builder)]
+pub struct ClientOptions {
+    /// Maximum number of documents sent to the driver in a single insert
+    #[builder(default = "1000")]
+    pub insert_batch_size: usize,
+
+    /// Number of documents per chunk handed to `Collection::par_scan` callbacks
+    #[builder(default = "1000")]
+    pub scan_chunk_size: usize,
+
+    /// Documents per page fetched by `Collection::stream`, unless `Find::batch_size` is set
+    #[builder(default = "100")]
+    pub stream_batch_size: usize,
+
+    /// Maximum number of driver operations allowed in flight at once. Further operations wait for a slot; `None` is unlimited.
+    #[builder(default, setter(into, strip_option))]
+    pub max_in_flight: Option<usize>,
+
+    /// Limit of reads returning many documents (`find`, `all`, ...) that don't set one; `None` leaves them unbounded
+    #[builder(default, setter(into, strip_option))]
+    pub default_limit: Option<usize>,
+
+    /// Cap on the limit of reads returning many documents, applied to reads setting a higher limit or none at all, so
+    /// an accidental unbounded read can't load a whole collection. `Collection::stream` pages through results instead,
+    /// so it isn't capped.
+    #[builder(default, setter(into, strip_option))]
+    pub max_limit: Option<usize>,
+
+    /// Whether inserts & saves run `Document::validate` first
+    #[builder(default = "true")]
+    pub validate_writes: bool,
+
+    /// Records writes into this plan instead of running them (see `DryRunDriver`), ie to review what a migration
+    /// script would do. Writes are still validated & their queries rendered.
+    #[builder(default, setter(strip_option))]
+    pub dry_run: Option<WritePlan>,
+
+    /// Whether inserts & saves check that the documents' `Ref` fields point to existing documents, failing with
+    /// `OrmoxError::BrokenReference` otherwise. Costs one lookup per referenced collection.
+    #[builder(default = "false")]
+    pub check_references: bool,
+
+    /// Maximum number of outbox events handed to the relay's handler per pass
+    #[builder(default = "100")]
+    pub outbox_batch_size: usize,
+
+    /// How long the outbox relay waits between passes
+    #[builder(default = "std::time::Duration::from_secs(1)")]
+    pub outbox_poll_interval: std::time::Duration,
+
+    /// How long `Client::lock` waits between attempts to take a held lock
+    #[builder(default = "std::time::Duration::from_millis(250)")]
+    pub lock_retry_interval: std::time::Duration,
+
+    /// Runtime for background tasks (`Client::start_outbox_relay`, `Client::schedule`). Defaults to the one enabled by the
+    /// `tokio`, `async-std` or `smol` feature.
+    #[builder(default, setter(strip_option))]
+    pub runtime: Option<Arc<dyn Runtime>>,
+
+    /// Fraction of their interval by which the waits between runs of scheduled jobs are randomly lengthened or shortened,
+    /// so the jobs of several processes sharing a database don't run in lockstep. `0.0` runs them at exact intervals.
+    #[builder(default = "0.1")]
+    pub schedule_jitter: f64,
+
+    /// How loaded documents are checked against their types, unless the type declares a mode or a collection handle
+    /// overrides it (see `Collection::with_decode_mode`). Use `Strict` in tests to catch documents the types drift from.
+    #[builder(default)]
+    pub decode_mode: DecodeMode,
+
+    /// How integers BSON can't hold (ie `u64`s above `i64::MAX`) are written by collections & compared against in their
+    /// queries
+    #[builder(default)]
+    pub numeric_policy: NumericPolicy,
+
+    /// Keys of `#[ormox(encrypted)]` fields. Documents with encrypted fields can't be written or loaded without one.
+    #[cfg(feature = "encryption")]
+    #[builder(default, setter(strip_option))]
+    pub key_provider: Option<Arc<dyn KeyProvider>>
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            insert_batch_size: 1000,
+            scan_chunk_size: 1000,
+            stream_batch_size: 100,
+            max_in_flight: None,
+            default_limit: None,
+            max_limit: None,
+            validate_writes: true,
+            dry_run: None,
+            check_references: false,
+            outbox_batch_size: 100,
+            outbox_poll_interval: std::time::Duration::from_secs(1),
+            lock_retry_interval: std::time::Duration::from_millis(250),
+            runtime: None,
+            schedule_jitter: 0.1,
+            decode_mode: DecodeMode::Lenient,
+            numeric_policy: NumericPolicy::Error,
+            #[cfg(feature = "encryption")]
+            key_provider: None
+        }
+    }
+}
+
+/// Query fragment of a named scope, see `Client::register_scope`
+type ScopeFn = Arc<dyn Fn() -> Query + Send + Sync>;
+
+/// Background tasks spawned by a client, stopped by `Client::close`, and the observers & scopes registered on it
+#[derive(Default)]
+struct Background {
+    tasks: Mutex<Vec<AbortHandle>>,
+
+    /// Jobs started by `Client::schedule`, for `Client::scheduled_jobs`
+    jobs: Mutex<Vec<(AbortHandle, Arc<Mutex<JobStatus>>)>>,
+    observers: Mutex<Vec<Arc<dyn Observer>>>,
+
+    /// Named scopes by document type's collection & name
+    scopes: Mutex<HashMap<(String, String), ScopeFn>>,
+    closed: AtomicBool
+}
 
 #[derive(Clone)]
-pub struct Client(Arc<dyn DatabaseDriver + Send + Sync>);
+pub struct Client(Arc<dyn DatabaseDriver + Send + Sync>, Arc<ClientOptions>, Arc<Background>);
+
+impl Client {
+    pub fn create<D: DatabaseDriver + Send + Sync + 'static>(driver: D) -> Arc<Self> {
+        Self::create_with_options(driver, ClientOptions::default())
+    }
+
+    pub fn create_with_options<D: DatabaseDriver + Send + Sync + 'static>(driver: D, options: ClientOptions) -> Arc<Self> {
+        let driver: Arc<dyn DatabaseDriver + Send + Sync> = match (options.max_in_flight, options.dry_run.clone()) {
+            (Some(limit), Some(plan)) => Arc::new(DryRunDriver::new(LimitedDriver::new(driver, limit), plan)),
+            (Some(limit), None) => Arc::new(LimitedDriver::new(driver, limit)),
+            (None, Some(plan)) => Arc::new(DryRunDriver::new(driver, plan)),
+            (None, None) => Arc::new(driver)
+        };
+        Arc::new(Self(driver, Arc::new(options), Default::default()))
+    }
+
+    pub fn create_global<D: DatabaseDriver + Send + Sync + 'static>(driver: D) -> Arc<Self> {
+        Self::create_global_with_options(driver, ClientOptions::default())
+    }
+
+    pub fn create_global_with_options<D: DatabaseDriver + Send + Sync + 'static>(driver: D, options: ClientOptions) -> Arc<Self> {
+        if let Ok(_) = ORMOX.set(Self::create_with_options(driver, options)) {
+            ORMOX.get().unwrap().clone()
+        } else {
+            panic!("Global instance already set!");
+        }
+    }
+
+    pub fn options(&self) -> &ClientOptions {
+        &self.1
+    }
+
+    pub fn global() -> Option<Arc<Self>> {
+        ORMOX.get().cloned()
+    }
+
+    pub fn driver(&self) -> Arc<dyn DatabaseDriver + Send + Sync> {
+        self.0.clone()
+    }
+
+    /// Runtime background tasks are spawned on: `ClientOptions::runtime`, or the one enabled by features
+    pub fn runtime(&self) -> Option<Arc<dyn Runtime>> {
+        self.options().runtime.clone().or_else(default_runtime)
+    }
+
+    fn background_runtime(&self) -> Arc<dyn Runtime> {
+        self.runtime().expect("No runtime for background tasks: enable the tokio, async-std or smol feature, or set ClientOptions::runtime")
+    }
+
+    /// Stops `task` when the client is closed
+    fn track(&self, task: &Task) {
+        let mut tasks = self.2.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_aborted());
+        tasks.push(task.abort_handle());
+    }
+
+    /// Stops the client's background tasks (outbox relays & scheduled jobs) and shuts its driver down, closing
+    /// embedded databases & connection pools instead of leaving it to process exit. Clones of the client and its
+    /// collections share the driver, so none of them should be used afterwards. Closing again does nothing.
+    pub async fn close(&self) -> OResult<()> {
+        if self.2.closed.swap(true, atomic::Ordering::AcqRel) {
+            return Ok(());
+        }
+        for task in self.2.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+        self.driver().shutdown().await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.2.closed.load(atomic::Ordering::Acquire)
+    }
+
+    /// Checks that the database is reachable, timing the round trip. Meant for readiness probes: a closed client is
+    /// reported unhealthy without contacting the database.
+    pub async fn health(&self) -> HealthReport {
+        if self.is_closed() {
+            return HealthReport::failed(self.driver().driver_name(), std::time::Duration::ZERO, OrmoxError::Closed);
+        }
+        self.driver().health().await
+    }
+
+    /// Downcasts the driver to its concrete type, for running driver-specific commands
+    pub fn downcast_driver<D: DatabaseDriver + 'static>(&self) -> Option<&D> {
+        self.0.as_any().downcast_ref::<D>()
+    }
+
+    pub fn capabilities(&self) -> DriverCapabilities {
+        self.0.capabilities()
+    }
+
+    pub fn supports(&self, capabilities: DriverCapabilities) -> bool {
+        self.capabilities().contains(capabilities)
+    }
+
+    pub fn require(&self, capabilities: DriverCapabilities) -> OResult<()> {
+        self.capabilities().require(capabilities)
+    }
+
+    pub async fn collections(&self) -> OResult<Vec<String>> {
+        self.driver().collections().await
+    }
+
+    pub fn collection<D: Document>(&self) -> Collection<D> {
+        Collection::<D>::new(self.clone())
+    }
+
+    /// Runs `observer` after writes to its collection through this client (and its clones), see `Observer`. It replaces
+    /// any observer registered with the same key.
+    pub fn observe(&self, observer: impl Observer + 'static) {
+        let mut observers = self.2.observers.lock().unwrap();
+        if let Some(key) = observer.key() {
+            observers.retain(|registered| registered.key().as_ref() != Some(&key));
+        }
+        observers.push(Arc::new(observer));
+    }
+
+    /// Observers of the collection `name`
+    fn observers(&self, name: &str) -> Vec<Arc<dyn Observer>> {
+        self.2.observers.lock().unwrap().iter().filter(|observer| observer.collection() == name).cloned().collect()
+    }
+
+    /// Registers the named scope `name` of the document type `D`: a query fragment built by `scope` whenever a read
+    /// through `Collection::scope(name)` runs, so filters shared across the application are declared once. Scopes of a
+    /// handle merge with each other and with the read's query, see `Query::merge`. It replaces any scope of `D`
+    /// registered with the same name.
+    pub fn register_scope<D: Document>(&self, name: impl AsRef<str>, scope: impl Fn() -> Query + Send + Sync + 'static) {
+        let key = (D::collection_name(), name.as_ref().to_string());
+        self.2.scopes.lock().unwrap().insert(key, Arc::new(scope));
+    }
+
+    /// Query fragment of the named scope `name` of the document type `D`
+    fn scope<D: Document>(&self, name: &str) -> OResult<Query> {
+        let scopes = self.2.scopes.lock().unwrap();
+        match scopes.get(&(D::collection_name(), name.to_string())) {
+            Some(scope) => Ok(scope()),
+            None => Err(OrmoxError::compaibility(format!("No scope `{name}` is registered for {}", D::collection_name())))
+        }
+    }
+
+    /// Starts a session in which reads observe prior writes. Drivers without session support return a handle that runs operations normally.
+    pub async fn session(&self) -> OResult<Session> {
+        let id = if self.supports(DriverCapabilities::SESSIONS) {
+            Some(self.driver().start_session().await?)
+        } else {
+            None
+        };
+
+        Ok(Session { client: self.clone(), id })
+    }
+
+    /// Next value of the named sequence, starting at 1. Each call atomically increments a counter in the
+    /// `_ormox_sequences` collection, so values are unique across clients.
+    pub async fn next_sequence(&self, name: impl AsRef<str>) -> OResult<i64> {
+        let query = Query::new().field("_id", name.as_ref());
+        let counter = self
+            .driver()
+            .find_one_and_update(SEQUENCES_COLLECTION.to_string(), query, doc! {"$inc": {"value": 1_i64}}, true, WriteOptions::default())
+            .await?
+            .ok_or_else(|| OrmoxError::not_found(format!("sequence {}", name.as_ref())))?;
+        match counter.get("value") {
+            Some(Bson::Int64(value)) => Ok(*value),
+            Some(Bson::Int32(value)) => Ok(i64::from(*value)),
+            value => Err(OrmoxError::deserialization(format!("Invalid value of sequence {}: {value:?}", name.as_ref())))
+        }
+    }
+
+    /// Takes the named lock for `ttl` if no other holder has a live lease on it, see `LockGuard`
+    pub async fn try_lock(&self, name: impl AsRef<str>, ttl: std::time::Duration) -> OResult<Option<LockGuard>> {
+        LockGuard::acquire(self, name.as_ref(), ttl).await
+    }
+
+    /// Takes the named lock for `ttl`, waiting `lock_retry_interval` between attempts while another holder has a live
+    /// lease on it, see `LockGuard`
+    ///
+    /// # Panics
+    /// If the lock is held and there's no runtime to wait on, see `Client::runtime`
+    pub async fn lock(&self, name: impl AsRef<str>, ttl: std::time::Duration) -> OResult<LockGuard> {
+        loop {
+            if let Some(guard) = self.try_lock(name.as_ref(), ttl).await? {
+                return Ok(guard);
+            }
+            self.background_runtime().sleep(self.options().lock_retry_interval).await;
+        }
+    }
+
+    /// Persistent work queue of documents of type `D`, see `Queue`
+    pub fn queue<D: Document>(&self) -> Queue<D> {
+        Queue::new(self.clone())
+    }
+
+    /// Limiter allowing `limit` requests per key every `window`, shared by every client of the database, see
+    /// `RateLimiter`
+    pub fn rate_limiter(&self, name: impl AsRef<str>, limit: u64, window: std::time::Duration) -> RateLimiter {
+        RateLimiter::new(self.clone(), name, limit, window)
+    }
+
+    /// Starts collecting writes to commit together, see `UnitOfWork`
+    pub fn unit_of_work(&self) -> UnitOfWork {
+        UnitOfWork::new(self.clone())
+    }
+
+    /// Pending outbox events, oldest first
+    pub async fn pending_events(&self, limit: Option<usize>) -> OResult<Vec<OutboxEvent>> {
+        let options = Find { sort: Some(Sorting::asc("created_at")), limit, ..Find::many() };
+        let documents = self.driver().find(OUTBOX_COLLECTION.to_string(), Query::new().field("delivered", false), options).await?;
+        documents.iter().map(OutboxEvent::from_document).collect()
+    }
+
+    /// Hands up to `outbox_batch_size` pending outbox events to `handler` in the order they were enqueued, returning how
+    /// many it acknowledged. Events are acknowledged when the handler succeeds; failed events record the error and are
+    /// retried on the next pass.
+    ///
+    /// Delivery is at-least-once: an event whose handler succeeded may be delivered again if acknowledging it fails, so
+    /// handlers should be idempotent (ie keyed on `OutboxEvent::id`).
+    pub async fn relay_outbox<F, Fut, E>(&self, handler: &F) -> OResult<u64>
+    where
+        F: Fn(OutboxEvent) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut delivered = 0;
+        for event in self.pending_events(Some(self.options().outbox_batch_size.max(1))).await? {
+            let query = Query::new().field("_id", event.id.to_string());
+            let update = match handler(event).await {
+                Ok(()) => {
+                    delivered += 1;
+                    doc! {"$set": {"delivered": true, "delivered_at": bson::DateTime::from_chrono(chrono::Utc::now())}, "$inc": {"attempts": 1_i64}}
+                },
+                Err(e) => doc! {"$set": {"last_error": e.to_string()}, "$inc": {"attempts": 1_i64}}
+            };
+            self.driver().update(OUTBOX_COLLECTION.to_string(), query, update, OperationCount::One, WriteOptions::default()).await?;
+        }
+        Ok(delivered)
+    }
+
+    /// Spawns a task relaying outbox events to `handler` every `outbox_poll_interval`, see `relay_outbox`. The task
+    /// stops when the returned handle is stopped or dropped. Run one relay per database, or events will be delivered
+    /// more than once.
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub fn start_outbox_relay<F, Fut, E>(&self, handler: F) -> OutboxRelay
+    where
+        F: Fn(OutboxEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send,
+        E: Display,
+    {
+        let (client, runtime) = (self.clone(), self.background_runtime());
+        let task = Task::spawn(runtime.clone().as_ref(), async move {
+            loop {
+                let _ = client.relay_outbox(&handler).await;
+                runtime.sleep(client.options().outbox_poll_interval).await;
+            }
+        });
+        self.track(&task);
+        OutboxRelay(task)
+    }
+
+    /// Deletes acknowledged outbox events
+    pub async fn purge_outbox(&self) -> OResult<()> {
+        self.driver()
+            .delete(OUTBOX_COLLECTION.to_string(), Query::new().field("delivered", true), OperationCount::Many, WriteOptions::default())
+            .await
+    }
+
+    pub async fn maintain(&self) -> OResult<()> {
+        self.driver().maintain().await
+    }
+
+    /// Schedules driver maintenance every `interval`, as the `"maintenance"` job. It stops when the returned handle is
+    /// stopped or dropped.
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub fn schedule_maintenance(&self, interval: std::time::Duration) -> MaintenanceHandle {
+        let client = self.clone();
+        MaintenanceHandle(self.schedule("maintenance", interval, move || {
+            let client = client.clone();
+            async move { client.maintain().await }
+        }))
+    }
+
+    /// Spawns a task running `job` every `interval` (give or take `ClientOptions::schedule_jitter`), starting one
+    /// interval from now. Runs never overlap: the next wait starts once a run completes. Failed runs are recorded in the
+    /// job's status (see `ScheduledJob::status` & `Client::scheduled_jobs`) and don't stop the job, which runs until the
+    /// returned handle is stopped or dropped, or the client is closed.
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub fn schedule<F, Fut>(&self, name: impl AsRef<str>, interval: std::time::Duration, job: F) -> ScheduledJob
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = OResult<()>> + Send,
+    {
+        let jitter = self.options().schedule_jitter;
+        self.spawn_job(name, interval, move |runtime, status| async move {
+            loop {
+                runtime.sleep(jittered(interval, jitter)).await;
+                let result = job().await;
+                status.lock().unwrap().record(result);
+            }
+        })
+    }
+
+    /// Spawns `job` as a tracked background task listed in `Client::scheduled_jobs`, handing it the runtime and the
+    /// status to record its runs in
+    fn spawn_job<F, Fut>(&self, name: impl AsRef<str>, interval: std::time::Duration, job: F) -> ScheduledJob
+    where
+        F: FnOnce(Arc<dyn Runtime>, Arc<Mutex<JobStatus>>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let runtime = self.background_runtime();
+        let status = Arc::new(Mutex::new(JobStatus {
+            name: name.as_ref().to_string(),
+            interval,
+            runs: 0,
+            failures: 0,
+            last_run: None,
+            last_error: None
+        }));
+        let task = Task::spawn(runtime.as_ref(), job(runtime.clone(), status.clone()));
+        self.track(&task);
+
+        let mut jobs = self.2.jobs.lock().unwrap();
+        jobs.retain(|(task, _)| !task.is_aborted());
+        jobs.push((task.abort_handle(), status.clone()));
+        ScheduledJob { task, status }
+    }
+
+    /// Registers the materialized view `V`, filling it from its source then keeping it up to date as `refresh` says, as
+    /// the `"view:<collection>"` job. The view is read through `Client::collection::<V>()`, and is no longer refreshed
+    /// once the returned handle is stopped or dropped, or the client is closed.
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub async fn register_view<V: View>(&self, refresh: ViewRefresh) -> OResult<ViewHandle> {
+        let name = format!("view:{}", V::collection_name());
+        let job = match refresh {
+            ViewRefresh::Scheduled(interval) => {
+                view::refresh::<V>(self).await?;
+                let client = self.clone();
+                self.schedule(name, interval, move || {
+                    let client = client.clone();
+                    async move { view::refresh::<V>(&client).await }
+                })
+            },
+            ViewRefresh::Incremental => {
+                self.require(DriverCapabilities::CHANGE_STREAMS)?;
+                // Watching before filling the view, so that no change made in between is missed
+                let mut watching = Some(self.driver().watch(V::Source::collection_name(), None).await?);
+                view::refresh::<V>(self).await?;
+                let client = self.clone();
+                self.spawn_job(name, std::time::Duration::ZERO, move |runtime, status| async move {
+                    loop {
+                        let result = async {
+                            let mut changes = match watching.take() {
+                                Some(changes) => changes,
+                                None => {
+                                    let changes = client.driver().watch(V::Source::collection_name(), None).await?;
+                                    view::refresh::<V>(&client).await?;
+                                    changes
+                                }
+                            };
+                            while let Some(change) = changes.next().await {
+                                view::apply::<V>(&client, change?).await?;
+                                status.lock().unwrap().record(Ok(()));
+                            }
+                            Ok::<_, OrmoxError>(())
+                        }.await;
+                        // The stream ended or failed: changes may have been missed until it's watched again, so the
+                        // view is rebuilt then
+                        if let Err(error) = result {
+                            status.lock().unwrap().record(Err(error));
+                        }
+                        runtime.sleep(view::REWATCH_DELAY).await;
+                    }
+                })
+            }
+        };
+        Ok(ViewHandle(job))
+    }
+
+    /// Rebuilds the materialized view `V` from its whole source, ie after a registered view's job failed
+    pub async fn refresh_view<V: View>(&self) -> OResult<()> {
+        view::refresh::<V>(self).await
+    }
+
+    /// Status of the jobs started by `Client::schedule` that are still running, ie for exporting to metrics or health
+    /// checks
+    pub fn scheduled_jobs(&self) -> Vec<JobStatus> {
+        let mut jobs = self.2.jobs.lock().unwrap();
+        jobs.retain(|(task, _)| !task.is_aborted());
+        jobs.iter().map(|(_, status)| status.lock().unwrap().clone()).collect()
+    }
+}
+
+/// `interval` randomly lengthened or shortened by up to `jitter` of itself
+fn jittered(interval: std::time::Duration, jitter: f64) -> std::time::Duration {
+    if jitter <= 0.0 {
+        return interval;
+    }
+    // Low 52 bits of a v4 UUID (clear of its version & variant bits), as a fraction in [-1, 1)
+    let random = (Uuid::new_v4().as_u128() & ((1 << 52) - 1)) as f64 / (1_u64 << 52) as f64 * 2.0 - 1.0;
+    interval.mul_f64((1.0 + random * jitter.min(1.0)).max(0.0))
+}
+
+/// Runs of a job started by `Client::schedule` (or another background job, ie `Client::register_view`)
+#[derive(Clone, Debug)]
+pub struct JobStatus {
+    pub name: String,
+
+    /// Time between runs, zero for jobs run on changes (ie incrementally refreshed views)
+    pub interval: std::time::Duration,
+
+    /// Number of completed runs, failed ones included
+    pub runs: u64,
+    pub failures: u64,
+
+    /// When the last run completed
+    pub last_run: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Error of the last failed run
+    pub last_error: Option<OrmoxError>
+}
+
+impl JobStatus {
+    fn record(&mut self, result: OResult<()>) {
+        self.runs += 1;
+        self.last_run = Some(chrono::Utc::now());
+        if let Err(error) = result {
+            self.failures += 1;
+            self.last_error = Some(error);
+        }
+    }
+}
+
+/// A job started by `Client::schedule`, stopped when the handle is stopped or dropped
+pub struct ScheduledJob {
+    task: Task,
+    status: Arc<Mutex<JobStatus>>
+}
+
+impl ScheduledJob {
+    pub fn name(&self) -> String {
+        self.status.lock().unwrap().name.clone()
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn stop(self) {}
+
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+pub struct MaintenanceHandle(ScheduledJob);
+
+impl MaintenanceHandle {
+    pub fn stop(self) {}
+
+    pub fn is_running(&self) -> bool {
+        self.0.is_running()
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.0.status()
+    }
+}
+
+pub struct OutboxRelay(Task);
+
+impl OutboxRelay {
+    pub fn stop(self) {}
+
+    pub fn is_running(&self) -> bool {
+        !self.0.is_finished()
+    }
+}
+
+/// An index being built in the background, returned by `Collection::build_index`. Dropping the handle stops waiting for
+/// the build, though the database may still finish it.
+pub struct IndexBuild {
+    task: Task,
+    result: oneshot::Receiver<OResult<()>>,
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+    collection: String,
+    name: String
+}
+
+impl IndexBuild {
+    /// Name of the index being built
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// How far along the build is, if the driver can tell (MongoDB). `None` once it's finished.
+    pub async fn progress(&self) -> OResult<Option<IndexProgress>> {
+        self.driver.index_build_progress(self.collection.clone(), self.name.clone()).await
+    }
+
+    /// Waits for the build to finish
+    pub async fn wait(self) -> OResult<()> {
+        // The task only stops without a result if the client was closed
+        self.result.await.unwrap_or(Err(OrmoxError::Closed))
+    }
+}
+
+/// Which document of each group of duplicates `Collection::dedupe` keeps, by a stored field ordering the group (ie a
+/// timestamp). Documents with equal values of the field are in no particular order.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KeepStrategy {
+    /// The document with the lowest value of the field, ie the oldest by `created_at`
+    FirstBy(String),
+
+    /// The document with the highest value of the field, ie the newest by `updated_at`
+    LastBy(String)
+}
+
+impl KeepStrategy {
+    /// Field the groups of duplicates are ordered by
+    fn field(&self) -> &str {
+        match self {
+            Self::FirstBy(field) | Self::LastBy(field) => field
+        }
+    }
+}
+
+/// What `Collection::dedupe` removed
+#[derive(Clone, Debug, Default)]
+pub struct DedupeReport {
+    /// Number of groups of documents sharing their key values
+    pub groups: u64,
+
+    /// Documents removed, as they were stored
+    pub deleted: Vec<bson::Document>
+}
+
+#[derive(Clone)]
+pub struct Session {
+    client: Client,
+    id: Option<Uuid>
+}
+
+impl Session {
+    /// Driver session ID, or `None` if the driver doesn't support sessions
+    pub fn id(&self) -> Option<Uuid> {
+        self.id
+    }
+
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Returns a collection handle whose operations run in this session
+    pub fn collection<D: Document>(&self) -> Collection<D> {
+        let mut collection = self.client.collection::<D>();
+        collection.session = self.id;
+        collection.write_options.session = self.id;
+        collection
+    }
+
+    /// Records an event in the `_ormox_outbox` collection within this session, see `Collection::enqueue`
+    pub async fn enqueue(&self, topic: impl AsRef<str>, payload: impl Serialize) -> OResult<Uuid> {
+        self.enqueue_event(OutboxEvent::new(topic, payload)?).await
+    }
+
+    pub(crate) async fn enqueue_event(&self, event: OutboxEvent) -> OResult<Uuid> {
+        let options = WriteOptions { session: self.id, ..WriteOptions::default() };
+        self.client.driver().insert(OUTBOX_COLLECTION.to_string(), vec![event.to_document()], options).await?;
+        Ok(event.id)
+    }
+
+    fn transaction_id(&self) -> OResult<Uuid> {
+        self.client.require(DriverCapabilities::SESSIONS | DriverCapabilities::TRANSACTIONS)?;
+        self.id.ok_or(OrmoxError::unsupported("SESSIONS"))
+    }
+
+    /// Starts a multi-document transaction in this session. Requires a driver with `TRANSACTIONS`.
+    pub async fn start_transaction(&self) -> OResult<()> {
+        self.client.driver().start_transaction(self.transaction_id()?).await
+    }
+
+    pub async fn commit_transaction(&self) -> OResult<()> {
+        self.client.driver().commit_transaction(self.transaction_id()?).await
+    }
+
+    pub async fn abort_transaction(&self) -> OResult<()> {
+        self.client.driver().abort_transaction(self.transaction_id()?).await
+    }
+
+    pub async fn end(self) -> OResult<()> {
+        if let Some(id) = self.id {
+            self.client.driver().end_session(id).await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Unbounded so documents (including generic ones) can hold a handle to their own collection
+pub struct Collection<T> {
+    client: Client,
+    write_options: WriteOptions,
+    read_preference: Option<ReadPreference>,
+    session: Option<Uuid>,
+    decode_mode: Option<DecodeMode>,
+
+    /// Collection the documents are stored in instead of the type's, ie a partition
+    name: Option<String>,
+
+    /// Whether reads ignore the type's default scope & sort
+    unscoped: bool,
+
+    /// Named scopes the handle's reads apply, see `Client::register_scope`
+    scopes: Vec<String>,
+    _document: PhantomData<T>
+}
+
+impl<T> Clone for Collection<T> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            write_options: self.write_options.clone(),
+            read_preference: self.read_preference,
+            session: self.session,
+            decode_mode: self.decode_mode,
+            name: self.name.clone(),
+            unscoped: self.unscoped,
+            scopes: self.scopes.clone(),
+            _document: PhantomData
+        }
+    }
+}
+
+impl<T: Document> Debug for Collection<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Collection")
+            .field("name", &self.name())
+            .field("driver", &self.client.0.driver_name())
+            .finish()
+    }
+}
+
+/// State of `Collection::stream`
+struct Pages<'a, T: Document> {
+    collection: &'a Collection<T>,
+    query: Query,
+    options: Find,
+    batch_size: usize,
+
+    /// Offset of the next page
+    offset: usize,
+
+    /// Documents left to fetch under `Find::limit`
+    remaining: Option<usize>,
+    buffer: VecDeque<T>,
+
+    /// Page being fetched, with the number of documents asked for
+    next: Option<(usize, BoxFuture<'a, OResult<Vec<T>>>)>,
+    error: Option<OrmoxError>
+}
+
+impl<'a, T: Document> Pages<'a, T> {
+    /// Starts fetching the next page, unless the limit's been reached
+    fn fetch_next(&mut self) {
+        let limit = self.remaining.map_or(self.batch_size, |remaining| remaining.min(self.batch_size));
+        if limit == 0 {
+            return;
+        }
+        let options = Find { offset: Some(self.offset), limit: Some(limit), ..self.options.clone() };
+        self.offset += limit;
+        self.remaining = self.remaining.map(|remaining| remaining - limit);
+        let (collection, query) = (self.collection, self.query.clone());
+        self.next = Some((limit, Box::pin(async move { collection.find_prepared(query, options).await })));
+    }
+}
+
+impl<T: Document> Collection<T> {
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    pub fn driver(&self) -> Arc<dyn DatabaseDriver + Send + Sync> {
+        self.client().driver()
+    }
+
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            write_options: WriteOptions::default(),
+            read_preference: None,
+            session: None,
+            decode_mode: None,
+            name: None,
+            unscoped: false,
+            scopes: Vec::new(),
+            _document: PhantomData
+        }
+    }
+
+    /// Returns a handle to this collection whose writes use the given write concern
+    pub fn with_write_concern(&self, concern: WriteConcern) -> Self {
+        let mut collection = self.clone();
+        collection.write_options.write_concern = Some(concern);
+        collection
+    }
+
+    /// Returns a handle to this collection whose reads use the given read preference, unless overridden in `Find`
+    pub fn with_read_preference(&self, preference: ReadPreference) -> Self {
+        let mut collection = self.clone();
+        collection.read_preference = Some(preference);
+        collection
+    }
+
+    /// Returns a handle to this collection that loads documents with the given decode mode, whatever the type declares
+    pub fn with_decode_mode(&self, mode: DecodeMode) -> Self {
+        let mut collection = self.clone();
+        collection.decode_mode = Some(mode);
+        collection
+    }
+
+    /// Returns a handle to the documents of this type stored in the collection `name` instead of the type's own, ie a
+    /// partition or an archive. Documents loaded through it are attached to it, so they're saved back there.
+    pub fn with_name(&self, name: impl AsRef<str>) -> Self {
+        let mut collection = self.clone();
+        collection.name = Some(name.as_ref().to_string());
+        collection
+    }
+
+    /// Returns a handle to this collection whose reads also apply the document type's named scope `name` (see
+    /// `Client::register_scope`), ie `collection.scope("active").scope("adults").find(query, None)`. Reads through it
+    /// fail if the scope isn't registered. Writes don't apply scopes.
+    pub fn scope(&self, name: impl AsRef<str>) -> Self {
+        let mut collection = self.clone();
+        collection.scopes.push(name.as_ref().to_string());
+        collection
+    }
+
+    /// Returns a handle to this collection whose reads ignore the document type's default scope & sort, ie to load
+    /// archived documents. Named scopes still apply.
+    pub fn unscoped(&self) -> Self {
+        let mut collection = self.clone();
+        collection.unscoped = true;
+        collection
+    }
+
+    /// Spreads this collection's documents across one collection per `period` of the date in `field`, see `Partitioned`
+    pub fn partitioned(&self, field: impl AsRef<str>, period: PartitionPeriod) -> Partitioned<T> {
+        Partitioned::new(self.clone(), field, period)
+    }
+
+    /// Decode mode documents are loaded with: the handle's, the type's, or the client's
+    pub fn decode_mode(&self) -> DecodeMode {
+        self.decode_mode.or(T::decode_mode()).unwrap_or(self.client.options().decode_mode)
+    }
+
+    /// Handle to the collection of another document type on the same client, sharing this handle's write concern, read
+    /// preference & session
+    pub fn related<U: Document>(&self) -> Collection<U> {
+        Collection {
+            client: self.client.clone(),
+            write_options: self.write_options.clone(),
+            read_preference: self.read_preference,
+            session: self.session,
+            decode_mode: None,
+            name: None,
+            unscoped: false,
+            scopes: Vec::new(),
+            _document: PhantomData
+        }
+    }
+
+    /// Converts a query, encrypting the values it compares encrypted fields against, and writing the integers BSON
+    /// can't hold per `ClientOptions::numeric_policy`
+    fn query(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<Query> {
+        let query = query.try_into().map_err(|e| OrmoxError::Compatibility { error: e.to_string() })?;
+        let query = self.client.options().numeric_policy.query(query)?;
+        #[cfg(feature = "encryption")]
+        if !T::encrypted_fields().is_empty() {
+            let provider = self.client.options().key_provider.as_deref();
+            return Query::try_from(encryption::encrypt_query(provider, &T::encrypted_fields(), query.try_into()?)?);
+        }
+        Ok(query)
+    }
+
+    /// Converts the query of a read, adding the handle's named scopes and the document type's default scope unless the
+    /// handle is unscoped
+    fn read_query(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<Query> {
+        let mut query = query.try_into().map_err(|e| OrmoxError::Compatibility { error: e.to_string() })?;
+        query.merge(self.scopes()?);
+        self.query(query)
+    }
+
+    /// Named & default scopes the handle's reads apply, merged
+    fn scopes(&self) -> OResult<Query> {
+        let mut scopes = Query::new();
+        for name in &self.scopes {
+            scopes.merge(self.client.scope::<T>(name)?);
+        }
+        if let Some(scope) = T::default_scope().filter(|_| !self.unscoped) {
+            scopes.merge(scope);
+        }
+        Ok(scopes)
+    }
+
+    /// Unique indexes declared by the document type that the ORM enforces itself, as the driver doesn't support them.
+    /// Inserts, updates & upserts check the documents they'd write against these before writing, inside a transaction
+    /// if the driver supports them; without one, concurrent writes can still race past the check.
+    pub fn emulated_unique_indexes(&self) -> Vec<Index> {
+        if self.client.supports(DriverCapabilities::UNIQUE_INDEXES) {
+            return Vec::new();
+        }
+        T::indexes().into_iter().filter(|index| index.unique).collect()
+    }
+
+    /// Runs `write` in a new transaction when checking emulated unique indexes, so the check & the write it guards are
+    /// committed together. Handles already in a session run `write` as is.
+    async fn unique_transaction<R, F, Fut>(&self, write: F) -> OResult<R>
+    where
+        F: FnOnce(Collection<T>) -> Fut,
+        Fut: Future<Output = OResult<R>>
+    {
+        self.transaction_if(!self.emulated_unique_indexes().is_empty(), write).await
+    }
+
+    /// Runs `write` in a new transaction if `needed` and the driver supports transactions, committing it if `write`
+    /// succeeds. Handles already in a session run `write` as is.
+    pub(crate) async fn transaction_if<R, F, Fut>(&self, needed: bool, write: F) -> OResult<R>
+    where
+        F: FnOnce(Collection<T>) -> Fut,
+        Fut: Future<Output = OResult<R>>
+    {
+        let transactional = self.client.supports(DriverCapabilities::SESSIONS | DriverCapabilities::TRANSACTIONS);
+        if !transactional || self.session.is_some() || !needed {
+            return write(self.clone()).await;
+        }
+
+        let session = self.client.session().await?;
+        session.start_transaction().await?;
+        let mut collection = self.clone();
+        collection.session = session.id();
+        collection.write_options.session = session.id();
+
+        let result = match write(collection).await {
+            Ok(result) => session.commit_transaction().await.and(Ok(result)),
+            Err(e) => {
+                let _ = session.abort_transaction().await;
+                Err(e)
+            }
+        };
+        let _ = session.end().await;
+        result
+    }
+
+    /// Checks documents about to be written (in their stored form) against the emulated unique indexes: against each
+    /// other, and against stored documents other than themselves
+    async fn check_unique(&self, documents: &[bson::Document]) -> OResult<()> {
+        let id_field = T::id_field();
+        let ids = documents.iter().filter_map(|document| document.get(&id_field).cloned()).collect::<Vec<Bson>>();
+        for index in self.emulated_unique_indexes() {
+            let filter: Option<bson::Document> = index.partial_filter.clone().map(TryInto::try_into).transpose()?;
+            let mut seen = HashSet::new();
+            for document in documents {
+                // Documents outside a partial index aren't in it, so they can't conflict
+                if let Some(filter) = &filter {
+                    if !memory::matches(filter, document)? {
+                        continue;
+                    }
+                }
+
+                // Like MongoDB, missing fields are indexed as null
+                let values = index.fields.iter().map(|field| memory::get_path(document, field).cloned().unwrap_or(Bson::Null)).collect::<Vec<Bson>>();
+                let key = Bson::Array(values.clone()).to_string();
+                let duplicate = || OrmoxError::duplicate_key(self.name(), index.resolved_name(), key.clone());
+                if !seen.insert(key.clone()) {
+                    return Err(duplicate());
+                }
+
+                let mut conflicts = doc! {&id_field: {"$nin": ids.clone()}};
+                for (field, value) in index.fields.iter().zip(values) {
+                    conflicts.insert(field, doc! {"$eq": value});
+                }
+                if let Some(filter) = &filter {
+                    conflicts = doc! {"$and": [filter.clone(), conflicts]};
+                }
+                if self.driver().count(self.name(), Query::try_from(conflicts)?, self.find_options(None, Find::many())).await? > 0 {
+                    return Err(duplicate());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the documents an update (or upsert) would leave behind, if it can change an emulated unique index's fields
+    async fn check_unique_update(&self, query: &Query, update: &bson::Document, operations: &OperationCount, upsert: bool) -> OResult<()> {
+        let indexes = self.emulated_unique_indexes();
+        let overlaps = |key: &str| {
+            indexes.iter().flat_map(|index| &index.fields).any(|field| {
+                field == key || field.starts_with(&format!("{key}.")) || key.starts_with(&format!("{field}."))
+            })
+        };
+        let changes_index = update.iter().any(|(key, value)| match (key.starts_with('$'), value) {
+            (true, Bson::Document(fields)) => fields.keys().any(|field| overlaps(field)),
+            (true, _) => false,
+            // Replacement documents rewrite every field
+            (false, _) => true
+        });
+        if !changes_index {
+            return Ok(());
+        }
+
+        let options = self.find_options(None, match operations {
+            OperationCount::One => Find::one(),
+            OperationCount::Many => Find::many()
+        });
+        let mut documents = self.driver().find(self.name(), query.clone(), options).await?;
+        for document in documents.iter_mut() {
+            memory::apply_update(document, update)?;
+        }
+        if documents.is_empty() && upsert {
+            documents.push(memory::upserted(&query.clone().try_into()?, update)?);
+        }
+        self.check_unique(&documents).await
+    }
+
+    /// Serializes a document or update, writing the integers BSON can't hold per `ClientOptions::numeric_policy`
+    /// Options of the handle's writes, including its session
+    pub(crate) fn write_options(&self) -> WriteOptions {
+        self.write_options.clone()
+    }
+
+    pub(crate) fn serialize(&self, value: &(impl Serialize + ?Sized)) -> OResult<bson::Document> {
+        numeric::to_document(value, self.client.options().numeric_policy)
+    }
+
+    /// Encrypts the encrypted fields written by a serialized document or update
+    fn encode_write(&self, data: bson::Document) -> OResult<bson::Document> {
+        #[cfg(feature = "encryption")]
+        if !T::encrypted_fields().is_empty() {
+            return encryption::encrypt_update(self.client.options().key_provider.as_deref(), &T::encrypted_fields(), data);
+        }
+        Ok(data)
+    }
+
+    fn find_options(&self, options: Option<Find>, default: Find) -> Find {
+        let mut options = options.unwrap_or(default);
+        if options.read_preference.is_none() {
+            options.read_preference = self.read_preference;
+        }
+        if options.session.is_none() {
+            options.session = self.session;
+        }
+        options
+    }
+
+    /// Options of a read requested by the application: validated, sorted per the document type's default sort unless
+    /// set, with the client's default & maximum limits applied to reads returning many documents
+    fn read_options(&self, options: Option<Find>) -> OResult<Find> {
+        let mut options = self.sorted_options(options)?;
+        if let OperationCount::Many = options.operation {
+            let client = self.client.options();
+            options.limit = match (options.limit.or(client.default_limit), client.max_limit) {
+                (Some(limit), Some(max)) => Some(limit.min(max)),
+                (limit, max) => limit.or(max)
+            };
+        }
+        Ok(options)
+    }
+
+    /// Validated options, sorted per the document type's default sort unless set
+    fn sorted_options(&self, options: Option<Find>) -> OResult<Find> {
+        let mut options = self.find_options(options, Find::many());
+        options.validate()?;
+        if options.sort.is_none() && !self.unscoped {
+            options.sort = T::default_sort();
+        }
+        Ok(options)
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(T::collection_name)
+    }
+
+    pub async fn register_indices(&self) -> OResult<()> {
+        for index in T::indexes() {
+            self.create_index(index).await?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the document type's counter caches up to date from now on, as observers of the counted collections: counts
+    /// go up on `insert` and down on `delete` of the counted documents through this client. Other writes (ie upserts,
+    /// or changing a counted document's foreign key) aren't counted; `recount_counter_caches` rebuilds the counts.
+    pub fn register_counter_caches(&self) {
+        for cache in T::counter_caches() {
+            self.client.observe(CounterCacheObserver { cache, counting: self.name(), id_field: T::id_field() });
+        }
+    }
+
+    /// Recomputes the document type's counter caches from the counted collections, ie after registering them on a
+    /// collection with existing documents. Counts written concurrently may be lost; run it while the counted
+    /// collections aren't written to.
+    pub async fn recount_counter_caches(&self) -> OResult<()> {
+        for cache in T::counter_caches() {
+            let counts = cache.counts(&self.client).await?;
+            self.driver().update(self.name(), Query::new(), doc! {"$set": {&cache.field: 0_i64}}, OperationCount::Many, self.write_options.clone()).await?;
+            for (key, count) in counts {
+                let query = Query::try_from(doc! {T::id_field(): key})?;
+                let update = doc! {"$set": {&cache.field: count}};
+                self.driver().update(self.name(), query, update, OperationCount::One, self.write_options.clone()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn find(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> OResult<Vec<T>> {
+        let query = self.read_query(query)?;
+        self.find_prepared(query, self.read_options(options)?).await
+    }
 
-impl Client {
-    pub fn create<D: DatabaseDriver + Send + Sync + 'static>(driver: D) -> Arc<Self> {
-        Arc::new(Self(Arc::new(driver)))
+    /// Every document matching `query`, read like `find` but regardless of `ClientOptions::default_limit` &
+    /// `max_limit`, for relations, which have no options to page through them with
+    pub(crate) async fn find_every(&self, query: Query) -> OResult<Vec<T>> {
+        let query = self.read_query(query)?;
+        self.find_prepared(query, self.sorted_options(None)?).await
     }
 
-    pub fn create_global<D: DatabaseDriver + Send + Sync + 'static>(driver: D) -> Arc<Self> {
-        if let Ok(_) = ORMOX.set(Self::create(driver)) {
-            ORMOX.get().unwrap().clone()
-        } else {
-            panic!("Global instance already set!");
+    /// Finds documents with a query already converted by `Collection::query`
+    async fn find_prepared(&self, query: Query, options: Find) -> OResult<Vec<T>> {
+        if self.client.supports(DriverCapabilities::RAW_DOCUMENTS) {
+            let raw = self.driver().find_raw(self.name(), query, options).await?;
+            return self.parse_raw(raw);
+        }
+
+        let raw = self
+            .driver()
+            .find(self.name(), query, options)
+            .await?;
+
+        let mut results: Vec<T> = Vec::new();
+        for r in raw {
+            results.push(T::parse(r, Some(self.clone()))?);
         }
+        Ok(results)
     }
 
-    pub fn global() -> Option<Arc<Self>> {
-        ORMOX.get().cloned()
+    /// Streams the documents matching `query`, fetched in pages of `Find::batch_size` documents (or
+    /// `ClientOptions::stream_batch_size`). The next page is fetched while the current one is read, so at most two pages
+    /// are held at once. Documents are ordered by id unless `Find::sort` is set, so pages don't overlap.
+    ///
+    /// Pages are offset ranges, so documents inserted or deleted while streaming may be skipped or seen twice.
+    pub fn stream(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> impl Stream<Item = OResult<T>> + Send + '_ {
+        let mut options = Find { operation: OperationCount::Many, ..self.find_options(options, Find::many()) };
+        let invalid = options.validate().err();
+        if options.sort.is_none() && !self.unscoped {
+            options.sort = T::default_sort();
+        }
+        options.sort.get_or_insert_with(|| Sorting::asc(T::id_field()));
+        let mut pages = Pages {
+            collection: self,
+            query: Query::new(),
+            batch_size: options.batch_size.unwrap_or(self.client.options().stream_batch_size).max(1),
+            offset: options.offset.unwrap_or(0),
+            remaining: options.limit,
+            options,
+            buffer: VecDeque::new(),
+            next: None,
+            error: None
+        };
+        // Invalid options fail the stream before anything is fetched
+        match invalid.map_or_else(|| self.read_query(query), Err) {
+            Ok(query) => {
+                pages.query = query;
+                pages.fetch_next();
+            }
+            Err(e) => pages.error = Some(e)
+        }
+
+        stream::unfold(pages, |mut pages| async move {
+            loop {
+                if let Some(document) = pages.buffer.pop_front() {
+                    // Moves the prefetch along while documents are handed out
+                    if let Some((_, next)) = &mut pages.next {
+                        if let Poll::Ready(page) = futures::poll!(next) {
+                            let (limit, _) = pages.next.take().unwrap();
+                            pages.next = Some((limit, Box::pin(future::ready(page))));
+                        }
+                    }
+                    return Some((Ok(document), pages));
+                }
+                if let Some(e) = pages.error.take() {
+                    pages.next = None;
+                    return Some((Err(e), pages));
+                }
+
+                let (limit, next) = pages.next.take()?;
+                match next.await {
+                    Ok(documents) => {
+                        // A short page is the last one
+                        if documents.len() == limit {
+                            pages.fetch_next();
+                        }
+                        pages.buffer = documents.into();
+                    }
+                    Err(e) => pages.error = Some(e)
+                }
+            }
+        })
     }
 
-    pub fn driver(&self) -> Arc<dyn DatabaseDriver + Send + Sync> {
-        self.0.clone()
+    /// Streams changes to the collection as they happen, with their documents parsed. Store the token of the last event
+    /// handled to resume from it with `watch_from` after a restart.
+    pub async fn watch(&self) -> OResult<impl Stream<Item = OResult<ChangeEvent<T>>> + Send + '_> {
+        self.watch_after(None).await
     }
 
-    pub async fn collections(&self) -> OResult<Vec<String>> {
-        self.driver().collections().await
+    /// Streams the changes made after the event `token` belongs to, then the ones made from now on
+    pub async fn watch_from(&self, token: ResumeToken) -> OResult<impl Stream<Item = OResult<ChangeEvent<T>>> + Send + '_> {
+        self.watch_after(Some(token)).await
     }
 
-    pub fn collection<D: Document>(&self) -> Collection<D> {
-        Collection::<D>::new(self.clone())
+    async fn watch_after(&self, token: Option<ResumeToken>) -> OResult<impl Stream<Item = OResult<ChangeEvent<T>>> + Send + '_> {
+        self.client.require(DriverCapabilities::CHANGE_STREAMS)?;
+        let changes = self.driver().watch(self.name(), token).await?;
+        Ok(changes.map(move |change| ChangeEvent::parse(change?, self)))
     }
-}
 
-#[derive(Clone)]
-pub struct Collection<T: Document>(Client, PhantomData<T>);
+    /// Number of documents matching `query`
+    pub async fn count(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<u64> {
+        let query = self.read_query(query)?;
+        self.driver().count(self.name(), query, self.find_options(None, Find::many())).await
+    }
 
-impl<T: Document> Collection<T> {
-    pub fn client(&self) -> Client {
-        self.0.clone()
+    /// Finds a page of documents along with the total number of documents matching `query`, for paginated listings
+    pub async fn find_with_count(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> OResult<(Vec<T>, u64)> {
+        let query = self.read_query(query)?;
+        let (raw, total) = self.driver().find_with_count(self.name(), query, self.read_options(options)?).await?;
+        let mut results: Vec<T> = Vec::new();
+        for r in raw {
+            results.push(T::parse(r, Some(self.clone()))?);
+        }
+        Ok((results, total))
     }
 
-    pub fn driver(&self) -> Arc<dyn DatabaseDriver + Send + Sync> {
-        self.client().driver()
+    /// Finds documents of one variant of an enum document, adding the variant's discriminator to `query`
+    pub async fn find_variant<V: Variant<Of = T>>(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> OResult<Vec<T>> {
+        self.find(variant_query::<V>(query)?, options).await
     }
 
-    pub fn new(client: Client) -> Self {
-        Self(client, PhantomData)
+    /// Counts documents of one variant of an enum document matching `query`
+    pub async fn count_variant<V: Variant<Of = T>>(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<u64> {
+        self.count(variant_query::<V>(query)?).await
     }
 
-    pub fn name(&self) -> String {
-        T::collection_name().clone()
+    /// Finds documents, loading only the fields of the projection `P`
+    pub async fn find_as<P: Projection<Of = T>>(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> OResult<Vec<P>> {
+        let query = self.read_query(query)?;
+        let options = Find { projection: Some(P::projection()), ..self.read_options(options)? };
+        #[cfg(feature = "encryption")]
+        if !T::encrypted_fields().is_empty() {
+            let raw = self.driver().find(self.name(), query, options).await?;
+            return raw
+                .into_iter()
+                .map(|r| numeric::from_document::<P>(decrypt_fields::<T>(r, Some(self))?))
+                .collect();
+        }
+
+        if self.client.supports(DriverCapabilities::RAW_DOCUMENTS) {
+            let raw = self.driver().find_raw(self.name(), query, options).await?;
+            return raw
+                .iter()
+                .map(|r| bson::from_slice::<HumanReadable<Lenient<P>>>(r.as_bytes()).map(|HumanReadable(Lenient(p))| p).map_err(OrmoxError::deserialization))
+                .collect();
+        }
+
+        let raw = self.driver().find(self.name(), query, options).await?;
+        raw.into_iter().map(numeric::from_document::<P>).collect()
     }
 
-    pub async fn register_indices(&self) -> OResult<()> {
-        for index in T::indexes() {
-            self.create_index(index).await?;
+    fn parse_raw(&self, raw: Vec<RawDocumentBuf>) -> OResult<Vec<T>> {
+        raw.iter().map(|r| T::parse_raw(r, Some(self.clone()))).collect()
+    }
+
+    /// Finds documents, parsing each one separately: documents that fail to parse come back as their stored form (as
+    /// returned by the driver, before decryption or migration) with the error, so batch jobs can process the good ones
+    /// and quarantine the rest. The query itself failing is still an error.
+    pub async fn find_lossy(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> OResult<Vec<Result<T, (bson::Document, OrmoxError)>>> {
+        let (query, options) = (self.read_query(query)?, self.read_options(options)?);
+        let mut results = Vec::new();
+        if self.client.supports(DriverCapabilities::RAW_DOCUMENTS) {
+            for r in self.driver().find_raw(self.name(), query, options).await? {
+                results.push(match T::parse_raw(&r, Some(self.clone())) {
+                    Ok(parsed) => Ok(parsed),
+                    // Documents that aren't even valid BSON come back empty
+                    Err(e) => Err((bson::Document::try_from(r.as_ref()).unwrap_or_default(), e))
+                });
+            }
+            return Ok(results);
         }
-        Ok(())
+
+        for r in self.driver().find(self.name(), query, options).await? {
+            results.push(match T::parse(r.clone(), Some(self.clone())) {
+                Ok(parsed) => Ok(parsed),
+                Err(e) => Err((r, e))
+            });
+        }
+        Ok(results)
     }
 
-    pub async fn find(
+    /// Finds documents, returning load & parse errors alongside the documents that loaded successfully
+    pub async fn find_partial(
         &self,
         query: impl TryInto<Query, Error = impl Error>,
         options: Option<Find>,
-    ) -> OResult<Vec<T>> {
+    ) -> OResult<PartialResult<T>> {
         let raw = self
             .driver()
-            .find(self.name(), query.try_into().or_else(|e| Err(OrmoxError::Compatibility { error: e.to_string() }))?, options.unwrap_or(Find::many()))
+            .find_partial(self.name(), self.read_query(query)?, self.read_options(options)?)
             .await?;
 
-        let mut results: Vec<T> = Vec::new();
-        for r in raw {
-            results.push(T::parse(r, Some(self.clone()))?);
+        let mut results = PartialResult { items: Vec::new(), errors: raw.errors };
+        for r in raw.items {
+            match T::parse(r, Some(self.clone())) {
+                Ok(parsed) => results.items.push(parsed),
+                Err(e) => results.errors.push(e)
+            }
         }
         Ok(results)
     }
 
     pub async fn all(&self, options: Option<Find>) -> OResult<Vec<T>> {
+        let options = self.read_options(options)?;
+        let scopes = self.scopes()?;
+        if !scopes.is_empty() {
+            return self.find_prepared(self.query(scopes)?, options).await;
+        }
+        if self.client.supports(DriverCapabilities::RAW_DOCUMENTS) {
+            let raw = self.driver().all_raw(self.name(), options).await?;
+            return self.parse_raw(raw);
+        }
+
         let raw = self
             .driver()
-            .all(self.name(), options.unwrap_or(Find::many()))
+            .all(self.name(), options)
             .await?;
 
         let mut results: Vec<T> = Vec::new();
@@ -103,17 +1454,236 @@ impl<T: Document> Collection<T> {
         Ok(results)
     }
 
-    pub async fn insert(&self, docs: Vec<T>) -> OResult<Vec<Uuid>> {
+    /// Inserts documents, returning their ids
+    pub async fn insert(&self, mut docs: Vec<T>) -> OResult<Vec<T::Id>> {
+        for doc in docs.iter_mut() {
+            doc.fill_sequences(&self.client).await?;
+        }
+        if self.client.options().validate_writes {
+            docs.iter().try_for_each(Document::validate)?;
+        }
+        docs.iter_mut().try_for_each(Document::hash_fields)?;
+
+        if self.client.options().check_references {
+            self.check_references(&docs).await?;
+        }
+
+        let ids: Vec<T::Id> = docs.iter().map(|d| d.id()).collect();
         let mut serialized: Vec<bson::Document> = Vec::new();
         for d in docs {
-            serialized.push(bson::to_document(&d).or_else(|e| {
-                Err(OrmoxError::Serialization {
-                    error: e.to_string(),
-                })
-            })?);
+            serialized.push(self.encode_write(self.serialize(&d)?)?);
+        }
+
+        let batch_size = self.client.options().insert_batch_size.max(1);
+        let observers = self.client.observers(&self.name());
+        let inserted = if observers.is_empty() { Vec::new() } else { serialized.clone() };
+        self.unique_transaction(|collection| async move {
+            collection.check_unique(&serialized).await?;
+            let mut remaining = serialized.into_iter().peekable();
+            while remaining.peek().is_some() {
+                let batch: Vec<bson::Document> = remaining.by_ref().take(batch_size).collect();
+                collection.driver().insert(collection.name(), batch, collection.write_options.clone()).await?;
+            }
+            Ok(())
+        }).await?;
+        for observer in observers {
+            observer.inserted(&self.client, &inserted).await?;
+        }
+        Ok(ids)
+    }
+
+    /// Inserts or replaces documents matched by the values of `key_fields` (ie an external system's id), for idempotent
+    /// imports & syncs. Documents matching a stored one keep its id; later documents win if several share a key.
+    /// Batches are sent in one round trip by drivers supporting `BULK_UPSERTS` (ie MongoDB's bulk writes), and otherwise
+    /// written in a transaction where supported.
+    pub async fn upsert_many(&self, mut docs: Vec<T>, key_fields: &[&str]) -> OResult<()> {
+        if key_fields.is_empty() {
+            return Err(OrmoxError::compaibility("upsert_many needs at least one key field"));
+        }
+        for doc in docs.iter_mut() {
+            doc.fill_sequences(&self.client).await?;
+        }
+        if self.client.options().validate_writes {
+            docs.iter().try_for_each(Document::validate)?;
+        }
+        docs.iter_mut().try_for_each(Document::hash_fields)?;
+        if self.client.options().check_references {
+            self.check_references(&docs).await?;
+        }
+
+        // Keyed by the canonical form of the key values, keeping the last document of each key
+        let mut keyed: HashMap<String, usize> = HashMap::new();
+        let mut upserts: Vec<(bson::Document, bson::Document)> = Vec::new();
+        for doc in docs {
+            let document = self.serialize(&doc)?;
+            let key = key_values(&document, key_fields);
+            match keyed.get(&canonical(&key)) {
+                Some(index) => upserts[*index] = (key, document),
+                None => {
+                    keyed.insert(canonical(&key), upserts.len());
+                    upserts.push((key, document));
+                }
+            }
+        }
+
+        let batch_size = self.client.options().insert_batch_size.max(1);
+        let bulk = self.client.supports(DriverCapabilities::BULK_UPSERTS);
+        self.transaction_if(!bulk || !self.emulated_unique_indexes().is_empty(), |collection| async move {
+            let mut remaining = upserts.into_iter().peekable();
+            while remaining.peek().is_some() {
+                let batch: Vec<(bson::Document, bson::Document)> = remaining.by_ref().take(batch_size).collect();
+                collection.upsert_batch(batch, key_fields).await?;
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Upserts a batch of `(key, document)` pairs for `upsert_many`, giving documents matching a stored one its id so
+    /// references to it stay valid
+    async fn upsert_batch(&self, batch: Vec<(bson::Document, bson::Document)>, key_fields: &[&str]) -> OResult<()> {
+        let id_field = T::id_field();
+        let keys = batch.iter().map(|(key, _)| Bson::Document(key.clone())).collect::<Vec<_>>();
+        let projection = key_fields.iter().copied().chain([id_field.as_str()]).map(|field| (field.to_string(), Bson::Int32(1))).collect();
+        let options = Find { projection: Some(projection), ..self.find_options(None, Find::many()) };
+        let mut stored_ids: HashMap<String, Bson> = HashMap::new();
+        for stored in self.driver().find(self.name(), self.query(Query::try_from(doc! {"$or": keys})?)?, options).await? {
+            if let Some(id) = memory::get_path(&stored, &id_field) {
+                stored_ids.insert(canonical(&key_values(&stored, key_fields)), id.clone());
+            }
+        }
+
+        let mut upserts = Vec::with_capacity(batch.len());
+        let mut documents = Vec::with_capacity(batch.len());
+        for (key, mut document) in batch {
+            if let Some(id) = stored_ids.get(&canonical(&key)) {
+                memory::set_path(&mut document, &id_field, id.clone())?;
+            }
+            let document = self.encode_write(document)?;
+            upserts.push((self.query(Query::try_from(key)?)?, document.clone()));
+            documents.push(document);
+        }
+        self.check_unique(&documents).await?;
+        self.driver().upsert_many(self.name(), upserts, self.write_options.clone()).await
+    }
+
+    /// Imports the rows of a CSV file with headers as documents, coercing cells to their column's type and validating
+    /// each row. Rows are inserted in batches of `ClientOptions::insert_batch_size`, each in a transaction where
+    /// supported; the rows of a failed batch are retried one by one, so only the offending rows fail. Fails outright if
+    /// a mapped column is missing, the file can't be read or the database can't be queried, and otherwise reports each
+    /// row that failed.
+    #[cfg(feature = "csv")]
+    pub async fn import_csv<R: std::io::Read + Send>(&self, reader: R, mapping: CsvMapping) -> OResult<ImportReport> {
+        let mut reader = mapping.reader(reader);
+        let columns = mapping.resolve(reader.headers().map_err(OrmoxError::deserialization)?)?;
+        let batch_size = self.client.options().insert_batch_size.max(1);
+        let mut report = ImportReport::default();
+        let mut batch: Vec<(u64, T)> = Vec::new();
+        let mut records = reader.into_records();
+        loop {
+            let next = records.next();
+            match &next {
+                Some(Ok(record)) => {
+                    let line = record.position().map_or(0, |position| position.line());
+                    match self.import_row(&columns, record) {
+                        Ok(document) => batch.push((line, document)),
+                        Err(error) => report.failed.push(ImportFailure { line, error })
+                    }
+                },
+                Some(Err(e)) if e.is_io_error() => return Err(OrmoxError::deserialization(e)),
+                Some(Err(e)) => {
+                    let line = e.position().map_or(0, |position| position.line());
+                    report.failed.push(ImportFailure { line, error: OrmoxError::deserialization(e) });
+                },
+                None => {}
+            }
+
+            if batch.len() >= batch_size || (next.is_none() && !batch.is_empty()) {
+                self.import_batch(std::mem::take(&mut batch), &mut report).await?;
+            }
+            if next.is_none() {
+                return Ok(report);
+            }
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    fn import_row(&self, columns: &[Option<(String, ColumnType)>], record: &csv::StringRecord) -> OResult<T> {
+        let document = row_document(&self.name(), columns, record)?;
+        let document: T = bson::from_document(document).map_err(OrmoxError::deserialization)?;
+        if self.client.options().validate_writes {
+            document.validate()?;
+        }
+        Ok(document)
+    }
+
+    /// Inserts a batch of imported rows, in a transaction where supported. Without one, a failed insert may have stored
+    /// some of the rows (ie those before a duplicate key): the rows stored since the batch started count as imported
+    /// rather than being retried, which would fail them as duplicates of themselves.
+    #[cfg(feature = "csv")]
+    async fn import_batch(&self, batch: Vec<(u64, T)>, report: &mut ImportReport) -> OResult<()> {
+        let documents = batch.iter().map(|(_, document)| document.clone()).collect::<Vec<_>>();
+        if batch.len() == 1 {
+            match self.insert(documents).await {
+                Ok(_) => report.imported += 1,
+                Err(error) => report.failed.push(ImportFailure { line: batch[0].0, error })
+            }
+            return Ok(());
+        }
+
+        let atomic = self.session.is_none() && self.client.supports(DriverCapabilities::SESSIONS | DriverCapabilities::TRANSACTIONS);
+        let id_field = T::id_field();
+        let ids = batch.iter().map(|(_, document)| {
+            self.serialize(document).ok().and_then(|stored| memory::get_path(&stored, &id_field).cloned())
+        }).collect::<Vec<_>>();
+        let stored_before = if atomic { Vec::new() } else { self.stored_ids(&ids).await? };
+
+        if self.transaction_if(true, |collection| async move { collection.insert(documents).await }).await.is_ok() {
+            report.imported += batch.len() as u64;
+            return Ok(());
         }
+        let mut stored = if atomic { Vec::new() } else { self.stored_ids(&ids).await? };
+        stored.retain(|id| !stored_before.contains(id));
+        for ((line, document), id) in batch.into_iter().zip(ids) {
+            // Each stored id counts for the first row with it; later rows with the same id are duplicates
+            if let Some(position) = id.and_then(|id| stored.iter().position(|stored| *stored == id)) {
+                stored.swap_remove(position);
+                report.imported += 1;
+                continue;
+            }
+            match self.insert(vec![document]).await {
+                Ok(_) => report.imported += 1,
+                Err(error) => report.failed.push(ImportFailure { line, error })
+            }
+        }
+        Ok(())
+    }
+
+    /// Which of `ids` (in their stored form) are stored
+    #[cfg(feature = "csv")]
+    async fn stored_ids(&self, ids: &[Option<Bson>]) -> OResult<Vec<Bson>> {
+        let id_field = T::id_field();
+        let query = Query::try_from(doc! {&id_field: {"$in": ids.iter().flatten().cloned().collect::<Vec<_>>()}})?;
+        let options = Find { projection: Some(doc! {&id_field: 1}), ..self.find_options(None, Find::many()) };
+        let stored = self.driver().find(self.name(), query, options).await?;
+        Ok(stored.iter().filter_map(|document| memory::get_path(document, &id_field).cloned()).collect())
+    }
+
+    /// Records an event in the `_ormox_outbox` collection for the outbox relay, returning its id. Enqueue through a
+    /// `Session` collection inside a transaction so the event is only recorded if the transaction's writes commit.
+    pub async fn enqueue(&self, topic: impl AsRef<str>, payload: impl Serialize) -> OResult<Uuid> {
+        let event = OutboxEvent { source: Some(self.name()), ..OutboxEvent::new(topic, payload)? };
+        self.driver().insert(OUTBOX_COLLECTION.to_string(), vec![event.to_document()], self.write_options.clone()).await?;
+        Ok(event.id)
+    }
 
-        self.driver().insert(self.name(), serialized).await
+    /// Inserts documents from a stream, sending them to the driver in batches of `insert_batch_size`
+    pub async fn insert_stream(&self, docs: impl Stream<Item = T>) -> OResult<Vec<T::Id>> {
+        let mut batches = pin!(docs.chunks(self.client.options().insert_batch_size.max(1)));
+        let mut ids: Vec<T::Id> = Vec::new();
+        while let Some(batch) = batches.next().await {
+            ids.extend(self.insert(batch).await?);
+        }
+        Ok(ids)
     }
 
     pub async fn update(
@@ -122,18 +1692,12 @@ impl<T: Document> Collection<T> {
         update: impl Serialize,
         operations: OperationCount,
     ) -> OResult<()> {
-        self.driver()
-            .update(
-                self.name(),
-                query.try_into().or_else(|e| Err(OrmoxError::Compatibility { error: e.to_string() }))?,
-                bson::to_document(&update).or_else(|e| {
-                    Err(OrmoxError::Deserialization {
-                        error: e.to_string(),
-                    })
-                })?,
-                operations
-            )
-            .await
+        let query = self.query(query)?;
+        let update = self.encode_write(self.serialize(&update)?)?;
+        self.unique_transaction(|collection| async move {
+            collection.check_unique_update(&query, &update, &operations, false).await?;
+            collection.driver().update(collection.name(), query, update, operations, collection.write_options.clone()).await
+        }).await
     }
 
     pub async fn upsert(
@@ -142,28 +1706,45 @@ impl<T: Document> Collection<T> {
         update: impl Serialize,
         operations: OperationCount,
     ) -> OResult<()> {
-        self.driver()
-            .upsert(
-                self.name(),
-                query.try_into().or_else(|e| Err(OrmoxError::Compatibility { error: e.to_string() }))?,
-                bson::to_document(&update).or_else(|e| {
-                    Err(OrmoxError::Deserialization {
-                        error: e.to_string(),
-                    })
-                })?,
-                operations
-            )
-            .await
+        let query = self.query(query)?;
+        let update = self.encode_write(self.serialize(&update)?)?;
+        self.unique_transaction(|collection| async move {
+            collection.check_unique_update(&query, &update, &operations, true).await?;
+            collection.driver().upsert(collection.name(), query, update, operations, collection.write_options.clone()).await
+        }).await
     }
 
+    /// Deletes the documents matching `query`. With observers registered for the collection (ie counter caches), the
+    /// matching documents are found first and then deleted by ID, so observers see exactly the deleted documents.
+    /// Finding & deleting isn't atomic: a concurrent delete of the same documents runs their observers twice, ie
+    /// decrementing a counter cache twice.
     pub async fn delete(
         &self,
         query: impl TryInto<Query, Error = impl Error>,
         operations: OperationCount,
     ) -> OResult<()> {
-        self.driver()
-            .delete(self.name(), query.try_into().or_else(|e| Err(OrmoxError::Compatibility { error: e.to_string() }))?, operations)
-            .await
+        let query = self.query(query)?;
+        let observers = self.client.observers(&self.name());
+        if observers.is_empty() {
+            return self.driver().delete(self.name(), query, operations, self.write_options.clone()).await;
+        }
+
+        // Deleting the matching documents by ID, so observers get exactly the deleted ones
+        let options = match operations {
+            OperationCount::One => self.find_options(None, Find::one()),
+            OperationCount::Many => self.find_options(None, Find::many())
+        };
+        let deleted = self.driver().find(self.name(), query, options).await?;
+        if deleted.is_empty() {
+            return Ok(());
+        }
+        let ids = deleted.iter().map(|document| memory::get_path(document, &T::id_field()).cloned().unwrap_or(Bson::Null)).collect::<Vec<_>>();
+        let query = Query::try_from(doc! {T::id_field(): {"$in": ids}})?;
+        self.driver().delete(self.name(), query, OperationCount::Many, self.write_options.clone()).await?;
+        for observer in observers {
+            observer.deleted(&self.client, &deleted).await?;
+        }
+        Ok(())
     }
 
     pub async fn find_one(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<T> {
@@ -171,9 +1752,7 @@ impl<T: Document> Collection<T> {
         if let Some(result) = self.find(_query.clone(), Some(Find::one())).await?.get(0) {
             Ok(result.clone())
         } else {
-            Err(OrmoxError::NotFound {
-                query: TryInto::<bson::Document>::try_into(_query).and_then(|d| Ok(d.to_string())).or::<()>(Ok(String::from("Unparseable query"))).unwrap(),
-            })
+            Err(OrmoxError::NotFound { query: redact_query::<T>(_query) })
         }
     }
 
@@ -181,24 +1760,286 @@ impl<T: Document> Collection<T> {
         self.find(query, Some(Find::many())).await
     }
 
-    pub async fn get(&self, id: impl AsRef<str>) -> OResult<T> {
-        self.find_one(
-            Query::new()
-                .field(T::id_field(), id.as_ref().to_string())
-                .build(),
-        )
-        .await
+    /// First document matching `query` when sorted ascending by `sort_field`, if any
+    pub async fn first(&self, query: impl TryInto<Query, Error = impl Error>, sort_field: impl AsRef<str>) -> OResult<Option<T>> {
+        self.first_sorted(query, Sorting::asc(sort_field)).await
     }
 
-    pub async fn save(&self, document: T) -> OResult<()> {
-        self.upsert(
-            Query::new()
-                .field(T::id_field(), document.id().to_string())
-                .build(),
-            document,
-            OperationCount::One
-        )
-        .await
+    /// Last document matching `query` when sorted ascending by `sort_field` (ie the latest, for timestamps), if any
+    pub async fn last(&self, query: impl TryInto<Query, Error = impl Error>, sort_field: impl AsRef<str>) -> OResult<Option<T>> {
+        self.first_sorted(query, Sorting::desc(sort_field)).await
+    }
+
+    async fn first_sorted(&self, query: impl TryInto<Query, Error = impl Error>, sort: Sorting) -> OResult<Option<T>> {
+        let options = Find { sort: Some(sort), limit: Some(1), ..Find::many() };
+        Ok(self.find(query, Some(options)).await?.into_iter().next())
+    }
+
+    pub async fn get(&self, id: impl Serialize) -> OResult<T> {
+        self.find_one(id_query::<T>(&id)?).await
+    }
+
+    /// Saves a document, inserting it if it doesn't exist. Documents that track changes and were loaded from the database
+    /// only write the fields changed since loading, and don't recreate the document if it was deleted in the meantime.
+    pub async fn save(&self, mut document: T) -> OResult<()> {
+        self.prepare_save(&mut document).await?;
+        if let Some(loaded) = document.loaded_state() {
+            let current = self.serialize(&document)?;
+            let update = Update::diff(loaded, &current);
+            if update.is_empty() {
+                return Ok(());
+            }
+            return self.update_by_id(document.id(), update).await;
+        }
+
+        self.upsert(id_query::<T>(&document.id())?, document, OperationCount::One).await
+    }
+
+    /// Fills sequences, validates, hashes & checks the references of a document about to be saved
+    async fn prepare_save(&self, document: &mut T) -> OResult<()> {
+        document.fill_sequences(&self.client).await?;
+        if self.client.options().validate_writes {
+            document.validate()?;
+        }
+        document.hash_fields()?;
+        if self.client.options().check_references {
+            self.check_references(std::slice::from_ref(document)).await?;
+        }
+        Ok(())
+    }
+
+    /// Saves a document loaded with change tracking, merging in changes written by others since it was loaded. The
+    /// changed fields are only written if they still hold their loaded values; otherwise the stored document is
+    /// refetched, merged field-wise with `merge` (resolving fields changed on both sides by `policy`) and the save
+    /// retried, up to `MERGE_ATTEMPTS` times. Documents without a loaded state are saved like `save`.
+    /// Returns the document as saved, including the merged changes.
+    pub async fn save_merging(&self, mut document: T, policy: MergePolicy) -> OResult<T> {
+        self.prepare_save(&mut document).await?;
+        let Some(mut base) = document.loaded_state().cloned() else {
+            self.upsert(id_query::<T>(&document.id())?, document.clone(), OperationCount::One).await?;
+            return Ok(document);
+        };
+
+        for _ in 0..MERGE_ATTEMPTS {
+            let ours = self.serialize(&document)?;
+            let changes = diff(&base, &ours);
+            if changes.is_empty() {
+                return Ok(document);
+            }
+
+            // Only write if nobody changed the same fields in the meantime
+            let mut guard: bson::Document = id_query::<T>(&document.id())?.try_into()?;
+            for (path, _) in changes.iter() {
+                let condition = match memory::get_path(&base, path) {
+                    Some(value) => doc! {"$eq": value.clone()},
+                    None => doc! {"$exists": false}
+                };
+                guard.insert(path, condition);
+            }
+            let query = self.query(Query::try_from(guard)?)?;
+            let update = self.encode_write(bson::to_document(&changes.to_update()).map_err(OrmoxError::serialization)?)?;
+            let saved = self.unique_transaction(|collection| async move {
+                collection.check_unique_update(&query, &update, &OperationCount::One, false).await?;
+                let driver = collection.driver();
+                match driver.find_one_and_update(collection.name(), query.clone(), update.clone(), false, collection.write_options.clone()).await {
+                    Ok(found) => Ok(found.is_some()),
+                    Err(OrmoxError::Unimplemented) => {
+                        if driver.count(collection.name(), query.clone(), collection.find_options(None, Find::one())).await? == 0 {
+                            return Ok(false);
+                        }
+                        driver.update(collection.name(), query, update, OperationCount::One, collection.write_options.clone()).await?;
+                        Ok(true)
+                    },
+                    Err(e) => Err(e)
+                }
+            }).await?;
+            if saved {
+                document.set_loaded_state(Some(ours));
+                return Ok(document);
+            }
+
+            let id = id_query::<T>(&document.id())?;
+            let Some(current) = self.unscoped().find(id.clone(), Some(Find::one())).await?.into_iter().next() else {
+                return Err(OrmoxError::not_found(redact_query::<T>(id)));
+            };
+            let theirs = self.serialize(&current)?;
+            let mut merged: T = numeric::from_document(merge(&base, &ours, &theirs, policy)?)?;
+            if let Some(collection) = document.attached_collection() {
+                merged.attach_collection(collection);
+            }
+            base = current.loaded_state().cloned().unwrap_or(theirs);
+            merged.set_loaded_state(Some(base.clone()));
+            document = merged;
+        }
+
+        let ours = self.serialize(&document)?;
+        Err(OrmoxError::merge_conflict(diff(&base, &ours).into_iter().map(|(path, _)| path).collect()))
+    }
+
+    /// Applies `update` (usually an `Update`) to the document with the given id
+    pub async fn update_by_id(&self, id: impl Serialize, update: impl Serialize) -> OResult<()> {
+        self.update(id_query::<T>(&id)?, update, OperationCount::One).await
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to the document with the given id: nulls remove fields, objects merge
+    /// recursively and anything else replaces the field. Patched objects are merged via dotted paths, so replacing a
+    /// non-object field with an object needs a full update instead.
+    pub async fn patch(&self, id: impl Serialize, patch: Value) -> OResult<()> {
+        let Value::Object(fields) = patch else {
+            return Err(OrmoxError::compaibility("Merge patches must be JSON objects"));
+        };
+
+        let update = merge_patch(Update::new(), "", fields)?;
+        if update.is_empty() {
+            return Ok(());
+        }
+        self.update_by_id(id, update).await
+    }
+
+    /// Re-encrypts encrypted fields written with an older key, or stored before the field was encrypted, with the key
+    /// provider's current key. Returns the number of documents rewritten. Run after rotating keys, as deterministic
+    /// fields are queried with the current key only.
+    #[cfg(feature = "encryption")]
+    pub async fn rotate_keys(&self) -> OResult<u64> {
+        let fields = T::encrypted_fields();
+        let Some(provider) = self.client.options().key_provider.clone() else {
+            return Err(OrmoxError::encryption("No key provider is configured"));
+        };
+
+        let current = provider.current_key();
+        let mut rotated = 0;
+        for stored in self.driver().all(self.name(), self.find_options(None, Find::many())).await? {
+            let mut changes = bson::Document::new();
+            for (path, mode) in &fields {
+                let Some(value) = memory::get_path(&stored, path).filter(|v| **v != Bson::Null) else {
+                    continue;
+                };
+                if encryption::key_id(value).as_deref() == Some(current.as_str()) {
+                    continue;
+                }
+                let plaintext = encryption::decrypt_value(provider.as_ref(), path, value)?;
+                changes.insert(*path, encryption::encrypt_value(provider.as_ref(), *mode, path, &plaintext)?);
+            }
+
+            if !changes.is_empty() {
+                let id = memory::get_path(&stored, &T::id_field()).cloned().unwrap_or(Bson::Null);
+                self.driver().update(self.name(), id_query::<T>(&id)?, doc! {"$set": changes}, OperationCount::One, self.write_options.clone()).await?;
+                rotated += 1;
+            }
+        }
+        Ok(rotated)
+    }
+
+    /// Atomically adds `delta` to the numeric field `field` of the document with the given id; a missing field starts at
+    /// 0. Returns the new value if the driver supports `find_one_and_update`, and `None` if it only supports plain updates.
+    pub async fn increment<N: Serialize + DeserializeOwned>(&self, id: impl Serialize, field: impl AsRef<str>, delta: N) -> OResult<Option<N>> {
+        let query = id_query::<T>(&id)?;
+        let delta = numeric::to_bson(&delta, self.client.options().numeric_policy)?;
+        let update = self.encode_write(bson::to_document(&Update::new().inc(field.as_ref(), delta)).map_err(OrmoxError::serialization)?)?;
+        match self.driver().find_one_and_update(self.name(), query.clone(), update.clone(), false, self.write_options.clone()).await {
+            Ok(Some(document)) => {
+                let value = memory::get_path(&document, field.as_ref()).cloned().unwrap_or(Bson::Null);
+                bson::from_bson(value).map(Some).map_err(OrmoxError::deserialization)
+            },
+            Ok(None) => Err(OrmoxError::not_found(redact_query::<T>(query))),
+            Err(OrmoxError::Unimplemented) => {
+                self.driver().update(self.name(), query, update, OperationCount::One, self.write_options.clone()).await?;
+                Ok(None)
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Atomically subtracts `delta` from the numeric field `field`, like `increment`
+    pub async fn decrement<N: Serialize + DeserializeOwned + Neg<Output = N>>(
+        &self,
+        id: impl Serialize,
+        field: impl AsRef<str>,
+        delta: N
+    ) -> OResult<Option<N>> {
+        self.increment(id, field, -delta).await
+    }
+
+    /// Checks that every `Ref` in `docs` points to an existing document, with one lookup per referenced collection.
+    /// Fails with `OrmoxError::BrokenReference` for the first dangling reference.
+    pub async fn check_references(&self, docs: &[T]) -> OResult<()> {
+        let mut by_collection: BTreeMap<String, (String, Vec<Reference>)> = BTreeMap::new();
+        for doc in docs {
+            for reference in doc.references()? {
+                by_collection.entry(reference.collection.clone()).or_insert_with(|| (reference.id_field.clone(), Vec::new())).1.push(reference);
+            }
+        }
+
+        for (collection, (id_field, references)) in by_collection {
+            let mut ids: Vec<Value> = Vec::new();
+            for reference in &references {
+                if !ids.contains(&reference.id) {
+                    ids.push(reference.id.clone());
+                }
+            }
+
+            let options = Find { projection: Some(doc! {id_field.clone(): 1}), ..self.find_options(None, Find::many()) };
+            let query = Query::new().subquery(&id_field, Query::new().in_array(ids));
+            let found: Vec<Value> = self
+                .driver()
+                .find(collection, query, options)
+                .await?
+                .iter()
+                .filter_map(|d| memory::get_path(d, &id_field))
+                .map(|id| id.clone().into_relaxed_extjson())
+                .collect();
+            if let Some(missing) = references.iter().find(|r| !found.contains(&r.id)) {
+                let id = match &missing.id {
+                    Value::String(id) => id.clone(),
+                    id => id.to_string()
+                };
+                return Err(OrmoxError::broken_reference(&missing.field, id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Links `document` to `other` in the junction collection of their many-to-many relation. Linking an already linked
+    /// pair does nothing.
+    pub async fn link<U: Document>(&self, document: &T, other: &U) -> OResult<()>
+    where
+        T: ManyToMany<U>
+    {
+        let (source, target) = junction_pair::<T, U>(document, other)?;
+        let mut link = bson::Document::new();
+        link.insert(T::source_key(), bson::to_bson(&source).map_err(OrmoxError::serialization)?);
+        link.insert(T::target_key(), bson::to_bson(&target).map_err(OrmoxError::serialization)?);
+        let query = Query::new().field(T::source_key(), source).field(T::target_key(), target);
+        self.driver().upsert(T::junction(), query, link, OperationCount::One, self.write_options.clone()).await
+    }
+
+    /// Removes the link between `document` and `other` from the junction collection of their many-to-many relation
+    pub async fn unlink<U: Document>(&self, document: &T, other: &U) -> OResult<()>
+    where
+        T: ManyToMany<U>
+    {
+        let (source, target) = junction_pair::<T, U>(document, other)?;
+        let query = Query::new().field(T::source_key(), source).field(T::target_key(), target);
+        self.driver().delete(T::junction(), query, OperationCount::Many, self.write_options.clone()).await
+    }
+
+    /// Every `U` linked to `document` through their many-to-many relation, fetched in a single query regardless of the
+    /// client's default & maximum limits
+    pub async fn linked<U: Document>(&self, document: &T) -> OResult<Vec<U>>
+    where
+        T: ManyToMany<U>
+    {
+        let source = serde_json::to_value(document.id()).map_err(OrmoxError::serialization)?;
+        let links = self.driver().find(T::junction(), Query::new().field(T::source_key(), source), self.find_options(None, Find::many())).await?;
+        let targets: Vec<Value> = links.into_iter().filter_map(|mut link| link.remove(T::target_key())).map(Bson::into_relaxed_extjson).collect();
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.related::<U>().find_every(Query::new().subquery(U::id_field(), Query::new().in_array(targets))).await
+    }
+
+    pub async fn delete_by_id(&self, id: impl Serialize) -> OResult<()> {
+        self.delete_one(id_query::<T>(&id)?).await
     }
 
     pub async fn delete_one(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<()> {
@@ -209,11 +2050,316 @@ impl<T: Document> Collection<T> {
         self.delete(query, OperationCount::Many).await
     }
 
-    pub async fn create_index(&self, index: Index) -> OResult<()> {
+    /// Creates an index. Unique indexes declared by the document type are still created on drivers without unique
+    /// indexes, as regular indexes (if the driver has those), with uniqueness enforced by the ORM (see
+    /// `emulated_unique_indexes`).
+    pub async fn create_index(&self, mut index: Index) -> OResult<()> {
+        let name = index.resolved_name();
+        if self.emulated_unique_indexes().iter().any(|emulated| emulated.resolved_name() == name) {
+            index.unique = false;
+            if !self.client().supports(index.required_capabilities()) {
+                return Ok(());
+            }
+        }
+        self.client().require(index.required_capabilities())?;
         self.driver().create_index(self.name(), index).await
     }
 
+    /// Creates an index on a background task, built online (see `Index::background`) so writes continue while it's built.
+    /// PoloDB builds it in one pass off the async runtime. If there's no runtime, see `Client::runtime`.
+    pub fn build_index(&self, mut index: Index) -> OResult<IndexBuild> {
+        self.client().require(index.required_capabilities())?;
+        let name = index.resolved_name();
+        index.name = Some(name.clone());
+        index.background = true;
+
+        let (driver, collection, client) = (self.driver(), self.name(), self.client());
+        let (sender, result) = oneshot::channel();
+        let task = Task::spawn(client.background_runtime().as_ref(), {
+            let (driver, collection) = (driver.clone(), collection.clone());
+            async move {
+                let _ = sender.send(driver.create_index(collection, index).await);
+            }
+        });
+        client.track(&task);
+        Ok(IndexBuild { task, result, driver, collection, name })
+    }
+
     pub async fn drop_index(&self, index_name: impl AsRef<str>) -> OResult<()> {
+        self.client().require(DriverCapabilities::INDEXES)?;
         self.driver().drop_index(self.name(), index_name.as_ref().to_string()).await
     }
+
+    pub async fn stats(&self) -> OResult<CollectionStats> {
+        self.client().require(DriverCapabilities::COLLECTION_STATS)?;
+        self.driver().collection_stats(self.name()).await
+    }
+
+    /// How often each of the collection's indexes is used, to find unused ones worth dropping
+    pub async fn index_stats(&self) -> OResult<Vec<IndexStats>> {
+        self.client().require(DriverCapabilities::INDEX_STATS)?;
+        self.driver().index_stats(self.name()).await
+    }
+
+    /// Explains how the driver would execute a query. Drivers without native plan output fall back to an estimate based on the document's declared indexes.
+    pub async fn explain(
+        &self,
+        query: impl TryInto<Query, Error = impl Error>,
+        options: Option<Find>,
+    ) -> OResult<QueryPlan> {
+        let query = self.query(query)?;
+        if self.client().supports(DriverCapabilities::EXPLAIN) {
+            self.driver().explain(self.name(), query, self.find_options(options, Find::many())).await
+        } else {
+            Ok(QueryPlan::estimate(&query.try_into()?, &T::indexes()))
+        }
+    }
+
+    /// Scans the whole collection in chunks of `scan_chunk_size` documents ordered by id, running up to `partitions` chunks
+    /// concurrently. Partition `p` handles chunks `p`, `p + partitions`, `p + 2 * partitions`, etc. Stops at the first error.
+    ///
+    /// Chunks are offset ranges, so documents inserted or deleted during the scan may be skipped or seen twice.
+    pub async fn par_scan<F, Fut>(&self, partitions: usize, f: F) -> OResult<()>
+    where
+        F: Fn(Vec<T>) -> Fut,
+        Fut: Future<Output = OResult<()>>,
+    {
+        let partitions = partitions.max(1);
+        let chunk_size = self.client.options().scan_chunk_size.max(1);
+        let query = self.read_query(Query::new())?;
+        try_join_all((0..partitions).map(|partition| {
+            let (f, query) = (&f, &query);
+            async move {
+                let mut chunk = partition;
+                loop {
+                    // Not a read requested by the application: `ClientOptions::max_limit` would cut chunks short, ending
+                    // the partition early
+                    let options = Find {
+                        offset: Some(chunk * chunk_size),
+                        limit: Some(chunk_size),
+                        sort: Some(Sorting::asc(T::id_field())),
+                        ..self.find_options(None, Find::many())
+                    };
+                    let documents = self.find_prepared(query.clone(), options).await?;
+                    let last = documents.len() < chunk_size;
+                    if !documents.is_empty() {
+                        f(documents).await?;
+                    }
+                    if last {
+                        return Ok(());
+                    }
+                    chunk += partitions;
+                }
+            }
+        }))
+        .await
+        .and(Ok(()))
+    }
+
+    /// Loads `field` from every document matching `query`, for aggregation fallbacks
+    async fn field_values(&self, field: &str, query: Query) -> OResult<Vec<Bson>> {
+        let options = Find { projection: Some(doc! {field: 1}), ..self.find_options(None, Find::many()) };
+        let documents = self.driver().find(self.name(), query, options).await?;
+        Ok(documents.iter().map(|d| memory::get_path(d, field).cloned().unwrap_or(Bson::Null)).collect())
+    }
+
+    /// Applies a single `$group` accumulator (ie `$sum`) to `field` across documents matching `query`
+    async fn accumulate(&self, operator: &str, field: &str, query: Query) -> OResult<Option<Bson>> {
+        if self.client.supports(DriverCapabilities::AGGREGATION) {
+            let pipeline = vec![
+                doc! {"$match": TryInto::<bson::Document>::try_into(query)?},
+                doc! {"$group": {"_id": Bson::Null, "value": {operator: format!("${field}")}}},
+            ];
+            let results = self.driver().aggregate(self.name(), pipeline, self.find_options(None, Find::many())).await?;
+            return Ok(results.first().and_then(|r| r.get("value")).filter(|v| *v != &Bson::Null).cloned());
+        }
+
+        let values = self.field_values(field, query).await?;
+        let numbers: Vec<f64> = values.iter().filter_map(bson_f64).collect();
+        Ok(match operator {
+            "$sum" => Some(Bson::Double(numbers.iter().sum())),
+            "$avg" if numbers.is_empty() => None,
+            "$avg" => Some(Bson::Double(numbers.iter().sum::<f64>() / numbers.len() as f64)),
+            "$min" => extreme(values, Ordering::Less),
+            "$max" => extreme(values, Ordering::Greater),
+            _ => return Err(OrmoxError::Unimplemented)
+        })
+    }
+
+    /// Sum of numeric values of `field` across documents matching `query`. Non-numeric values are ignored.
+    pub async fn sum(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<f64> {
+        let query = self.read_query(query)?;
+        Ok(self.accumulate("$sum", field.as_ref(), query).await?.as_ref().and_then(bson_f64).unwrap_or(0.0))
+    }
+
+    /// Average of numeric values of `field` across documents matching `query`, or `None` if there are none
+    pub async fn avg(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<Option<f64>> {
+        let query = self.read_query(query)?;
+        Ok(self.accumulate("$avg", field.as_ref(), query).await?.as_ref().and_then(bson_f64))
+    }
+
+    /// Smallest value of `field` across documents matching `query`, ignoring missing & null values
+    pub async fn min(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<Option<Value>> {
+        let query = self.read_query(query)?;
+        self.accumulate("$min", field.as_ref(), query).await?.map(|v| json_value(&v)).transpose()
+    }
+
+    /// Largest value of `field` across documents matching `query`, ignoring missing & null values
+    pub async fn max(&self, field: impl AsRef<str>, query: impl TryInto<Query, Error = impl Error>) -> OResult<Option<Value>> {
+        let query = self.read_query(query)?;
+        self.accumulate("$max", field.as_ref(), query).await?.map(|v| json_value(&v)).transpose()
+    }
+
+    /// Number of documents for each distinct value of `field`. Documents missing the field are counted under `Value::Null`.
+    pub async fn group_by(&self, field: impl AsRef<str>) -> OResult<HashMap<Value, u64>> {
+        let field = field.as_ref();
+        let query = self.read_query(Query::new())?;
+        let mut groups: HashMap<Value, u64> = HashMap::new();
+        if self.client.supports(DriverCapabilities::AGGREGATION) {
+            let pipeline = vec![
+                doc! {"$match": TryInto::<bson::Document>::try_into(query)?},
+                doc! {"$group": {"_id": format!("${field}"), "count": {"$sum": 1}}},
+            ];
+            for group in self.driver().aggregate(self.name(), pipeline, self.find_options(None, Find::many())).await? {
+                let key = json_value(group.get("_id").unwrap_or(&Bson::Null))?;
+                let count = group.get("count").and_then(bson_f64).unwrap_or(0.0) as u64;
+                *groups.entry(key).or_default() += count;
+            }
+        } else {
+            for value in self.field_values(field, query).await? {
+                *groups.entry(json_value(&value)?).or_default() += 1;
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Removes documents sharing the values of `key_fields` with another, keeping one per group according to `keep`,
+    /// ie to clean up legacy data before adding a unique index over those fields. Groups are found by aggregation where
+    /// supported, and duplicates deleted in batches of `ClientOptions::insert_batch_size`. Documents missing a key field
+    /// are grouped as if it were null. Which document is kept depends on the order `keep` sorts each group in, not the
+    /// order documents were stored in.
+    pub async fn dedupe(&self, key_fields: &[&str], keep: KeepStrategy) -> OResult<DedupeReport> {
+        if key_fields.is_empty() {
+            return Err(OrmoxError::compaibility("dedupe needs at least one key field"));
+        }
+        let id_field = T::id_field();
+        let order = keep.field();
+
+        // Ids of each group's documents, ordered by the strategy's field
+        let mut groups: Vec<Vec<Bson>> = Vec::new();
+        if self.client.supports(DriverCapabilities::AGGREGATION) {
+            let key = key_fields.iter().enumerate().map(|(i, field)| (format!("k{i}"), bson!({"$ifNull": [format!("${field}"), Bson::Null]}))).collect::<bson::Document>();
+            let pipeline = vec![
+                doc! {"$sort": {order: 1}},
+                doc! {"$group": {"_id": key, "ids": {"$push": format!("${id_field}")}, "count": {"$sum": 1}}},
+                doc! {"$match": {"count": {"$gt": 1}}},
+            ];
+            for group in self.driver().aggregate(self.name(), pipeline, self.find_options(None, Find::many())).await? {
+                groups.push(group.get_array("ids").cloned().unwrap_or_default());
+            }
+        } else {
+            let projection = key_fields.iter().copied().chain([id_field.as_str()]).map(|field| (field.to_string(), Bson::Int32(1))).collect();
+            let options = Find { projection: Some(projection), sort: Some(Sorting::asc(order)), ..self.find_options(None, Find::many()) };
+            let mut keyed: HashMap<String, usize> = HashMap::new();
+            for stored in self.driver().all(self.name(), options).await? {
+                let id = memory::get_path(&stored, &id_field).cloned().unwrap_or(Bson::Null);
+                let key = canonical(&key_values(&stored, key_fields));
+                match keyed.get(&key) {
+                    Some(index) => groups[*index].push(id),
+                    None => {
+                        keyed.insert(key, groups.len());
+                        groups.push(vec![id]);
+                    }
+                }
+            }
+            groups.retain(|ids| ids.len() > 1);
+        }
+
+        let mut extras: Vec<Bson> = Vec::new();
+        for mut ids in groups.iter().cloned() {
+            match keep {
+                KeepStrategy::FirstBy(_) => ids.remove(0),
+                KeepStrategy::LastBy(_) => ids.remove(ids.len() - 1)
+            };
+            extras.extend(ids);
+        }
+
+        let mut report = DedupeReport { groups: groups.len() as u64, deleted: Vec::new() };
+        for batch in extras.chunks(self.client.options().insert_batch_size.max(1)) {
+            let query = Query::try_from(doc! {&id_field: {"$in": batch}})?;
+            report.deleted.extend(self.driver().find(self.name(), query.clone(), self.find_options(None, Find::many())).await?);
+            self.driver().delete(self.name(), query, OperationCount::Many, self.write_options.clone()).await?;
+        }
+        for observer in self.client.observers(&self.name()) {
+            observer.deleted(&self.client, &report.deleted).await?;
+        }
+        Ok(report)
+    }
+}
+
+/// `query` restricted to documents of the variant `V`
+fn variant_query<V: Variant>(query: impl TryInto<Query, Error = impl Error>) -> OResult<Query> {
+    let query = query.try_into().map_err(|e| OrmoxError::Compatibility { error: e.to_string() })?;
+    Ok(query.field(V::tag_field(), V::tag()))
+}
+
+/// IDs of both sides of a many-to-many link, as stored in the junction collection
+fn junction_pair<T: ManyToMany<U>, U: Document>(document: &T, other: &U) -> OResult<(Value, Value)> {
+    let source = serde_json::to_value(document.id()).map_err(OrmoxError::serialization)?;
+    let target = serde_json::to_value(other.id()).map_err(OrmoxError::serialization)?;
+    Ok((source, target))
+}
+
+/// Values of `fields` in a stored document, as an equality query matching them. Missing fields are null.
+fn key_values(document: &bson::Document, fields: &[&str]) -> bson::Document {
+    fields.iter().map(|field| (field.to_string(), memory::get_path(document, field).cloned().unwrap_or(Bson::Null))).collect()
+}
+
+/// Query matching the document with the given id
+pub(crate) fn id_query<T: Document>(id: &impl Serialize) -> OResult<Query> {
+    let id = serde_json::to_value(id).map_err(OrmoxError::serialization)?;
+    Ok(Query::new().field(T::id_field(), id))
+}
+
+/// Translates merge patch fields under `prefix` into `$set` & `$unset` operations
+fn merge_patch(mut update: Update, prefix: &str, fields: serde_json::Map<String, Value>) -> OResult<Update> {
+    for (key, value) in fields {
+        let path = if prefix.is_empty() { key } else { format!("{prefix}.{key}") };
+        update = match value {
+            Value::Null => update.unset(path),
+            Value::Object(children) => merge_patch(update, &path, children)?,
+            value => update.set(path, Bson::try_from(value).map_err(OrmoxError::serialization)?)
+        };
+    }
+    Ok(update)
+}
+
+fn bson_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None
+    }
+}
+
+fn compare_bson(a: &Bson, b: &Bson) -> Option<Ordering> {
+    match (a, b) {
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::Boolean(a), Bson::Boolean(b)) => Some(a.cmp(b)),
+        (Bson::DateTime(a), Bson::DateTime(b)) => Some(a.cmp(b)),
+        (a, b) => bson_f64(a)?.partial_cmp(&bson_f64(b)?)
+    }
+}
+
+/// Picks the value that compares as `direction` against all others, skipping nulls & incomparable values
+fn extreme(values: Vec<Bson>, direction: Ordering) -> Option<Bson> {
+    values.into_iter().filter(|v| *v != Bson::Null).fold(None, |best, value| match best {
+        Some(best) if compare_bson(&value, &best) != Some(direction) => Some(best),
+        _ => Some(value)
+    })
 }
+
+fn json_value(value: &Bson) -> OResult<Value> {
+    serde_json::to_value(value).map_err(OrmoxError::deserialization)
+}
\ No newline at end of file
@@ -0,0 +1,54 @@
+use bson::{Bson, Document};
+use serde::Serialize;
+
+/// Copy of a value with the keys of every document sorted
+fn sorted(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(document) => Bson::Document(sorted_document(document)),
+        Bson::Array(items) => Bson::Array(items.iter().map(sorted).collect()),
+        value => value.clone()
+    }
+}
+
+fn sorted_document(document: &Document) -> Document {
+    let mut keys = document.keys().collect::<Vec<_>>();
+    keys.sort();
+    keys.into_iter().map(|key| (key.clone(), sorted(&document[key]))).collect()
+}
+
+/// Canonical form of a BSON document, for comparing & hashing options by content: canonical extended JSON with the keys
+/// of every document sorted, so key order doesn't matter but value types (ie `Int32` vs `Int64`) do
+pub(crate) fn canonical(document: &Document) -> String {
+    Bson::Document(sorted_document(document)).into_canonical_extjson().to_string()
+}
+
+/// Canonical form of a value serialized to BSON, falling back to its `Debug` output if it can't be serialized
+pub(crate) fn canonical_serialized<T: Serialize + std::fmt::Debug>(value: &T) -> String {
+    match bson::to_document(value) {
+        Ok(document) => canonical(&document),
+        Err(_) => format!("{value:?}")
+    }
+}
+
+/// Implements `PartialEq`, `Eq` & `Hash` for a type by comparing its canonical form, built by `$canonical`
+macro_rules! canonical_eq {
+    ($type:ty, $canonical:expr) => {
+        impl PartialEq for $type {
+            fn eq(&self, other: &Self) -> bool {
+                let canonical: fn(&Self) -> String = $canonical;
+                canonical(self) == canonical(other)
+            }
+        }
+
+        impl Eq for $type {}
+
+        impl std::hash::Hash for $type {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                let canonical: fn(&Self) -> String = $canonical;
+                canonical(self).hash(state)
+            }
+        }
+    };
+}
+
+pub(crate) use canonical_eq;
@@ -0,0 +1,93 @@
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+
+use crate::client::Collection;
+
+use super::{
+    document::Document,
+    error::{OResult, OrmoxError},
+    memory::get_path,
+};
+
+/// Position in a change stream. Store the token of the last event handled and pass it to `Collection::watch_from`
+/// to resume after it; tokens serialize as plain documents.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct ResumeToken(pub bson::Document);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Replace,
+    Delete,
+
+    /// Anything else the driver reports, ie MongoDB's `drop` or `invalidate`, which end the stream
+    Other(String)
+}
+
+/// A change to a collection, as reported by `DatabaseDriver::watch`
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub token: ResumeToken,
+    pub kind: ChangeKind,
+
+    /// The driver's key of the changed document, ie `{"_id": ...}`
+    pub key: Option<bson::Document>,
+
+    /// The document after the change, for inserts, replaces & (if the driver looks it up) updates
+    pub document: Option<bson::Document>,
+
+    /// The document before the change, if the driver keeps it (ie MongoDB 6.0+ collections with pre-images enabled)
+    pub previous: Option<bson::Document>,
+
+    /// Fields set by an update, by path
+    pub updated_fields: Option<bson::Document>,
+
+    /// Paths unset by an update
+    pub removed_fields: Vec<String>
+}
+
+/// Stream of changes returned by `DatabaseDriver::watch`
+pub type ChangeStream = BoxStream<'static, OResult<Change>>;
+
+/// A change to a document of type `T`, yielded by `Collection::watch`
+#[derive(Clone, Debug)]
+pub struct ChangeEvent<T: Document> {
+    pub token: ResumeToken,
+    pub kind: ChangeKind,
+
+    /// ID of the changed document, if the driver reported the document or its ID
+    pub id: Option<T::Id>,
+    pub document: Option<T>,
+    pub previous: Option<T>,
+    pub updated_fields: Option<bson::Document>,
+    pub removed_fields: Vec<String>
+}
+
+impl<T: Document> ChangeEvent<T> {
+    /// Parses the documents of a driver's change, as loaded through `collection`
+    pub fn parse(change: Change, collection: &Collection<T>) -> OResult<Self> {
+        let document = change.document.map(|d| T::parse(d, Some(collection.clone()))).transpose()?;
+        let previous = change.previous.map(|d| T::parse(d, Some(collection.clone()))).transpose()?;
+        let id = match document.as_ref().or(previous.as_ref()) {
+            Some(document) => Some(document.id()),
+            None => change
+                .key
+                .as_ref()
+                .and_then(|key| get_path(key, &T::id_field()))
+                .map(|id| bson::from_bson::<T::Id>(id.clone()).map_err(OrmoxError::deserialization))
+                .transpose()?
+        };
+
+        Ok(Self {
+            token: change.token,
+            kind: change.kind,
+            id,
+            document,
+            previous,
+            updated_fields: change.updated_fields,
+            removed_fields: change.removed_fields
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt::Debug;
+use bson::Document;
+
+use super::error::{OResult, OrmoxError};
+
+/// Encoding of documents for drivers that store raw bytes. BSON is always available; CBOR & MessagePack are enabled
+/// by the `cbor` & `msgpack` features and are usually smaller for plain data, as they don't store field lengths &
+/// terminators. Neither keeps integer widths, so 64-bit integers small enough for 32 bits decode as `Bson::Int32`.
+///
+/// Only storage is affected: documents are still handed to drivers as BSON, and queries are translated the same way
+/// whatever the codec.
+pub trait Codec: Debug + Send + Sync {
+    /// Name of the format, recorded by drivers alongside encoded documents to detect data written with another codec
+    fn name(&self) -> &'static str;
+
+    fn encode(&self, document: &Document) -> OResult<Vec<u8>>;
+
+    fn decode(&self, bytes: &[u8]) -> OResult<Document>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BsonCodec;
+
+impl Codec for BsonCodec {
+    fn name(&self) -> &'static str {
+        "bson"
+    }
+
+    fn encode(&self, document: &Document) -> OResult<Vec<u8>> {
+        bson::to_vec(document).map_err(OrmoxError::serialization)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> OResult<Document> {
+        bson::from_slice(bytes).map_err(OrmoxError::deserialization)
+    }
+}
+
+/// CBOR (RFC 8949). BSON-specific values (ie `ObjectId`, dates) are stored in their extended JSON form.
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, document: &Document) -> OResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(document, &mut bytes).map_err(OrmoxError::serialization)?;
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> OResult<Document> {
+        ciborium::from_reader(bytes).map_err(OrmoxError::deserialization)
+    }
+}
+
+/// MessagePack, with named fields. BSON-specific values (ie `ObjectId`, dates) are stored in their extended JSON form.
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode(&self, document: &Document) -> OResult<Vec<u8>> {
+        rmp_serde::to_vec_named(document).map_err(OrmoxError::serialization)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> OResult<Document> {
+        rmp_serde::from_slice(bytes).map_err(OrmoxError::deserialization)
+    }
+}
@@ -0,0 +1,80 @@
+use std::{cell::RefCell, collections::BTreeMap, future::Future, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+use serde::{Deserialize, Serialize};
+
+/// Who & what an operation is run for, set with `scope`. The client records it with the outbox events it enqueues, and
+/// remote drivers send it along so the server runs their operations in the same context.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperationContext {
+    /// The user the operation is run on behalf of
+    #[serde(default)]
+    pub user_id: Option<String>,
+
+    /// ID of the request that caused the operation, for correlating with the application's logs
+    #[serde(default)]
+    pub request_id: Option<String>,
+
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>
+}
+
+impl OperationContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<OperationContext>>> = const { RefCell::new(None) };
+}
+
+/// The context of the running task, if it's inside a `scope`
+pub fn current() -> Option<Arc<OperationContext>> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Runs `future` with `context` as its current context, replacing any outer one (`None` runs it without a context).
+/// Works on any runtime, but tasks spawned by the future don't inherit the context.
+pub fn scope<F: Future>(context: impl Into<Option<OperationContext>>, future: F) -> Scoped<F> {
+    Scoped { context: context.into().map(Arc::new), future: Box::pin(future) }
+}
+
+/// Future returned by `scope`
+pub struct Scoped<F> {
+    context: Option<Arc<OperationContext>>,
+    future: Pin<Box<F>>
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let outer = CURRENT.with(|current| current.replace(self.context.clone()));
+
+        // Restores the outer context even if the future panics
+        struct Restore(Option<Arc<OperationContext>>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let outer = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = outer);
+            }
+        }
+        let _restore = Restore(outer);
+        self.future.as_mut().poll(cx)
+    }
+}
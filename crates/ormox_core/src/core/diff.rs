@@ -0,0 +1,148 @@
+use bson::{Bson, Document};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{OResult, OrmoxError},
+    redaction::{redact_paths, REDACTED},
+    update::Update,
+};
+
+/// How one field differs between two versions of a document
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FieldChange {
+    Added { after: Bson },
+    Removed { before: Bson },
+    Modified { before: Bson, after: Bson }
+}
+
+impl FieldChange {
+    /// The field's old value, unless it was added
+    pub fn before(&self) -> Option<&Bson> {
+        match self {
+            Self::Added { .. } => None,
+            Self::Removed { before } | Self::Modified { before, .. } => Some(before)
+        }
+    }
+
+    /// The field's new value, unless it was removed
+    pub fn after(&self) -> Option<&Bson> {
+        match self {
+            Self::Removed { .. } => None,
+            Self::Added { after } | Self::Modified { after, .. } => Some(after)
+        }
+    }
+}
+
+/// Per-field changes between two versions of a document, keyed by dotted path. Sub-documents are compared field by
+/// field, while arrays & other values are compared whole. Fields are listed in the new version's order, followed by the
+/// removed ones.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct DocumentDiff(IndexMap<String, FieldChange>);
+
+impl DocumentDiff {
+    /// Changes between two typed documents, as they're stored
+    pub fn between<T: Serialize>(old: &T, new: &T) -> OResult<Self> {
+        let old = bson::to_document(old).map_err(OrmoxError::serialization)?;
+        let new = bson::to_document(new).map_err(OrmoxError::serialization)?;
+        Ok(diff(&old, &new))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The change to the field at `path`, if it changed
+    pub fn get(&self, path: impl AsRef<str>) -> Option<&FieldChange> {
+        self.0.get(path.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &FieldChange)> {
+        self.0.iter()
+    }
+
+    /// Update applying the changes to the old version: `$set` for added & modified fields, `$unset` for removed ones
+    pub fn to_update(&self) -> Update {
+        self.0.iter().fold(Update::new(), |update, (path, change)| match change.after() {
+            Some(after) => update.set(path, after.clone()),
+            None => update.unset(path)
+        })
+    }
+
+    /// Copy with the values of the stored paths `fields` (and below them) replaced by `REDACTED`, ie
+    /// `Document::sensitive_fields`, for logs & change histories
+    pub fn redacted(&self, fields: &[&str]) -> Self {
+        let redact = |path: &str, value: &Bson| {
+            if fields.iter().any(|field| path == *field || path.strip_prefix(field).is_some_and(|rest| rest.starts_with('.'))) {
+                return Bson::String(REDACTED.to_string());
+            }
+            match value {
+                // Sensitive fields below an added or removed sub-document
+                Bson::Document(inner) => {
+                    let below = fields.iter().filter_map(|field| field.strip_prefix(path)?.strip_prefix('.')).collect::<Vec<_>>();
+                    Bson::Document(redact_paths(inner, &below))
+                },
+                value => value.clone()
+            }
+        };
+
+        Self(
+            self.0
+                .iter()
+                .map(|(path, change)| {
+                    let change = match change {
+                        FieldChange::Added { after } => FieldChange::Added { after: redact(path, after) },
+                        FieldChange::Removed { before } => FieldChange::Removed { before: redact(path, before) },
+                        FieldChange::Modified { before, after } => FieldChange::Modified { before: redact(path, before), after: redact(path, after) }
+                    };
+                    (path.clone(), change)
+                })
+                .collect()
+        )
+    }
+}
+
+impl IntoIterator for DocumentDiff {
+    type Item = (String, FieldChange);
+    type IntoIter = indexmap::map::IntoIter<String, FieldChange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Per-field changes turning `old` into `new`, ie for audit logs & "what changed" views. Typed documents can be compared
+/// with `DocumentDiff::between`.
+pub fn diff(old: &Document, new: &Document) -> DocumentDiff {
+    let mut changes = IndexMap::new();
+    diff_under(old, new, "", &mut changes);
+    DocumentDiff(changes)
+}
+
+fn diff_under(old: &Document, new: &Document, prefix: &str, changes: &mut IndexMap<String, FieldChange>) {
+    for (key, after) in new {
+        let path = format!("{prefix}{key}");
+        match (old.get(key), after) {
+            (None, after) => {
+                changes.insert(path, FieldChange::Added { after: after.clone() });
+            },
+            (Some(Bson::Document(before)), Bson::Document(after)) => diff_under(before, after, &format!("{path}."), changes),
+            (Some(before), after) if before != after => {
+                changes.insert(path, FieldChange::Modified { before: before.clone(), after: after.clone() });
+            },
+            _ => {}
+        }
+    }
+
+    for (key, before) in old {
+        if !new.contains_key(key) {
+            changes.insert(format!("{prefix}{key}"), FieldChange::Removed { before: before.clone() });
+        }
+    }
+}
@@ -1,11 +1,43 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, hash::{Hash, Hasher}};
 
+use bson::{serde_helpers::HumanReadable, RawDocument};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use uuid::Uuid;
+use crate::client::{id_query, Client, Collection};
 
-use crate::client::{Client, Collection};
+use super::{canonical::{canonical_eq, canonical_serialized}, driver::{Collation, DriverCapabilities, Find, Sorting}, error::{OResult, OrmoxError}, merge::MergePolicy, numeric::{self, Lenient, NumericPolicy}, query::Query, redaction::redact, relation::{CounterCache, ManyToMany, Reference}, update::Update};
+#[cfg(feature = "encryption")]
+use super::encryption::{decrypt_fields, EncryptionMode};
 
-use super::{error::{OResult, OrmoxError}, query::Query};
+/// How strictly stored documents are checked against their type when loaded
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DecodeMode {
+    /// Keys the type doesn't know are ignored, and missing `Option` fields load as `None`
+    #[default]
+    Lenient,
+
+    /// Like `Lenient`, but keys the type doesn't know (at any depth) fail the load with `OrmoxError::Deserialization`,
+    /// like serde's `deny_unknown_fields`. Keys holding null are treated as missing. Keys are checked as serde
+    /// deserializes them, so content serde buffers first isn't checked: `#[serde(flatten)]` fields, and internally
+    /// tagged or untagged enums, including the variants of enum documents.
+    Strict
+}
+
+/// How an index organizes its keys
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IndexKind {
+    /// Ordered keys, for equality, range & sort queries
+    #[default]
+    BTree,
+
+    /// Hashes of the keys, for equality queries & hashed sharding
+    Hashed,
+
+    /// Full-text search over string fields
+    Text,
+
+    /// GeoJSON geometries on a sphere, for `$near` & `$geoWithin` queries
+    Geo
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Index {
@@ -15,7 +47,43 @@ pub struct Index {
     pub name: Option<String>,
 
     #[serde(default)]
-    pub unique: bool
+    pub unique: bool,
+
+    /// Collation of the index's string keys. Queries only use the index if they sort with the same collation.
+    #[serde(default)]
+    pub collation: Option<Collation>,
+
+    /// Build the index online, without blocking writes to the collection while it's built. MongoDB 4.2+ builds every
+    /// index this way; `Collection::build_index` also keeps the build off the caller's task.
+    #[serde(default)]
+    pub background: bool,
+
+    #[serde(default)]
+    pub kind: IndexKind,
+
+    /// Only index documents matching this query. A partial unique index only enforces uniqueness among them, ie rows
+    /// that aren't soft-deleted.
+    #[serde(default, with = "filter_document")]
+    pub partial_filter: Option<Query>
+}
+
+// Indexes are equal if they serialize the same, with partial filters compared as the queries they render to
+canonical_eq!(Index, canonical_serialized);
+
+/// Stores a partial filter as the document it renders to, as a `Query`'s own keys can't be JSON object keys
+mod filter_document {
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Query;
+
+    pub fn serialize<S: Serializer>(filter: &Option<Query>, serializer: S) -> Result<S::Ok, S::Error> {
+        let document: Option<bson::Document> = filter.clone().map(TryInto::try_into).transpose().map_err(S::Error::custom)?;
+        document.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Query>, D::Error> {
+        Option::<bson::Document>::deserialize(deserializer)?.map(Query::try_from).transpose().map_err(D::Error::custom)
+    }
 }
 
 impl Index {
@@ -23,7 +91,11 @@ impl Index {
         Self {
             fields: vec![field.as_ref().to_string()],
             name: None,
-            unique: false
+            unique: false,
+            collation: None,
+            background: false,
+            kind: IndexKind::BTree,
+            partial_filter: None
         }
     }
 
@@ -34,7 +106,11 @@ impl Index {
         Self {
             fields: f,
             name: None,
-            unique: false
+            unique: false,
+            collation: None,
+            background: false,
+            kind: IndexKind::BTree,
+            partial_filter: None
         }
     }
 
@@ -53,6 +129,54 @@ impl Index {
         self
     }
 
+    pub fn collation(&mut self, collation: Collation) -> &mut Self {
+        self.collation = Some(collation);
+        self
+    }
+
+    pub fn background(&mut self, background: bool) -> &mut Self {
+        self.background = background;
+        self
+    }
+
+    pub fn kind(&mut self, kind: IndexKind) -> &mut Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn partial_filter(&mut self, filter: Query) -> &mut Self {
+        self.partial_filter = Some(filter);
+        self
+    }
+
+    /// What a driver must support to create the index
+    pub fn required_capabilities(&self) -> DriverCapabilities {
+        let mut required = DriverCapabilities::INDEXES;
+        required.set(DriverCapabilities::UNIQUE_INDEXES, self.unique);
+        required.set(DriverCapabilities::PARTIAL_INDEXES, self.partial_filter.is_some());
+        required | match self.kind {
+            IndexKind::BTree => DriverCapabilities::empty(),
+            IndexKind::Hashed => DriverCapabilities::HASHED_INDEXES,
+            IndexKind::Text => DriverCapabilities::TEXT_INDEXES,
+            IndexKind::Geo => DriverCapabilities::GEO_INDEXES
+        }
+    }
+
+    /// Value of each field in the index's key specification, as MongoDB expects it
+    pub fn key_value(&self) -> bson::Bson {
+        match self.kind {
+            IndexKind::BTree => bson::Bson::Int32(1),
+            IndexKind::Hashed => bson::Bson::from("hashed"),
+            IndexKind::Text => bson::Bson::from("text"),
+            IndexKind::Geo => bson::Bson::from("2dsphere")
+        }
+    }
+
+    /// The index's name, or the `field_1` style name MongoDB & PoloDB give unnamed indexes
+    pub fn resolved_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.fields.iter().map(|field| format!("{field}_1")).collect::<Vec<_>>().join("_"))
+    }
+
     pub fn field(&mut self, field: impl AsRef<str>) -> &mut Self {
         if !self.fields.contains(&field.as_ref().to_string()) {
             self.fields.push(field.as_ref().to_string());
@@ -67,20 +191,285 @@ impl Index {
     }
 }
 
+/// Wrapper for ORM-managed fields on documents (attached collection, loaded state). Always compares equal, hashes to
+/// nothing & debug-prints as `Hidden { .. }`, so derives like `PartialEq` & `Debug` on a document only consider its data.
+#[derive(Clone, Default)]
+pub struct Hidden<V>(pub V);
+
+impl<V> Debug for Hidden<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hidden").finish_non_exhaustive()
+    }
+}
+
+impl<V> PartialEq for Hidden<V> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<V> Eq for Hidden<V> {}
+
+impl<V> PartialOrd for Hidden<V> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V> Ord for Hidden<V> {
+    fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
+        std::cmp::Ordering::Equal
+    }
+}
+
+impl<V> Hash for Hidden<V> {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// Field holding the schema version of versioned documents (`#[ormox_document(schema_version = ...)]`)
+pub const SCHEMA_FIELD: &str = "_schema";
+
+/// Hides generated defaults (like random IDs) from JSON Schemas, as `#[schemars(skip_serializing_if = "...")]`
+#[cfg(feature = "schemars")]
+#[doc(hidden)]
+pub fn omit_schema_default<T>(_value: &T) -> bool {
+    true
+}
+
+/// Schema version of stored data, or 1 if it was written before the document was versioned
+fn stored_version(data: &bson::Document) -> OResult<u32> {
+    let version = match data.get(SCHEMA_FIELD) {
+        None => return Ok(1),
+        Some(bson::Bson::Int32(v)) => u32::try_from(*v).ok(),
+        Some(bson::Bson::Int64(v)) => u32::try_from(*v).ok(),
+        Some(_) => None
+    };
+    version.ok_or_else(|| OrmoxError::deserialization(format!("Invalid {SCHEMA_FIELD} field: {:?}", data.get(SCHEMA_FIELD))))
+}
+
+/// Brings stored data up to the document's schema version by applying its upcasts in order. Also returns the data as
+/// stored if it was migrated.
+fn migrate<T: Document>(data: bson::Document) -> OResult<(bson::Document, Option<bson::Document>)> {
+    let Some(current) = T::schema_version() else {
+        return Ok((data, None));
+    };
+    let version = stored_version(&data)?;
+    if version == current {
+        return Ok((data, None));
+    }
+    if version > current {
+        return Err(OrmoxError::deserialization(format!(
+            "Document has schema version {version}, newer than the latest known version ({current}) of {}",
+            T::collection_name()
+        )));
+    }
+
+    let mut migrated = data.clone();
+    for from in version..current {
+        migrated = T::upcast(from, migrated)?;
+    }
+    migrated.insert(SCHEMA_FIELD, bson::to_bson(&current).map_err(OrmoxError::serialization)?);
+    Ok((migrated, Some(data)))
+}
+
+/// Decode mode for documents loaded through `collection`, or declared by the type if they're loaded without one
+fn decode_mode<T: Document>(collection: Option<&Collection<T>>) -> DecodeMode {
+    match collection {
+        Some(collection) => collection.decode_mode(),
+        None => T::decode_mode().unwrap_or_default()
+    }
+}
+
+/// Parses `data` as `T`, failing on the keys serde ignores while deserializing it, ie ones its type doesn't know
+fn parse_strict<T: Document>(data: bson::Document) -> OResult<T> {
+    let mut ignored = Vec::new();
+    let deserializer = bson::Deserializer::new(bson::Bson::Document(data.clone()));
+    let Lenient(parsed) = serde_ignored::deserialize::<_, _, Lenient<T>>(deserializer, |path| ignored.push(path_segments(&path)))
+        .map_err(OrmoxError::deserialization)?;
+    let unknown = ignored.into_iter()
+        .filter(|path| stored_value(&data, path).is_some_and(|value| *value != bson::Bson::Null))
+        .map(|path| path.join("."))
+        .collect::<Vec<_>>();
+    if unknown.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(OrmoxError::deserialization(format!("Unknown fields in {}: {}", T::collection_name(), unknown.join(", "))))
+    }
+}
+
+/// Keys & array indices leading to an ignored value
+fn path_segments(path: &serde_ignored::Path) -> Vec<String> {
+    let (parent, segment) = match path {
+        serde_ignored::Path::Root => return Vec::new(),
+        serde_ignored::Path::Seq { parent, index } => (parent, Some(index.to_string())),
+        serde_ignored::Path::Map { parent, key } => (parent, Some(key.clone())),
+        serde_ignored::Path::Some { parent } | serde_ignored::Path::NewtypeStruct { parent } | serde_ignored::Path::NewtypeVariant { parent } => (parent, None)
+    };
+    let mut segments = path_segments(parent);
+    segments.extend(segment);
+    segments
+}
+
+fn stored_value<'a>(document: &'a bson::Document, path: &[String]) -> Option<&'a bson::Bson> {
+    let (first, rest) = path.split_first()?;
+    rest.iter().try_fold(document.get(first)?, |value, segment| match value {
+        bson::Bson::Document(document) => document.get(segment),
+        bson::Bson::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None
+    })
+}
+
+/// Finishes loading a parsed document: attaches its collection and snapshots its state if it tracks changes. Migrated
+/// documents snapshot their data as stored, so saving them rewrites every field the upcasts changed.
+fn loaded<T: Document>(mut parsed: T, collection: Option<Collection<T>>, stored: Option<bson::Document>) -> OResult<T> {
+    if T::tracks_changes() {
+        let state = match stored {
+            Some(stored) => stored,
+            None => match &collection {
+                Some(collection) => collection.serialize(&parsed)?,
+                None => numeric::to_document(&parsed, NumericPolicy::default())?
+            }
+        };
+        parsed.set_loaded_state(Some(state));
+    }
+    if let Some(coll) = collection {
+        parsed.attach_collection(coll);
+    }
+    Ok(parsed)
+}
+
 #[async_trait::async_trait]
 pub trait Document: Serialize + DeserializeOwned + Clone + Sync + Send {
-    fn id(&self) -> Uuid;
+    /// Type of the document's ID: a `Uuid`, unless the document uses one of its own fields as its ID
+    type Id: Serialize + DeserializeOwned + Clone + Debug + Sync + Send;
+    fn id(&self) -> Self::Id;
     fn id_field() -> String;
     fn collection_name() -> String;
     fn indexes() -> Vec<Index>;
     fn attached_collection(&self) -> Option<Collection<Self>>;
     fn attach_collection(&mut self, collection: Collection<Self>) -> ();
+    /// Whether instances keep a snapshot of their loaded state, so `save` only writes changed fields
+    fn tracks_changes() -> bool {
+        false
+    }
+    /// Serialized state of this instance when it was loaded, if change tracking is enabled
+    fn loaded_state(&self) -> Option<&bson::Document> {
+        None
+    }
+    fn set_loaded_state(&mut self, _state: Option<bson::Document>) {}
+    /// Checks the document's `#[validate(...)]` rules, failing with `OrmoxError::Validation` listing every broken rule.
+    /// Called before inserts & saves unless `ClientOptions::validate_writes` is off.
+    fn validate(&self) -> OResult<()> {
+        Ok(())
+    }
+    /// IDs held by the document's `Ref` fields, checked on writes when `ClientOptions::check_references` is on
+    fn references(&self) -> OResult<Vec<Reference>> {
+        Ok(Vec::new())
+    }
+    /// Counts of referencing documents kept in the document's fields, from `#[relation(..., counter_cache = "...")]`
+    fn counter_caches() -> Vec<CounterCache> {
+        Vec::new()
+    }
+    /// Stored paths of the `#[ormox(sensitive)]` (and encrypted) fields, masked wherever the ORM renders documents or
+    /// queries into errors
+    fn sensitive_fields() -> Vec<&'static str> {
+        Vec::new()
+    }
+    /// The stored document with its sensitive fields masked, for logging
+    fn redacted(&self) -> OResult<bson::Document> {
+        let data = numeric::to_document(self, NumericPolicy::String)?;
+        Ok(redact::<Self>(&data))
+    }
+    /// `#[ormox(sequence = "...")]` fields that are still unset, as `(field, sequence)`. Filled on insert & save.
+    fn unset_sequences(&self) -> Vec<(&'static str, &'static str)> {
+        Vec::new()
+    }
+    /// Sets a sequence-filled field (by name) to a value from `Client::next_sequence`
+    fn set_sequence(&mut self, _field: &str, _value: i64) -> OResult<()> {
+        Ok(())
+    }
+    /// Hashes the raw passwords held by `#[ormox(hashed = "argon2")]` fields. Done automatically on insert & save.
+    fn hash_fields(&mut self) -> OResult<()> {
+        Ok(())
+    }
+    /// Fills unset `#[ormox(sequence = "...")]` fields from their sequences. Done automatically on insert & save; call it
+    /// beforehand to know the values.
+    async fn fill_sequences(&mut self, client: &Client) -> OResult<()> {
+        for (field, sequence) in self.unset_sequences() {
+            let value = client.next_sequence(sequence).await?;
+            self.set_sequence(field, value)?;
+        }
+        Ok(())
+    }
+    /// Current schema version, stored in `_schema`, if the document is versioned
+    fn schema_version() -> Option<u32> {
+        None
+    }
+    /// Upgrades stored data from schema version `from` to `from + 1`. Applied in order by `parse` to documents written
+    /// with an older schema; generated from `#[ormox_document(schema_version = ..., migrations(...))]`.
+    fn upcast(from: u32, _data: bson::Document) -> OResult<bson::Document> {
+        Err(OrmoxError::deserialization(format!("No upcast from schema version {from} of {}", Self::collection_name())))
+    }
+    /// JSON Schema of the stored document, including its ID field and rename rules. Documents derive `JsonSchema` when
+    /// the `schemars` feature is enabled.
+    #[cfg(feature = "schemars")]
+    fn json_schema() -> schemars::schema::RootSchema
+    where
+        Self: schemars::JsonSchema
+    {
+        schemars::schema_for!(Self)
+    }
+    /// Decode mode declared by the type (`#[ormox_document(strict)]`), overriding `ClientOptions::decode_mode`
+    fn decode_mode() -> Option<DecodeMode> {
+        None
+    }
+    /// Filter every read of the collection applies on top of its query (`#[ormox_document(default_scope(...))]`), ie
+    /// leaving out archived documents. `Collection::unscoped` reads without it.
+    fn default_scope() -> Option<Query> {
+        None
+    }
+    /// Order of the collection's reads that don't set `Find::sort` (`#[ormox_document(default_sort = "...")]`), unless
+    /// read through `Collection::unscoped`
+    fn default_sort() -> Option<Sorting> {
+        None
+    }
+    /// Stored paths of the `#[ormox(encrypted)]` fields, encrypted on writes & queries and decrypted by `parse`
+    #[cfg(feature = "encryption")]
+    fn encrypted_fields() -> Vec<(&'static str, EncryptionMode)> {
+        Vec::new()
+    }
+    /// Parses a stored document, first decrypting its encrypted fields and migrating it to the current schema version
+    /// if it's older
     fn parse(data: bson::Document, collection: Option<Collection<Self>>) -> OResult<Self> {
-        let mut parsed = bson::from_document::<Self>(data.clone()).or_else(|e| Err(OrmoxError::Deserialization { error: e.to_string() }))?;
-        if let Some(coll) = collection {
-            parsed.attach_collection(coll);
+        #[cfg(feature = "encryption")]
+        let data = decrypt_fields::<Self>(data, collection.as_ref())?;
+        let (data, stored) = migrate::<Self>(data)?;
+        let parsed = match decode_mode::<Self>(collection.as_ref()) {
+            DecodeMode::Strict => parse_strict::<Self>(data)?,
+            DecodeMode::Lenient => numeric::from_document::<Self>(data)?
+        };
+        loaded(parsed, collection, stored)
+    }
+    /// Parses a document straight from raw BSON bytes, without building a `bson::Document` first.
+    /// Deserializes as human readable so types like `Uuid` load the same way they do through `parse`.
+    /// Documents needing migration or decryption, or decoded strictly, go through `parse`.
+    fn parse_raw(data: &RawDocument, collection: Option<Collection<Self>>) -> OResult<Self> {
+        if decode_mode::<Self>(collection.as_ref()) == DecodeMode::Strict {
+            return Self::parse(bson::Document::try_from(data).map_err(OrmoxError::deserialization)?, collection);
         }
-        Ok(parsed)
+        #[cfg(feature = "encryption")]
+        if !Self::encrypted_fields().is_empty() {
+            return Self::parse(bson::Document::try_from(data).map_err(OrmoxError::deserialization)?, collection);
+        }
+        if let Some(current) = Self::schema_version() {
+            let version = data.get(SCHEMA_FIELD).ok().flatten().and_then(|v| v.as_i32().map(i64::from).or_else(|| v.as_i64()));
+            if version != Some(i64::from(current)) {
+                return Self::parse(bson::Document::try_from(data).map_err(OrmoxError::deserialization)?, collection);
+            }
+        }
+
+        let HumanReadable(Lenient(parsed)) = bson::from_slice::<HumanReadable<Lenient<Self>>>(data.as_bytes()).map_err(OrmoxError::deserialization)?;
+        loaded(parsed, collection, None)
     }
     fn collection(&self) -> Option<Collection<Self>> {
         if let Some(attached) = self.attached_collection() {
@@ -100,11 +489,105 @@ pub trait Document: Serialize + DeserializeOwned + Clone + Sync + Send {
         }
     }
 
+    /// Saves this document like `Collection::save_merging`, replacing the instance's fields with the merged result
+    async fn save_merging(&mut self, policy: MergePolicy) -> OResult<()> {
+        if let Some(collection) = self.collection() {
+            *self = collection.save_merging(self.clone(), policy).await?;
+            Ok(())
+        } else {
+            Err(OrmoxError::Uninitialized)
+        }
+    }
+
+    /// Refetches this document from the database, replacing the instance's fields. Returns `false` (leaving the instance
+    /// untouched) if the document no longer exists.
+    async fn reload(&mut self) -> OResult<bool> {
+        if let Some(collection) = self.collection() {
+            let query = id_query::<Self>(&self.id())?;
+            match collection.unscoped().find(query, Some(Find::one())).await?.into_iter().next() {
+                Some(current) => {
+                    *self = current;
+                    Ok(true)
+                }
+                None => Ok(false)
+            }
+        } else {
+            Err(OrmoxError::Uninitialized)
+        }
+    }
+
+    /// Applies `update` to this document in the database. The loaded instance is not modified.
+    async fn update(&self, update: Update) -> OResult<()> {
+        if let Some(collection) = self.collection() {
+            collection.update_by_id(self.id(), update).await
+        } else {
+            Err(OrmoxError::Uninitialized)
+        }
+    }
+
     async fn delete(self) -> OResult<()> {
         if let Some(collection) = self.collection() {
-            collection.delete_one(Query::new().field(Self::id_field(), self.id().to_string()).build()).await
+            collection.delete_by_id(self.id()).await
+        } else {
+            Err(OrmoxError::Uninitialized)
+        }
+    }
+
+    /// Links this document to `other` through a many-to-many relation. Linking an already linked pair does nothing.
+    async fn link<U: Document>(&self, other: &U) -> OResult<()>
+    where
+        Self: ManyToMany<U>
+    {
+        if let Some(collection) = self.collection() {
+            collection.link(self, other).await
+        } else {
+            Err(OrmoxError::Uninitialized)
+        }
+    }
+
+    /// Removes the link between this document and `other` in a many-to-many relation
+    async fn unlink<U: Document>(&self, other: &U) -> OResult<()>
+    where
+        Self: ManyToMany<U>
+    {
+        if let Some(collection) = self.collection() {
+            collection.unlink(self, other).await
         } else {
             Err(OrmoxError::Uninitialized)
         }
     }
-}
\ No newline at end of file
+
+    /// Every document linked to this one through a many-to-many relation, ie `post.linked::<Tag>()`
+    async fn linked<U: Document>(&self) -> OResult<Vec<U>>
+    where
+        Self: ManyToMany<U>
+    {
+        if let Some(collection) = self.collection() {
+            collection.linked(self).await
+        } else {
+            Err(OrmoxError::Uninitialized)
+        }
+    }
+}
+
+/// A subset of a document's fields, loaded with `Collection::find_as`. Usually derived with `#[derive(Projection)]`.
+pub trait Projection: DeserializeOwned + Send {
+    /// The document this is a projection of
+    type Of: Document;
+
+    /// Projection document selecting this type's fields (ie `{"name": 1}`)
+    fn projection() -> bson::Document;
+}
+
+/// One variant of an enum document, for `Collection::find_variant`. Generated by `#[ormox_document]` on enums, as
+/// marker types in the `<enum>_variants` module.
+pub trait Variant {
+    /// The enum document this is a variant of
+    type Of: Document;
+
+    /// Discriminator field (serde's `tag`)
+    fn tag_field() -> String;
+
+    /// This variant's discriminator value
+    fn tag() -> String;
+}
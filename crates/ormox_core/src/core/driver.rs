@@ -1,9 +1,15 @@
+use std::{any::Any, collections::HashMap, future::Future, time::Duration};
+
 use async_trait::async_trait;
+use bitflags::bitflags;
+use bson::RawDocumentBuf;
+use chrono::{DateTime, Utc};
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use web_time::Instant;
 
-use super::{document::Index, error::{OResult, OrmoxError}, query::Query};
+use super::{canonical::{canonical_eq, canonical_serialized}, change::{ChangeStream, ResumeToken}, document::Index, error::{OResult, OrmoxError}, query::Query};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum OperationCount {
@@ -27,7 +33,177 @@ impl Sorting {
     }
 }
 
+/// Which differences between strings a `Collation` considers
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CollationStrength {
+    /// Base letters only: `a`, `A` & `á` are equal
+    Primary,
+
+    /// Base letters & diacritics: `a` & `A` are equal, `a` & `á` aren't
+    Secondary,
+
+    /// Base letters, diacritics & case
+    Tertiary,
+
+    /// Like `Tertiary`, also considering punctuation when MongoDB ignores it
+    Quaternary,
+
+    /// Like `Quaternary`, breaking ties by code point
+    Identical
+}
+
+/// Language-aware string comparison for sorts & indexes, mapped to MongoDB collations. Drivers without collation
+/// support sort by the Unicode root collation (see `core::memory::compare_strings`) whatever the locale, except for
+/// `"simple"`, which compares code points.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Collation {
+    /// ICU locale, ie `"en"` or `"fr_CA"`
+    pub locale: String,
+
+    /// Defaults to `Tertiary`
+    #[serde(default)]
+    pub strength: Option<CollationStrength>,
+
+    /// Ignores case, lowering the strength to `Secondary` if it's higher
+    #[serde(default)]
+    pub case_insensitive: bool
+}
+
+impl Collation {
+    pub fn new(locale: impl AsRef<str>) -> Self {
+        Self { locale: locale.as_ref().to_string(), strength: None, case_insensitive: false }
+    }
+
+    pub fn strength(mut self, strength: CollationStrength) -> Self {
+        self.strength = Some(strength);
+        self
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Strength strings are compared at, after `case_insensitive`
+    pub fn effective_strength(&self) -> CollationStrength {
+        let strength = self.strength.unwrap_or(CollationStrength::Tertiary);
+        if self.case_insensitive {
+            strength.min(CollationStrength::Secondary)
+        } else {
+            strength
+        }
+    }
+}
+
+/// Which replica set members reads may be routed to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadPreference {
+    Primary,
+    PrimaryPreferred,
+    Secondary,
+    SecondaryPreferred,
+    Nearest
+}
+
+/// Number or kind of nodes that must acknowledge a write
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Acknowledgment {
+    Nodes(u32),
+    Majority,
+    Custom(String)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteConcern {
+    #[serde(default)]
+    pub w: Option<Acknowledgment>,
+
+    #[serde(default)]
+    pub journal: Option<bool>,
+
+    #[serde(default)]
+    pub timeout: Option<Duration>
+}
+
+impl WriteConcern {
+    pub fn nodes(count: u32) -> Self {
+        Self { w: Some(Acknowledgment::Nodes(count)), ..Default::default() }
+    }
+
+    pub fn majority() -> Self {
+        Self { w: Some(Acknowledgment::Majority), ..Default::default() }
+    }
+
+    pub fn journal(mut self, journal: bool) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Per-operation options for writes
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Builder)]
+pub struct WriteOptions {
+    #[builder(default, setter(into, strip_option))]
+    pub write_concern: Option<WriteConcern>,
+
+    /// Session to run the write in, as returned by `DatabaseDriver::start_session`
+    #[builder(default, setter(into, strip_option))]
+    pub session: Option<Uuid>
+}
+
+/// How drivers handle individual results that fail to load
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Fail the whole operation on the first bad result
+    #[default]
+    FailFast,
+
+    /// Log and skip bad results
+    Skip,
+
+    /// Keep going and collect errors. Only `find_partial` returns the collected errors; other operations skip them.
+    Collect
+}
+
+/// Results of an operation that may have partially failed
+#[derive(Clone, Debug)]
+pub struct PartialResult<T> {
+    pub items: Vec<T>,
+    pub errors: Vec<OrmoxError>
+}
+
+impl<T> PartialResult<T> {
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl<T> From<Vec<T>> for PartialResult<T> {
+    fn from(items: Vec<T>) -> Self {
+        Self { items, errors: Vec::new() }
+    }
+}
+
+/// Largest offset or limit a `Find` may hold, as MongoDB takes them as `i64`
+pub const MAX_FIND_BOUND: usize = i64::MAX as usize;
+
+/// Checks that an offset & limit fit every driver
+fn check_bounds(offset: Option<usize>, limit: Option<usize>) -> Result<(), String> {
+    for (name, value) in [("offset", offset), ("limit", limit)] {
+        if let Some(value) = value.filter(|v| *v > MAX_FIND_BOUND) {
+            return Err(format!("Find {name} {value} exceeds the maximum of {MAX_FIND_BOUND}"));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct Find {
     #[builder(default = "OperationCount::Many")]
     pub operation: OperationCount,
@@ -39,16 +215,70 @@ pub struct Find {
     pub limit: Option<usize>,
 
     #[builder(default, setter(into, strip_option))]
-    pub sort: Option<Sorting>
+    pub sort: Option<Sorting>,
+
+    #[builder(default, setter(into, strip_option))]
+    pub read_preference: Option<ReadPreference>,
+
+    /// Session to run the read in, as returned by `DatabaseDriver::start_session`
+    #[builder(default, setter(into, strip_option))]
+    pub session: Option<Uuid>,
+
+    #[builder(default)]
+    #[serde(default)]
+    pub error_policy: ErrorPolicy,
+
+    /// Fields to return, as a MongoDB-style projection document (ie `{"name": 1}`). Drivers without projection support return whole documents.
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default)]
+    pub projection: Option<bson::Document>,
+
+    /// Documents fetched per round trip: the batch size of MongoDB cursors, and the page size `Collection::stream`
+    /// fetches in, which bounds how much of the results drivers without cursors hold at once
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+
+    /// How strings are compared when sorting
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default)]
+    pub collation: Option<Collation>,
+
+    /// Name of the index the query planner must use. Drivers without index hints ignore it.
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default)]
+    pub hint: Option<String>
+}
+
+// Options are equal if they serialize the same, whatever order their projection's keys are in
+canonical_eq!(Find, canonical_serialized);
+
+impl FindBuilder {
+    fn validate(&self) -> Result<(), String> {
+        check_bounds(self.offset.flatten(), self.limit.flatten())
+    }
 }
 
 impl Find {
+    /// Checks that the offset & limit fit every driver, for options built without `FindBuilder`. Collections check the
+    /// options of every read.
+    pub fn validate(&self) -> OResult<()> {
+        check_bounds(self.offset, self.limit).map_err(OrmoxError::compaibility)
+    }
+
     pub fn many() -> Self {
         Self {
             operation: OperationCount::Many,
             offset: None,
             limit: None,
-            sort: None
+            sort: None,
+            read_preference: None,
+            session: None,
+            error_policy: ErrorPolicy::FailFast,
+            projection: None,
+            batch_size: None,
+            collation: None,
+            hint: None
         }
     }
 
@@ -57,11 +287,216 @@ impl Find {
             operation: OperationCount::One,
             offset: None,
             limit: None,
-            sort: None
+            sort: None,
+            read_preference: None,
+            session: None,
+            error_policy: ErrorPolicy::FailFast,
+            projection: None,
+            batch_size: None,
+            collation: None,
+            hint: None
+        }
+    }
+}
+
+bitflags! {
+    /// Optional features a driver supports natively
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DriverCapabilities: u32 {
+        const INDEXES = 1;
+        const UNIQUE_INDEXES = 1 << 1;
+        const TTL_INDEXES = 1 << 2;
+        const TRANSACTIONS = 1 << 3;
+        const SESSIONS = 1 << 4;
+        const REGEX = 1 << 5;
+        const EXPLAIN = 1 << 6;
+        const COLLECTION_STATS = 1 << 7;
+        /// `find_raw` & `all_raw` read raw BSON without building a `bson::Document` first
+        const RAW_DOCUMENTS = 1 << 8;
+        const AGGREGATION = 1 << 9;
+        /// `watch` streams changes to collections
+        const CHANGE_STREAMS = 1 << 10;
+        /// `index_stats` reports how often each index is used
+        const INDEX_STATS = 1 << 11;
+        /// Indexes with a `partial_filter`
+        const PARTIAL_INDEXES = 1 << 12;
+        const HASHED_INDEXES = 1 << 13;
+        const TEXT_INDEXES = 1 << 14;
+        const GEO_INDEXES = 1 << 15;
+        /// `upsert_many` writes a batch in one round trip
+        const BULK_UPSERTS = 1 << 16;
+    }
+}
+
+impl DriverCapabilities {
+    /// Human-readable names of the contained flags, ie "INDEXES | REGEX"
+    pub fn names(&self) -> String {
+        self.iter_names().map(|(name, _)| name).collect::<Vec<&str>>().join(" | ")
+    }
+
+    /// Returns `Ok` if all of `required` are supported, otherwise an `Unsupported` error naming the missing capabilities
+    pub fn require(&self, required: DriverCapabilities) -> OResult<()> {
+        let missing = required.difference(*self);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(OrmoxError::unsupported(missing.names()))
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CollectionStats {
+    /// Number of documents in the collection
+    pub count: u64,
+
+    /// Approximate storage size in bytes, if the driver can report it
+    #[serde(default)]
+    pub size: Option<u64>,
+
+    /// Size in bytes of each index, keyed by index name
+    #[serde(default)]
+    pub index_sizes: HashMap<String, u64>
+}
+
+impl CollectionStats {
+    pub fn total_index_size(&self) -> u64 {
+        self.index_sizes.values().sum()
+    }
+}
+
+/// Usage of an index, as returned by `DatabaseDriver::index_stats`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexStats {
+    pub name: String,
+
+    /// Number of queries that used the index since `since`
+    pub accesses: u64,
+
+    /// When counting started, ie when the server started or the index was created. Counts don't survive restarts, so
+    /// check this before dropping an index for being unused.
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// When a query last used the index, if the driver tracks it
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>
+}
+
+/// Progress of an index build, in documents scanned
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexProgress {
+    pub done: u64,
+    pub total: u64
+}
+
+impl IndexProgress {
+    /// Fraction of the collection scanned so far, from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.done as f64 / self.total as f64).min(1.0)
+        }
+    }
+}
+
+/// Health of a driver, as returned by `DatabaseDriver::health` & `Client::health`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HealthReport {
+    pub driver: String,
+
+    /// Whether the database answered, and every component is healthy
+    pub healthy: bool,
+
+    /// Round-trip time of the check
+    pub latency: Duration,
+
+    /// Why the check failed, if it did
+    #[serde(default)]
+    pub error: Option<String>,
+
+    /// Reports of the drivers a composite driver is built on, ie the backend of a remote driver
+    #[serde(default)]
+    pub components: Vec<HealthReport>
+}
+
+impl HealthReport {
+    /// Times `check`, which returns the reports of the driver's components (if any)
+    pub async fn measure(driver: impl Into<String>, check: impl Future<Output = OResult<Vec<HealthReport>>>) -> Self {
+        let start = Instant::now();
+        let result = check.await;
+        let latency = start.elapsed();
+        match result {
+            Ok(components) => Self {
+                driver: driver.into(),
+                healthy: components.iter().all(|c| c.healthy),
+                latency,
+                error: None,
+                components
+            },
+            Err(error) => Self::failed(driver, latency, error)
+        }
+    }
+
+    pub fn failed(driver: impl Into<String>, latency: Duration, error: OrmoxError) -> Self {
+        Self {
+            driver: driver.into(),
+            healthy: false,
+            latency,
+            error: Some(error.to_string()),
+            components: Vec::new()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QueryPlan {
+    /// Names of the indexes selected by the planner, in plan order
+    pub indexes: Vec<String>,
+
+    /// Whether the plan scans the entire collection
+    pub collection_scan: bool,
+
+    /// Driver-specific plan output, if available
+    #[serde(default)]
+    pub raw: Option<bson::Document>
+}
+
+impl QueryPlan {
+    /// Estimates a plan from declared indexes, using the first index whose leading field is matched by equality at the top level of the query
+    pub fn estimate(query: &bson::Document, indexes: &[Index]) -> Self {
+        for index in indexes {
+            if let Some(field) = index.fields.first() {
+                if query.get(field).is_some_and(|v| v.as_document().is_none()) {
+                    return Self {
+                        indexes: vec![index.name.clone().unwrap_or(format!("{field}_1"))],
+                        collection_scan: false,
+                        raw: None
+                    };
+                }
+            }
+        }
+
+        Self {
+            indexes: Vec::new(),
+            collection_scan: true,
+            raw: None
+        }
+    }
+
+    pub fn uses_index(&self, name: impl AsRef<str>) -> bool {
+        self.indexes.iter().any(|i| i == name.as_ref())
+    }
+}
+
+fn to_raw(documents: Vec<bson::Document>) -> OResult<Vec<RawDocumentBuf>> {
+    documents
+        .iter()
+        .map(|d| RawDocumentBuf::from_document(d).map_err(OrmoxError::serialization))
+        .collect()
+}
+
 #[allow(unused_variables)]
 #[async_trait]
 pub trait DatabaseDriver {
@@ -69,27 +504,85 @@ pub trait DatabaseDriver {
     /// Name of this driver (ie "mongodb")
     fn driver_name(&self) -> String;
 
+    /// Reference to the concrete driver, for downcasting to driver-specific APIs
+    fn as_any(&self) -> &dyn Any;
+
+    /// Optional features this driver supports
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities::empty()
+    }
+
     // Operation functions
     /// Function to return all collection names
     async fn collections(&self) -> OResult<Vec<String>>;
 
-    /// Base function to insert document(s)
-    async fn insert(&self, collection: String, documents: Vec<bson::Document>) -> OResult<Vec<Uuid>>;
+    /// Base function to insert document(s). Returns the inserted `_id`s that are UUIDs; documents with other ids
+    /// (natural keys, driver-generated ids) are left out.
+    async fn insert(&self, collection: String, documents: Vec<bson::Document>, options: WriteOptions) -> OResult<Vec<Uuid>>;
 
     /// Base function to update document(s)
-    async fn update(&self, collection: String, query: Query, update: bson::Document, count: OperationCount) -> OResult<()>;
+    async fn update(&self, collection: String, query: Query, update: bson::Document, count: OperationCount, options: WriteOptions) -> OResult<()>;
 
     /// Base function to delete document(s)
-    async fn delete(&self, collection: String, query: Query, count: OperationCount) -> OResult<()>;
+    async fn delete(&self, collection: String, query: Query, count: OperationCount, options: WriteOptions) -> OResult<()>;
 
     /// Base function to find document(s)
     async fn find(&self, collection: String, query: Query, options: Find) -> OResult<Vec<bson::Document>>;
 
+    /// Base function to find document(s), returning per-item errors alongside the results
+    async fn find_partial(&self, collection: String, query: Query, options: Find) -> OResult<PartialResult<bson::Document>> {
+        Ok(self.find(collection, query, options).await?.into())
+    }
+
     /// Base function to return all documents in a collection
     async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>>;
 
+    /// Base function to find document(s) as raw BSON. Drivers that can skip the intermediate `bson::Document` should override this and report `RAW_DOCUMENTS`.
+    async fn find_raw(&self, collection: String, query: Query, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        to_raw(self.find(collection, query, options).await?)
+    }
+
+    /// Base function to return all documents in a collection as raw BSON
+    async fn all_raw(&self, collection: String, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        to_raw(self.all(collection, options).await?)
+    }
+
     /// Base function to upsert document(s)
-    async fn upsert(&self, collection: String, query: Query, document: bson::Document, count: OperationCount) -> OResult<()>;
+    async fn upsert(&self, collection: String, query: Query, document: bson::Document, count: OperationCount, options: WriteOptions) -> OResult<()>;
+
+    /// Base function to upsert a batch of documents, each matched by its own query like `upsert` with
+    /// `OperationCount::One`. The default upserts them one at a time; drivers that can send the batch in one round trip
+    /// should override it and report `BULK_UPSERTS`.
+    async fn upsert_many(&self, collection: String, upserts: Vec<(Query, bson::Document)>, options: WriteOptions) -> OResult<()> {
+        for (query, document) in upserts {
+            self.upsert(collection.clone(), query, document, OperationCount::One, options.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Base function to count documents matching a query. `offset`, `limit` & `sort` in `options` are ignored.
+    async fn count(&self, collection: String, query: Query, options: Find) -> OResult<u64> {
+        let options = Find { offset: None, limit: None, sort: None, ..options };
+        Ok(self.find_raw(collection, query, options).await?.len() as u64)
+    }
+
+    /// Base function to find a page of documents along with the total number matching the query. The default makes two calls;
+    /// drivers that can do both in one round trip should override it.
+    async fn find_with_count(&self, collection: String, query: Query, options: Find) -> OResult<(Vec<bson::Document>, u64)> {
+        let total = self.count(collection.clone(), query.clone(), options.clone()).await?;
+        Ok((self.find(collection, query, options).await?, total))
+    }
+
+    /// Base function to run a MongoDB-style aggregation pipeline. Only `read_preference` & `session` are read from `options`.
+    async fn aggregate(&self, collection: String, pipeline: Vec<bson::Document>, options: Find) -> OResult<Vec<bson::Document>> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to atomically update the first document matching a query, returning it as updated. With `upsert`, a
+    /// document built from the query's fields & the update is inserted if none matches.
+    async fn find_one_and_update(&self, collection: String, query: Query, update: bson::Document, upsert: bool, options: WriteOptions) -> OResult<Option<bson::Document>> {
+        Err(OrmoxError::Unimplemented)
+    }
 
     /// Base function to create an index
     async fn create_index(&self, collection: String, index: Index) -> OResult<()> {
@@ -100,4 +593,78 @@ pub trait DatabaseDriver {
     async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
         Err(OrmoxError::Unimplemented)
     }
+
+    /// Base function to report how often each index of a collection is used
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to report how far along the build of an index is, or `None` if it isn't being built (or the driver
+    /// can't tell)
+    async fn index_build_progress(&self, collection: String, name: String) -> OResult<Option<IndexProgress>> {
+        Ok(None)
+    }
+
+    /// Base function to report document count & storage statistics for a collection
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to explain how a query would be executed
+    async fn explain(&self, collection: String, query: Query, options: Find) -> OResult<QueryPlan> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to start a causally-consistent session, returning its ID
+    async fn start_session(&self) -> OResult<Uuid> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to end a session started with `start_session`
+    async fn end_session(&self, session: Uuid) -> OResult<()> {
+        Ok(())
+    }
+
+    /// Base function to start a multi-document transaction in a session
+    async fn start_transaction(&self, session: Uuid) -> OResult<()> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to commit the session's current transaction
+    async fn commit_transaction(&self, session: Uuid) -> OResult<()> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to abort the session's current transaction, discarding its writes
+    async fn abort_transaction(&self, session: Uuid) -> OResult<()> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to stream changes to a collection as they happen, starting after `resume_after` if given
+    async fn watch(&self, collection: String, resume_after: Option<ResumeToken>) -> OResult<ChangeStream> {
+        Err(OrmoxError::Unimplemented)
+    }
+
+    /// Base function to run maintenance (compaction, vacuuming, etc). Drivers without maintenance work can leave this as a no-op.
+    async fn maintain(&self) -> OResult<()> {
+        Ok(())
+    }
+
+    /// Base function to release the driver's resources (open files, connection pools) ahead of dropping it, called by
+    /// `Client::close`. Operations afterwards may fail. Drivers with nothing to release can leave this as a no-op.
+    async fn shutdown(&self) -> OResult<()> {
+        Ok(())
+    }
+
+    /// Base function to check that the database is reachable. Defaults to listing collections; drivers with a cheaper
+    /// round trip should override it.
+    async fn ping(&self) -> OResult<()> {
+        self.collections().await.and(Ok(()))
+    }
+
+    /// Base function to report the driver's health. Defaults to timing `ping`; composite drivers should override it to
+    /// include their components' reports.
+    async fn health(&self) -> HealthReport {
+        HealthReport::measure(self.driver_name(), async { self.ping().await.and(Ok(Vec::new())) }).await
+    }
 }
\ No newline at end of file
@@ -0,0 +1,357 @@
+use std::{any::Any, fmt::{Debug, Display}, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf};
+use uuid::Uuid;
+
+use super::{
+    change::{ChangeStream, ResumeToken},
+    document::Index,
+    driver::{CollectionStats, DatabaseDriver, DriverCapabilities, Find, HealthReport, IndexProgress, IndexStats, OperationCount, PartialResult, QueryPlan, WriteOptions},
+    error::OResult,
+    memory,
+    query::Query,
+};
+
+/// A write skipped by a dry run, with its query rendered to the document the driver would have been given.
+/// Displays in MongoDB shell syntax, ie `db.users.updateOne({"name":"a"}, {"$set":{"age":3}})`.
+#[derive(Clone, Debug)]
+pub enum PlannedWrite {
+    Insert { collection: String, documents: Vec<bson::Document> },
+    Update { collection: String, query: bson::Document, update: bson::Document, count: OperationCount },
+    Upsert { collection: String, query: bson::Document, document: bson::Document, count: OperationCount },
+    Delete { collection: String, query: bson::Document, count: OperationCount },
+    FindOneAndUpdate { collection: String, query: bson::Document, update: bson::Document, upsert: bool },
+
+    /// An aggregation ending in a `$out` or `$merge` stage
+    Aggregate { collection: String, pipeline: Vec<bson::Document> },
+    CreateIndex { collection: String, index: Index },
+    DropIndex { collection: String, name: String },
+    Maintain
+}
+
+impl PlannedWrite {
+    /// Collection written to, if any
+    pub fn collection(&self) -> Option<&str> {
+        match self {
+            Self::Insert { collection, .. }
+            | Self::Update { collection, .. }
+            | Self::Upsert { collection, .. }
+            | Self::Delete { collection, .. }
+            | Self::FindOneAndUpdate { collection, .. }
+            | Self::Aggregate { collection, .. }
+            | Self::CreateIndex { collection, .. }
+            | Self::DropIndex { collection, .. } => Some(collection),
+            Self::Maintain => None
+        }
+    }
+}
+
+fn json(document: &bson::Document) -> String {
+    Bson::Document(document.clone()).into_relaxed_extjson().to_string()
+}
+
+fn json_all(documents: &[bson::Document]) -> String {
+    format!("[{}]", documents.iter().map(json).collect::<Vec<String>>().join(", "))
+}
+
+fn by_count(count: &OperationCount, one: &'static str, many: &'static str) -> &'static str {
+    match count {
+        OperationCount::One => one,
+        OperationCount::Many => many
+    }
+}
+
+impl Display for PlannedWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Insert { collection, documents } => write!(f, "db.{collection}.insertMany({})", json_all(documents)),
+            Self::Update { collection, query, update, count } => {
+                write!(f, "db.{collection}.{}({}, {})", by_count(count, "updateOne", "updateMany"), json(query), json(update))
+            },
+            Self::Upsert { collection, query, document, count } => write!(
+                f,
+                "db.{collection}.{}({}, {{\"$set\":{}}}, {{\"upsert\":true}})",
+                by_count(count, "updateOne", "updateMany"),
+                json(query),
+                json(document)
+            ),
+            Self::Delete { collection, query, count } => write!(f, "db.{collection}.{}({})", by_count(count, "deleteOne", "deleteMany"), json(query)),
+            Self::FindOneAndUpdate { collection, query, update, upsert } => {
+                write!(f, "db.{collection}.findOneAndUpdate({}, {}, {{\"upsert\":{upsert}}})", json(query), json(update))
+            },
+            Self::Aggregate { collection, pipeline } => write!(f, "db.{collection}.aggregate({})", json_all(pipeline)),
+            Self::CreateIndex { collection, index } => {
+                let keys = index.fields.iter().map(|field| (field.clone(), index.key_value())).collect::<bson::Document>();
+                let mut options = bson::Document::new();
+                if let Some(name) = &index.name {
+                    options.insert("name", name);
+                }
+                if index.unique {
+                    options.insert("unique", true);
+                }
+                if index.background {
+                    options.insert("background", true);
+                }
+                if let Some(Ok(filter)) = index.partial_filter.clone().map(TryInto::<bson::Document>::try_into) {
+                    options.insert("partialFilterExpression", filter);
+                }
+                write!(f, "db.{collection}.createIndex({}, {})", json(&keys), json(&options))
+            },
+            Self::DropIndex { collection, name } => write!(f, "db.{collection}.dropIndex({})", Bson::String(name.clone()).into_relaxed_extjson()),
+            Self::Maintain => f.write_str("maintain()")
+        }
+    }
+}
+
+type Logger = Arc<dyn Fn(&PlannedWrite) + Send + Sync>;
+
+/// Writes collected by a `DryRunDriver`, in the order they were made. Clones share the same writes, so keep one to
+/// review what a client configured with `ClientOptions::dry_run` would have done.
+#[derive(Clone, Default)]
+pub struct WritePlan {
+    writes: Arc<Mutex<Vec<PlannedWrite>>>,
+    logger: Option<Logger>
+}
+
+impl WritePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also hands each write to `logger` as it's planned, ie `|write| println!("{write}")`
+    pub fn with_logger(logger: impl Fn(&PlannedWrite) + Send + Sync + 'static) -> Self {
+        Self { writes: Default::default(), logger: Some(Arc::new(logger)) }
+    }
+
+    pub fn writes(&self) -> Vec<PlannedWrite> {
+        self.writes.lock().unwrap().clone()
+    }
+
+    /// Removes & returns the planned writes, ie after reviewing a batch
+    pub fn take(&self) -> Vec<PlannedWrite> {
+        std::mem::take(&mut *self.writes.lock().unwrap())
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn record(&self, write: PlannedWrite) {
+        if let Some(logger) = &self.logger {
+            logger(&write);
+        }
+        self.writes.lock().unwrap().push(write);
+    }
+}
+
+impl Debug for WritePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WritePlan").field("writes", &self.writes()).finish_non_exhaustive()
+    }
+}
+
+/// One write per line
+impl Display for WritePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for write in self.writes() {
+            writeln!(f, "{write}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a driver, recording writes into a `WritePlan` instead of running them. Queries are still rendered, so writes
+/// that would fail to convert fail here too. Reads go to the wrapped driver & don't see planned writes;
+/// `find_one_and_update` returns the matched document with the update applied in memory, without storing it.
+pub struct DryRunDriver<D: DatabaseDriver + Send + Sync> {
+    driver: D,
+    plan: WritePlan
+}
+
+impl<D: DatabaseDriver + Send + Sync> DryRunDriver<D> {
+    pub fn new(driver: D, plan: WritePlan) -> Self {
+        Self { driver, plan }
+    }
+
+    pub fn plan(&self) -> &WritePlan {
+        &self.plan
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.driver
+    }
+}
+
+#[async_trait]
+impl<D: DatabaseDriver + Send + Sync + 'static> DatabaseDriver for DryRunDriver<D> {
+    fn driver_name(&self) -> String {
+        self.driver.driver_name()
+    }
+
+    // Downcasts see through the dry run to the wrapped driver
+    fn as_any(&self) -> &dyn Any {
+        self.driver.as_any()
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        self.driver.capabilities()
+    }
+
+    async fn collections(&self) -> OResult<Vec<String>> {
+        self.driver.collections().await
+    }
+
+    async fn insert(&self, collection: String, documents: Vec<bson::Document>, _options: WriteOptions) -> OResult<Vec<Uuid>> {
+        let ids = documents.iter().filter_map(|document| bson::from_bson::<Uuid>(document.get("_id")?.clone()).ok()).collect();
+        self.plan.record(PlannedWrite::Insert { collection, documents });
+        Ok(ids)
+    }
+
+    async fn update(&self, collection: String, query: Query, update: bson::Document, count: OperationCount, _options: WriteOptions) -> OResult<()> {
+        self.plan.record(PlannedWrite::Update { collection, query: query.try_into()?, update, count });
+        Ok(())
+    }
+
+    async fn delete(&self, collection: String, query: Query, count: OperationCount, _options: WriteOptions) -> OResult<()> {
+        self.plan.record(PlannedWrite::Delete { collection, query: query.try_into()?, count });
+        Ok(())
+    }
+
+    async fn find(&self, collection: String, query: Query, options: Find) -> OResult<Vec<bson::Document>> {
+        self.driver.find(collection, query, options).await
+    }
+
+    async fn find_partial(&self, collection: String, query: Query, options: Find) -> OResult<PartialResult<bson::Document>> {
+        self.driver.find_partial(collection, query, options).await
+    }
+
+    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
+        self.driver.all(collection, options).await
+    }
+
+    async fn find_raw(&self, collection: String, query: Query, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        self.driver.find_raw(collection, query, options).await
+    }
+
+    async fn all_raw(&self, collection: String, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        self.driver.all_raw(collection, options).await
+    }
+
+    async fn upsert(&self, collection: String, query: Query, document: bson::Document, count: OperationCount, _options: WriteOptions) -> OResult<()> {
+        self.plan.record(PlannedWrite::Upsert { collection, query: query.try_into()?, document, count });
+        Ok(())
+    }
+
+    async fn upsert_many(&self, collection: String, upserts: Vec<(Query, bson::Document)>, _options: WriteOptions) -> OResult<()> {
+        for (query, document) in upserts {
+            self.plan.record(PlannedWrite::Upsert { collection: collection.clone(), query: query.try_into()?, document, count: OperationCount::One });
+        }
+        Ok(())
+    }
+
+    async fn count(&self, collection: String, query: Query, options: Find) -> OResult<u64> {
+        self.driver.count(collection, query, options).await
+    }
+
+    async fn find_with_count(&self, collection: String, query: Query, options: Find) -> OResult<(Vec<bson::Document>, u64)> {
+        self.driver.find_with_count(collection, query, options).await
+    }
+
+    async fn aggregate(&self, collection: String, pipeline: Vec<bson::Document>, options: Find) -> OResult<Vec<bson::Document>> {
+        if pipeline.iter().any(|stage| stage.contains_key("$out") || stage.contains_key("$merge")) {
+            self.plan.record(PlannedWrite::Aggregate { collection, pipeline });
+            return Ok(Vec::new());
+        }
+        self.driver.aggregate(collection, pipeline, options).await
+    }
+
+    async fn find_one_and_update(&self, collection: String, query: Query, update: bson::Document, upsert: bool, options: WriteOptions) -> OResult<Option<bson::Document>> {
+        let filter: bson::Document = query.clone().try_into()?;
+        let mut find = Find::one();
+        find.session = options.session;
+        let found = self.driver.find(collection.clone(), query, find).await?.into_iter().next();
+        let result = match found {
+            Some(mut document) => {
+                memory::apply_update(&mut document, &update)?;
+                Some(document)
+            },
+            None if upsert => Some(memory::upserted(&filter, &update)?),
+            None => None
+        };
+
+        self.plan.record(PlannedWrite::FindOneAndUpdate { collection, query: filter, update, upsert });
+        Ok(result)
+    }
+
+    async fn create_index(&self, collection: String, index: Index) -> OResult<()> {
+        self.plan.record(PlannedWrite::CreateIndex { collection, index });
+        Ok(())
+    }
+
+    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
+        self.plan.record(PlannedWrite::DropIndex { collection, name });
+        Ok(())
+    }
+
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        self.driver.index_stats(collection).await
+    }
+
+    async fn index_build_progress(&self, collection: String, name: String) -> OResult<Option<IndexProgress>> {
+        self.driver.index_build_progress(collection, name).await
+    }
+
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        self.driver.collection_stats(collection).await
+    }
+
+    async fn explain(&self, collection: String, query: Query, options: Find) -> OResult<QueryPlan> {
+        self.driver.explain(collection, query, options).await
+    }
+
+    async fn start_session(&self) -> OResult<Uuid> {
+        self.driver.start_session().await
+    }
+
+    async fn end_session(&self, session: Uuid) -> OResult<()> {
+        self.driver.end_session(session).await
+    }
+
+    async fn start_transaction(&self, session: Uuid) -> OResult<()> {
+        self.driver.start_transaction(session).await
+    }
+
+    async fn commit_transaction(&self, session: Uuid) -> OResult<()> {
+        self.driver.commit_transaction(session).await
+    }
+
+    async fn abort_transaction(&self, session: Uuid) -> OResult<()> {
+        self.driver.abort_transaction(session).await
+    }
+
+    async fn watch(&self, collection: String, resume_after: Option<ResumeToken>) -> OResult<ChangeStream> {
+        self.driver.watch(collection, resume_after).await
+    }
+
+    async fn maintain(&self) -> OResult<()> {
+        self.plan.record(PlannedWrite::Maintain);
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> OResult<()> {
+        self.driver.shutdown().await
+    }
+
+    async fn ping(&self) -> OResult<()> {
+        self.driver.ping().await
+    }
+
+    async fn health(&self) -> HealthReport {
+        self.driver.health().await
+    }
+}
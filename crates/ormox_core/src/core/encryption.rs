@@ -0,0 +1,289 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use bson::{doc, spec::BinarySubtype, Binary, Bson, Document as BsonDocument};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::client::{Client, Collection};
+
+use super::{
+    document::Document,
+    error::{OResult, OrmoxError},
+};
+
+const RANDOM: u8 = 1;
+const DETERMINISTIC: u8 = 2;
+const NONCE_LENGTH: usize = 12;
+
+/// How an `#[ormox(encrypted)]` field is encrypted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EncryptionMode {
+    /// A fresh nonce per write: equal values encrypt differently, so the field can't be queried by value
+    Random,
+
+    /// The nonce is derived from the value: equal values encrypt the same under the same key, so equality queries
+    /// work, at the cost of revealing which documents share a value
+    Deterministic
+}
+
+/// Source of the 256-bit AES keys of encrypted fields, set with `ClientOptions::key_provider`. Every stored value
+/// records the ID of its key, so old keys stay readable after rotating to a new one.
+pub trait KeyProvider: Debug + Send + Sync {
+    /// ID of the key new values are encrypted with
+    fn current_key(&self) -> String;
+
+    /// Key with the given ID
+    fn key(&self, id: &str) -> OResult<[u8; 32]>;
+}
+
+/// `KeyProvider` over keys held in memory
+#[derive(Clone)]
+pub struct StaticKeyProvider {
+    current: String,
+    keys: HashMap<String, [u8; 32]>
+}
+
+impl StaticKeyProvider {
+    /// Provider encrypting with `key`, under the ID `id`
+    pub fn new(id: impl AsRef<str>, key: [u8; 32]) -> Self {
+        Self { current: id.as_ref().to_string(), keys: HashMap::from([(id.as_ref().to_string(), key)]) }
+    }
+
+    /// Adds a key that's only used to read values written before a rotation
+    pub fn with_key(mut self, id: impl AsRef<str>, key: [u8; 32]) -> Self {
+        self.keys.insert(id.as_ref().to_string(), key);
+        self
+    }
+}
+
+impl Debug for StaticKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticKeyProvider").field("current", &self.current).field("keys", &self.keys.keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key(&self) -> String {
+        self.current.clone()
+    }
+
+    fn key(&self, id: &str) -> OResult<[u8; 32]> {
+        self.keys.get(id).copied().ok_or_else(|| OrmoxError::encryption(format!("Unknown key {id:?}")))
+    }
+}
+
+fn is_encrypted(value: &Bson) -> bool {
+    matches!(value, Bson::Binary(Binary { subtype: BinarySubtype::Encrypted, .. }))
+}
+
+/// ID of the key an encrypted value was written with, or `None` if the value isn't encrypted
+pub fn key_id(value: &Bson) -> Option<String> {
+    let Bson::Binary(Binary { subtype: BinarySubtype::Encrypted, bytes }) = value else {
+        return None;
+    };
+    let length = usize::from(*bytes.get(1)?);
+    bytes.get(2..2 + length).map(|id| String::from_utf8_lossy(id).to_string())
+}
+
+/// Encrypts a value stored at `path`, as an encrypted BSON binary of `[mode, key ID length, key ID, nonce, ciphertext]`.
+/// The path is authenticated, so values can't be moved between fields. Nulls are left as is.
+pub fn encrypt_value(provider: &dyn KeyProvider, mode: EncryptionMode, path: &str, value: &Bson) -> OResult<Bson> {
+    if value == &Bson::Null || is_encrypted(value) {
+        return Ok(value.clone());
+    }
+
+    let id = provider.current_key();
+    let key = provider.key(&id)?;
+    let id_length = u8::try_from(id.len()).map_err(|_| OrmoxError::encryption("Key IDs can be at most 255 bytes"))?;
+    // Integers are encrypted as 64-bit, so deterministic values match whichever width a query uses
+    let value = match value {
+        Bson::Int32(v) if mode == EncryptionMode::Deterministic => Bson::Int64(i64::from(*v)),
+        value => value.clone()
+    };
+    let plaintext = bson::to_vec(&doc! {"v": value}).map_err(OrmoxError::serialization)?;
+    let (marker, nonce) = match mode {
+        EncryptionMode::Random => (RANDOM, Aes256Gcm::generate_nonce(&mut OsRng)),
+        EncryptionMode::Deterministic => {
+            // Synthetic nonce: a MAC of the value under a key derived from the field's key
+            let mut derived = <Hmac<Sha256> as Mac>::new_from_slice(&key).map_err(OrmoxError::encryption)?;
+            derived.update(b"ormox deterministic nonce");
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&derived.finalize().into_bytes()).map_err(OrmoxError::encryption)?;
+            mac.update(path.as_bytes());
+            mac.update(&plaintext);
+            (DETERMINISTIC, *Nonce::from_slice(&mac.finalize().into_bytes()[..NONCE_LENGTH]))
+        }
+    };
+
+    let cipher = Aes256Gcm::new(&key.into());
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: &plaintext, aad: path.as_bytes() })
+        .map_err(|_| OrmoxError::encryption(format!("Failed to encrypt {path}")))?;
+
+    let mut bytes = vec![marker, id_length];
+    bytes.extend_from_slice(id.as_bytes());
+    bytes.extend_from_slice(&nonce);
+    bytes.extend(ciphertext);
+    Ok(Bson::Binary(Binary { subtype: BinarySubtype::Encrypted, bytes }))
+}
+
+/// Decrypts a value written by `encrypt_value` at `path`. Values that aren't encrypted are returned as is.
+pub fn decrypt_value(provider: &dyn KeyProvider, path: &str, value: &Bson) -> OResult<Bson> {
+    let Bson::Binary(Binary { subtype: BinarySubtype::Encrypted, bytes }) = value else {
+        return Ok(value.clone());
+    };
+
+    let invalid = || OrmoxError::encryption(format!("Invalid encrypted value in {path}"));
+    let id = key_id(value).ok_or_else(invalid)?;
+    let start = 2 + id.len();
+    if bytes.len() < start + NONCE_LENGTH || !matches!(bytes[0], RANDOM | DETERMINISTIC) {
+        return Err(invalid());
+    }
+
+    let cipher = Aes256Gcm::new(&provider.key(&id)?.into());
+    let nonce = Nonce::from_slice(&bytes[start..start + NONCE_LENGTH]);
+    let plaintext = cipher
+        .decrypt(nonce, Payload { msg: &bytes[start + NONCE_LENGTH..], aad: path.as_bytes() })
+        .map_err(|_| OrmoxError::encryption(format!("Failed to decrypt {path} with key {id:?}")))?;
+    let mut wrapper = bson::from_slice::<BsonDocument>(&plaintext).map_err(OrmoxError::deserialization)?;
+    wrapper.remove("v").ok_or_else(invalid)
+}
+
+fn value_mut<'a>(document: &'a mut BsonDocument, path: &str) -> Option<&'a mut Bson> {
+    match path.split_once('.') {
+        Some((head, rest)) => value_mut(document.get_document_mut(head).ok()?, rest),
+        None => document.get_mut(path)
+    }
+}
+
+fn provider_for(provider: Option<&dyn KeyProvider>) -> OResult<&dyn KeyProvider> {
+    provider.ok_or_else(|| OrmoxError::encryption("Document has encrypted fields, but no key provider is configured"))
+}
+
+/// Encrypts the `fields` of a full document
+pub(crate) fn encrypt_document(provider: Option<&dyn KeyProvider>, fields: &[(&str, EncryptionMode)], mut data: BsonDocument) -> OResult<BsonDocument> {
+    for (path, mode) in fields {
+        if let Some(value) = value_mut(&mut data, path) {
+            *value = encrypt_value(provider_for(provider)?, *mode, path, value)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Decrypts the `fields` of a stored document
+pub(crate) fn decrypt_document(provider: Option<&dyn KeyProvider>, fields: &[(&str, EncryptionMode)], mut data: BsonDocument) -> OResult<BsonDocument> {
+    for (path, _) in fields {
+        if let Some(value) = value_mut(&mut data, path).filter(|v| is_encrypted(v)) {
+            *value = decrypt_value(provider_for(provider)?, path, value)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Encrypts the values an update writes to `fields`. Only `$set`, `$setOnInsert` & `$unset` may touch encrypted
+/// fields, and only as a whole; updates without operators are replacements.
+pub(crate) fn encrypt_update(provider: Option<&dyn KeyProvider>, fields: &[(&str, EncryptionMode)], update: BsonDocument) -> OResult<BsonDocument> {
+    if !update.keys().any(|k| k.starts_with('$')) {
+        return encrypt_document(provider, fields, update);
+    }
+
+    let mut result = BsonDocument::new();
+    for (operator, values) in update {
+        let Bson::Document(mut values) = values else {
+            result.insert(operator, values);
+            continue;
+        };
+
+        let writes = matches!(operator.as_str(), "$set" | "$setOnInsert");
+        for (path, mode) in fields {
+            for (key, value) in values.iter_mut() {
+                let within = key.strip_prefix(*path).is_some_and(|rest| rest.starts_with('.'));
+                let parent = path.strip_prefix(key.as_str()).and_then(|rest| rest.strip_prefix('.'));
+                if key == *path && writes {
+                    *value = encrypt_value(provider_for(provider)?, *mode, path, value)?;
+                } else if let (Some(rest), true, Bson::Document(parent)) = (parent, writes, &mut *value) {
+                    if let Some(value) = value_mut(parent, rest) {
+                        *value = encrypt_value(provider_for(provider)?, *mode, path, value)?;
+                    }
+                } else if within || (key == *path && operator != "$unset") {
+                    return Err(OrmoxError::encryption(format!("Encrypted field {path} can only be set or unset as a whole, not updated with {operator}")));
+                }
+            }
+        }
+        result.insert(operator, values);
+    }
+    Ok(result)
+}
+
+/// Encrypts the values a query compares `fields` against. Deterministic fields support equality (`$eq`, `$ne`, `$in`,
+/// `$nin`) and `$exists`; random fields only `$exists` & null checks.
+pub(crate) fn encrypt_query(provider: Option<&dyn KeyProvider>, fields: &[(&str, EncryptionMode)], query: BsonDocument) -> OResult<BsonDocument> {
+    let mut result = BsonDocument::new();
+    for (key, value) in query {
+        let value = match (key.as_str(), fields.iter().find(|(path, _)| *path == key)) {
+            ("$and" | "$or" | "$nor", _) => match value {
+                Bson::Array(cases) => Bson::Array(
+                    cases
+                        .into_iter()
+                        .map(|case| match case {
+                            Bson::Document(case) => encrypt_query(provider, fields, case).map(Bson::Document),
+                            other => Ok(other)
+                        })
+                        .collect::<OResult<_>>()?
+                ),
+                other => other
+            },
+            (_, Some((path, mode))) => encrypt_condition(provider, path, *mode, value)?,
+            _ => value
+        };
+        result.insert(key, value);
+    }
+    Ok(result)
+}
+
+fn encrypt_condition(provider: Option<&dyn KeyProvider>, path: &str, mode: EncryptionMode, condition: Bson) -> OResult<Bson> {
+    let encrypt = |value: Bson| {
+        if value == Bson::Null {
+            return Ok(value);
+        }
+        if mode == EncryptionMode::Random {
+            return Err(OrmoxError::encryption(format!("Randomly encrypted field {path} can't be queried by value; use deterministic encryption")));
+        }
+        encrypt_value(provider_for(provider)?, mode, path, &value)
+    };
+
+    match condition {
+        Bson::Document(operators) if operators.keys().next().is_some_and(|k| k.starts_with('$')) => {
+            let mut result = BsonDocument::new();
+            for (operator, value) in operators {
+                let value = match (operator.as_str(), value) {
+                    ("$eq" | "$ne", value) => encrypt(value)?,
+                    ("$in" | "$nin", Bson::Array(values)) => Bson::Array(values.into_iter().map(encrypt).collect::<OResult<_>>()?),
+                    ("$not", value) => encrypt_condition(provider, path, mode, value)?,
+                    ("$exists", value) => value,
+                    (operator, _) => {
+                        return Err(OrmoxError::encryption(format!("Encrypted field {path} only supports equality queries, not {operator}")));
+                    }
+                };
+                result.insert(operator, value);
+            }
+            Ok(Bson::Document(result))
+        },
+        value => encrypt(value)
+    }
+}
+
+/// Decrypts the encrypted fields of a document of `T` loaded from `collection` (or the global client)
+pub(crate) fn decrypt_fields<T: Document>(data: BsonDocument, collection: Option<&Collection<T>>) -> OResult<BsonDocument> {
+    let fields = T::encrypted_fields();
+    if fields.is_empty() {
+        return Ok(data);
+    }
+
+    let client = collection.map(Collection::client).or_else(|| Client::global().map(|c| c.as_ref().clone()));
+    let provider = client.as_ref().and_then(|c| c.options().key_provider.clone());
+    decrypt_document(provider.as_deref(), &fields, data)
+}
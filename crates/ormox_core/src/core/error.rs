@@ -1,8 +1,11 @@
 use std::fmt::{Debug, Display};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Error, Debug, Clone)]
+use super::validation::FieldError;
+
+#[derive(Error, Debug, Clone, Serialize, Deserialize)]
 pub enum OrmoxError {
     #[error("Failed to retrieve collection {name:?}: {reason:?}")]
     CollectionRetrieval { name: String, reason: String },
@@ -31,8 +34,63 @@ pub enum OrmoxError {
     #[error("Method is not implemented on this driver")]
     Unimplemented,
 
+    #[error("Driver does not support required capability: {capability}")]
+    Unsupported {capability: String},
+
+    #[error("Duplicate key in {collection} (index {index}): {key}")]
+    DuplicateKey {collection: String, index: String, key: String},
+
     #[error("Driver-specific error: {driver_name}: {error:?}")]
-    Driver {driver_name: String, error: String}
+    Driver {driver_name: String, error: String},
+
+    #[error("Transient driver error, safe to retry: {driver_name}: {error:?}")]
+    Transient {driver_name: String, error: String},
+
+    #[error("Validation failed in {collection}: {}", .errors.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    Validation {collection: String, errors: Vec<FieldError>},
+
+    #[error("Broken reference in {field}: {id} does not exist")]
+    BrokenReference {field: String, id: String},
+
+    #[error("Encryption error: {error}")]
+    Encryption {error: String},
+
+    #[error("Client is closed")]
+    Closed,
+
+    #[error("Conflicting changes to {}", .fields.join(", "))]
+    MergeConflict {fields: Vec<String>},
+
+    #[error("Lock {name} is no longer held")]
+    LockLost {name: String}
+}
+
+/// Broad category of an `OrmoxError`, for retry & fallback logic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The requested document doesn't exist
+    NotFound,
+
+    /// The write conflicts with existing data, ie a unique index violation
+    Conflict,
+
+    /// The driver doesn't support the operation
+    Unsupported,
+
+    /// A query, id or value couldn't be used
+    InvalidInput,
+
+    /// A value couldn't be converted to or from BSON
+    Serialization,
+
+    /// The document has no collection to operate on
+    Uninitialized,
+
+    /// A temporary failure (network, failover, write conflict in a transaction); retrying may succeed
+    Transient,
+
+    /// Any other driver failure
+    Driver
 }
 
 impl OrmoxError {
@@ -60,6 +118,85 @@ impl OrmoxError {
         Self::Id { provided: id.as_ref().to_string() }
     }
 
+    pub fn unsupported(capability: impl AsRef<str>) -> Self {
+        Self::Unsupported { capability: capability.as_ref().to_string() }
+    }
+
+    pub fn duplicate_key(collection: impl AsRef<str>, index: impl AsRef<str>, key: impl AsRef<str>) -> Self {
+        Self::DuplicateKey {
+            collection: collection.as_ref().to_string(),
+            index: index.as_ref().to_string(),
+            key: key.as_ref().to_string()
+        }
+    }
+
+    pub fn transient(driver: impl AsRef<str>, error: impl std::error::Error) -> Self {
+        Self::Transient { driver_name: driver.as_ref().to_string(), error: error.to_string() }
+    }
+
+    pub fn validation(collection: impl AsRef<str>, errors: Vec<FieldError>) -> Self {
+        Self::Validation { collection: collection.as_ref().to_string(), errors }
+    }
+
+    pub fn broken_reference(field: impl AsRef<str>, id: impl AsRef<str>) -> Self {
+        Self::BrokenReference { field: field.as_ref().to_string(), id: id.as_ref().to_string() }
+    }
+
+    pub fn encryption(error: impl Display) -> Self {
+        Self::Encryption { error: error.to_string() }
+    }
+
+    pub fn merge_conflict(fields: Vec<String>) -> Self {
+        Self::MergeConflict { fields }
+    }
+
+    pub fn lock_lost(name: impl AsRef<str>) -> Self {
+        Self::LockLost { name: name.as_ref().to_string() }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound { .. } => ErrorKind::NotFound,
+            Self::DuplicateKey { .. } | Self::MergeConflict { .. } | Self::LockLost { .. } => ErrorKind::Conflict,
+            Self::Unsupported { .. } | Self::Unimplemented => ErrorKind::Unsupported,
+            Self::Compatibility { .. } | Self::Id { .. } | Self::Validation { .. } | Self::BrokenReference { .. } => ErrorKind::InvalidInput,
+            Self::Serialization { .. } | Self::Deserialization { .. } | Self::Encryption { .. } => ErrorKind::Serialization,
+            Self::Uninitialized => ErrorKind::Uninitialized,
+            Self::Transient { .. } => ErrorKind::Transient,
+            Self::CollectionRetrieval { .. } | Self::Insert { .. } | Self::Driver { .. } | Self::Closed => ErrorKind::Driver
+        }
+    }
+
+    /// Whether this is a unique index violation
+    pub fn is_duplicate(&self) -> bool {
+        matches!(self, Self::DuplicateKey { .. })
+    }
+
+    /// Per-field errors, if this is a validation failure
+    pub fn validation_errors(&self) -> Option<&[FieldError]> {
+        match self {
+            Self::Validation { errors, .. } => Some(errors),
+            _ => None
+        }
+    }
+
+    /// Whether retrying the operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        self.kind() == ErrorKind::Transient
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        self.kind() == ErrorKind::Conflict
+    }
+
+    pub fn is_unsupported(&self) -> bool {
+        self.kind() == ErrorKind::Unsupported
+    }
+
     pub fn driver(driver: impl AsRef<str>, error: impl std::error::Error) -> Self {
         Self::Driver { driver_name: driver.as_ref().to_string(), error: error.to_string() }
     }
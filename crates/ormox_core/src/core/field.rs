@@ -0,0 +1,141 @@
+use std::{borrow::Cow, fmt::Debug, marker::PhantomData};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{error::{OResult, OrmoxError}, query::{Query, QueryKey, QueryValue}};
+
+/// Typed handle on a stored field of the document `D`, generated by `#[derive(Document)]` as an associated constant per
+/// field (ie `User::EMAIL`), and for fields of embedded documents by typed paths (ie `User::FIELDS.address().city()`,
+/// see `FieldPaths`). Queries built from it encode values the way the field is stored, including through
+/// `#[ormox(with = "...")]` converters, so fields of custom types stay queryable.
+pub struct Field<D, T> {
+    path: Cow<'static, str>,
+    encode: fn(&T) -> OResult<Value>,
+    _document: PhantomData<fn() -> D>
+}
+
+impl<D, T> Field<D, T> {
+    pub const fn new(path: &'static str, encode: fn(&T) -> OResult<Value>) -> Self {
+        Self { path: Cow::Borrowed(path), encode, _document: PhantomData }
+    }
+
+    /// Field at a path built at runtime, ie in an embedded document
+    pub fn at(path: String, encode: fn(&T) -> OResult<Value>) -> Self {
+        Self { path: Cow::Owned(path), encode, _document: PhantomData }
+    }
+
+    /// Stored path of the field, in dot notation
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// `value` as it's stored in the field, before integers BSON can't hold are written per the collection's
+    /// `NumericPolicy`
+    pub fn encode(&self, value: &T) -> OResult<Value> {
+        (self.encode)(value)
+    }
+
+    fn compare(&self, operator: QueryKey, value: Value) -> OResult<Query> {
+        Ok(Query::new().subquery(&self.path, Query::new().push(operator, QueryValue::Value(value))))
+    }
+
+    fn compare_all<'a>(&self, operator: QueryKey, values: impl IntoIterator<Item = &'a T>) -> OResult<Query> where T: 'a {
+        let values = values.into_iter().map(|value| self.encode(value)).collect::<OResult<Vec<_>>>()?;
+        self.compare(operator, Value::Array(values))
+    }
+
+    pub fn eq(&self, value: &T) -> OResult<Query> {
+        self.compare(QueryKey::Equals, self.encode(value)?)
+    }
+
+    pub fn ne(&self, value: &T) -> OResult<Query> {
+        self.compare(QueryKey::NotEquals, self.encode(value)?)
+    }
+
+    /// Compares stored values, which for fields stored through a converter may not order like `T`
+    pub fn gt(&self, value: &T) -> OResult<Query> {
+        self.compare(QueryKey::GreaterThan, self.encode(value)?)
+    }
+
+    pub fn gte(&self, value: &T) -> OResult<Query> {
+        self.compare(QueryKey::GreaterThanEqual, self.encode(value)?)
+    }
+
+    pub fn lt(&self, value: &T) -> OResult<Query> {
+        self.compare(QueryKey::LessThan, self.encode(value)?)
+    }
+
+    pub fn lte(&self, value: &T) -> OResult<Query> {
+        self.compare(QueryKey::LessThanEqual, self.encode(value)?)
+    }
+
+    pub fn is_in<'a>(&self, values: impl IntoIterator<Item = &'a T>) -> OResult<Query> where T: 'a {
+        self.compare_all(QueryKey::In, values)
+    }
+
+    pub fn not_in<'a>(&self, values: impl IntoIterator<Item = &'a T>) -> OResult<Query> where T: 'a {
+        self.compare_all(QueryKey::NotIn, values)
+    }
+}
+
+impl<D, T> Clone for Field<D, T> {
+    fn clone(&self) -> Self {
+        Self { path: self.path.clone(), encode: self.encode, _document: PhantomData }
+    }
+}
+
+impl<D, T> Debug for Field<D, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Field").field(&self.path).finish()
+    }
+}
+
+/// Types whose fields have typed paths, so they can be queried field by field when embedded in a document: documents,
+/// and embedded documents (`#[derive(EmbeddedDocument)]`). A document's fields holding such a type are marked
+/// `#[ormox(nested)]`, and their path method returns the embedded type's paths under the field's, ie
+/// `User::FIELDS.address().city()` for `address.city`. Nested fields are also validated, so types implementing this
+/// by hand implement `ValidateFields` too.
+pub trait FieldPaths {
+    /// Typed paths of the type's fields, within documents of `D`
+    type Paths<D>;
+
+    /// Paths of the type's fields under `prefix`, which is empty or ends with a dot (ie `"address."`)
+    fn paths<D>(prefix: String) -> Self::Paths<D>;
+}
+
+/// Optional embedded documents share the paths of the documents they hold
+impl<T: FieldPaths> FieldPaths for Option<T> {
+    type Paths<D> = T::Paths<D>;
+
+    fn paths<D>(prefix: String) -> Self::Paths<D> {
+        T::paths(prefix)
+    }
+}
+
+/// Paths through arrays of embedded documents match documents where any element matches, as with dot notation
+impl<T: FieldPaths> FieldPaths for Vec<T> {
+    type Paths<D> = T::Paths<D>;
+
+    fn paths<D>(prefix: String) -> Self::Paths<D> {
+        T::paths(prefix)
+    }
+}
+
+impl<T: FieldPaths> FieldPaths for Box<T> {
+    type Paths<D> = T::Paths<D>;
+
+    fn paths<D>(prefix: String) -> Self::Paths<D> {
+        T::paths(prefix)
+    }
+}
+
+/// Encoder of fields stored through their `Serialize` implementation
+pub fn encode<T: Serialize>(value: &T) -> OResult<Value> {
+    serde_json::to_value(value).map_err(OrmoxError::serialization)
+}
+
+/// Encoder of fields stored through a `#[ormox(with = "...")]` module, from the BSON the module serialized them to
+pub fn encode_bson(value: Result<bson::Bson, bson::ser::Error>) -> OResult<Value> {
+    serde_json::to_value(value.map_err(OrmoxError::serialization)?).map_err(OrmoxError::serialization)
+}
@@ -0,0 +1,196 @@
+use bson::{Bson, Document};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{OResult, OrmoxError},
+    memory::set_path,
+    validation::FieldError,
+};
+
+/// Type a CSV column's cells are coerced to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    /// An integer if the cell parses as one, then a float, then a boolean (`true`/`false`), else a string
+    #[default]
+    Auto,
+    String,
+    Integer,
+    Float,
+    /// `true`/`false`, `yes`/`no` or `1`/`0`, in any case
+    Boolean,
+    /// RFC 3339 date & time, or a date (`2024-01-31`) or naive date & time taken as UTC. Stored as RFC 3339, the way
+    /// `chrono` serializes `DateTime<Utc>`.
+    DateTime,
+    /// A JSON value, ie for array or object fields
+    Json
+}
+
+impl ColumnType {
+    /// Coerces a non-empty cell, describing the expected type on failure
+    fn coerce(&self, cell: &str) -> Result<Bson, &'static str> {
+        let parsed = match self {
+            Self::Auto => Some(if let Ok(v) = cell.parse::<i64>() {
+                Bson::Int64(v)
+            } else if let Ok(v) = cell.parse::<f64>() {
+                Bson::Double(v)
+            } else if let Ok(v) = cell.parse::<bool>() {
+                Bson::Boolean(v)
+            } else {
+                Bson::String(cell.to_string())
+            }),
+            Self::String => Some(Bson::String(cell.to_string())),
+            Self::Integer => cell.parse::<i64>().ok().map(Bson::Int64),
+            Self::Float => cell.parse::<f64>().ok().map(Bson::Double),
+            Self::Boolean => match cell.to_lowercase().as_str() {
+                "true" | "yes" | "1" => Some(Bson::Boolean(true)),
+                "false" | "no" | "0" => Some(Bson::Boolean(false)),
+                _ => None
+            },
+            Self::DateTime => DateTime::parse_from_rfc3339(cell)
+                .map(|v| v.to_utc())
+                .or_else(|_| NaiveDateTime::parse_from_str(cell, "%Y-%m-%d %H:%M:%S").map(|v| v.and_utc()))
+                .or_else(|_| NaiveDateTime::parse_from_str(cell, "%Y-%m-%dT%H:%M:%S").map(|v| v.and_utc()))
+                .or_else(|_| NaiveDate::parse_from_str(cell, "%Y-%m-%d").map(|v| v.and_time(NaiveTime::MIN).and_utc()))
+                .ok()
+                .map(|v| Bson::String(v.to_rfc3339())),
+            Self::Json => serde_json::from_str::<serde_json::Value>(cell).ok().and_then(|v| Bson::try_from(v).ok())
+        };
+
+        parsed.ok_or_else(|| self.expected())
+    }
+
+    fn expected(&self) -> &'static str {
+        match self {
+            Self::Auto | Self::String => "a string",
+            Self::Integer => "an integer",
+            Self::Float => "a number",
+            Self::Boolean => "a boolean",
+            Self::DateTime => "a date",
+            Self::Json => "JSON"
+        }
+    }
+}
+
+/// A CSV column mapped to a document field
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ColumnMapping {
+    /// Header of the column
+    pub column: String,
+
+    /// Stored (dotted) path of the field it fills
+    pub field: String,
+
+    pub kind: ColumnType
+}
+
+/// How `Collection::import_csv` turns CSV rows into documents. Columns are matched by their header; empty cells leave
+/// their field unset, so optional & defaulted fields take their default. Fields without a column, like the document's
+/// id, take their serde default.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CsvMapping {
+    pub columns: Vec<ColumnMapping>,
+
+    /// Type of the columns without a mapping, filling the field named like their header. `None` ignores them.
+    pub other_columns: Option<ColumnType>,
+
+    pub delimiter: u8,
+
+    /// Whether surrounding whitespace is trimmed from cells & headers
+    pub trim: bool
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        Self { columns: Vec::new(), other_columns: None, delimiter: b',', trim: true }
+    }
+}
+
+impl CsvMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `column` to the stored path `field`, coercing its cells to `kind`
+    pub fn column(mut self, column: impl AsRef<str>, field: impl AsRef<str>, kind: ColumnType) -> Self {
+        self.columns.push(ColumnMapping { column: column.as_ref().to_string(), field: field.as_ref().to_string(), kind });
+        self
+    }
+
+    /// Maps every column without a mapping to the field named like its header, coercing its cells to `kind`
+    pub fn other_columns(mut self, kind: ColumnType) -> Self {
+        self.other_columns = Some(kind);
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Field & type of each column of a file with these headers, failing if a mapped column is missing
+    pub(crate) fn resolve(&self, headers: &csv::StringRecord) -> OResult<Vec<Option<(String, ColumnType)>>> {
+        if let Some(missing) = self.columns.iter().find(|mapping| !headers.iter().any(|header| header == mapping.column)) {
+            return Err(OrmoxError::compaibility(format!("CSV has no column {:?}", missing.column)));
+        }
+
+        Ok(headers
+            .iter()
+            .map(|header| match self.columns.iter().find(|mapping| mapping.column == header) {
+                Some(mapping) => Some((mapping.field.clone(), mapping.kind)),
+                None => self.other_columns.map(|kind| (header.to_string(), kind))
+            })
+            .collect())
+    }
+
+    pub(crate) fn reader<R: std::io::Read>(&self, reader: R) -> csv::Reader<R> {
+        csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .trim(if self.trim { csv::Trim::All } else { csv::Trim::None })
+            .from_reader(reader)
+    }
+}
+
+/// Builds a document from a CSV row, failing with every cell that couldn't be coerced
+pub(crate) fn row_document(collection: &str, columns: &[Option<(String, ColumnType)>], record: &csv::StringRecord) -> OResult<Document> {
+    let mut document = Document::new();
+    let mut errors = Vec::new();
+    for (cell, column) in record.iter().zip(columns) {
+        let Some((field, kind)) = column.as_ref().filter(|_| !cell.is_empty()) else {
+            continue;
+        };
+        match kind.coerce(cell) {
+            Ok(value) => set_path(&mut document, field, value)?,
+            Err(expected) => errors.push(FieldError::new(field, "type", format!("expected {expected}, got {cell:?}")))
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(document)
+    } else {
+        Err(OrmoxError::validation(collection, errors))
+    }
+}
+
+/// A CSV row `Collection::import_csv` couldn't import
+#[derive(Clone, Debug)]
+pub struct ImportFailure {
+    /// Line of the row in the file, counting the header as line 1
+    pub line: u64,
+
+    pub error: OrmoxError
+}
+
+/// What `Collection::import_csv` imported
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    /// Number of rows inserted
+    pub imported: u64,
+
+    pub failed: Vec<ImportFailure>
+}
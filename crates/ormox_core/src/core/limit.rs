@@ -0,0 +1,213 @@
+use std::any::Any;
+
+use async_lock::Semaphore;
+use async_trait::async_trait;
+use bson::RawDocumentBuf;
+use uuid::Uuid;
+
+use super::{
+    change::{ChangeStream, ResumeToken},
+    document::Index,
+    driver::{CollectionStats, DatabaseDriver, DriverCapabilities, Find, HealthReport, IndexProgress, IndexStats, OperationCount, PartialResult, QueryPlan, WriteOptions},
+    error::OResult,
+    query::Query,
+};
+
+/// Wraps a driver, allowing at most a fixed number of operations to run against it at once.
+/// Further operations wait for a running one to finish.
+pub struct LimitedDriver<D: DatabaseDriver + Send + Sync> {
+    driver: D,
+    permits: Semaphore,
+    limit: usize
+}
+
+impl<D: DatabaseDriver + Send + Sync> LimitedDriver<D> {
+    /// Limits `driver` to `limit` in-flight operations. A limit of 0 is treated as 1.
+    pub fn new(driver: D, limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self { driver, permits: Semaphore::new(limit), limit }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.driver
+    }
+}
+
+#[async_trait]
+impl<D: DatabaseDriver + Send + Sync + 'static> DatabaseDriver for LimitedDriver<D> {
+    fn driver_name(&self) -> String {
+        self.driver.driver_name()
+    }
+
+    // Downcasts see through the limiter to the wrapped driver
+    fn as_any(&self) -> &dyn Any {
+        self.driver.as_any()
+    }
+
+    fn capabilities(&self) -> DriverCapabilities {
+        self.driver.capabilities()
+    }
+
+    async fn collections(&self) -> OResult<Vec<String>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.collections().await
+    }
+
+    async fn insert(&self, collection: String, documents: Vec<bson::Document>, options: WriteOptions) -> OResult<Vec<Uuid>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.insert(collection, documents, options).await
+    }
+
+    async fn update(&self, collection: String, query: Query, update: bson::Document, count: OperationCount, options: WriteOptions) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.update(collection, query, update, count, options).await
+    }
+
+    async fn delete(&self, collection: String, query: Query, count: OperationCount, options: WriteOptions) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.delete(collection, query, count, options).await
+    }
+
+    async fn find(&self, collection: String, query: Query, options: Find) -> OResult<Vec<bson::Document>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.find(collection, query, options).await
+    }
+
+    async fn find_partial(&self, collection: String, query: Query, options: Find) -> OResult<PartialResult<bson::Document>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.find_partial(collection, query, options).await
+    }
+
+    async fn all(&self, collection: String, options: Find) -> OResult<Vec<bson::Document>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.all(collection, options).await
+    }
+
+    async fn find_raw(&self, collection: String, query: Query, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.find_raw(collection, query, options).await
+    }
+
+    async fn all_raw(&self, collection: String, options: Find) -> OResult<Vec<RawDocumentBuf>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.all_raw(collection, options).await
+    }
+
+    async fn upsert(&self, collection: String, query: Query, document: bson::Document, count: OperationCount, options: WriteOptions) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.upsert(collection, query, document, count, options).await
+    }
+
+    async fn upsert_many(&self, collection: String, upserts: Vec<(Query, bson::Document)>, options: WriteOptions) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.upsert_many(collection, upserts, options).await
+    }
+
+    async fn count(&self, collection: String, query: Query, options: Find) -> OResult<u64> {
+        let _permit = self.permits.acquire().await;
+        self.driver.count(collection, query, options).await
+    }
+
+    async fn find_with_count(&self, collection: String, query: Query, options: Find) -> OResult<(Vec<bson::Document>, u64)> {
+        let _permit = self.permits.acquire().await;
+        self.driver.find_with_count(collection, query, options).await
+    }
+
+    async fn aggregate(&self, collection: String, pipeline: Vec<bson::Document>, options: Find) -> OResult<Vec<bson::Document>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.aggregate(collection, pipeline, options).await
+    }
+
+    async fn find_one_and_update(&self, collection: String, query: Query, update: bson::Document, upsert: bool, options: WriteOptions) -> OResult<Option<bson::Document>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.find_one_and_update(collection, query, update, upsert, options).await
+    }
+
+    async fn create_index(&self, collection: String, index: Index) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.create_index(collection, index).await
+    }
+
+    async fn drop_index(&self, collection: String, name: String) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.drop_index(collection, name).await
+    }
+
+    async fn index_stats(&self, collection: String) -> OResult<Vec<IndexStats>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.index_stats(collection).await
+    }
+
+    async fn index_build_progress(&self, collection: String, name: String) -> OResult<Option<IndexProgress>> {
+        let _permit = self.permits.acquire().await;
+        self.driver.index_build_progress(collection, name).await
+    }
+
+    async fn collection_stats(&self, collection: String) -> OResult<CollectionStats> {
+        let _permit = self.permits.acquire().await;
+        self.driver.collection_stats(collection).await
+    }
+
+    async fn explain(&self, collection: String, query: Query, options: Find) -> OResult<QueryPlan> {
+        let _permit = self.permits.acquire().await;
+        self.driver.explain(collection, query, options).await
+    }
+
+    async fn start_session(&self) -> OResult<Uuid> {
+        let _permit = self.permits.acquire().await;
+        self.driver.start_session().await
+    }
+
+    async fn end_session(&self, session: Uuid) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.end_session(session).await
+    }
+
+    async fn start_transaction(&self, session: Uuid) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.start_transaction(session).await
+    }
+
+    async fn commit_transaction(&self, session: Uuid) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.commit_transaction(session).await
+    }
+
+    async fn abort_transaction(&self, session: Uuid) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.abort_transaction(session).await
+    }
+
+    // Only opening the stream takes a slot, as streams stay open indefinitely
+    async fn watch(&self, collection: String, resume_after: Option<ResumeToken>) -> OResult<ChangeStream> {
+        let _permit = self.permits.acquire().await;
+        self.driver.watch(collection, resume_after).await
+    }
+
+    async fn maintain(&self) -> OResult<()> {
+        let _permit = self.permits.acquire().await;
+        self.driver.maintain().await
+    }
+
+    // Takes every slot, so operations in flight finish first & later ones wait for the shutdown
+    async fn shutdown(&self) -> OResult<()> {
+        let mut permits = Vec::with_capacity(self.limit);
+        for _ in 0..self.limit {
+            permits.push(self.permits.acquire().await);
+        }
+        self.driver.shutdown().await
+    }
+
+    // Health checks skip the queue, so they report on the database rather than on how busy the client is
+    async fn ping(&self) -> OResult<()> {
+        self.driver.ping().await
+    }
+
+    async fn health(&self) -> HealthReport {
+        self.driver.health().await
+    }
+}
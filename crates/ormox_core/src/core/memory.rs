@@ -0,0 +1,575 @@
+//! Query, update & find option evaluation over documents held in memory, for drivers without a query engine of their
+//! own (ie IndexedDB). Follows MongoDB semantics for the supported operators; anything else is an `Unsupported` error.
+
+use std::cmp::Ordering;
+
+use bson::{Bson, DateTime, Document};
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::{
+    driver::{Collation, CollationStrength, Find, OperationCount, Sorting},
+    error::{OResult, OrmoxError},
+};
+
+fn invalid(error: impl AsRef<str>) -> OrmoxError {
+    OrmoxError::compaibility(error.as_ref())
+}
+
+/// Collects the values at a dotted `path`, descending into arrays of subdocuments like MongoDB does
+fn lookup<'a>(value: &'a Bson, path: &[&str], found: &mut Vec<&'a Bson>) {
+    let Some((first, rest)) = path.split_first() else {
+        found.push(value);
+        return;
+    };
+
+    match value {
+        Bson::Document(document) => {
+            if let Some(child) = document.get(*first) {
+                lookup(child, rest, found);
+            }
+        }
+        Bson::Array(items) => match first.parse::<usize>() {
+            Ok(index) => {
+                if let Some(child) = items.get(index) {
+                    lookup(child, rest, found);
+                }
+            }
+            Err(_) => {
+                for item in items.iter().filter(|i| i.as_document().is_some()) {
+                    lookup(item, path, found);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+fn values_at<'a>(document: &'a Document, path: &str) -> Vec<&'a Bson> {
+    let mut found = Vec::new();
+    let mut segments = path.split('.');
+    if let Some(child) = segments.next().and_then(|first| document.get(first)) {
+        lookup(child, &segments.collect::<Vec<_>>(), &mut found);
+    }
+    found
+}
+
+/// Value at a dotted `path`, without descending into arrays
+pub fn get_path<'a>(document: &'a Document, path: &str) -> Option<&'a Bson> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (get_path(document, parent)?.as_document()?, key),
+        None => (document, path)
+    };
+    parent.get(key)
+}
+
+/// Parent document of a dotted `path` & the last segment, creating missing parents
+fn parent_mut<'a, 'b>(document: &'a mut Document, path: &'b str) -> OResult<(&'a mut Document, &'b str)> {
+    let Some((parent, key)) = path.split_once('.') else {
+        return Ok((document, path));
+    };
+
+    if !document.contains_key(parent) {
+        document.insert(parent, Document::new());
+    }
+    match document.get_mut(parent) {
+        Some(Bson::Document(child)) => parent_mut(child, key),
+        _ => Err(invalid(format!("Cannot create field {key:?} in non-document {parent:?}")))
+    }
+}
+
+/// Sets the value at a dotted `path`, creating missing parents
+pub fn set_path(document: &mut Document, path: &str, value: Bson) -> OResult<()> {
+    let (parent, key) = parent_mut(document, path)?;
+    parent.insert(key, value);
+    Ok(())
+}
+
+/// Removes & returns the value at a dotted `path`
+pub fn remove_path(document: &mut Document, path: &str) -> Option<Bson> {
+    match path.split_once('.') {
+        Some((parent, key)) => match document.get_mut(parent) {
+            Some(Bson::Document(child)) => remove_path(child, key),
+            _ => None
+        },
+        None => document.remove(path)
+    }
+}
+
+/// Rank of a value's type in MongoDB's cross-type sort order
+fn type_rank(value: &Bson) -> u8 {
+    match value {
+        Bson::MinKey => 0,
+        Bson::Null | Bson::Undefined => 1,
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) => 2,
+        Bson::String(_) | Bson::Symbol(_) => 3,
+        Bson::Document(_) => 4,
+        Bson::Array(_) => 5,
+        Bson::Binary(_) => 6,
+        Bson::ObjectId(_) => 7,
+        Bson::Boolean(_) => 8,
+        Bson::DateTime(_) => 9,
+        Bson::Timestamp(_) => 10,
+        Bson::RegularExpression(_) => 11,
+        Bson::MaxKey => 13,
+        _ => 12
+    }
+}
+
+fn as_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None
+    }
+}
+
+/// Compares two values of the same type bracket, ie an `Int32` with a `Double`. Values that can't be compared give `None`.
+pub fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    match (a, b) {
+        (Bson::Int32(a), Bson::Int32(b)) => Some(a.cmp(b)),
+        (Bson::Int64(a), Bson::Int64(b)) => Some(a.cmp(b)),
+        (Bson::Int32(a), Bson::Int64(b)) => Some((*a as i64).cmp(b)),
+        (Bson::Int64(a), Bson::Int32(b)) => Some(a.cmp(&(*b as i64))),
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::Boolean(a), Bson::Boolean(b)) => Some(a.cmp(b)),
+        (Bson::DateTime(a), Bson::DateTime(b)) => Some(a.cmp(b)),
+        (Bson::Timestamp(a), Bson::Timestamp(b)) => Some((a.time, a.increment).cmp(&(b.time, b.increment))),
+        (Bson::ObjectId(a), Bson::ObjectId(b)) => Some(a.bytes().cmp(&b.bytes())),
+        (Bson::Binary(a), Bson::Binary(b)) => Some(a.bytes.cmp(&b.bytes)),
+        (Bson::Null, Bson::Null) | (Bson::MinKey, Bson::MinKey) | (Bson::MaxKey, Bson::MaxKey) => Some(Ordering::Equal),
+        (Bson::Array(a), Bson::Array(b)) => {
+            for (a, b) in a.iter().zip(b) {
+                match sort_order(a, b) {
+                    Ordering::Equal => continue,
+                    other => return Some(other)
+                }
+            }
+            Some(a.len().cmp(&b.len()))
+        }
+        _ => as_number(a)?.partial_cmp(&as_number(b)?)
+    }
+}
+
+/// Total order over any two values, for sorting
+fn sort_order(a: &Bson, b: &Bson) -> Ordering {
+    type_rank(a).cmp(&type_rank(b)).then_with(|| compare(a, b).unwrap_or(Ordering::Equal))
+}
+
+fn equal(a: &Bson, b: &Bson) -> bool {
+    match (a, b) {
+        (Bson::Document(a), Bson::Document(b)) => a.len() == b.len() && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && equal(va, vb)),
+        (Bson::Array(a), Bson::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b)),
+        _ if as_number(a).is_some() => compare(a, b) == Some(Ordering::Equal),
+        _ => a == b
+    }
+}
+
+/// Candidates a condition is tested against: each value, and the elements of array values
+fn candidates<'a>(values: &[&'a Bson]) -> Vec<&'a Bson> {
+    let mut result = Vec::new();
+    for value in values {
+        result.push(*value);
+        if let Bson::Array(items) = value {
+            result.extend(items.iter());
+        }
+    }
+    result
+}
+
+fn pattern(source: &str, options: &str) -> OResult<Regex> {
+    RegexBuilder::new(source)
+        .case_insensitive(options.contains('i'))
+        .multi_line(options.contains('m'))
+        .dot_matches_new_line(options.contains('s'))
+        .ignore_whitespace(options.contains('x'))
+        .build()
+        .map_err(|e| invalid(format!("Invalid regex {source:?}: {e}")))
+}
+
+fn matches_regex(values: &[&Bson], regex: &Regex) -> bool {
+    candidates(values).iter().any(|v| matches!(v, Bson::String(s) if regex.is_match(s)))
+}
+
+fn matches_value(values: &[&Bson], expected: &Bson) -> OResult<bool> {
+    match expected {
+        Bson::RegularExpression(regex) => Ok(matches_regex(values, &pattern(&regex.pattern, &regex.options)?)),
+        // Missing fields match null
+        Bson::Null if values.is_empty() => Ok(true),
+        _ => Ok(candidates(values).iter().any(|v| equal(v, expected)))
+    }
+}
+
+fn matches_comparison(values: &[&Bson], expected: &Bson, accept: impl Fn(Ordering) -> bool) -> bool {
+    candidates(values).iter().any(|v| compare(v, expected).is_some_and(&accept))
+}
+
+fn as_array<'a>(operator: &str, value: &'a Bson) -> OResult<&'a Vec<Bson>> {
+    value.as_array().ok_or_else(|| invalid(format!("{operator} needs an array")))
+}
+
+fn as_document<'a>(operator: &str, value: &'a Bson) -> OResult<&'a Document> {
+    value.as_document().ok_or_else(|| invalid(format!("{operator} needs a document")))
+}
+
+fn is_operator_document(value: &Bson) -> bool {
+    value.as_document().is_some_and(|d| d.keys().next().is_some_and(|k| k.starts_with('$')))
+}
+
+/// Tests the values at a field against an operator document, ie `{"$gt": 1, "$lt": 5}`
+fn matches_operators(values: &[&Bson], operators: &Document) -> OResult<bool> {
+    for (operator, operand) in operators {
+        let matched = match operator.as_str() {
+            "$eq" => matches_value(values, operand)?,
+            "$ne" => !matches_value(values, operand)?,
+            "$gt" => matches_comparison(values, operand, |o| o == Ordering::Greater),
+            "$gte" => matches_comparison(values, operand, |o| o != Ordering::Less),
+            "$lt" => matches_comparison(values, operand, |o| o == Ordering::Less),
+            "$lte" => matches_comparison(values, operand, |o| o != Ordering::Greater),
+            "$in" => {
+                let mut found = false;
+                for expected in as_array(operator, operand)? {
+                    if matches_value(values, expected)? {
+                        found = true;
+                        break;
+                    }
+                }
+                found
+            }
+            "$nin" => !matches_operators(values, &bson::doc! {"$in": operand.clone()})?,
+            "$exists" => values.is_empty() != operand.as_bool().unwrap_or_else(|| as_number(operand).is_some_and(|n| n != 0.0)),
+            "$not" => match operand {
+                Bson::RegularExpression(_) => !matches_value(values, operand)?,
+                _ => !matches_operators(values, as_document(operator, operand)?)?
+            },
+            "$regex" => {
+                let source = match operand {
+                    Bson::String(source) => source.as_str(),
+                    Bson::RegularExpression(regex) => regex.pattern.as_str(),
+                    _ => return Err(invalid("$regex needs a string"))
+                };
+                let options = operators.get_str("$options").unwrap_or_default();
+                matches_regex(values, &pattern(source, options)?)
+            }
+            "$options" => true,
+            "$size" => {
+                let size = as_number(operand).ok_or_else(|| invalid("$size needs a number"))?;
+                values.iter().any(|v| matches!(v, Bson::Array(items) if items.len() as f64 == size))
+            }
+            "$all" => {
+                let mut all = true;
+                for expected in as_array(operator, operand)? {
+                    all &= matches_value(values, expected)?;
+                }
+                all
+            }
+            "$elemMatch" => {
+                let condition = as_document(operator, operand)?;
+                let mut found = false;
+                for item in values.iter().filter_map(|v| v.as_array()).flatten() {
+                    found = match item {
+                        Bson::Document(item) if !is_operator_document(operand) => matches(condition, item)?,
+                        _ => matches_operators(&[item], condition)?
+                    };
+                    if found {
+                        break;
+                    }
+                }
+                found
+            }
+            other => return Err(OrmoxError::unsupported(format!("{other} in in-memory queries")))
+        };
+
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn matches_clauses(operator: &str, clauses: &Bson, document: &Document) -> OResult<Vec<bool>> {
+    as_array(operator, clauses)?.iter().map(|clause| matches(as_document(operator, clause)?, document)).collect()
+}
+
+/// Whether `document` matches a MongoDB-style `query`
+pub fn matches(query: &Document, document: &Document) -> OResult<bool> {
+    for (key, condition) in query {
+        let matched = match key.as_str() {
+            "$and" => matches_clauses(key, condition, document)?.into_iter().all(|m| m),
+            "$or" => matches_clauses(key, condition, document)?.into_iter().any(|m| m),
+            "$nor" => !matches_clauses(key, condition, document)?.into_iter().any(|m| m),
+            operator if operator.starts_with('$') => return Err(OrmoxError::unsupported(format!("{operator} in in-memory queries"))),
+            path => {
+                let values = values_at(document, path);
+                match condition {
+                    Bson::Document(operators) if is_operator_document(condition) => matches_operators(&values, operators)?,
+                    _ => matches_value(&values, condition)?
+                }
+            }
+        };
+
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn add(a: &Bson, b: &Bson, operator: &str) -> OResult<Bson> {
+    let sum = match (a, b) {
+        (Bson::Int32(a), Bson::Int32(b)) => a.checked_add(*b).map(Bson::Int32).unwrap_or(Bson::Int64(*a as i64 + *b as i64)),
+        (Bson::Int32(a), Bson::Int64(b)) | (Bson::Int64(b), Bson::Int32(a)) => Bson::Int64(*a as i64 + b),
+        (Bson::Int64(a), Bson::Int64(b)) => Bson::Int64(a.wrapping_add(*b)),
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => Bson::Double(a + b),
+            _ => return Err(invalid(format!("{operator} needs numeric values")))
+        }
+    };
+    Ok(sum)
+}
+
+fn multiply(a: &Bson, b: &Bson) -> OResult<Bson> {
+    let product = match (a, b) {
+        (Bson::Int32(a), Bson::Int32(b)) => a.checked_mul(*b).map(Bson::Int32).unwrap_or(Bson::Int64(*a as i64 * *b as i64)),
+        (Bson::Int32(a), Bson::Int64(b)) | (Bson::Int64(b), Bson::Int32(a)) => Bson::Int64(*a as i64 * b),
+        (Bson::Int64(a), Bson::Int64(b)) => Bson::Int64(a.wrapping_mul(*b)),
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => Bson::Double(a * b),
+            _ => return Err(invalid("$mul needs numeric values"))
+        }
+    };
+    Ok(product)
+}
+
+/// Array at `path`, created empty if missing
+fn array_mut<'a>(document: &'a mut Document, path: &str, operator: &str) -> OResult<&'a mut Vec<Bson>> {
+    let (parent, key) = parent_mut(document, path)?;
+    if !parent.contains_key(key) {
+        parent.insert(key, Bson::Array(Vec::new()));
+    }
+    match parent.get_mut(key) {
+        Some(Bson::Array(items)) => Ok(items),
+        _ => Err(invalid(format!("{operator} needs {path:?} to be an array")))
+    }
+}
+
+/// Values to add for `$push` & `$addToSet`, unwrapping `{"$each": [...]}`
+fn each(value: &Bson) -> Vec<Bson> {
+    match value.as_document().and_then(|d| d.get_array("$each").ok()) {
+        Some(items) => items.clone(),
+        None => vec![value.clone()]
+    }
+}
+
+fn apply(document: &mut Document, update: &Document, inserting: bool) -> OResult<()> {
+    // Without operators, the update replaces everything but the id
+    if !update.keys().any(|k| k.starts_with('$')) {
+        let id = document.get("_id").cloned();
+        *document = update.clone();
+        if let Some(id) = id {
+            document.insert("_id", id);
+        }
+        return Ok(());
+    }
+
+    for (operator, fields) in update {
+        let fields = as_document(operator, fields)?;
+        for (path, value) in fields {
+            match operator.as_str() {
+                "$set" => set_path(document, path, value.clone())?,
+                "$setOnInsert" if inserting => set_path(document, path, value.clone())?,
+                "$setOnInsert" => {}
+                "$unset" => {
+                    remove_path(document, path);
+                }
+                "$inc" => {
+                    let current = get_path(document, path).cloned().unwrap_or(Bson::Int32(0));
+                    set_path(document, path, add(&current, value, operator)?)?;
+                }
+                "$mul" => {
+                    let current = get_path(document, path).cloned().unwrap_or(Bson::Int32(0));
+                    set_path(document, path, multiply(&current, value)?)?;
+                }
+                "$min" | "$max" => {
+                    let wanted = if operator == "$min" { Ordering::Less } else { Ordering::Greater };
+                    let replace = get_path(document, path).is_none_or(|current| sort_order(value, current) == wanted);
+                    if replace {
+                        set_path(document, path, value.clone())?;
+                    }
+                }
+                // `DateTime::now` reads `SystemTime`, which panics on wasm32-unknown-unknown
+                "$currentDate" => set_path(document, path, Bson::DateTime(DateTime::from_chrono(Utc::now())))?,
+                "$rename" => {
+                    let target = value.as_str().ok_or_else(|| invalid("$rename needs a field name"))?;
+                    if let Some(moved) = remove_path(document, path) {
+                        set_path(document, target, moved)?;
+                    }
+                }
+                "$push" => array_mut(document, path, operator)?.extend(each(value)),
+                "$addToSet" => {
+                    let items = array_mut(document, path, operator)?;
+                    for value in each(value) {
+                        if !items.iter().any(|i| equal(i, &value)) {
+                            items.push(value);
+                        }
+                    }
+                }
+                "$pull" => {
+                    let items = array_mut(document, path, operator)?;
+                    let mut kept = Vec::with_capacity(items.len());
+                    for item in items.drain(..) {
+                        let pulled = match (value, &item) {
+                            (Bson::Document(condition), _) if is_operator_document(value) => matches_operators(&[&item], condition)?,
+                            (Bson::Document(condition), Bson::Document(item)) => matches(condition, item)?,
+                            _ => equal(&item, value)
+                        };
+                        if !pulled {
+                            kept.push(item);
+                        }
+                    }
+                    *items = kept;
+                }
+                "$pop" => {
+                    let items = array_mut(document, path, operator)?;
+                    if as_number(value).is_some_and(|n| n < 0.0) {
+                        if !items.is_empty() {
+                            items.remove(0);
+                        }
+                    } else {
+                        items.pop();
+                    }
+                }
+                other => return Err(OrmoxError::unsupported(format!("{other} in in-memory updates")))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies a MongoDB-style update (`{"$set": ...}`, or a replacement document) to `document` in place
+pub fn apply_update(document: &mut Document, update: &Document) -> OResult<()> {
+    apply(document, update, false)
+}
+
+/// Document inserted by an upsert that matched nothing: the query's equality fields, with the update (including
+/// `$setOnInsert`) applied
+pub fn upserted(query: &Document, update: &Document) -> OResult<Document> {
+    let mut document = Document::new();
+    for (key, value) in query {
+        if !key.starts_with('$') && !is_operator_document(value) {
+            set_path(&mut document, key, value.clone())?;
+        }
+    }
+
+    apply(&mut document, update, true)?;
+    Ok(document)
+}
+
+/// Applies a MongoDB-style projection (`{"name": 1}` or `{"secret": 0}`) to `document`. `_id` is kept unless excluded.
+pub fn project(document: Document, projection: &Document) -> Document {
+    let included = |v: &Bson| v.as_bool().unwrap_or_else(|| as_number(v).is_none_or(|n| n != 0.0));
+    let inclusive = projection.iter().any(|(k, v)| k != "_id" && included(v));
+
+    if inclusive {
+        let mut result = Document::new();
+        if projection.get("_id").is_none_or(included) {
+            if let Some(id) = document.get("_id") {
+                result.insert("_id", id.clone());
+            }
+        }
+        for (path, _) in projection.iter().filter(|(k, v)| *k != "_id" && included(v)) {
+            if let Some(value) = get_path(&document, path) {
+                // Paths were checked to exist, so parents are documents
+                let _ = set_path(&mut result, path, value.clone());
+            }
+        }
+        result
+    } else {
+        let mut result = document;
+        for (path, _) in projection {
+            remove_path(&mut result, path);
+        }
+        result
+    }
+}
+
+/// Compares strings under `collation`, approximating the Unicode root collation: by base letters (ignoring case &
+/// diacritics), then by diacritics, then by case, lowercase first, as far as the collation's strength goes. Locales
+/// aren't tailored, except for `"simple"`, which compares code points.
+pub fn compare_strings(a: &str, b: &str, collation: &Collation) -> Ordering {
+    if collation.locale == "simple" {
+        return a.cmp(b);
+    }
+
+    let strength = collation.effective_strength();
+    let (a, b): (String, String) = (a.nfd().collect(), b.nfd().collect());
+    let base = |s: &str| s.chars().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase).collect::<String>();
+    let order = base(&a).cmp(&base(&b));
+    if order != Ordering::Equal || strength == CollationStrength::Primary {
+        return order;
+    }
+
+    let accented = |s: &str| s.chars().flat_map(char::to_lowercase).collect::<String>();
+    let order = accented(&a).cmp(&accented(&b));
+    if order != Ordering::Equal || strength == CollationStrength::Secondary {
+        return order;
+    }
+
+    let case = |s: &str| s.chars().map(char::is_uppercase).collect::<Vec<bool>>();
+    let order = case(&a).cmp(&case(&b));
+    if order != Ordering::Equal || strength != CollationStrength::Identical {
+        return order;
+    }
+    a.cmp(&b)
+}
+
+/// Sorts documents in place, ordering missing fields first as MongoDB does. Strings are compared by code point, or
+/// under `collation` if there is one.
+pub fn sort(documents: &mut [Document], sorting: &Sorting, collation: Option<&Collation>) {
+    sort_by(documents, |document| document, sorting, collation)
+}
+
+/// Sorts items in place like `sort`, by the document each holds
+pub fn sort_by<T>(items: &mut [T], document: impl Fn(&T) -> &Document, sorting: &Sorting, collation: Option<&Collation>) {
+    let (field, descending) = match sorting {
+        Sorting::Ascending(field) => (field, false),
+        Sorting::Descending(field) => (field, true)
+    };
+
+    items.sort_by(|a, b| {
+        let (a, b) = (document(a), document(b));
+        let order = match (values_at(a, field).first(), values_at(b, field).first(), collation) {
+            (Some(Bson::String(a)), Some(Bson::String(b)), Some(collation)) => compare_strings(a, b, collation),
+            (Some(a), Some(b), _) => sort_order(a, b),
+            (a, b, _) => a.is_some().cmp(&b.is_some())
+        };
+        if descending { order.reverse() } else { order }
+    });
+}
+
+/// Runs a find over `documents`: filters by `query`, then applies the sort, offset, limit, operation count &
+/// projection from `options`
+pub fn find(documents: impl IntoIterator<Item = Document>, query: &Document, options: &Find) -> OResult<Vec<Document>> {
+    let mut found = Vec::new();
+    for document in documents {
+        if matches(query, &document)? {
+            found.push(document);
+        }
+    }
+
+    if let Some(sorting) = &options.sort {
+        sort(&mut found, sorting, options.collation.as_ref());
+    }
+
+    let limit = match options.operation {
+        OperationCount::One => Some(options.limit.unwrap_or(1).min(1)),
+        OperationCount::Many => options.limit
+    };
+    let found = found.into_iter().skip(options.offset.unwrap_or(0)).take(limit.unwrap_or(usize::MAX));
+    Ok(match &options.projection {
+        Some(projection) => found.map(|d| project(d, projection)).collect(),
+        None => found.collect()
+    })
+}
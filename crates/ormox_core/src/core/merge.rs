@@ -0,0 +1,79 @@
+use bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    diff::diff,
+    error::{OResult, OrmoxError},
+    memory::{get_path, remove_path, set_path},
+};
+
+/// Times `Collection::save_merging` refetches & merges the stored document before giving up
+pub const MERGE_ATTEMPTS: usize = 5;
+
+/// How `merge` resolves fields changed differently on both sides
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MergePolicy {
+    /// Fail with `OrmoxError::MergeConflict` listing the conflicting fields
+    #[default]
+    Fail,
+    /// Keep our changes
+    Ours,
+    /// Keep their changes
+    Theirs
+}
+
+/// Whether `a` & `b` are the same field, or one is nested in the other
+fn overlaps(a: &str, b: &str) -> bool {
+    let nested = |outer: &str, inner: &str| inner.strip_prefix(outer).is_some_and(|rest| rest.starts_with('.'));
+    a == b || nested(a, b) || nested(b, a)
+}
+
+/// Sets or removes the field at `path`
+fn put(document: &mut Document, path: &str, value: Option<&Bson>) -> OResult<()> {
+    match value {
+        Some(value) => set_path(document, path, value.clone()),
+        None => {
+            remove_path(document, path);
+            Ok(())
+        }
+    }
+}
+
+/// Three-way merge of two versions (`ours` & `theirs`) of a document both derived from `base`: fields changed on one
+/// side only take that side's value, and fields changed the same way on both sides are kept. Fields changed differently
+/// on both sides (including changes nested in a field the other side replaced) are resolved by `policy`.
+pub fn merge(base: &Document, ours: &Document, theirs: &Document, policy: MergePolicy) -> OResult<Document> {
+    let their_changes = diff(base, theirs);
+    let mut merged = theirs.clone();
+    let mut conflicts = Vec::new();
+
+    for (path, change) in diff(base, ours) {
+        let clashing = their_changes
+            .iter()
+            .filter(|(theirs, their_change)| overlaps(&path, theirs) && !(**theirs == path && their_change.after() == change.after()))
+            .map(|(theirs, _)| theirs)
+            .collect::<Vec<_>>();
+        if clashing.is_empty() {
+            put(&mut merged, &path, change.after())?;
+            continue;
+        }
+
+        match policy {
+            MergePolicy::Fail => conflicts.push(path),
+            MergePolicy::Theirs => {},
+            MergePolicy::Ours => {
+                // Restore our version of fields they replaced wholesale before applying the nested change
+                for theirs in clashing.into_iter().filter(|theirs| theirs.len() < path.len()) {
+                    put(&mut merged, theirs, get_path(ours, theirs))?;
+                }
+                put(&mut merged, &path, change.after())?;
+            }
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(OrmoxError::merge_conflict(conflicts))
+    }
+}
@@ -1,4 +1,31 @@
+pub(crate) mod canonical;
+pub mod change;
+pub mod codec;
+pub mod context;
+pub mod diff;
 pub mod document;
 pub mod driver;
+pub mod dry_run;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
-pub mod query;
\ No newline at end of file
+pub mod field;
+#[cfg(feature = "csv")]
+pub mod import;
+pub mod limit;
+pub mod memory;
+pub mod merge;
+pub mod numeric;
+pub mod observer;
+pub mod outbox;
+#[cfg(feature = "argon2")]
+pub mod password;
+pub mod query;
+pub mod redaction;
+pub mod relation;
+pub mod remote;
+pub mod runtime;
+pub mod sanitization;
+pub mod sequence;
+pub mod update;
+pub mod validation;
\ No newline at end of file
@@ -0,0 +1,640 @@
+use std::fmt::{self, Display};
+
+use bson::{Bson, Decimal128};
+use serde::{
+    de::{self, value::MapAccessDeserializer, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
+    ser::{self, Error as _, Serializer},
+    Deserialize, Serialize,
+};
+use serde_json::Value;
+
+use super::{error::{OResult, OrmoxError}, query::Query};
+
+/// Largest integer `Decimal128` holds exactly: 34 nines
+const DECIMAL128_MAX: u128 = 10_u128.pow(34) - 1;
+
+/// How integers BSON can't hold (`u64`s above `i64::MAX`, `i128`s & `u128`s outside `i64`'s range) are written, and
+/// compared against in queries, see `ClientOptions::numeric_policy`. Integers written as `Decimal128` or strings load
+/// back into integer fields of any width that fits them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumericPolicy {
+    /// Fail, naming the field
+    #[default]
+    Error,
+
+    /// Clamp to `i64::MIN` or `i64::MAX`, losing the value
+    Saturate,
+
+    /// Write as `Decimal128`, which holds integers of up to 34 digits exactly (larger ones fail) and compares numerically
+    /// on MongoDB. The in-memory drivers only compare `Decimal128`s for equality.
+    Decimal128,
+
+    /// Write as decimal strings, which keep any integer exactly but compare as strings
+    String
+}
+
+/// An integer outside `i64`'s range
+#[derive(Clone, Copy)]
+enum Wide {
+    Signed(i128),
+    Unsigned(u128)
+}
+
+impl Display for Wide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Signed(value) => value.fmt(f),
+            Self::Unsigned(value) => value.fmt(f)
+        }
+    }
+}
+
+impl NumericPolicy {
+    /// How `value` is written under this policy, or why it can't be
+    fn represent(self, value: Wide) -> Result<Bson, String> {
+        let (negative, magnitude) = match value {
+            Wide::Signed(value) => (value < 0, value.unsigned_abs()),
+            Wide::Unsigned(value) => (false, value)
+        };
+        match self {
+            Self::Error => Err(format!("{value} is out of the range of BSON integers (64 bits); see `NumericPolicy`")),
+            Self::Saturate => Ok(Bson::Int64(if negative { i64::MIN } else { i64::MAX })),
+            Self::Decimal128 if magnitude > DECIMAL128_MAX => Err(format!("{value} has more digits than a Decimal128 holds")),
+            Self::Decimal128 => value.to_string().parse::<Decimal128>().map(Bson::Decimal128).map_err(|e| e.to_string()),
+            Self::String => Ok(Bson::String(value.to_string()))
+        }
+    }
+
+    /// Applies the policy to the values `query` compares fields against
+    pub(crate) fn query(self, query: Query) -> OResult<Query> {
+        query.try_map_values(&mut |field, value| self.query_value(field, value))
+    }
+
+    fn query_value(self, field: Option<&str>, value: Value) -> OResult<Value> {
+        Ok(match value {
+            Value::Number(number) => match number.as_u64() {
+                Some(unsigned) if i64::try_from(unsigned).is_err() => {
+                    let represented = self.represent(Wide::Unsigned(unsigned.into())).map_err(|e| OrmoxError::serialization(described(field, e)))?;
+                    match represented {
+                        Bson::Int64(value) => Value::from(value),
+                        Bson::Decimal128(value) => serde_json::json!({"$numberDecimal": value.to_string()}),
+                        other => Value::String(other.as_str().unwrap_or_default().to_string())
+                    }
+                },
+                _ => Value::Number(number)
+            },
+            Value::Array(values) => Value::Array(values.into_iter().map(|value| self.query_value(field, value)).collect::<OResult<_>>()?),
+            Value::Object(entries) => Value::Object(entries.into_iter().map(|(key, value)| Ok((key, self.query_value(field, value)?))).collect::<OResult<_>>()?),
+            other => other
+        })
+    }
+}
+
+fn described(field: Option<&str>, error: String) -> String {
+    match field {
+        Some(field) => format!("Field `{field}`: {error}"),
+        None => error
+    }
+}
+
+/// `value` as a document, writing the integers BSON can't hold per `policy`
+pub fn to_document<T: Serialize + ?Sized>(value: &T, policy: NumericPolicy) -> OResult<bson::Document> {
+    bson::to_document(&Checked { value, policy, path: &Path::Root }).map_err(OrmoxError::serialization)
+}
+
+/// `value` as BSON, writing the integers BSON can't hold per `policy`
+pub fn to_bson<T: Serialize + ?Sized>(value: &T, policy: NumericPolicy) -> OResult<Bson> {
+    bson::to_bson(&Checked { value, policy, path: &Path::Root }).map_err(OrmoxError::serialization)
+}
+
+/// Location of a value being serialized, rendered in errors
+enum Path<'a> {
+    Root,
+    Field(&'a Path<'a>, &'a str),
+    Index(&'a Path<'a>, usize)
+}
+
+impl Path<'_> {
+    fn field(&self) -> Option<String> {
+        match self {
+            Self::Root => None,
+            Self::Field(parent, name) => Some(parent.field().map_or_else(|| name.to_string(), |parent| format!("{parent}.{name}"))),
+            Self::Index(parent, index) => Some(parent.field().map_or_else(|| index.to_string(), |parent| format!("{parent}.{index}")))
+        }
+    }
+}
+
+/// Runs `f` on the path of a compound value's items: under the variant's name for enum variants
+fn within<R>(path: &Path<'_>, variant: Option<&'static str>, f: impl FnOnce(&Path<'_>) -> R) -> R {
+    match variant {
+        Some(variant) => f(&Path::Field(path, variant)),
+        None => f(path)
+    }
+}
+
+/// A value serialized through `Checker`
+struct Checked<'a, T: ?Sized> {
+    value: &'a T,
+    policy: NumericPolicy,
+    path: &'a Path<'a>
+}
+
+impl<T: Serialize + ?Sized> Serialize for Checked<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(Checker { inner: serializer, policy: self.policy, path: self.path })
+    }
+}
+
+/// Serializer adapter writing the integers BSON can't hold per a policy, and passing everything else on
+struct Checker<'a, S> {
+    inner: S,
+    policy: NumericPolicy,
+    path: &'a Path<'a>
+}
+
+impl<'a, S: Serializer> Checker<'a, S> {
+    fn wide(self, value: Wide) -> Result<S::Ok, S::Error> {
+        match self.policy.represent(value) {
+            Ok(represented) => represented.serialize(self.inner),
+            Err(e) => Err(S::Error::custom(described(self.path.field().as_deref(), e)))
+        }
+    }
+}
+
+macro_rules! forward_serialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method(self, $($arg: $ty),*) -> Result<S::Ok, S::Error> {
+            self.inner.$method($($arg),*)
+        }
+    )*};
+}
+
+impl<'a, S: Serializer> Serializer for Checker<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<'a, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<'a, S::SerializeMap>;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<'a, S::SerializeStructVariant>;
+
+    forward_serialize! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i64(v: i64);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(name: &'static str);
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str);
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        match i64::try_from(v) {
+            Ok(_) => self.inner.serialize_u64(v),
+            Err(_) => self.wide(Wide::Unsigned(v.into()))
+        }
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.inner.serialize_i64(v),
+            Err(_) => self.wide(Wide::Signed(v))
+        }
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.inner.serialize_i64(v),
+            Err(_) => self.wide(Wide::Unsigned(v))
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_some(&Checked { value, policy: self.policy, path: self.path })
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_struct(name, &Checked { value, policy: self.policy, path: self.path })
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, name: &'static str, index: u32, variant: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+        let path = Path::Field(self.path, variant);
+        self.inner.serialize_newtype_variant(name, index, variant, &Checked { value, policy: self.policy, path: &path })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_seq(len)?, policy, path, variant: None, index: 0, key: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_tuple(len)?, policy, path, variant: None, index: 0, key: None })
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_tuple_struct(name, len)?, policy, path, variant: None, index: 0, key: None })
+    }
+
+    fn serialize_tuple_variant(self, name: &'static str, index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_tuple_variant(name, index, variant, len)?, policy, path, variant: Some(variant), index: 0, key: None })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_map(len)?, policy, path, variant: None, index: 0, key: None })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_struct(name, len)?, policy, path, variant: None, index: 0, key: None })
+    }
+
+    fn serialize_struct_variant(self, name: &'static str, index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeStructVariant, S::Error> {
+        let (policy, path) = (self.policy, self.path);
+        Ok(Compound { inner: self.inner.serialize_struct_variant(name, index, variant, len)?, policy, path, variant: Some(variant), index: 0, key: None })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Compound serializer passing its items through `Checker`, tracking their paths
+struct Compound<'a, C> {
+    inner: C,
+    policy: NumericPolicy,
+    path: &'a Path<'a>,
+
+    /// Name of the enum variant being serialized, if any
+    variant: Option<&'static str>,
+
+    /// Index of the next item of sequences & tuples
+    index: usize,
+
+    /// Name of the map key whose value is serialized next
+    key: Option<String>
+}
+
+impl<C> Compound<'_, C> {
+    fn next_index(&mut self) -> usize {
+        self.index += 1;
+        self.index - 1
+    }
+}
+
+/// Name of a map key in paths: the key itself if it serializes to a string or number
+fn key_name<T: Serialize + ?Sized>(key: &T) -> String {
+    match serde_json::to_value(key) {
+        Ok(Value::String(name)) => name,
+        Ok(Value::Number(number)) => number.to_string(),
+        _ => String::from("?")
+    }
+}
+
+macro_rules! compound_items {
+    ($($trait:ident::$method:ident;)*) => {$(
+        impl<C: ser::$trait> ser::$trait for Compound<'_, C> {
+            type Ok = C::Ok;
+            type Error = C::Error;
+
+            fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+                let (index, policy, variant) = (self.next_index(), self.policy, self.variant);
+                let inner = &mut self.inner;
+                within(self.path, variant, |parent| inner.$method(&Checked { value, policy, path: &Path::Index(parent, index) }))
+            }
+
+            fn end(self) -> Result<C::Ok, C::Error> {
+                self.inner.end()
+            }
+        }
+    )*};
+}
+
+compound_items! {
+    SerializeSeq::serialize_element;
+    SerializeTuple::serialize_element;
+    SerializeTupleStruct::serialize_field;
+    SerializeTupleVariant::serialize_field;
+}
+
+macro_rules! compound_fields {
+    ($($trait:ident;)*) => {$(
+        impl<C: ser::$trait> ser::$trait for Compound<'_, C> {
+            type Ok = C::Ok;
+            type Error = C::Error;
+
+            fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), C::Error> {
+                let (policy, variant, inner) = (self.policy, self.variant, &mut self.inner);
+                within(self.path, variant, |parent| inner.serialize_field(key, &Checked { value, policy, path: &Path::Field(parent, key) }))
+            }
+
+            fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+                self.inner.skip_field(key)
+            }
+
+            fn end(self) -> Result<C::Ok, C::Error> {
+                self.inner.end()
+            }
+        }
+    )*};
+}
+
+compound_fields! {
+    SerializeStruct;
+    SerializeStructVariant;
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        self.key = Some(key_name(key));
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let key = self.key.take().unwrap_or_default();
+        let path = Path::Field(self.path, &key);
+        self.inner.serialize_value(&Checked { value, policy: self.policy, path: &path })
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+/// Deserializes `T`, also loading integer fields from the `Decimal128`s & strings written by `NumericPolicy`
+pub struct Lenient<T>(pub T);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Lenient<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(Loose(deserializer)).map(Lenient)
+    }
+}
+
+/// `value` loaded from a document, reading integers written per any `NumericPolicy`
+pub fn from_document<T: serde::de::DeserializeOwned>(document: bson::Document) -> OResult<T> {
+    bson::from_document::<Lenient<T>>(document).map(|Lenient(value)| value).map_err(OrmoxError::deserialization)
+}
+
+/// Deserializer adapter handing integer fields `Decimal128`s & strings holding integers as integers
+struct Loose<D>(D);
+
+/// Visitor adapter, parsing integers out of `Decimal128`s & strings if it visits an integer field
+struct LooseVisitor<V> {
+    inner: V,
+    integer: bool
+}
+
+/// Seed, access & variant adapter passing the values they deserialize through `Loose`
+struct LooseAccess<A>(A);
+
+macro_rules! forward_deserialize {
+    ($integer:literal => $($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+            self.0.$method($($arg,)* LooseVisitor { inner: visitor, integer: $integer })
+        }
+    )*};
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Loose<D> {
+    type Error = D::Error;
+
+    forward_deserialize! {false =>
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    forward_deserialize! {true =>
+        deserialize_i64();
+        deserialize_u64();
+    }
+
+    // BSON deserializers don't take 128-bit integers, but hand over the stored value as is
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.0.deserialize_any(LooseVisitor { inner: visitor, integer: true })
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.0.deserialize_any(LooseVisitor { inner: visitor, integer: true })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+impl<V> LooseVisitor<V> {
+    fn parse<'de, E: de::Error>(self, value: &str) -> Result<V::Value, E> where V: Visitor<'de> {
+        // Visitors of narrower integers don't all take 128-bit ones
+        if let Ok(unsigned) = value.parse::<u64>() {
+            self.inner.visit_u64(unsigned)
+        } else if let Ok(signed) = value.parse::<i64>() {
+            self.inner.visit_i64(signed)
+        } else if let Ok(unsigned) = value.parse::<u128>() {
+            self.inner.visit_u128(unsigned)
+        } else if let Ok(signed) = value.parse::<i128>() {
+            self.inner.visit_i128(signed)
+        } else {
+            self.inner.visit_str(value)
+        }
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for LooseVisitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        if self.integer {
+            return self.parse(v);
+        }
+        self.inner.visit_str(v)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<V::Value, E> {
+        if self.integer {
+            return self.parse(v);
+        }
+        self.inner.visit_borrowed_str(v)
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<V::Value, E> {
+        if self.integer {
+            return self.parse(&v);
+        }
+        self.inner.visit_string(v)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.inner.visit_some(Loose(deserializer))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.inner.visit_newtype_struct(Loose(deserializer))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_seq(LooseAccess(seq))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        if !self.integer {
+            return self.inner.visit_map(LooseAccess(map));
+        }
+        // BSON deserializers hand `Decimal128`s over as maps of their bytes
+        match Bson::deserialize(MapAccessDeserializer::new(map))? {
+            Bson::Decimal128(decimal) => self.parse(&decimal.to_string()),
+            other => Err(de::Error::invalid_type(de::Unexpected::Other(&format!("{other}")), &self.inner))
+        }
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_enum(LooseAccess(data))
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for LooseAccess<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        self.0.deserialize(Loose(deserializer))
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for LooseAccess<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, A::Error> {
+        self.0.next_element_seed(LooseAccess(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for LooseAccess<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, A::Error> {
+        self.0.next_key_seed(seed)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        self.0.next_value_seed(LooseAccess(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for LooseAccess<A> {
+    type Error = A::Error;
+    type Variant = LooseAccess<A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<(T::Value, Self::Variant), A::Error> {
+        self.0.variant_seed(seed).map(|(value, variant)| (value, LooseAccess(variant)))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for LooseAccess<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        self.0.newtype_variant_seed(LooseAccess(seed))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.0.tuple_variant(len, LooseVisitor { inner: visitor, integer: false })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, A::Error> {
+        self.0.struct_variant(fields, LooseVisitor { inner: visitor, integer: false })
+    }
+}
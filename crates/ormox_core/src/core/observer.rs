@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+
+use crate::client::Client;
+
+use super::error::OResult;
+
+/// Hook run after writes to a collection through the client it's registered on with `Client::observe`, ie to keep
+/// denormalized data up to date (see `CounterCache`). Observers get the documents as stored, once the write succeeded;
+/// an observer's error is returned by the write, which has happened regardless. Writes made by other clients, or
+/// directly through the driver, aren't observed.
+#[async_trait]
+pub trait Observer: Send + Sync {
+    /// Name of the observed collection
+    fn collection(&self) -> String;
+
+    /// Key replacing any observer registered under the same key, so that registering an observer twice doesn't run it
+    /// twice; `None` to never replace others
+    fn key(&self) -> Option<String> {
+        None
+    }
+
+    /// After documents were inserted by `Collection::insert` (or the methods built on it, ie `insert_stream`)
+    async fn inserted(&self, _client: &Client, _documents: &[bson::Document]) -> OResult<()> {
+        Ok(())
+    }
+
+    /// After documents were deleted by `Collection::delete` (or the methods built on it, ie `delete_many`) or
+    /// `Collection::dedupe`. While a collection has observers, its deletes first find the matching documents then delete
+    /// those by ID, so that observers get exactly the deleted documents.
+    async fn deleted(&self, _client: &Client, _documents: &[bson::Document]) -> OResult<()> {
+        Ok(())
+    }
+}
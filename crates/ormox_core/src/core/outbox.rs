@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use bson::{doc, Bson};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use super::{context::{self, OperationContext}, error::{OResult, OrmoxError}};
+
+/// Collection holding events enqueued for the outbox relay, as `{_id, topic, source, context, payload, created_at,
+/// attempts, last_error, delivered, delivered_at}`
+pub const OUTBOX_COLLECTION: &str = "_ormox_outbox";
+
+/// An event recorded in the outbox by `Collection::enqueue`, handed to the relay's handler until it's acknowledged
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    pub topic: String,
+
+    /// Collection the event was enqueued through
+    pub source: Option<String>,
+
+    /// The `OperationContext` the event was created in, attributing it to a user or request
+    pub context: Option<OperationContext>,
+    pub payload: Bson,
+    pub created_at: DateTime<Utc>,
+
+    /// Number of delivery attempts so far
+    pub attempts: u32,
+
+    /// Error of the last failed delivery
+    pub last_error: Option<String>,
+
+    /// When the handler acknowledged the event, or `None` while it's pending
+    pub delivered_at: Option<DateTime<Utc>>
+}
+
+impl OutboxEvent {
+    pub fn new(topic: impl AsRef<str>, payload: impl Serialize) -> OResult<Self> {
+        Ok(Self {
+            id: Uuid::new_v4(),
+            topic: topic.as_ref().to_string(),
+            source: None,
+            context: context::current().map(Arc::unwrap_or_clone),
+            payload: bson::to_bson(&payload).map_err(OrmoxError::serialization)?,
+            created_at: Utc::now(),
+            attempts: 0,
+            last_error: None,
+            delivered_at: None
+        })
+    }
+
+    /// Deserializes the payload
+    pub fn payload<P: DeserializeOwned>(&self) -> OResult<P> {
+        bson::from_bson(self.payload.clone()).map_err(OrmoxError::deserialization)
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        self.delivered_at.is_some()
+    }
+
+    pub(crate) fn to_document(&self) -> bson::Document {
+        doc! {
+            "_id": self.id.to_string(),
+            "topic": &self.topic,
+            "source": self.source.clone(),
+            "context": bson::to_bson(&self.context).unwrap_or(Bson::Null),
+            "payload": self.payload.clone(),
+            "created_at": bson::DateTime::from_chrono(self.created_at),
+            "attempts": i64::from(self.attempts),
+            "last_error": self.last_error.clone(),
+            "delivered": self.delivered_at.is_some(),
+            "delivered_at": self.delivered_at.map(bson::DateTime::from_chrono)
+        }
+    }
+
+    pub(crate) fn from_document(document: &bson::Document) -> OResult<Self> {
+        let invalid = |field: &str| OrmoxError::deserialization(format!("Invalid outbox event: bad {field}"));
+        let optional_string = |field: &str| match document.get(field) {
+            Some(Bson::String(value)) => Ok(Some(value.clone())),
+            None | Some(Bson::Null) => Ok(None),
+            _ => Err(invalid(field))
+        };
+
+        Ok(Self {
+            id: document.get_str("_id").ok().and_then(|id| Uuid::parse_str(id).ok()).ok_or_else(|| invalid("_id"))?,
+            topic: document.get_str("topic").map_err(|_| invalid("topic"))?.to_string(),
+            source: optional_string("source")?,
+            context: match document.get("context") {
+                Some(Bson::Document(context)) => Some(bson::from_document(context.clone()).map_err(|_| invalid("context"))?),
+                None | Some(Bson::Null) => None,
+                _ => return Err(invalid("context"))
+            },
+            payload: document.get("payload").cloned().unwrap_or(Bson::Null),
+            created_at: document.get_datetime("created_at").map_err(|_| invalid("created_at"))?.to_chrono(),
+            attempts: match document.get("attempts") {
+                Some(Bson::Int64(value)) => u32::try_from(*value).map_err(|_| invalid("attempts"))?,
+                Some(Bson::Int32(value)) => u32::try_from(*value).map_err(|_| invalid("attempts"))?,
+                _ => return Err(invalid("attempts"))
+            },
+            last_error: optional_string("last_error")?,
+            delivered_at: match document.get("delivered_at") {
+                Some(Bson::DateTime(value)) => Some(value.to_chrono()),
+                None | Some(Bson::Null) => None,
+                _ => return Err(invalid("delivered_at"))
+            }
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+use super::error::{OResult, OrmoxError};
+
+/// Hashes a password with Argon2id, returning the PHC string (`$argon2id$v=19$...`) that's stored
+pub fn hash_password(raw: &str) -> OResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(raw.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| OrmoxError::serialization(format!("Failed to hash password: {e}")))
+}
+
+/// Whether a value is an Argon2 PHC string rather than a raw password
+pub fn is_hashed(value: &str) -> bool {
+    PasswordHash::new(value).is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"))
+}
+
+/// Checks `input` against a stored value: an Argon2 digest, or a raw password that hasn't been hashed yet
+pub fn verify_password(stored: &str, input: &str) -> bool {
+    match PasswordHash::new(stored) {
+        Ok(hash) if is_hashed(stored) => Argon2::default().verify_password(input.as_bytes(), &hash).is_ok(),
+        _ => constant_time_eq(stored.as_bytes(), input.as_bytes())
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Types of `#[ormox(hashed = "argon2")]` fields: `String`, or `Option<String>` for optional passwords. Empty strings
+/// count as no password.
+pub trait HashedField {
+    /// The raw password, if the field holds one that hasn't been hashed yet
+    fn raw(&self) -> Option<&str>;
+
+    /// Replaces the field's raw password with its digest
+    fn set_digest(&mut self, digest: String);
+
+    /// Checks `input` against the field's password; `false` if there's none
+    fn verify(&self, input: &str) -> bool;
+
+    /// Whether the field holds a raw password, which is never serialized
+    fn is_raw(&self) -> bool {
+        self.raw().is_some()
+    }
+
+    /// Hashes the field's raw password, if any
+    fn hash(&mut self) -> OResult<()> {
+        if let Some(raw) = self.raw() {
+            let digest = hash_password(raw)?;
+            self.set_digest(digest);
+        }
+        Ok(())
+    }
+}
+
+impl HashedField for String {
+    fn raw(&self) -> Option<&str> {
+        (!self.is_empty() && !is_hashed(self)).then_some(self.as_str())
+    }
+
+    fn set_digest(&mut self, digest: String) {
+        *self = digest;
+    }
+
+    fn verify(&self, input: &str) -> bool {
+        !self.is_empty() && verify_password(self, input)
+    }
+}
+
+impl HashedField for Option<String> {
+    fn raw(&self) -> Option<&str> {
+        self.as_ref().and_then(HashedField::raw)
+    }
+
+    fn set_digest(&mut self, digest: String) {
+        *self = Some(digest);
+    }
+
+    fn verify(&self, input: &str) -> bool {
+        self.as_ref().is_some_and(|stored| stored.verify(input))
+    }
+}
@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::borrow::Borrow;
 
 use bson::Bson;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_json::{to_value, Number, Value};
 
-use super::error::{OResult, OrmoxError};
+use super::{canonical::{canonical, canonical_eq}, error::{OResult, OrmoxError}, memory};
 
 #[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub enum QueryKey {
@@ -50,8 +51,19 @@ pub enum QueryValue {
     Mapping(Query),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Query(HashMap<QueryKey, QueryValue>);
+/// Conditions keyed by field or operator, kept in the order their keys were first added, which is the order they're
+/// rendered to BSON and iterated in. A key may hold several conditions, all of which have to match: sub-queries on one
+/// field render as one sub-document (`{age: {$gte: 18, $lt: 65}}`), and other repeated conditions, like a second `$or`
+/// group, are moved into `$and`.
+///
+/// Queries are equal (& hash the same) if they render to the same BSON, whatever order their keys were added in.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Query(IndexMap<QueryKey, Vec<QueryValue>>);
+
+canonical_eq!(Query, |query| match TryInto::<bson::Document>::try_into(query.clone()) {
+    Ok(rendered) => canonical(&rendered),
+    Err(_) => format!("{query:?}")
+});
 
 impl From<&Query> for Query {
     fn from(value: &Query) -> Self {
@@ -59,86 +71,151 @@ impl From<&Query> for Query {
     }
 }
 
+impl IntoIterator for Query {
+    type Item = (QueryKey, QueryValue);
+    type IntoIter = std::vec::IntoIter<(QueryKey, QueryValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0
+            .into_iter()
+            .flat_map(|(key, values)| values.into_iter().map(move |value| (key.clone(), value)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Query {
+    type Item = (&'a QueryKey, &'a QueryValue);
+    type IntoIter = std::vec::IntoIter<(&'a QueryKey, &'a QueryValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().collect::<Vec<_>>().into_iter()
+    }
+}
+
 impl Query {
     pub fn new() -> Self {
-        Query(HashMap::new())
+        Query(IndexMap::new())
+    }
+
+    /// Conditions in the order their keys were first added, with the conditions of each key in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&QueryKey, &QueryValue)> {
+        self.0
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))
+    }
+
+    /// Number of conditions, counting each condition of a repeated key
+    pub fn len(&self) -> usize {
+        self.0.values().map(Vec::len).sum()
     }
 
-    fn push(&mut self, key: QueryKey, value: QueryValue) -> &mut Self {
-        let _ = self.0.insert(key.clone(), value.clone());
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn push(mut self, key: QueryKey, value: QueryValue) -> Self {
+        self.0.entry(key).or_default().push(value);
         self
     }
 
-    pub fn field(&mut self, key: impl AsRef<str>, value: impl Into<Value>) -> &mut Self {
+    /// Adds `other`'s conditions to this query's, so documents must match both. Conditions on a key both queries set
+    /// are all kept, and rendered side by side (see `TryInto<bson::Document>`).
+    pub fn merge(&mut self, other: impl Into<Query>) -> &mut Self {
+        for (key, values) in other.into().0 {
+            self.0.entry(key).or_default().extend(values);
+        }
+        self
+    }
+
+    /// Splits the query into queries holding one condition per key: the first condition of every key, then the
+    /// second, and so on. Empty queries split into one empty query.
+    fn layers(self) -> Vec<Query> {
+        let mut layers = vec![Query::new()];
+        for (key, values) in self.0 {
+            for (index, value) in values.into_iter().enumerate() {
+                if layers.len() <= index {
+                    layers.push(Query::new());
+                }
+                layers[index].0.insert(key.clone(), vec![value]);
+            }
+        }
+        layers
+    }
+
+    /// Applies a builder chain to a query in place, for callers holding a `&mut Query`
+    pub fn modify(&mut self, f: impl FnOnce(Query) -> Query) -> &mut Self {
+        *self = f(std::mem::take(self));
+        self
+    }
+
+    pub fn field(self, key: impl AsRef<str>, value: impl Into<Value>) -> Self {
         self.push(
             QueryKey::String(key.as_ref().to_string()),
             QueryValue::Value(value.into()),
         )
     }
 
-    pub fn subquery(&mut self, key: impl AsRef<str>, child: impl Into<Query>) -> &mut Self {
+    pub fn subquery(self, key: impl AsRef<str>, child: impl Into<Query>) -> Self {
         self.push(
             QueryKey::String(key.as_ref().to_string()),
             QueryValue::Mapping(child.into()),
         )
     }
 
-    pub fn operation(&mut self, operation: impl AsRef<str>, value: QueryValue) -> &mut Self {
-        self.push(
-            QueryKey::Operator(operation.as_ref().to_string()),
-            value.clone(),
-        )
+    pub fn operation(self, operation: impl AsRef<str>, value: QueryValue) -> Self {
+        self.push(QueryKey::Operator(operation.as_ref().to_string()), value)
     }
 
-    pub fn greater_than(&mut self, value: impl Into<Number>) -> &mut Self {
+    pub fn greater_than(self, value: impl Into<Number>) -> Self {
         self.push(
             QueryKey::GreaterThan,
             QueryValue::Value(Into::<Number>::into(value).into()),
         )
     }
 
-    pub fn greater_than_equal(&mut self, value: impl Into<Number>) -> &mut Self {
+    pub fn greater_than_equal(self, value: impl Into<Number>) -> Self {
         self.push(
             QueryKey::GreaterThanEqual,
             QueryValue::Value(Into::<Number>::into(value).into()),
         )
     }
 
-    pub fn less_than(&mut self, value: impl Into<Number>) -> &mut Self {
+    pub fn less_than(self, value: impl Into<Number>) -> Self {
         self.push(
             QueryKey::LessThan,
             QueryValue::Value(Into::<Number>::into(value).into()),
         )
     }
 
-    pub fn less_than_equal(&mut self, value: impl Into<Number>) -> &mut Self {
+    pub fn less_than_equal(self, value: impl Into<Number>) -> Self {
         self.push(
             QueryKey::LessThanEqual,
             QueryValue::Value(Into::<Number>::into(value).into()),
         )
     }
 
-    pub fn equals(&mut self, value: impl Into<Value>) -> &mut Self {
+    pub fn equals(self, value: impl Into<Value>) -> Self {
         self.push(QueryKey::Equals, QueryValue::Value(value.into()))
     }
 
-    pub fn not_equals(&mut self, value: impl Into<Value>) -> &mut Self {
+    pub fn not_equals(self, value: impl Into<Value>) -> Self {
         self.push(QueryKey::NotEquals, QueryValue::Value(value.into()))
     }
 
-    pub fn in_array(&mut self, value: impl IntoIterator<Item = impl Into<Value>>) -> &mut Self {
+    pub fn in_array(self, value: impl IntoIterator<Item = impl Into<Value>>) -> Self {
         self.push(
             QueryKey::In,
             QueryValue::Value(Value::Array(
                 value
                     .into_iter()
-                    .map(|v| Into::<Value>::into(v).clone())
+                    .map(|v| Into::<Value>::into(v))
                     .collect::<Vec<Value>>(),
             )),
         )
     }
 
-    pub fn not_in_array(&mut self, value: impl IntoIterator<Item = impl Into<Value>>) -> &mut Self {
+    pub fn not_in_array(self, value: impl IntoIterator<Item = impl Into<Value>>) -> Self {
         self.push(
             QueryKey::NotIn,
             QueryValue::Value(Value::Array(
@@ -150,11 +227,11 @@ impl Query {
         )
     }
 
-    pub fn not(&mut self, value: impl Into<Query>) -> &mut Self {
+    pub fn not(self, value: impl Into<Query>) -> Self {
         self.push(QueryKey::Not, QueryValue::Mapping(value.into()))
     }
 
-    pub fn and(&mut self, cases: impl IntoIterator<Item = impl Into<Query>>) -> &mut Self {
+    pub fn and(self, cases: impl IntoIterator<Item = impl Into<Query>>) -> Self {
         self.push(
             QueryKey::And,
             QueryValue::Casematch(
@@ -166,7 +243,7 @@ impl Query {
         )
     }
 
-    pub fn or(&mut self, cases: impl IntoIterator<Item = impl Into<Query>>) -> &mut Self {
+    pub fn or(self, cases: impl IntoIterator<Item = impl Into<Query>>) -> Self {
         self.push(
             QueryKey::Or,
             QueryValue::Casematch(
@@ -178,9 +255,186 @@ impl Query {
         )
     }
 
-    pub fn build(&self) -> Self {
+    /// Whether an in-memory document matches the query, evaluated like the in-memory drivers do
+    pub fn matches(&self, document: &bson::Document) -> OResult<bool> {
+        let query: bson::Document = self.clone().try_into()?;
+        memory::matches(&query, document)
+    }
+
+    /// The documents matching the query, in order. The query is only rendered once, so prefer this over calling
+    /// `matches` for every document.
+    pub fn filter<D: Borrow<bson::Document>>(&self, documents: impl IntoIterator<Item = D>) -> OResult<Vec<D>> {
+        let query: bson::Document = self.clone().try_into()?;
+        let mut matching = Vec::new();
+        for document in documents {
+            if memory::matches(&query, document.borrow())? {
+                matching.push(document);
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Replaces every value the query compares against, given the field it's compared to (the closest enclosing field
+    /// key), if any
+    pub(crate) fn try_map_values(
+        self,
+        f: &mut dyn FnMut(Option<&str>, Value) -> OResult<Value>,
+    ) -> OResult<Self> {
+        self.map_values_under(None, f)
+    }
+
+    fn map_values_under(
+        self,
+        field: Option<&str>,
+        f: &mut dyn FnMut(Option<&str>, Value) -> OResult<Value>,
+    ) -> OResult<Self> {
+        let mut mapped = Query::new();
+        for (key, values) in self.0 {
+            let field = match &key {
+                QueryKey::String(name) => Some(name.clone()),
+                _ => field.map(str::to_string),
+            };
+            for value in values {
+                let value = match value {
+                    QueryValue::Value(value) => QueryValue::Value(f(field.as_deref(), value)?),
+                    QueryValue::Casematch(cases) => QueryValue::Casematch(
+                        cases
+                            .into_iter()
+                            .map(|case| case.map_values_under(field.as_deref(), f))
+                            .collect::<OResult<Vec<Query>>>()?,
+                    ),
+                    QueryValue::Mapping(query) => {
+                        QueryValue::Mapping(query.map_values_under(field.as_deref(), f)?)
+                    }
+                };
+                mapped = mapped.push(key.clone(), value);
+            }
+        }
+        Ok(mapped)
+    }
+
+    /// Ends a builder chain. Builder methods take the query by value, so this is free; it is kept
+    /// so existing `Query::new().field(...).build()` chains keep compiling.
+    pub fn build(self) -> Self {
+        self
+    }
+
+    /// Copy of the query, for callers of the previous `build(&self)` that keep using the query afterwards
+    pub fn build_ref(&self) -> Self {
         self.clone()
     }
+
+    /// Whether both queries hold the same conditions, whatever order they were added in
+    #[cfg(feature = "proptest")]
+    pub(crate) fn equivalent(&self, other: &Query) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().all(|(key, values)| {
+                other.0.get(key).is_some_and(|other| {
+                    values.len() == other.len()
+                        && values.iter().zip(other).all(|(value, other)| value.equivalent(other))
+                })
+            })
+    }
+}
+
+/// The builder methods as they were before queries were built by value: each adds a condition to a `&mut Query` in
+/// place and returns it, so code building a query through a mutable reference keeps compiling once this trait is in
+/// scope. New code should chain the by-value methods of `Query` instead.
+pub trait QueryMut<'a> {
+    fn field(self, key: impl AsRef<str>, value: impl Into<Value>) -> &'a mut Query;
+    fn subquery(self, key: impl AsRef<str>, child: impl Into<Query>) -> &'a mut Query;
+    fn operation(self, operation: impl AsRef<str>, value: QueryValue) -> &'a mut Query;
+    fn greater_than(self, value: impl Into<Number>) -> &'a mut Query;
+    fn greater_than_equal(self, value: impl Into<Number>) -> &'a mut Query;
+    fn less_than(self, value: impl Into<Number>) -> &'a mut Query;
+    fn less_than_equal(self, value: impl Into<Number>) -> &'a mut Query;
+    fn equals(self, value: impl Into<Value>) -> &'a mut Query;
+    fn not_equals(self, value: impl Into<Value>) -> &'a mut Query;
+    fn in_array(self, value: impl IntoIterator<Item = impl Into<Value>>) -> &'a mut Query;
+    fn not_in_array(self, value: impl IntoIterator<Item = impl Into<Value>>) -> &'a mut Query;
+    fn not(self, value: impl Into<Query>) -> &'a mut Query;
+    fn and(self, cases: impl IntoIterator<Item = impl Into<Query>>) -> &'a mut Query;
+    fn or(self, cases: impl IntoIterator<Item = impl Into<Query>>) -> &'a mut Query;
+
+    /// Copy of the query built so far
+    fn build(self) -> Query;
+}
+
+impl<'a> QueryMut<'a> for &'a mut Query {
+    fn field(self, key: impl AsRef<str>, value: impl Into<Value>) -> &'a mut Query {
+        self.modify(|query| query.field(key, value))
+    }
+
+    fn subquery(self, key: impl AsRef<str>, child: impl Into<Query>) -> &'a mut Query {
+        self.modify(|query| query.subquery(key, child))
+    }
+
+    fn operation(self, operation: impl AsRef<str>, value: QueryValue) -> &'a mut Query {
+        self.modify(|query| query.operation(operation, value))
+    }
+
+    fn greater_than(self, value: impl Into<Number>) -> &'a mut Query {
+        self.modify(|query| query.greater_than(value))
+    }
+
+    fn greater_than_equal(self, value: impl Into<Number>) -> &'a mut Query {
+        self.modify(|query| query.greater_than_equal(value))
+    }
+
+    fn less_than(self, value: impl Into<Number>) -> &'a mut Query {
+        self.modify(|query| query.less_than(value))
+    }
+
+    fn less_than_equal(self, value: impl Into<Number>) -> &'a mut Query {
+        self.modify(|query| query.less_than_equal(value))
+    }
+
+    fn equals(self, value: impl Into<Value>) -> &'a mut Query {
+        self.modify(|query| query.equals(value))
+    }
+
+    fn not_equals(self, value: impl Into<Value>) -> &'a mut Query {
+        self.modify(|query| query.not_equals(value))
+    }
+
+    fn in_array(self, value: impl IntoIterator<Item = impl Into<Value>>) -> &'a mut Query {
+        self.modify(|query| query.in_array(value))
+    }
+
+    fn not_in_array(self, value: impl IntoIterator<Item = impl Into<Value>>) -> &'a mut Query {
+        self.modify(|query| query.not_in_array(value))
+    }
+
+    fn not(self, value: impl Into<Query>) -> &'a mut Query {
+        self.modify(|query| query.not(value))
+    }
+
+    fn and(self, cases: impl IntoIterator<Item = impl Into<Query>>) -> &'a mut Query {
+        self.modify(|query| query.and(cases))
+    }
+
+    fn or(self, cases: impl IntoIterator<Item = impl Into<Query>>) -> &'a mut Query {
+        self.modify(|query| query.or(cases))
+    }
+
+    fn build(self) -> Query {
+        self.clone()
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl QueryValue {
+    fn equivalent(&self, other: &QueryValue) -> bool {
+        match (self, other) {
+            (Self::Value(value), Self::Value(other)) => value == other,
+            (Self::Casematch(cases), Self::Casematch(other)) => {
+                cases.len() == other.len()
+                    && cases.iter().zip(other).all(|(case, other)| case.equivalent(other))
+            }
+            (Self::Mapping(query), Self::Mapping(other)) => query.equivalent(other),
+            _ => false,
+        }
+    }
 }
 
 fn bson_value(input: &Bson) -> OResult<Value> {
@@ -205,15 +459,6 @@ fn bson_value_array(input: &Bson) -> OResult<Vec<Value>> {
         .cloned()
 }
 
-fn bson_number(input: &Bson) -> OResult<Number> {
-    bson_value(input)?
-        .as_number()
-        .ok_or(OrmoxError::Deserialization {
-            error: String::from("Invalid number"),
-        })
-        .cloned()
-}
-
 fn bson_query(input: &Bson) -> OResult<Query> {
     TryFrom::<bson::Document>::try_from(
         input
@@ -241,11 +486,23 @@ impl TryFrom<bson::Document> for Query {
         let mut result = Query::new();
         for (key, value) in value {
             if key.starts_with("$") {
-                match key.as_str() {
-                    "$gt" => result.greater_than(bson_number(&value)?),
-                    "$lt" => result.less_than(bson_number(&value)?),
-                    "$gte" => result.greater_than_equal(bson_number(&value)?),
-                    "$lte" => result.less_than_equal(bson_number(&value)?),
+                result = match key.as_str() {
+                    // Comparisons from BSON may hold any comparable value (dates, strings), not just numbers
+                    "$gt" => result.push(
+                        QueryKey::GreaterThan,
+                        QueryValue::Value(bson_value(&value)?),
+                    ),
+                    "$lt" => {
+                        result.push(QueryKey::LessThan, QueryValue::Value(bson_value(&value)?))
+                    }
+                    "$gte" => result.push(
+                        QueryKey::GreaterThanEqual,
+                        QueryValue::Value(bson_value(&value)?),
+                    ),
+                    "$lte" => result.push(
+                        QueryKey::LessThanEqual,
+                        QueryValue::Value(bson_value(&value)?),
+                    ),
                     "$eq" => result.equals(bson_value(&value)?),
                     "$ne" => result.not_equals(bson_value(&value)?),
                     "$in" => result.in_array(bson_value_array(&value)?),
@@ -265,11 +522,11 @@ impl TryFrom<bson::Document> for Query {
                     ),
                 };
             } else {
-                if let Bson::Document(subdoc) = value {
-                    result.subquery(key, Query::try_from(subdoc)?);
+                result = if let Bson::Document(subdoc) = value {
+                    result.subquery(key, Query::try_from(subdoc)?)
                 } else {
-                    result.field(key, bson_value(&value)?);
-                }
+                    result.field(key, bson_value(&value)?)
+                };
             }
         }
 
@@ -277,35 +534,85 @@ impl TryFrom<bson::Document> for Query {
     }
 }
 
+fn render_value(value: QueryValue) -> OResult<Bson> {
+    Ok(match value {
+        QueryValue::Value(v) => Bson::try_from(v).map_err(|e| OrmoxError::Deserialization {
+            error: e.to_string(),
+        })?,
+        QueryValue::Casematch(queries) => {
+            let mut cases: Vec<Bson> = Vec::new();
+            for q in queries {
+                cases.push(Bson::Document(q.try_into()?));
+            }
+            Bson::Array(cases)
+        }
+        QueryValue::Mapping(query) => Bson::Document(query.try_into()?),
+    })
+}
+
 impl TryInto<bson::Document> for Query {
     type Error = OrmoxError;
     fn try_into(self) -> Result<bson::Document, Self::Error> {
         let mut result = bson::Document::new();
-
-        for (key, value) in self.0 {
-            match value {
-                QueryValue::Value(v) => result.insert(
-                    key.to_string(),
-                    Bson::try_from(v).or_else(|e| {
-                        Err(OrmoxError::Deserialization {
-                            error: e.to_string(),
-                        })
-                    })?,
-                ),
-                QueryValue::Casematch(queries) => {
-                    let mut cases: Vec<Bson> = Vec::new();
-                    for q in queries {
-                        cases.push(Bson::Document(q.try_into()?));
+        // Cases of every `$and`, along with repeated conditions that can't share their key
+        let mut and: Vec<Bson> = Vec::new();
+
+        for (key, values) in self.0 {
+            let name = key.to_string();
+            if key == QueryKey::And {
+                // Holds the place of `$and`, which is filled in once every condition's been rendered
+                result.insert(name, Bson::Array(Vec::new()));
+                for value in values {
+                    match value {
+                        QueryValue::Casematch(cases) => {
+                            for case in cases {
+                                and.push(Bson::Document(case.try_into()?));
+                            }
+                        }
+                        other => and.push(render_value(other)?),
                     }
+                }
+                continue;
+            }
 
-                    result.insert(key.to_string(), Bson::Array(cases))
+            // Sub-queries under one field merge into one, which is split again where the same condition is set more
+            // than once: `{age: {$gt: 1}}` & `{age: {$gt: 2}}` render as `{age: {$gt: 1}, $and: [{age: {$gt: 2}}]}`.
+            // Operators' sub-queries are kept apart, as merging them changes their meaning (`$not: {a: 1, b: 2}`
+            // isn't `$not: {a: 1}` & `$not: {b: 2}`).
+            let field = matches!(key, QueryKey::String(_));
+            let mut rendered: Vec<Bson> = Vec::new();
+            let mut mapping: Option<(usize, Query)> = None;
+            for value in values {
+                match (value, &mut mapping) {
+                    (QueryValue::Mapping(query), Some((_, merged))) if field => {
+                        merged.merge(query);
+                    }
+                    (QueryValue::Mapping(query), None) if field => mapping = Some((rendered.len(), query)),
+                    (other, _) => rendered.push(render_value(other)?),
                 }
-                QueryValue::Mapping(query) => {
-                    result.insert(key.to_string(), Bson::Document(query.try_into()?))
+            }
+            if let Some((position, merged)) = mapping {
+                let mut layers: Vec<Bson> = Vec::new();
+                for layer in merged.layers() {
+                    layers.push(Bson::Document(layer.try_into()?));
                 }
-            };
+                rendered.splice(position..position, layers);
+            }
+
+            let mut rendered = rendered.into_iter();
+            if let Some(first) = rendered.next() {
+                result.insert(name.clone(), first);
+            }
+            for repeated in rendered {
+                let mut condition = bson::Document::new();
+                condition.insert(name.clone(), repeated);
+                and.push(Bson::Document(condition));
+            }
         }
 
+        if !and.is_empty() {
+            result.insert(QueryKey::And.to_string(), Bson::Array(and));
+        }
         Ok(result)
     }
 }
@@ -323,31 +630,46 @@ impl SimpleQuery {
     }
 
     pub fn equals(&mut self, key: impl AsRef<str>, value: impl Into<Value>) -> &mut Self {
-        self.q().field(key, value);
+        self.q().modify(|q| q.field(key, value));
         self
     }
 
+    /// Equality on a field of an embedded document, given the path to it (ie `["address", "city"]` for
+    /// `address.city`)
+    pub fn nested(
+        &mut self,
+        path: impl IntoIterator<Item = impl AsRef<str>>,
+        value: impl Into<Value>,
+    ) -> &mut Self {
+        let key = path
+            .into_iter()
+            .map(|segment| segment.as_ref().to_string())
+            .collect::<Vec<String>>()
+            .join(".");
+        self.equals(key, value)
+    }
+
     pub fn not_equals(&mut self, key: impl AsRef<str>, value: impl Into<Value>) -> &mut Self {
         self.q()
-            .subquery(key, Query::new().not_equals(value).build());
+            .modify(|q| q.subquery(key, Query::new().not_equals(value).build()));
         self
     }
 
     pub fn less_than(&mut self, key: impl AsRef<str>, value: impl Into<Number>) -> &mut Self {
         self.q()
-            .subquery(key, Query::new().less_than(value).build());
+            .modify(|q| q.subquery(key, Query::new().less_than(value).build()));
         self
     }
 
     pub fn less_than_equal(&mut self, key: impl AsRef<str>, value: impl Into<Number>) -> &mut Self {
         self.q()
-            .subquery(key, Query::new().less_than_equal(value).build());
+            .modify(|q| q.subquery(key, Query::new().less_than_equal(value).build()));
         self
     }
 
     pub fn greater_than(&mut self, key: impl AsRef<str>, value: impl Into<Number>) -> &mut Self {
         self.q()
-            .subquery(key, Query::new().greater_than(value).build());
+            .modify(|q| q.subquery(key, Query::new().greater_than(value).build()));
         self
     }
 
@@ -357,7 +679,7 @@ impl SimpleQuery {
         value: impl Into<Number>,
     ) -> &mut Self {
         self.q()
-            .subquery(key, Query::new().greater_than_equal(value).build());
+            .modify(|q| q.subquery(key, Query::new().greater_than_equal(value).build()));
         self
     }
 
@@ -366,7 +688,8 @@ impl SimpleQuery {
         key: impl AsRef<str>,
         value: impl IntoIterator<Item = impl Into<Value>>,
     ) -> &mut Self {
-        self.q().subquery(key, Query::new().in_array(value).build());
+        self.q()
+            .modify(|q| q.subquery(key, Query::new().in_array(value).build()));
         self
     }
 
@@ -376,17 +699,18 @@ impl SimpleQuery {
         value: impl IntoIterator<Item = impl Into<Value>>,
     ) -> &mut Self {
         self.q()
-            .subquery(key, Query::new().not_in_array(value).build());
+            .modify(|q| q.subquery(key, Query::new().not_in_array(value).build()));
         self
     }
 
     pub fn not(&mut self, key: impl AsRef<str>, expr: impl Into<Query>) -> &mut Self {
-        self.q().subquery(key, Query::new().not(expr).build());
+        self.q()
+            .modify(|q| q.subquery(key, Query::new().not(expr).build()));
         self
     }
 
     pub fn build(&self) -> Query {
-        self.0.clone().build()
+        self.0.clone()
     }
 }
 
@@ -401,3 +725,64 @@ impl From<SimpleQuery> for Query {
         value.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, oid::ObjectId, DateTime};
+
+    use super::*;
+
+    /// Reads `document` as a query and renders it again, which must give back `document`
+    fn round_trip(document: bson::Document) {
+        let query = Query::try_from(document.clone()).expect("document should read as a query");
+        let rendered: bson::Document = query.try_into().expect("query should render");
+        assert_eq!(rendered, document);
+    }
+
+    #[test]
+    fn numbers_round_trip() {
+        round_trip(doc! {"small": 1, "wide": i64::MAX, "fraction": 1.5});
+        round_trip(doc! {"age": {"$gt": 18, "$lte": 65}, "score": {"$gte": -0.5, "$lt": i64::MIN}});
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        round_trip(doc! {"name": "ann", "empty": "", "unicode": "ß→✓"});
+        round_trip(doc! {"name": {"$gt": "a", "$lt": "b", "$ne": "ann"}});
+    }
+
+    #[test]
+    fn dates_round_trip() {
+        let date = DateTime::from_millis(1_700_000_000_123);
+        round_trip(doc! {"created_at": date});
+        round_trip(doc! {"expires_at": {"$gt": date}, "locked_at": {"$lte": date}});
+        round_trip(
+            doc! {"due": {"$gte": DateTime::from_millis(-86_400_000), "$lt": DateTime::MAX}},
+        );
+    }
+
+    #[test]
+    fn dates_compare_as_dates() {
+        let date = DateTime::from_millis(1_700_000_000_123);
+        let query = Query::try_from(doc! {"expires_at": {"$gt": date}}).unwrap();
+        assert!(query
+            .matches(&doc! {"expires_at": DateTime::from_millis(1_700_000_000_124)})
+            .unwrap());
+        assert!(!query.matches(&doc! {"expires_at": date}).unwrap());
+    }
+
+    #[test]
+    fn nested_subqueries_round_trip() {
+        round_trip(doc! {"address": {"city": "Oslo", "zip": {"$in": ["0150", "0151"]}}});
+        round_trip(doc! {"a": {"b": {"c": {"$gt": 1}}}, "$or": [{"x": 1}, {"y": {"$ne": null}}]});
+        round_trip(doc! {"$and": [{"n": {"$gt": 1}}, {"n": {"$lt": 5}}]});
+    }
+
+    #[test]
+    fn in_and_not_round_trip() {
+        round_trip(doc! {"status": {"$in": ["live", "draft"]}, "tag": {"$nin": [1, "x", null]}});
+        round_trip(doc! {"status": {"$not": {"$in": ["archived"]}}});
+        round_trip(doc! {"$not": {"age": {"$lt": 18}}});
+        round_trip(doc! {"id": {"$in": [ObjectId::from_bytes([7; 12])]}});
+    }
+}
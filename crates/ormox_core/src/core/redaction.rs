@@ -0,0 +1,84 @@
+use std::fmt::{Debug, Display};
+
+use bson::{Bson, Document as BsonDocument};
+
+use super::{document::Document, query::Query, validation::FieldError};
+
+/// Stand-in for the values of `#[ormox(sensitive)]` fields in rendered documents, queries & errors
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Formats as `[REDACTED]`, in place of a sensitive value
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Redacted;
+
+impl Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Display for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+fn is_sensitive(path: &str, fields: &[&str]) -> bool {
+    fields.iter().any(|field| path == *field || path.strip_prefix(field).is_some_and(|rest| rest.starts_with('.')))
+}
+
+fn redact_under(data: &BsonDocument, prefix: &str, fields: &[&str]) -> BsonDocument {
+    let mut result = BsonDocument::new();
+    for (key, value) in data {
+        let value = if key.starts_with('$') {
+            // Logical operators hold queries and update operators hold paths, both relative to the same document
+            match value {
+                Bson::Document(inner) => Bson::Document(redact_under(inner, prefix, fields)),
+                Bson::Array(items) => Bson::Array(
+                    items
+                        .iter()
+                        .map(|item| match item {
+                            Bson::Document(inner) => Bson::Document(redact_under(inner, prefix, fields)),
+                            item => item.clone()
+                        })
+                        .collect()
+                ),
+                value => value.clone()
+            }
+        } else {
+            let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+            match value {
+                _ if is_sensitive(&path, fields) => Bson::String(REDACTED.to_string()),
+                Bson::Document(inner) => Bson::Document(redact_under(inner, &path, fields)),
+                value => value.clone()
+            }
+        };
+        result.insert(key, value);
+    }
+    result
+}
+
+/// Copy of a document, query or update with the values at the stored paths `fields` (and below them) replaced by
+/// `REDACTED`
+pub fn redact_paths(data: &BsonDocument, fields: &[&str]) -> BsonDocument {
+    redact_under(data, "", fields)
+}
+
+/// Copy of a stored document, query or update of `T` with its sensitive fields masked, for logs & errors
+pub fn redact<T: Document>(data: &BsonDocument) -> BsonDocument {
+    redact_paths(data, &T::sensitive_fields())
+}
+
+/// Renders a query on `T` for logs & errors, with its sensitive fields masked
+pub fn redact_query<T: Document>(query: Query) -> String {
+    TryInto::<BsonDocument>::try_into(query).map(|q| redact::<T>(&q).to_string()).unwrap_or_else(|_| String::from("Unparseable query"))
+}
+
+/// Masks the values quoted in validation messages about the sensitive `fields`
+pub(crate) fn redact_errors(errors: &mut [FieldError], fields: &[&str]) {
+    for error in errors.iter_mut().filter(|e| is_sensitive(&e.field, fields)) {
+        if let Some(quoted) = error.message.rfind(", got ") {
+            error.message.replace_range(quoted + 6.., REDACTED);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::{fmt::Debug, hash::Hash, marker::PhantomData};
+
+use async_trait::async_trait;
+use bson::{doc, Bson};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::{id_query, Client, Collection};
+
+use super::{
+    document::Document,
+    driver::{DriverCapabilities, Find, OperationCount, WriteOptions},
+    error::{OResult, OrmoxError},
+    memory::get_path,
+    observer::Observer,
+    query::Query,
+};
+
+/// Typed reference to another document, stored as the document's ID
+#[derive(Serialize, Deserialize)]
+#[serde(transparent, bound = "")]
+pub struct Ref<T: Document> {
+    id: T::Id,
+
+    #[serde(skip)]
+    _document: PhantomData<fn() -> T>
+}
+
+impl<T: Document> Ref<T> {
+    pub fn new(id: T::Id) -> Self {
+        Self { id, _document: PhantomData }
+    }
+
+    pub fn id(&self) -> &T::Id {
+        &self.id
+    }
+
+    pub fn into_id(self) -> T::Id {
+        self.id
+    }
+
+    /// Loads the referenced document from `collection`, if it exists
+    pub async fn fetch(&self, collection: &Collection<T>) -> OResult<Option<T>> {
+        Ok(collection.find(id_query::<T>(&self.id)?, Some(Find::one())).await?.into_iter().next())
+    }
+}
+
+impl<T: Document> From<&T> for Ref<T> {
+    fn from(document: &T) -> Self {
+        Self::new(document.id())
+    }
+}
+
+impl<T: Document> Clone for Ref<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.id.clone())
+    }
+}
+
+impl<T: Document> Debug for Ref<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Ref").field(&self.id).finish()
+    }
+}
+
+impl<T: Document> PartialEq for Ref<T> where T::Id: PartialEq {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T: Document> Eq for Ref<T> where T::Id: Eq {}
+
+impl<T: Document> Hash for Ref<T> where T::Id: Hash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T: Document> schemars::JsonSchema for Ref<T> where T::Id: schemars::JsonSchema {
+    fn schema_name() -> String {
+        <T::Id as schemars::JsonSchema>::schema_name()
+    }
+
+    fn json_schema(generator: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        <T::Id as schemars::JsonSchema>::json_schema(generator)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+/// Schema of the id field in a document's OpenAPI schema, looking through the variants of enum documents
+#[cfg(feature = "utoipa")]
+fn id_schema(schema: &utoipa::openapi::RefOr<utoipa::openapi::Schema>, field: &str) -> Option<utoipa::openapi::RefOr<utoipa::openapi::Schema>> {
+    use utoipa::openapi::{RefOr, Schema};
+    match schema {
+        RefOr::T(Schema::Object(object)) => object.properties.get(field).cloned(),
+        RefOr::T(Schema::OneOf(variants)) => variants.items.iter().find_map(|variant| id_schema(variant, field)),
+        RefOr::T(Schema::AllOf(parts)) => parts.items.iter().find_map(|part| id_schema(part, field)),
+        _ => None
+    }
+}
+
+/// OpenAPI schema of the referenced document's id, as it appears in that document's schema. utoipa's derive composes
+/// the schemas of generic field types through `ComposeSchema`, which also provides `PartialSchema`.
+#[cfg(feature = "utoipa")]
+impl<T: Document + utoipa::ToSchema> utoipa::__dev::ComposeSchema for Ref<T> {
+    fn compose(_generics: Vec<utoipa::openapi::RefOr<utoipa::openapi::Schema>>) -> utoipa::openapi::RefOr<utoipa::openapi::Schema> {
+        id_schema(&T::schema(), &T::id_field()).unwrap_or_else(|| {
+            utoipa::openapi::ObjectBuilder::new().schema_type(utoipa::openapi::schema::SchemaType::AnyValue).into()
+        })
+    }
+}
+
+/// Named `Ref`; utoipa's derive suffixes it with the referenced document's name, ie `Ref_User`
+#[cfg(feature = "utoipa")]
+impl<T: Document + utoipa::ToSchema> utoipa::ToSchema for Ref<T> {}
+
+/// GraphQL output of the referenced document's id
+#[cfg(feature = "graphql")]
+impl<T: Document> async_graphql::OutputType for Ref<T> where T::Id: async_graphql::OutputType {
+    fn type_name() -> std::borrow::Cow<'static, str> {
+        <T::Id as async_graphql::OutputType>::type_name()
+    }
+
+    fn create_type_info(registry: &mut async_graphql::registry::Registry) -> String {
+        <T::Id as async_graphql::OutputType>::create_type_info(registry)
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &async_graphql::ContextSelectionSet<'_>,
+        field: &async_graphql::Positioned<async_graphql::parser::types::Field>
+    ) -> async_graphql::ServerResult<async_graphql::Value> {
+        self.id.resolve(ctx, field).await
+    }
+}
+
+/// An ID held by one of a document's `Ref` fields, from `Document::references`
+#[derive(Clone, Debug)]
+pub struct Reference {
+    /// Stored name of the referencing field
+    pub field: String,
+
+    /// Collection of the referenced document
+    pub collection: String,
+
+    /// ID field of the referenced document
+    pub id_field: String,
+
+    pub id: Value
+}
+
+/// Field types holding `Ref`s: `Ref<T>`, and `Option`s & `Vec`s of them
+pub trait RefField {
+    fn collect_references(&self, field: &str, references: &mut Vec<Reference>) -> OResult<()>;
+}
+
+impl<T: Document> RefField for Ref<T> {
+    fn collect_references(&self, field: &str, references: &mut Vec<Reference>) -> OResult<()> {
+        references.push(Reference {
+            field: field.to_string(),
+            collection: T::collection_name(),
+            id_field: T::id_field(),
+            id: serde_json::to_value(&self.id).map_err(OrmoxError::serialization)?
+        });
+        Ok(())
+    }
+}
+
+impl<R: RefField> RefField for Option<R> {
+    fn collect_references(&self, field: &str, references: &mut Vec<Reference>) -> OResult<()> {
+        match self {
+            Some(value) => value.collect_references(field, references),
+            None => Ok(())
+        }
+    }
+}
+
+impl<R: RefField> RefField for Vec<R> {
+    fn collect_references(&self, field: &str, references: &mut Vec<Reference>) -> OResult<()> {
+        self.iter().try_for_each(|value| value.collect_references(field, references))
+    }
+}
+
+/// Collection of `U` on the same client as `document` (its attached collection, or the global client)
+fn related<T: Document, U: Document>(document: &T) -> OResult<Collection<U>> {
+    document.collection().map(|collection| collection.related::<U>()).ok_or(OrmoxError::Uninitialized)
+}
+
+/// The `U` whose ID is `key` (a field of `document`), for `#[relation(belongs_to = "...")]`. `None` if `key` is unset
+/// (`None`) or the document doesn't exist.
+pub async fn belongs_to<T: Document, U: Document>(document: &T, key: &impl Serialize) -> OResult<Option<U>> {
+    if serde_json::to_value(key).map_err(OrmoxError::serialization)?.is_null() {
+        return Ok(None);
+    }
+
+    let collection = related::<T, U>(document)?;
+    Ok(collection.find(id_query::<U>(key)?, Some(Find::one())).await?.into_iter().next())
+}
+
+/// Every `U` whose `foreign_key` (a stored field name) is the ID of `document`, for `#[relation(has_many = "...")]`.
+/// All of them are read, regardless of the client's default & maximum limits.
+pub async fn has_many<T: Document, U: Document>(document: &T, foreign_key: &str) -> OResult<Vec<U>> {
+    let id = serde_json::to_value(document.id()).map_err(OrmoxError::serialization)?;
+    related::<T, U>(document)?.find_every(Query::new().field(foreign_key, id)).await
+}
+
+/// A many-to-many relation to `U`, stored as `{source_key: <this ID>, target_key: <U's ID>}` pairs in a junction
+/// collection. Generated by `#[relation(many_to_many = "...", through = "...")]`; linking & fetching is done with
+/// `Document::link`, `Document::unlink` & `Document::linked`.
+pub trait ManyToMany<U: Document>: Document {
+    /// Name of the junction collection
+    fn junction() -> String;
+
+    /// Junction field holding this document's ID
+    fn source_key() -> String;
+
+    /// Junction field holding the related document's ID
+    fn target_key() -> String;
+}
+
+/// Count of the documents referencing a document, kept in one of its fields, declared with
+/// `#[relation(has_many = "Comment", foreign_key = "post_id", counter_cache = "comments_count")]` and maintained once
+/// registered with `Collection::register_counter_caches`, so listing documents with their counts needs no aggregation
+#[derive(Clone, Debug)]
+pub struct CounterCache {
+    /// Collection of the counted documents
+    pub collection: String,
+
+    /// Stored name of the counted documents' field holding the counting document's ID
+    pub foreign_key: String,
+
+    /// Stored name of the counting document's field holding the count
+    pub field: String
+}
+
+impl CounterCache {
+    /// Count of the `U` referencing a document through `foreign_key`, kept in its `field`
+    pub fn new<U: Document>(foreign_key: impl AsRef<str>, field: impl AsRef<str>) -> Self {
+        Self { collection: U::collection_name(), foreign_key: foreign_key.as_ref().to_string(), field: field.as_ref().to_string() }
+    }
+
+    /// Number of counted documents referencing each ID, grouped by aggregation where supported
+    pub(crate) async fn counts(&self, client: &Client) -> OResult<Vec<(Bson, i64)>> {
+        if !client.supports(DriverCapabilities::AGGREGATION) {
+            let options = Find { projection: Some(doc! {&self.foreign_key: 1}), ..Find::many() };
+            let documents = client.driver().find(self.collection.clone(), Query::new(), options).await?;
+            return Ok(tally(&documents, &self.foreign_key, 1));
+        }
+
+        let pipeline = vec![
+            doc! {"$match": {&self.foreign_key: {"$ne": Bson::Null}}},
+            doc! {"$group": {"_id": format!("${}", self.foreign_key), "count": {"$sum": 1}}},
+        ];
+        let groups = client.driver().aggregate(self.collection.clone(), pipeline, Find::many()).await?;
+        Ok(groups
+            .into_iter()
+            .filter_map(|group| {
+                let count = match group.get("count") {
+                    Some(Bson::Int32(count)) => i64::from(*count),
+                    Some(Bson::Int64(count)) => *count,
+                    Some(Bson::Double(count)) => *count as i64,
+                    _ => 0
+                };
+                group.get("_id").filter(|key| **key != Bson::Null).map(|key| (key.clone(), count))
+            })
+            .collect())
+    }
+}
+
+/// `sign` per document, summed by the (non-null) value of its `foreign_key`
+fn tally(documents: &[bson::Document], foreign_key: &str, sign: i64) -> Vec<(Bson, i64)> {
+    let mut tallies: Vec<(Bson, i64)> = Vec::new();
+    for key in documents.iter().filter_map(|document| get_path(document, foreign_key)) {
+        if key == &Bson::Null {
+            continue;
+        }
+        match tallies.iter_mut().find(|(counted, _)| counted == key) {
+            Some((_, tally)) => *tally += sign,
+            None => tallies.push((key.clone(), sign))
+        }
+    }
+    tallies
+}
+
+/// Observer of the counted collection keeping a `CounterCache` of documents in `counting`, ID'd by `id_field`
+pub(crate) struct CounterCacheObserver {
+    pub(crate) cache: CounterCache,
+    pub(crate) counting: String,
+    pub(crate) id_field: String
+}
+
+impl CounterCacheObserver {
+    /// Adds `sign` per document to the counters of the documents they reference
+    async fn count(&self, client: &Client, documents: &[bson::Document], sign: i64) -> OResult<()> {
+        for (key, delta) in tally(documents, &self.cache.foreign_key, sign) {
+            let query = Query::try_from(doc! {&self.id_field: key})?;
+            let update = doc! {"$inc": {&self.cache.field: delta}};
+            client.driver().update(self.counting.clone(), query, update, OperationCount::One, WriteOptions::default()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Observer for CounterCacheObserver {
+    fn collection(&self) -> String {
+        self.cache.collection.clone()
+    }
+
+    fn key(&self) -> Option<String> {
+        Some(format!("counter_cache:{}.{}", self.counting, self.cache.field))
+    }
+
+    async fn inserted(&self, client: &Client, documents: &[bson::Document]) -> OResult<()> {
+        self.count(client, documents, 1).await
+    }
+
+    async fn deleted(&self, client: &Client, documents: &[bson::Document]) -> OResult<()> {
+        self.count(client, documents, -1).await
+    }
+}
@@ -0,0 +1,143 @@
+use bson::Bson;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use super::{
+    context::{self, OperationContext},
+    driver::{DriverCapabilities, Find, OperationCount, WriteOptions},
+    error::{OResult, OrmoxError},
+    query::Query,
+};
+
+/// A document sent between remote drivers & `ormox_server`, as canonical Extended JSON so BSON types (dates, binary,
+/// 64-bit integers) survive the trip
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WireDocument(pub bson::Document);
+
+impl Serialize for WireDocument {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Bson::Document(self.0.clone()).into_canonical_extjson().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for WireDocument {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Bson::try_from(Value::deserialize(deserializer)?) {
+            Ok(Bson::Document(document)) => Ok(Self(document)),
+            Ok(other) => Err(serde::de::Error::custom(format!("Expected a document, got {other}"))),
+            Err(e) => Err(serde::de::Error::custom(e))
+        }
+    }
+}
+
+impl TryFrom<Query> for WireDocument {
+    type Error = OrmoxError;
+    fn try_from(value: Query) -> OResult<Self> {
+        Ok(Self(value.try_into()?))
+    }
+}
+
+impl TryFrom<WireDocument> for Query {
+    type Error = OrmoxError;
+    fn try_from(value: WireDocument) -> OResult<Self> {
+        Query::try_from(value.0)
+    }
+}
+
+impl From<bson::Document> for WireDocument {
+    fn from(value: bson::Document) -> Self {
+        Self(value)
+    }
+}
+
+/// Wraps documents for the wire
+pub fn wire(documents: Vec<bson::Document>) -> Vec<WireDocument> {
+    documents.into_iter().map(WireDocument).collect()
+}
+
+/// Unwraps documents from the wire
+pub fn unwire(documents: Vec<WireDocument>) -> Vec<bson::Document> {
+    documents.into_iter().map(|WireDocument(d)| d).collect()
+}
+
+/// Header (or gRPC metadata) carrying the current `OperationContext` to the server
+pub const CONTEXT_HEADER: &str = "ormox-context";
+
+/// The current context as a `CONTEXT_HEADER` value: JSON, with non-ASCII characters escaped so it's a valid header
+pub fn context_header() -> Option<String> {
+    let json = serde_json::to_string(context::current()?.as_ref()).ok()?;
+    let mut header = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() && !c.is_ascii_control() {
+            header.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                header.push_str(&format!("\\u{unit:04x}"));
+            }
+        }
+    }
+    Some(header)
+}
+
+/// Parses a `CONTEXT_HEADER` value, ignoring malformed ones
+pub fn parse_context_header(header: &[u8]) -> Option<OperationContext> {
+    serde_json::from_slice(header).ok()
+}
+
+/// The remote database's driver & what it supports
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ServerInfo {
+    pub driver_name: String,
+    pub capabilities: DriverCapabilities
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InsertRequest {
+    pub documents: Vec<WireDocument>,
+    pub options: WriteOptions
+}
+
+/// Body of `update` & `upsert` requests; `document` is the update or the replacement document. Queries are sent as
+/// documents.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WriteRequest {
+    pub query: WireDocument,
+    pub document: WireDocument,
+    pub count: OperationCount,
+    pub options: WriteOptions
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeleteRequest {
+    pub query: WireDocument,
+    pub count: OperationCount,
+    pub options: WriteOptions
+}
+
+/// Body of `find`, `all`, `count`, `find_with_count` & `explain` requests
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FindRequest {
+    pub query: WireDocument,
+    pub options: Find
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AggregateRequest {
+    pub pipeline: Vec<WireDocument>,
+    pub options: Find
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FindOneAndUpdateRequest {
+    pub query: WireDocument,
+    pub update: WireDocument,
+    pub upsert: bool,
+    pub options: WriteOptions
+}
+
+/// Response of `find_with_count` requests
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CountedDocuments {
+    pub documents: Vec<WireDocument>,
+    pub count: u64
+}
@@ -0,0 +1,119 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::Duration,
+};
+
+use futures::future::{AbortHandle, Abortable, BoxFuture};
+
+/// Async runtime running ormox's background tasks (outbox relays, scheduled maintenance). Implementations for tokio,
+/// async-std & smol are enabled by the features of the same name; others can be set with `ClientOptions::runtime`.
+pub trait Runtime: Debug + Send + Sync {
+    /// Runs `future` in the background, detached from the caller
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+
+    /// Completes after `duration`
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// Handle of a background task. The task is aborted when the handle is dropped.
+pub struct Task {
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>
+}
+
+impl Task {
+    /// Spawns `future` on `runtime`
+    pub fn spawn(runtime: &dyn Runtime, future: impl Future<Output = ()> + Send + 'static) -> Self {
+        let (abort, registration) = AbortHandle::new_pair();
+        let finished = Arc::new(AtomicBool::new(false));
+        let finishing = finished.clone();
+        runtime.spawn(Box::pin(async move {
+            let _ = Abortable::new(future, registration).await;
+            finishing.store(true, Ordering::Release);
+        }));
+        Self { abort, finished }
+    }
+
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.abort.is_aborted() || self.finished.load(Ordering::Acquire)
+    }
+
+    /// Handle aborting the task without owning it, ie for `Client::close`
+    pub(crate) fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+/// Runs tasks on the current tokio runtime. Spawning outside of one panics, as with `tokio::spawn`.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl Runtime for TokioRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Runs tasks on async-std's global executor
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        async_std::task::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// Runs tasks on smol's global executor
+#[cfg(feature = "smol")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "smol")]
+impl Runtime for SmolRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        smol::spawn(future).detach();
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
+
+/// Runtime enabled by features, preferring tokio, then async-std, then smol
+#[allow(unreachable_code)]
+pub fn default_runtime() -> Option<Arc<dyn Runtime>> {
+    #[cfg(feature = "tokio")]
+    return Some(Arc::new(TokioRuntime));
+    #[cfg(feature = "async-std")]
+    return Some(Arc::new(AsyncStdRuntime));
+    #[cfg(feature = "smol")]
+    return Some(Arc::new(SmolRuntime));
+    None
+}
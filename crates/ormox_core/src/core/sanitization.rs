@@ -0,0 +1,192 @@
+use serde_json::{Map, Value};
+
+use super::{
+    document::Document,
+    error::{OResult, OrmoxError},
+    query::Query,
+};
+
+/// Operators allowed by default: comparisons, membership, array & logical operators. `$regex` is left out, as patterns
+/// from clients can be arbitrarily slow to evaluate.
+pub const DEFAULT_OPERATORS: &[&str] = &[
+    "$eq", "$ne", "$gt", "$gte", "$lt", "$lte", "$in", "$nin", "$exists", "$not", "$size", "$all", "$elemMatch", "$and", "$or", "$nor"
+];
+
+const LOGICAL: &[&str] = &["$and", "$or", "$nor"];
+
+/// Rules for turning filters from untrusted clients (ie GraphQL arguments or query strings) into `Query` values: which
+/// fields may be filtered on, which operators may be used, and how large filters may get.
+#[derive(Clone, Debug)]
+pub struct SanitizationPolicy {
+    /// Stored paths that may be filtered on, along with their subfields; `None` allows every field that isn't denied
+    pub allowed_fields: Option<Vec<String>>,
+
+    /// Stored paths that may not be filtered on, either directly, through their subfields, or by matching a parent
+    /// document exactly
+    pub denied_fields: Vec<String>,
+
+    pub operators: Vec<String>,
+
+    /// Maximum nesting of operators, ie `$or` inside `$and` inside `$elemMatch`
+    pub max_depth: usize,
+
+    /// Maximum number of values in `$in`, `$nin` & `$all` lists, and of cases in `$and`, `$or` & `$nor`
+    pub max_values: usize
+}
+
+impl Default for SanitizationPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_fields: None,
+            denied_fields: Vec::new(),
+            operators: DEFAULT_OPERATORS.iter().map(|op| op.to_string()).collect(),
+            max_depth: 8,
+            max_values: 100
+        }
+    }
+}
+
+/// Whether `path` is `parent` or one of its subfields
+fn within(path: &str, parent: &str) -> bool {
+    path.strip_prefix(parent).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+fn has_document(value: &Value) -> bool {
+    match value {
+        Value::Object(_) => true,
+        Value::Array(values) => values.iter().any(has_document),
+        _ => false
+    }
+}
+
+impl SanitizationPolicy {
+    /// Default policy for filters on `T`, denying its sensitive (`#[ormox(sensitive)]`, encrypted & hashed) fields
+    pub fn for_document<T: Document>() -> Self {
+        Self {
+            denied_fields: T::sensitive_fields().into_iter().map(String::from).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Only allows filtering on the given fields (and any others allowed this way)
+    pub fn allow_field(mut self, path: impl AsRef<str>) -> Self {
+        self.allowed_fields.get_or_insert_with(Vec::new).push(path.as_ref().to_string());
+        self
+    }
+
+    pub fn deny_field(mut self, path: impl AsRef<str>) -> Self {
+        self.denied_fields.push(path.as_ref().to_string());
+        self
+    }
+
+    pub fn allow_operator(mut self, operator: impl AsRef<str>) -> Self {
+        self.operators.push(operator.as_ref().to_string());
+        self
+    }
+
+    /// Checks a MongoDB-style filter against the policy, converting it to a `Query` if it passes
+    pub fn sanitize(&self, filter: Value) -> OResult<Query> {
+        let Value::Object(fields) = &filter else {
+            return Err(OrmoxError::compaibility("Filters must be objects"));
+        };
+        self.check_filter(fields, "", 0)?;
+        Query::try_from(bson::to_document(&filter).map_err(OrmoxError::compaibility)?)
+    }
+
+    fn check_operator(&self, operator: &str) -> OResult<()> {
+        // $options only modifies $regex
+        let operator = if operator == "$options" { "$regex" } else { operator };
+        if self.operators.iter().any(|allowed| allowed == operator) {
+            Ok(())
+        } else {
+            Err(OrmoxError::compaibility(format!("Operator {operator} is not allowed in filters")))
+        }
+    }
+
+    fn check_field(&self, path: &str) -> OResult<()> {
+        let allowed = self.allowed_fields.as_ref().is_none_or(|allowed| allowed.iter().any(|field| within(path, field)));
+        if !allowed || self.denied_fields.iter().any(|field| within(path, field)) {
+            return Err(OrmoxError::compaibility(format!("Filtering on {path} is not allowed")));
+        }
+        Ok(())
+    }
+
+    /// Checks that matching `path` against a document value doesn't reveal denied subfields
+    fn check_document(&self, path: &str) -> OResult<()> {
+        match self.denied_fields.iter().find(|field| within(field, path)) {
+            Some(field) => Err(OrmoxError::compaibility(format!("Filtering on {path} is not allowed, as it contains {field}"))),
+            None => Ok(())
+        }
+    }
+
+    fn check_depth(&self, depth: usize) -> OResult<()> {
+        if depth > self.max_depth {
+            return Err(OrmoxError::compaibility(format!("Filters can't nest more than {} operators deep", self.max_depth)));
+        }
+        Ok(())
+    }
+
+    fn check_values<'a>(&self, operator: &str, operand: &'a Value) -> OResult<&'a Vec<Value>> {
+        let values = operand.as_array().ok_or_else(|| OrmoxError::compaibility(format!("{operator} needs a list")))?;
+        if values.len() > self.max_values {
+            return Err(OrmoxError::compaibility(format!("{operator} can't have more than {} values", self.max_values)));
+        }
+        Ok(values)
+    }
+
+    /// Checks the fields & logical operators of a filter whose paths are relative to `prefix`
+    fn check_filter(&self, filter: &Map<String, Value>, prefix: &str, depth: usize) -> OResult<()> {
+        self.check_depth(depth)?;
+        for (key, value) in filter {
+            match key.as_str() {
+                logical if LOGICAL.contains(&logical) => {
+                    self.check_operator(key)?;
+                    for case in self.check_values(key, value)? {
+                        let case = case.as_object().ok_or_else(|| OrmoxError::compaibility(format!("{key} needs a list of filters")))?;
+                        self.check_filter(case, prefix, depth + 1)?;
+                    }
+                },
+                operator if operator.starts_with('$') => {
+                    return Err(OrmoxError::compaibility(format!("Operator {operator} needs a field")));
+                },
+                field => {
+                    let path = if prefix.is_empty() { field.to_string() } else { format!("{prefix}.{field}") };
+                    self.check_field(&path)?;
+                    self.check_condition(value, &path, depth)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the condition on the field at `path`: a value to match, or an object of operators
+    fn check_condition(&self, condition: &Value, path: &str, depth: usize) -> OResult<()> {
+        let operators = match condition {
+            Value::Object(operators) if operators.keys().any(|key| key.starts_with('$')) => operators,
+            value if has_document(value) => return self.check_document(path),
+            _ => return Ok(())
+        };
+
+        self.check_depth(depth + 1)?;
+        for (operator, operand) in operators {
+            if !operator.starts_with('$') {
+                return Err(OrmoxError::compaibility(format!("Condition on {path} mixes operators and fields")));
+            }
+            self.check_operator(operator)?;
+            match operator.as_str() {
+                "$not" => self.check_condition(operand, path, depth + 1)?,
+                "$elemMatch" => match operand {
+                    Value::Object(filter) if filter.keys().any(|key| !key.starts_with('$') || LOGICAL.contains(&key.as_str())) => {
+                        self.check_filter(filter, path, depth + 1)?
+                    },
+                    condition => self.check_condition(condition, path, depth + 1)?
+                },
+                "$in" | "$nin" | "$all" if self.check_values(operator, operand)?.iter().any(has_document) => self.check_document(path)?,
+                "$in" | "$nin" | "$all" => {},
+                _ if has_document(operand) => self.check_document(path)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
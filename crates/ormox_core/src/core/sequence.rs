@@ -0,0 +1,39 @@
+use super::error::{OResult, OrmoxError};
+
+/// Collection holding the counters of `Client::next_sequence`, as `{_id: <name>, value: <last value>}`
+pub const SEQUENCES_COLLECTION: &str = "_ormox_sequences";
+
+/// Types of `#[ormox(sequence = "...")]` fields: integers, which are filled on insert when `0`, and `Option`s of them,
+/// filled when `None`
+pub trait SequenceValue: Sized {
+    fn is_unset(&self) -> bool;
+    fn from_sequence(value: i64) -> OResult<Self>;
+}
+
+macro_rules! sequence_value {
+    ($($t:ty),*) => {
+        $(
+            impl SequenceValue for $t {
+                fn is_unset(&self) -> bool {
+                    *self == 0
+                }
+
+                fn from_sequence(value: i64) -> OResult<Self> {
+                    <$t>::try_from(value).map_err(|_| OrmoxError::compaibility(format!("Sequence value {value} doesn't fit in {}", stringify!($t))))
+                }
+            }
+
+            impl SequenceValue for Option<$t> {
+                fn is_unset(&self) -> bool {
+                    self.is_none()
+                }
+
+                fn from_sequence(value: i64) -> OResult<Self> {
+                    <$t>::from_sequence(value).map(Some)
+                }
+            }
+        )*
+    };
+}
+
+sequence_value!(i32, i64, isize, u32, u64, usize);
@@ -0,0 +1,89 @@
+use bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+
+/// Builder for MongoDB-style update documents (`{"$set": {...}, "$inc": {...}}`), for use with
+/// `Collection::update` and friends.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Update(Document);
+
+impl Update {
+    pub fn new() -> Self {
+        Update(Document::new())
+    }
+
+    fn push(mut self, operator: &str, key: impl AsRef<str>, value: Bson) -> Self {
+        match self.0.get_mut(operator) {
+            Some(Bson::Document(fields)) => {
+                fields.insert(key.as_ref(), value);
+            }
+            _ => {
+                let mut fields = Document::new();
+                fields.insert(key.as_ref(), value);
+                self.0.insert(operator, fields);
+            }
+        }
+        self
+    }
+
+    /// Sets `key` to `value`
+    pub fn set(self, key: impl AsRef<str>, value: impl Into<Bson>) -> Self {
+        self.push("$set", key, value.into())
+    }
+
+    /// Removes `key` from the document
+    pub fn unset(self, key: impl AsRef<str>) -> Self {
+        self.push("$unset", key, Bson::String(String::new()))
+    }
+
+    /// Adds `amount` to the numeric field `key`
+    pub fn inc(self, key: impl AsRef<str>, amount: impl Into<Bson>) -> Self {
+        self.push("$inc", key, amount.into())
+    }
+
+    /// Appends `value` to the array field `key`
+    pub fn push_value(self, key: impl AsRef<str>, value: impl Into<Bson>) -> Self {
+        self.push("$push", key, value.into())
+    }
+
+    /// Removes all instances of `value` from the array field `key`
+    pub fn pull(self, key: impl AsRef<str>, value: impl Into<Bson>) -> Self {
+        self.push("$pull", key, value.into())
+    }
+
+    /// Adds an update operator not covered by the builder methods
+    pub fn operation(self, operator: impl AsRef<str>, key: impl AsRef<str>, value: impl Into<Bson>) -> Self {
+        self.push(operator.as_ref(), key, value.into())
+    }
+
+    /// Update turning the top-level fields of `old` into those of `new`: changed & added fields are `$set`, removed fields `$unset`
+    pub fn diff(old: &Document, new: &Document) -> Self {
+        let mut update = Update::new();
+        for (key, value) in new {
+            if old.get(key) != Some(value) {
+                update = update.set(key, value.clone());
+            }
+        }
+
+        for key in old.keys() {
+            if !new.contains_key(key) {
+                update = update.unset(key);
+            }
+        }
+        update
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn build(self) -> Self {
+        self
+    }
+}
+
+impl From<Update> for Document {
+    fn from(value: Update) -> Self {
+        value.0
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    sync::OnceLock,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    document::Document,
+    error::{OResult, OrmoxError},
+    redaction::redact_errors,
+};
+
+/// A failed validation rule on one field of a document
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FieldError {
+    /// Stored path of the field, in dot notation for fields of embedded documents (ie `address.city`)
+    pub field: String,
+
+    /// The rule that failed: `length`, `range`, `regex` or `email`
+    pub rule: String,
+
+    pub message: String
+}
+
+impl FieldError {
+    pub fn new(field: impl AsRef<str>, rule: impl AsRef<str>, message: impl AsRef<str>) -> Self {
+        Self { field: field.as_ref().to_string(), rule: rule.as_ref().to_string(), message: message.as_ref().to_string() }
+    }
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Lazily compiled pattern of a `#[validate(regex = "...")]` rule, cached in a static per field
+pub type Pattern = OnceLock<Option<Regex>>;
+
+/// Values with a length, for `#[validate(length(...))]`. Strings count characters; `None` always passes.
+pub trait ValidateLength {
+    fn validated_length(&self) -> Option<usize>;
+}
+
+/// Text values, for `#[validate(regex = "...")]` & `#[validate(email)]`. `None` always passes.
+pub trait ValidateText {
+    fn validated_text(&self) -> Option<&str>;
+}
+
+/// Ordered values, for `#[validate(range(...))]`. `None` always passes.
+pub trait ValidateRange {
+    type Value: PartialOrd + Display;
+    fn validated_value(&self) -> Option<&Self::Value>;
+}
+
+impl ValidateLength for str {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.chars().count())
+    }
+}
+
+impl ValidateLength for String {
+    fn validated_length(&self) -> Option<usize> {
+        self.as_str().validated_length()
+    }
+}
+
+impl ValidateLength for Cow<'_, str> {
+    fn validated_length(&self) -> Option<usize> {
+        self.as_ref().validated_length()
+    }
+}
+
+impl<T> ValidateLength for Vec<T> {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<K, V> ValidateLength for HashMap<K, V> {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<K, V> ValidateLength for BTreeMap<K, V> {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<T> ValidateLength for HashSet<T> {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<T> ValidateLength for BTreeSet<T> {
+    fn validated_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+impl<T: ValidateLength> ValidateLength for Option<T> {
+    fn validated_length(&self) -> Option<usize> {
+        self.as_ref().and_then(|v| v.validated_length())
+    }
+}
+
+impl ValidateText for str {
+    fn validated_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl ValidateText for String {
+    fn validated_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl ValidateText for Cow<'_, str> {
+    fn validated_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<T: ValidateText> ValidateText for Option<T> {
+    fn validated_text(&self) -> Option<&str> {
+        self.as_ref().and_then(|v| v.validated_text())
+    }
+}
+
+macro_rules! validate_range {
+    ($($t:ty),*) => {
+        $(
+            impl ValidateRange for $t {
+                type Value = $t;
+                fn validated_value(&self) -> Option<&$t> {
+                    Some(self)
+                }
+            }
+
+            impl ValidateRange for Option<$t> {
+                type Value = $t;
+                fn validated_value(&self) -> Option<&$t> {
+                    self.as_ref()
+                }
+            }
+        )*
+    };
+}
+
+validate_range!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// Checks a `length(min, max)` rule
+pub fn length<V: ValidateLength + ?Sized>(errors: &mut Vec<FieldError>, field: &str, value: &V, min: Option<usize>, max: Option<usize>) {
+    let Some(length) = value.validated_length() else { return };
+    if min.is_some_and(|min| length < min) || max.is_some_and(|max| length > max) {
+        let message = match (min, max) {
+            (Some(min), Some(max)) => format!("length must be between {min} and {max}, got {length}"),
+            (Some(min), None) => format!("length must be at least {min}, got {length}"),
+            (None, Some(max)) => format!("length must be at most {max}, got {length}"),
+            (None, None) => unreachable!()
+        };
+        errors.push(FieldError::new(field, "length", message));
+    }
+}
+
+/// Checks a `range(min, max)` rule
+pub fn range<V: ValidateRange + ?Sized>(errors: &mut Vec<FieldError>, field: &str, value: &V, min: Option<V::Value>, max: Option<V::Value>) {
+    let Some(value) = value.validated_value() else { return };
+    let below = min.as_ref().is_some_and(|min| value < min);
+    let above = max.as_ref().is_some_and(|max| value > max);
+    if below || above {
+        let message = match (min, max) {
+            (Some(min), Some(max)) => format!("must be between {min} and {max}, got {value}"),
+            (Some(min), None) => format!("must be at least {min}, got {value}"),
+            (None, Some(max)) => format!("must be at most {max}, got {value}"),
+            (None, None) => unreachable!()
+        };
+        errors.push(FieldError::new(field, "range", message));
+    }
+}
+
+/// Checks a `regex = "..."` rule. The compiled pattern is cached in `compiled`, one per field.
+pub fn regex<V: ValidateText + ?Sized>(errors: &mut Vec<FieldError>, field: &str, value: &V, compiled: &Pattern, pattern: &str) {
+    let Some(text) = value.validated_text() else { return };
+    match compiled.get_or_init(|| Regex::new(pattern).ok()) {
+        Some(regex) if regex.is_match(text) => {},
+        Some(_) => errors.push(FieldError::new(field, "regex", format!("must match the pattern {pattern:?}"))),
+        None => errors.push(FieldError::new(field, "regex", format!("the pattern {pattern:?} is not a valid regex")))
+    }
+}
+
+/// Checks an `email` rule: a single `@` with a non-empty local part and a dotted domain, and no whitespace
+pub fn email<V: ValidateText + ?Sized>(errors: &mut Vec<FieldError>, field: &str, value: &V) {
+    let Some(text) = value.validated_text() else { return };
+    let valid = match text.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|part| !part.is_empty())
+                && !text.chars().any(char::is_whitespace)
+        },
+        None => false
+    };
+    if !valid {
+        errors.push(FieldError::new(field, "email", "must be an email address"));
+    }
+}
+
+/// Types whose `#[validate(...)]` rules are checked by the documents holding them in `#[ormox(nested)]` fields:
+/// documents, and embedded documents (`#[derive(EmbeddedDocument)]`). Types without rules keep the default, which
+/// checks nothing.
+pub trait ValidateFields {
+    /// Checks the rules of the value's fields, pushing failures with their stored paths under `prefix`, which is empty
+    /// or ends with a dot (ie `"address."`)
+    fn validate_fields(&self, _errors: &mut Vec<FieldError>, _prefix: &str) {}
+}
+
+impl<T: ValidateFields> ValidateFields for Option<T> {
+    fn validate_fields(&self, errors: &mut Vec<FieldError>, prefix: &str) {
+        if let Some(value) = self {
+            value.validate_fields(errors, prefix);
+        }
+    }
+}
+
+/// Elements are checked under their index, ie `addresses.0.city`
+impl<T: ValidateFields> ValidateFields for Vec<T> {
+    fn validate_fields(&self, errors: &mut Vec<FieldError>, prefix: &str) {
+        for (index, value) in self.iter().enumerate() {
+            value.validate_fields(errors, &format!("{prefix}{index}."));
+        }
+    }
+}
+
+impl<T: ValidateFields> ValidateFields for Box<T> {
+    fn validate_fields(&self, errors: &mut Vec<FieldError>, prefix: &str) {
+        T::validate_fields(self, errors, prefix);
+    }
+}
+
+/// Checks the rules of an `#[ormox(nested)]` field's value, under the field's stored name
+pub fn nested<V: ValidateFields + ?Sized>(errors: &mut Vec<FieldError>, field: &str, value: &V) {
+    value.validate_fields(errors, &format!("{field}."));
+}
+
+/// Turns the errors collected by a document's `validate()` into its result, masking values quoted for its sensitive
+/// fields
+pub fn result<T: Document>(mut errors: Vec<FieldError>) -> OResult<()> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        redact_errors(&mut errors, &T::sensitive_fields());
+        Err(OrmoxError::validation(T::collection_name(), errors))
+    }
+}
@@ -0,0 +1,177 @@
+//! async-graphql integration: `#[ormox_document(graphql)]` documents are GraphQL objects, `Collection::connection`
+//! serves them as paginated connections, and `Filter` arguments from clients are checked by a `SanitizationPolicy`
+//! before they're queried.
+
+use std::error::Error;
+use async_graphql::{
+    connection::{Connection, Edge},
+    InputValueError, InputValueResult, OutputType, Scalar, ScalarType, Value as GraphQLValue,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    client::Collection,
+    core::{
+        document::Document,
+        driver::{Find, Sorting},
+        error::{OResult, OrmoxError},
+        query::{Query, QueryValue},
+        sanitization::SanitizationPolicy,
+    },
+};
+
+/// Page size of `Collection::connection` when `first` isn't given
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// Largest page `Collection::connection` returns, whatever `first` asks for
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Operators of filters, written with a `_` prefix instead of `$`
+const OPERATORS: &[&str] = &[
+    "eq", "ne", "gt", "gte", "lt", "lte", "in", "nin", "exists", "not", "size", "all", "elemMatch", "regex", "options", "and", "or", "nor"
+];
+
+const LOGICAL: &[&str] = &["$and", "$or", "$nor"];
+
+/// Filter argument, shaped like the input: fields map to a value to match or to an object of `_`-prefixed operators,
+/// objects of fields match subfields, and `_and`, `_or` & `_nor` take lists of filters. For example,
+/// `{age: {_gte: 18}, address: {city: "Oslo"}, _or: [{role: "admin"}, {verified: true}]}`.
+#[derive(Clone, Debug)]
+pub struct Filter(pub Value);
+
+/// A filter on a collection's documents, like `{age: {_gte: 18}, _or: [{role: "admin"}, {verified: true}]}`
+#[Scalar(name = "Filter")]
+impl ScalarType for Filter {
+    fn parse(value: GraphQLValue) -> InputValueResult<Self> {
+        match value.into_json()? {
+            filter @ Value::Object(_) => Ok(Self(filter)),
+            other => Err(InputValueError::custom(format!("Filters must be objects, not {other}")))
+        }
+    }
+
+    fn to_value(&self) -> GraphQLValue {
+        GraphQLValue::from_json(self.0.clone()).unwrap_or_default()
+    }
+}
+
+impl Filter {
+    /// Converts the filter to a `Query`, if `policy` allows it
+    pub fn into_query(self, policy: &SanitizationPolicy) -> OResult<Query> {
+        let Value::Object(fields) = self.0 else {
+            return Err(OrmoxError::compaibility("Filters must be objects"));
+        };
+        let mut filter = Map::new();
+        translate(fields, "", &mut filter)?;
+        policy.sanitize(Value::Object(filter))
+    }
+}
+
+/// MongoDB operator of a `_`-prefixed filter key, if it is one
+fn operator(key: &str) -> Option<String> {
+    key.strip_prefix('_').filter(|name| OPERATORS.contains(name)).map(|name| format!("${name}"))
+}
+
+/// Whether an object is a condition on a field (ie `{_gt: 1, _lt: 5}`) rather than a filter on its subfields
+fn is_condition(fields: &Map<String, Value>) -> bool {
+    !fields.is_empty() && fields.keys().all(|key| operator(key).is_some_and(|op| !LOGICAL.contains(&op.as_str())))
+}
+
+/// Adds a condition to a MongoDB filter, moving it into `$and` if its key is already there (ie `_or`s at two levels)
+fn add(filter: &mut Map<String, Value>, key: String, value: Value) {
+    if !filter.contains_key(&key) {
+        filter.insert(key, value);
+    } else if let Value::Array(cases) = filter.entry("$and").or_insert_with(|| json!([])) {
+        cases.push(json!({key: value}));
+    }
+}
+
+/// Translates a filter on the subfields of `prefix` into MongoDB syntax, adding its conditions to `into`
+fn translate(fields: Map<String, Value>, prefix: &str, into: &mut Map<String, Value>) -> OResult<()> {
+    for (key, value) in fields {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+        match operator(&key) {
+            Some(logical) if LOGICAL.contains(&logical.as_str()) => {
+                let Value::Array(cases) = value else {
+                    return Err(OrmoxError::compaibility(format!("{key} needs a list of filters")));
+                };
+                let mut translated = Vec::new();
+                for case in cases {
+                    let Value::Object(case) = case else {
+                        return Err(OrmoxError::compaibility(format!("{key} needs a list of filters")));
+                    };
+                    let mut filter = Map::new();
+                    translate(case, prefix, &mut filter)?;
+                    translated.push(Value::Object(filter));
+                }
+                add(into, logical, Value::Array(translated));
+            },
+            Some(_) => return Err(OrmoxError::compaibility(format!("{key} needs a field"))),
+            None => match value {
+                Value::Object(condition) if is_condition(&condition) => add(into, path.clone(), Value::Object(translate_condition(condition, &path)?)),
+                Value::Object(subfields) => translate(subfields, &path, into)?,
+                value => add(into, path, value)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Translates the operators of a condition on `path`
+fn translate_condition(condition: Map<String, Value>, path: &str) -> OResult<Map<String, Value>> {
+    let mut translated = Map::new();
+    for (key, operand) in condition {
+        let Some(op) = operator(&key) else {
+            return Err(OrmoxError::compaibility(format!("Condition on {path} mixes operators and fields")));
+        };
+        let operand = match (op.as_str(), operand) {
+            ("$not", Value::Object(condition)) => Value::Object(translate_condition(condition, path)?),
+            ("$elemMatch", Value::Object(condition)) if is_condition(&condition) => Value::Object(translate_condition(condition, path)?),
+            ("$elemMatch", Value::Object(filter)) => {
+                let mut elements = Map::new();
+                translate(filter, "", &mut elements)?;
+                Value::Object(elements)
+            },
+            (_, operand) => operand
+        };
+        translated.insert(op, operand);
+    }
+    Ok(translated)
+}
+
+impl<T: Document + OutputType> Collection<T> {
+    /// Connection-style page of the documents matching `filter` ordered by id, for resolvers taking `first` & `after`
+    /// arguments. `after` is the cursor of the last edge of the previous page; `first` defaults to `DEFAULT_PAGE_SIZE`
+    /// and is capped at `MAX_PAGE_SIZE`.
+    pub async fn connection(
+        &self,
+        filter: impl TryInto<Query, Error = impl Error>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> OResult<Connection<String, T>> {
+        let first = match first {
+            Some(first) => usize::try_from(first).map_err(|_| OrmoxError::compaibility("first can't be negative"))?,
+            None => DEFAULT_PAGE_SIZE
+        }.min(MAX_PAGE_SIZE);
+        let filter = filter.try_into().map_err(OrmoxError::compaibility)?;
+        let query = match &after {
+            Some(cursor) => {
+                let id = serde_json::from_str::<T::Id>(cursor).map_err(|_| OrmoxError::id(cursor))?;
+                let id = serde_json::to_value(id).map_err(OrmoxError::serialization)?;
+                Query::new().and([filter, Query::new().subquery(T::id_field(), Query::new().operation("$gt", QueryValue::Value(id)))])
+            },
+            None => filter
+        };
+
+        let options = Find { sort: Some(Sorting::asc(T::id_field())), limit: Some(first + 1), ..Find::many() };
+        let mut documents = self.find(query, Some(options)).await?;
+        let has_next_page = documents.len() > first;
+        documents.truncate(first);
+
+        let mut connection = Connection::new(after.is_some(), has_next_page);
+        for document in documents {
+            let cursor = serde_json::to_string(&document.id()).map_err(OrmoxError::serialization)?;
+            connection.edges.push(Edge::new(cursor, document));
+        }
+        Ok(connection)
+    }
+}
@@ -2,17 +2,87 @@ use std::sync::{Arc, OnceLock};
 
 pub mod core;
 pub mod client;
+pub mod unit_of_work;
+pub mod partition;
+pub mod lock;
+pub mod queue;
+pub mod rate_limit;
+pub mod view;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "ormox_axum")]
+pub mod axum;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "sessions")]
+pub mod sessions;
+#[cfg(feature = "proptest")]
+pub mod testing;
 pub use uuid;
 pub use serde;
 pub use bson;
 pub use thiserror;
+#[cfg(feature = "schemars")]
+pub use schemars;
+#[cfg(feature = "utoipa")]
+pub use utoipa;
+#[cfg(feature = "graphql")]
+pub use async_graphql;
+#[cfg(feature = "proptest")]
+pub use proptest;
 
 pub use {
-    core::error::{OResult, OrmoxError},
-    core::document::{Document, Index},
-    core::driver::{DatabaseDriver, Find, FindBuilder, FindBuilderError, Sorting},
-    core::query::{Query, QueryKey, QueryValue, SimpleQuery},
-    client::{Client, Collection}
+    core::error::{ErrorKind, OResult, OrmoxError},
+    core::change::{ChangeEvent, ChangeKind, ResumeToken},
+    core::codec::{BsonCodec, Codec},
+    core::context::OperationContext,
+    core::diff::{diff, DocumentDiff, FieldChange},
+    core::document::{DecodeMode, Document, Index, IndexKind, Projection, Variant},
+    core::observer::Observer,
+    core::relation::{CounterCache, ManyToMany, Ref},
+    core::driver::{
+        Acknowledgment, Collation, CollationStrength, CollectionStats, DatabaseDriver, DriverCapabilities, ErrorPolicy, Find,
+        FindBuilder, FindBuilderError, HealthReport, IndexProgress, IndexStats, PartialResult, QueryPlan, ReadPreference, Sorting, WriteConcern, WriteOptions,
+    },
+    core::dry_run::{DryRunDriver, PlannedWrite, WritePlan},
+    core::field::{Field, FieldPaths},
+    core::limit::LimitedDriver,
+    core::merge::{merge, MergePolicy},
+    core::numeric::NumericPolicy,
+    core::outbox::OutboxEvent,
+    core::query::{Query, QueryKey, QueryMut, QueryValue, SimpleQuery},
+    core::runtime::{Runtime, Task},
+    core::sanitization::SanitizationPolicy,
+    core::update::Update,
+    core::validation::{FieldError, ValidateFields},
+    client::{Client, ClientOptions, ClientOptionsBuilder, Collection, DedupeReport, JobStatus, KeepStrategy, ScheduledJob, Session},
+    lock::LockGuard,
+    partition::{Partition, PartitionPeriod, Partitioned},
+    queue::{Queue, QueueItem},
+    rate_limit::{RateLimit, RateLimitStrategy, RateLimiter},
+    unit_of_work::UnitOfWork,
+    view::{View, ViewDefinition, ViewHandle, ViewRefresh}
 };
 
+#[cfg(feature = "encryption")]
+pub use core::encryption::{EncryptionMode, KeyProvider, StaticKeyProvider};
+
+#[cfg(feature = "cbor")]
+pub use core::codec::CborCodec;
+
+#[cfg(feature = "csv")]
+pub use core::import::{ColumnMapping, ColumnType, CsvMapping, ImportFailure, ImportReport};
+
+#[cfg(feature = "msgpack")]
+pub use core::codec::MessagePackCodec;
+
+#[cfg(feature = "tokio")]
+pub use core::runtime::TokioRuntime;
+
+#[cfg(feature = "async-std")]
+pub use core::runtime::AsyncStdRuntime;
+
+#[cfg(feature = "smol")]
+pub use core::runtime::SmolRuntime;
+
 pub(crate) static ORMOX: OnceLock<Arc<Client>> = OnceLock::new();
\ No newline at end of file
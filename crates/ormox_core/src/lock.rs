@@ -0,0 +1,154 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use bson::doc;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    client::{Client, ScheduledJob},
+    core::{
+        driver::{OperationCount, WriteOptions},
+        error::{OResult, OrmoxError},
+        query::Query,
+    },
+};
+
+/// Collection holding the leases of `Client::lock`, as `{_id: <name>, holder, acquired_at, expires_at}`
+pub const LOCKS_COLLECTION: &str = "_ormox_locks";
+
+fn expiry(ttl: Duration) -> OResult<DateTime<Utc>> {
+    let ttl = chrono::Duration::from_std(ttl).map_err(|_| OrmoxError::compaibility(format!("Lock TTL {ttl:?} is too long")))?;
+    Ok(Utc::now() + ttl)
+}
+
+/// Extends `holder`'s lease on the lock `name` by `ttl` from now, failing with `OrmoxError::LockLost` if it expired and
+/// was taken by another holder (or released) since
+async fn extend(client: &Client, name: &str, holder: Uuid, ttl: Duration) -> OResult<DateTime<Utc>> {
+    let expires_at = expiry(ttl)?;
+    let query = Query::try_from(doc! {"_id": name, "holder": holder.to_string()})?;
+    let update = doc! {"$set": {"expires_at": bson::DateTime::from_chrono(expires_at)}};
+    match client.driver().find_one_and_update(LOCKS_COLLECTION.to_string(), query, update, false, WriteOptions::default()).await? {
+        Some(_) => Ok(expires_at),
+        None => Err(OrmoxError::lock_lost(name))
+    }
+}
+
+/// A lease on a named lock shared by every client of the database, taken with `Client::lock` or `Client::try_lock`,
+/// ie to run a background job on a single instance. The lease expires after its TTL unless renewed, so a crashed
+/// holder doesn't keep the lock forever; holders of long tasks should `renew` it or `keep_alive` it in the background.
+///
+/// Dropping the guard releases the lock in the background if the client has a runtime, and otherwise leaves it to
+/// expire. Leases rely on the clocks of the clients agreeing to within a fraction of the TTL.
+pub struct LockGuard {
+    client: Client,
+    name: String,
+    holder: Uuid,
+    ttl: Duration,
+    expires_at: Arc<Mutex<DateTime<Utc>>>,
+    renewal: Option<ScheduledJob>,
+    released: bool
+}
+
+impl LockGuard {
+    /// Takes the lock if it's free or its lease has expired. The lease is taken with a single atomic upsert matching only
+    /// an expired lease, so while another holder's lease is live the upsert fails on the lock's id instead.
+    pub(crate) async fn acquire(client: &Client, name: &str, ttl: Duration) -> OResult<Option<Self>> {
+        let (holder, expires_at) = (Uuid::new_v4(), expiry(ttl)?);
+        let query = Query::try_from(doc! {"_id": name, "expires_at": {"$lte": bson::DateTime::now()}})?;
+        let update = doc! {"$set": {
+            "holder": holder.to_string(),
+            "acquired_at": bson::DateTime::now(),
+            "expires_at": bson::DateTime::from_chrono(expires_at)
+        }};
+
+        match client.driver().find_one_and_update(LOCKS_COLLECTION.to_string(), query, update, true, WriteOptions::default()).await {
+            Ok(Some(lease)) if lease.get_str("holder").is_ok_and(|current| current == holder.to_string()) => Ok(Some(Self {
+                client: client.clone(),
+                name: name.to_string(),
+                holder,
+                ttl,
+                expires_at: Arc::new(Mutex::new(expires_at)),
+                renewal: None,
+                released: false
+            })),
+            Ok(_) => Ok(None),
+            Err(e) if e.is_duplicate() => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Id of this lease, stored as the lock's `holder`
+    pub fn holder(&self) -> Uuid {
+        self.holder
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// When the lease expires unless renewed
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        *self.expires_at.lock().unwrap()
+    }
+
+    /// Whether the lease has expired by this client's clock, after which another holder may have taken the lock
+    pub fn is_expired(&self) -> bool {
+        Utc::now() >= self.expires_at()
+    }
+
+    /// Extends the lease by its TTL from now. Fails with `OrmoxError::LockLost` if it expired and the lock was taken by
+    /// another holder since.
+    pub async fn renew(&self) -> OResult<()> {
+        let expires_at = extend(&self.client, &self.name, self.holder, self.ttl).await?;
+        *self.expires_at.lock().unwrap() = expires_at;
+        Ok(())
+    }
+
+    /// Renews the lease in the background every third of its TTL until the guard is released or dropped, as the
+    /// `"lock:<name>"` scheduled job. A lost lease shows up as a failure in the job's status, and in `is_expired`.
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub fn keep_alive(&mut self) {
+        let (client, name, holder, ttl, expires_at) = (self.client.clone(), self.name.clone(), self.holder, self.ttl, self.expires_at.clone());
+        self.renewal = Some(self.client.schedule(format!("lock:{}", self.name), ttl / 3, move || {
+            let (client, name, expires_at) = (client.clone(), name.clone(), expires_at.clone());
+            async move {
+                let extended = extend(&client, &name, holder, ttl).await?;
+                *expires_at.lock().unwrap() = extended;
+                Ok(())
+            }
+        }));
+    }
+
+    fn release_query(&self) -> OResult<Query> {
+        Query::try_from(doc! {"_id": &self.name, "holder": self.holder.to_string()})
+    }
+
+    /// Releases the lock, unless the lease expired and another holder took it since
+    pub async fn release(mut self) -> OResult<()> {
+        self.released = true;
+        self.renewal = None;
+        let query = self.release_query()?;
+        self.client.driver().delete(LOCKS_COLLECTION.to_string(), query, OperationCount::One, WriteOptions::default()).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.renewal = None;
+        if let (Some(runtime), Ok(query)) = (self.client.runtime(), self.release_query()) {
+            let driver = self.client.driver();
+            runtime.spawn(Box::pin(async move {
+                let _ = driver.delete(LOCKS_COLLECTION.to_string(), query, OperationCount::One, WriteOptions::default()).await;
+            }));
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::error::Error;
+
+use bson::{doc, Bson};
+use chrono::{DateTime, Months, NaiveDate, NaiveTime, Utc};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{Client, Collection},
+    core::{
+        document::Document,
+        driver::{Find, OperationCount, Sorting, WriteOptions},
+        error::{OResult, OrmoxError},
+        memory::{get_path, sort_by},
+        query::Query,
+        validation::FieldError,
+    },
+};
+
+/// Span of time each partition of a `Partitioned` collection holds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PartitionPeriod {
+    /// `events_2024_06_15`
+    Day,
+    /// `events_2024_06`
+    Month,
+    /// `events_2024`
+    Year
+}
+
+impl PartitionPeriod {
+    fn format(&self) -> &'static str {
+        match self {
+            Self::Day => "%Y_%m_%d",
+            Self::Month => "%Y_%m",
+            Self::Year => "%Y"
+        }
+    }
+
+    /// Suffix of the partition holding `at`, ie `2024_06` for monthly partitions
+    pub fn suffix(&self, at: DateTime<Utc>) -> String {
+        at.format(self.format()).to_string()
+    }
+
+    /// First day of the period a partition suffix names, if it names one
+    fn start(&self, suffix: &str) -> Option<NaiveDate> {
+        let start = match self {
+            Self::Day => NaiveDate::parse_from_str(suffix, "%Y_%m_%d").ok()?,
+            Self::Month => NaiveDate::parse_from_str(&format!("{suffix}_01"), "%Y_%m_%d").ok()?,
+            Self::Year => NaiveDate::from_ymd_opt(suffix.parse().ok()?, 1, 1)?
+        };
+        // Only names the period formats to, so `events_2024_6` or `events_2024_06_archive` aren't taken for partitions
+        (start.format(self.format()).to_string() == suffix).then_some(start)
+    }
+
+    /// First day of the period after the one starting on `start`
+    fn next(&self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Self::Day => start.succ_opt(),
+            Self::Month => start.checked_add_months(Months::new(1)),
+            Self::Year => start.checked_add_months(Months::new(12))
+        }
+    }
+}
+
+/// A partition of a `Partitioned` collection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Partition {
+    /// Name of the partition's collection
+    pub name: String,
+
+    /// Start of the period it holds
+    pub start: DateTime<Utc>,
+
+    /// Start of the next period, which it doesn't hold
+    pub end: DateTime<Utc>,
+
+    /// Whether it's on the archive client rather than the collection's own
+    pub archived: bool
+}
+
+/// Dates a query restricts the partitioned field to, as inclusive bounds
+#[derive(Clone, Copy, Debug, Default)]
+struct DateRange {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>
+}
+
+impl DateRange {
+    fn narrow(&mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) {
+        self.from = self.from.max(from);
+        self.to = match (self.to, to) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        };
+    }
+
+    fn overlaps(&self, partition: &Partition) -> bool {
+        self.from.is_none_or(|from| from < partition.end) && self.to.is_none_or(|to| to >= partition.start)
+    }
+
+    /// Narrows the range by the conditions of `query` on `field`, including those of its `$and` clauses. Conditions
+    /// the range can't express (`$or`, `$ne`, non-date values...) leave it as is, so it never excludes a match.
+    fn restrict(&mut self, query: &bson::Document, field: &str) {
+        for (key, value) in query {
+            match (key.as_str(), value) {
+                ("$and", Bson::Array(clauses)) => {
+                    for clause in clauses {
+                        if let Bson::Document(clause) = clause {
+                            self.restrict(clause, field);
+                        }
+                    }
+                },
+                (key, Bson::Document(operators)) if key == field && operators.keys().all(|operator| operator.starts_with('$')) => {
+                    for (operator, operand) in operators {
+                        match (operator.as_str(), operand) {
+                            ("$gt" | "$gte", operand) => self.narrow(date(operand), None),
+                            ("$lt" | "$lte", operand) => self.narrow(None, date(operand)),
+                            ("$eq", operand) => self.narrow(date(operand), date(operand)),
+                            ("$in", Bson::Array(values)) => {
+                                let dates = values.iter().map(date).collect::<Option<Vec<_>>>().unwrap_or_default();
+                                self.narrow(dates.iter().min().copied(), dates.iter().max().copied());
+                            },
+                            _ => {}
+                        }
+                    }
+                },
+                (key, value) if key == field => self.narrow(date(value), date(value)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A date as stored by BSON or `chrono`'s serializer
+fn date(value: &Bson) -> Option<DateTime<Utc>> {
+    match value {
+        Bson::DateTime(at) => Some(at.to_chrono()),
+        Bson::String(at) => DateTime::parse_from_rfc3339(at).ok().map(|at| at.to_utc()),
+        _ => None
+    }
+}
+
+/// Time-series documents spread across one collection per period of a date field, ie `events_2024_06` holding the
+/// events of June 2024 with monthly partitions. Writes go to the partition of each document's date, and reads only
+/// visit the partitions the query's range on that field can match, so old partitions can be archived whole.
+///
+/// Partitions are found by listing the client's collections, so every collection named like the type's collection
+/// followed by a period suffix is taken for one.
+pub struct Partitioned<T: Document> {
+    collection: Collection<T>,
+    field: String,
+    period: PartitionPeriod,
+    archive: Option<Client>
+}
+
+impl<T: Document> Clone for Partitioned<T> {
+    fn clone(&self) -> Self {
+        Self { collection: self.collection.clone(), field: self.field.clone(), period: self.period, archive: self.archive.clone() }
+    }
+}
+
+impl<T: Document> Partitioned<T> {
+    /// Partitions the documents of `collection` by the date in `field` (a stored path), holding either a BSON date or an
+    /// RFC 3339 string (as `chrono` serializes dates)
+    pub fn new(collection: Collection<T>, field: impl AsRef<str>, period: PartitionPeriod) -> Self {
+        Self { collection, field: field.as_ref().to_string(), period, archive: None }
+    }
+
+    /// Sets the client (ie one on a cheaper, slower driver) `archive_before` moves old partitions to. Reads include the
+    /// archived partitions their range can match.
+    pub fn archive_to(mut self, client: &Client) -> Self {
+        self.archive = Some(client.clone());
+        self
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn period(&self) -> PartitionPeriod {
+        self.period
+    }
+
+    /// Name of the partition holding documents dated `at`
+    pub fn partition_name(&self, at: DateTime<Utc>) -> String {
+        format!("{}_{}", self.collection.name(), self.period.suffix(at))
+    }
+
+    /// Handle to the partition holding documents dated `at`
+    pub fn partition(&self, at: DateTime<Utc>) -> Collection<T> {
+        self.collection.with_name(self.partition_name(at))
+    }
+
+    /// Partitions of the collection, oldest first, followed by their archived counterpart if there's one
+    pub async fn partitions(&self) -> OResult<Vec<Partition>> {
+        let mut partitions = self.list(&self.collection.client(), false).await?;
+        if let Some(archive) = &self.archive {
+            partitions.extend(self.list(archive, true).await?);
+        }
+        partitions.sort_by_key(|partition| (partition.start, partition.archived));
+        Ok(partitions)
+    }
+
+    async fn list(&self, client: &Client, archived: bool) -> OResult<Vec<Partition>> {
+        let prefix = format!("{}_", self.collection.name());
+        Ok(client
+            .collections()
+            .await?
+            .into_iter()
+            .filter_map(|name| {
+                let start = self.period.start(name.strip_prefix(&prefix)?)?;
+                let end = self.period.next(start)?;
+                Some(Partition {
+                    name,
+                    start: start.and_time(NaiveTime::MIN).and_utc(),
+                    end: end.and_time(NaiveTime::MIN).and_utc(),
+                    archived
+                })
+            })
+            .collect())
+    }
+
+    /// Handles to the partitions a query can match documents in, oldest first
+    async fn matching(&self, query: &Query) -> OResult<Vec<Collection<T>>> {
+        let mut range = DateRange::default();
+        range.restrict(&query.clone().try_into()?, &self.field);
+        Ok(self
+            .partitions()
+            .await?
+            .into_iter()
+            .filter(|partition| range.overlaps(partition))
+            .map(|partition| match (&self.archive, partition.archived) {
+                (Some(archive), true) => Collection::new(archive.clone()).with_name(partition.name),
+                _ => self.collection.with_name(partition.name)
+            })
+            .collect())
+    }
+
+    /// Date a document is partitioned by
+    fn date_of(&self, document: &T) -> OResult<DateTime<Utc>> {
+        let data = bson::to_document(document).map_err(OrmoxError::serialization)?;
+        get_path(&data, &self.field).and_then(date).ok_or_else(|| {
+            OrmoxError::validation(self.collection.name(), vec![FieldError::new(&self.field, "partition", "expected a date to partition by")])
+        })
+    }
+
+    /// Inserts documents into the partitions of their dates, returning their ids in order
+    pub async fn insert(&self, docs: Vec<T>) -> OResult<Vec<T::Id>> {
+        let mut partitions: IndexMap<String, (Vec<usize>, Vec<T>)> = IndexMap::new();
+        for (position, document) in docs.into_iter().enumerate() {
+            let (positions, documents) = partitions.entry(self.partition_name(self.date_of(&document)?)).or_default();
+            positions.push(position);
+            documents.push(document);
+        }
+
+        let mut ids = Vec::new();
+        for (name, (positions, documents)) in partitions {
+            let inserted = self.collection.with_name(name).insert(documents).await?;
+            ids.extend(positions.into_iter().zip(inserted));
+        }
+        ids.sort_by_key(|(position, _)| *position);
+        Ok(ids.into_iter().map(|(_, id)| id).collect())
+    }
+
+    /// Saves a document to the partition of its date. Documents loaded from another partition (as their date changed)
+    /// are written whole to the new one, then deleted from the old one.
+    pub async fn save(&self, mut document: T) -> OResult<()> {
+        let partition = self.partition(self.date_of(&document)?);
+        match document.attached_collection().filter(|attached| attached.name() != partition.name()) {
+            Some(previous) => {
+                document.set_loaded_state(None);
+                let id = document.id();
+                partition.save(document).await?;
+                previous.delete_by_id(id).await
+            },
+            None => partition.save(document).await
+        }
+    }
+
+    /// Finds documents across the partitions the query can match. Without `Find::sort`, documents are ordered by
+    /// partition, oldest first, then as each partition returns them; sorts are applied across partitions.
+    pub async fn find(&self, query: impl TryInto<Query, Error = impl Error>, options: Option<Find>) -> OResult<Vec<T>> {
+        let query = query.try_into().map_err(|e| OrmoxError::Compatibility { error: e.to_string() })?;
+        let options = options.unwrap_or_else(Find::many);
+        options.validate()?;
+        let limit = match options.operation {
+            OperationCount::One => Some(options.limit.unwrap_or(1).min(1)),
+            OperationCount::Many => options.limit
+        };
+        let offset = options.offset.unwrap_or(0);
+        // Every partition may hold the whole page, so each is asked for up to the page's end
+        let wanted = limit.map(|limit| limit.saturating_add(offset));
+        let partition_options = Find { operation: OperationCount::Many, offset: None, limit: wanted, ..options.clone() };
+
+        let mut found = Vec::new();
+        for partition in self.matching(&query).await? {
+            if options.sort.is_none() && wanted.is_some_and(|wanted| found.len() >= wanted) {
+                break;
+            }
+            found.extend(partition.find(query.clone(), Some(partition_options.clone())).await?);
+        }
+
+        if let Some(sorting) = &options.sort {
+            let mut keyed = found
+                .into_iter()
+                .map(|document| Ok((bson::to_document(&document).map_err(OrmoxError::serialization)?, document)))
+                .collect::<OResult<Vec<_>>>()?;
+            sort_by(&mut keyed, |(data, _)| data, sorting, options.collation.as_ref());
+            found = keyed.into_iter().map(|(_, document)| document).collect();
+        }
+        Ok(found.into_iter().skip(offset).take(limit.unwrap_or(usize::MAX)).collect())
+    }
+
+    /// Counts the documents matching a query across the partitions it can match
+    pub async fn count(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<u64> {
+        let query = query.try_into().map_err(|e| OrmoxError::Compatibility { error: e.to_string() })?;
+        let mut count = 0;
+        for partition in self.matching(&query).await? {
+            count += partition.count(query.clone()).await?;
+        }
+        Ok(count)
+    }
+
+    /// Deletes the documents matching a query from the partitions it can match, archived ones included
+    pub async fn delete_many(&self, query: impl TryInto<Query, Error = impl Error>) -> OResult<()> {
+        let query = query.try_into().map_err(|e| OrmoxError::Compatibility { error: e.to_string() })?;
+        for partition in self.matching(&query).await? {
+            partition.delete_many(query.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves the partitions of periods ending by `before` to the archive client set with `archive_to`, returning the names
+    /// of those that held documents. Stored documents are copied as is, in pages of `ClientOptions::insert_batch_size`, each page being
+    /// upserted into the archive before it's deleted from the partition, so an interrupted archive can be resumed by
+    /// calling this again. Emptied partitions are left in place, as drivers can't drop collections.
+    pub async fn archive_before(&self, before: DateTime<Utc>) -> OResult<Vec<String>> {
+        let archive = self.archive.as_ref().ok_or_else(|| OrmoxError::compaibility("No archive client set with Partitioned::archive_to"))?;
+        let (hot, cold) = (self.collection.driver(), archive.driver());
+        let id_field = T::id_field();
+        let page = self.collection.client().options().insert_batch_size.max(1);
+
+        let mut archived = Vec::new();
+        let client = self.collection.client();
+        for partition in self.list(&client, false).await?.into_iter().filter(|partition| partition.end <= before) {
+            let mut moved = false;
+            loop {
+                let options = Find { limit: Some(page), sort: Some(Sorting::asc(&id_field)), ..Find::many() };
+                let documents = hot.find(partition.name.clone(), Query::new(), options).await?;
+                if documents.is_empty() {
+                    break;
+                }
+
+                let ids = documents.iter().map(|document| document.get(&id_field).cloned().unwrap_or(Bson::Null)).collect::<Vec<_>>();
+                let upserts = documents
+                    .into_iter()
+                    .zip(&ids)
+                    .map(|(document, id)| Ok((Query::try_from(doc! { &id_field: id.clone() })?, document)))
+                    .collect::<OResult<Vec<_>>>()?;
+                cold.upsert_many(partition.name.clone(), upserts, WriteOptions::default()).await?;
+                hot.delete(partition.name.clone(), Query::try_from(doc! { &id_field: { "$in": ids } })?, OperationCount::Many, WriteOptions::default()).await?;
+                moved = true;
+            }
+            if moved {
+                archived.push(partition.name);
+            }
+        }
+        Ok(archived)
+    }
+}
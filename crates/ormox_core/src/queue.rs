@@ -0,0 +1,293 @@
+//! Persistent work queues of documents, see `Queue`
+
+use std::{fmt::Display, marker::PhantomData, time::Duration};
+
+use bson::{doc, Bson};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    client::Client,
+    core::{
+        document::Document,
+        driver::{Find, OperationCount, Sorting, WriteOptions},
+        error::{OResult, OrmoxError},
+        query::Query,
+    },
+};
+
+/// Items claimable by `Queue::dequeue`, including claimed ones whose visibility timeout expired
+const PENDING: &str = "pending";
+
+/// Items that failed `Queue::max_attempts` times, kept for inspection until requeued or purged
+const DEAD: &str = "dead";
+
+/// Acknowledged items, deleted right after being marked
+const DONE: &str = "done";
+
+/// Number of claimable items `Queue::dequeue` tries to claim, oldest first, before looking again
+const CLAIM_CANDIDATES: usize = 10;
+
+fn bson_date(at: DateTime<Utc>) -> Bson {
+    Bson::DateTime(bson::DateTime::from_chrono(at))
+}
+
+fn after(delay: Duration) -> OResult<DateTime<Utc>> {
+    let delay = chrono::Duration::from_std(delay).map_err(|_| OrmoxError::compaibility(format!("Delay {delay:?} is too long")))?;
+    Ok(Utc::now() + delay)
+}
+
+/// A document taken off a `Queue`, to be acknowledged with `Queue::ack` once processed, or handed back with
+/// `Queue::fail`
+#[derive(Clone, Debug)]
+pub struct QueueItem<T: Document> {
+    /// Id of the queue entry, not of the document
+    pub id: Uuid,
+    pub document: T,
+
+    /// Number of times the item was dequeued, this time included
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+
+    /// Claim the item was dequeued with, so a worker whose visibility timeout expired can't settle another's claim
+    receipt: Option<String>
+}
+
+impl<T: Document> QueueItem<T> {
+    fn from_document(data: bson::Document) -> OResult<Self> {
+        let invalid = |field: &str| OrmoxError::deserialization(format!("Invalid {field} of queue item: {:?}", data.get(field)));
+        let id = data.get_str("_id").ok().and_then(|id| Uuid::parse_str(id).ok()).ok_or_else(|| invalid("_id"))?;
+        let payload = data.get_document("payload").map_err(|_| invalid("payload"))?;
+        let attempts = match data.get("attempts") {
+            Some(Bson::Int64(attempts)) => u32::try_from(*attempts).ok(),
+            Some(Bson::Int32(attempts)) => u32::try_from(*attempts).ok(),
+            _ => None
+        };
+        Ok(Self {
+            id,
+            document: T::parse(payload.clone(), None)?,
+            attempts: attempts.ok_or_else(|| invalid("attempts"))?,
+            enqueued_at: data.get_datetime("enqueued_at").map_err(|_| invalid("enqueued_at"))?.to_chrono(),
+            last_error: data.get_str("last_error").ok().map(str::to_string),
+            receipt: data.get_str("receipt").ok().map(str::to_string)
+        })
+    }
+
+    /// Query matching the entry while it's still claimed by this item
+    fn claimed(&self) -> bson::Document {
+        doc! {"_id": self.id.to_string(), "state": PENDING, "receipt": self.receipt.clone()}
+    }
+}
+
+/// A persistent work queue of documents, stored in its own collection (`<collection>_queue` by default) so it works on
+/// any driver, embedded ones included. Items are claimed atomically by `dequeue` with `find_one_and_update`, then
+/// hidden from other workers for the visibility timeout: items that aren't acknowledged by then (ie as their worker
+/// crashed) are dequeued again. Failed items are retried with exponential backoff, and dead-lettered after
+/// `max_attempts` attempts.
+///
+/// Delivery is at-least-once, so processing should be idempotent. Documents are stored as serialized, without
+/// encrypting their `#[ormox(encrypted)]` fields.
+pub struct Queue<T: Document> {
+    client: Client,
+    name: String,
+    visibility_timeout: Duration,
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    _document: PhantomData<T>
+}
+
+impl<T: Document> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            name: self.name.clone(),
+            visibility_timeout: self.visibility_timeout,
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            max_backoff: self.max_backoff,
+            _document: PhantomData
+        }
+    }
+}
+
+impl<T: Document> Queue<T> {
+    /// Queue stored in `<collection>_queue`, with a 30 second visibility timeout & 5 attempts backing off from a second
+    /// up to 5 minutes
+    pub fn new(client: Client) -> Self {
+        Self {
+            name: format!("{}_queue", T::collection_name()),
+            client,
+            visibility_timeout: Duration::from_secs(30),
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            _document: PhantomData
+        }
+    }
+
+    /// Stores the queue in the collection `name`, ie to keep several queues of the same documents
+    pub fn named(mut self, name: impl AsRef<str>) -> Self {
+        self.name = name.as_ref().to_string();
+        self
+    }
+
+    /// How long a dequeued item stays hidden from other workers before it's dequeued again
+    pub fn visibility_timeout(mut self, timeout: Duration) -> Self {
+        self.visibility_timeout = timeout;
+        self
+    }
+
+    /// Number of attempts after which failed items are dead-lettered
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before retrying an item after its first failure, doubling with each further failure up to `max`
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.backoff = base;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Delay before retrying an item that failed its `attempts`th attempt
+    fn retry_delay(&self, attempts: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempts.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    async fn claim(&self, query: bson::Document, update: bson::Document) -> OResult<Option<bson::Document>> {
+        self.client.driver().find_one_and_update(self.name.clone(), Query::try_from(query)?, update, false, WriteOptions::default()).await
+    }
+
+    /// Adds a document to the queue, returning the id of its entry
+    pub async fn enqueue(&self, document: T) -> OResult<Uuid> {
+        self.enqueue_delayed(document, Duration::ZERO).await
+    }
+
+    /// Adds a document to the queue, to be dequeued once `delay` has passed
+    pub async fn enqueue_delayed(&self, document: T, delay: Duration) -> OResult<Uuid> {
+        if self.client.options().validate_writes {
+            document.validate()?;
+        }
+        let id = Uuid::new_v4();
+        let entry = doc! {
+            "_id": id.to_string(),
+            "payload": bson::to_document(&document).map_err(OrmoxError::serialization)?,
+            "state": PENDING,
+            "attempts": 0_i64,
+            "enqueued_at": bson_date(Utc::now()),
+            "visible_at": bson_date(after(delay)?),
+            "receipt": Bson::Null,
+            "last_error": Bson::Null
+        };
+        self.client.driver().insert(self.name.clone(), vec![entry], WriteOptions::default()).await?;
+        Ok(id)
+    }
+
+    /// Claims the oldest visible item, hiding it from other workers for the visibility timeout, or returns `None` if
+    /// there's none. Items whose last attempt timed out are dead-lettered instead once out of attempts.
+    pub async fn dequeue(&self) -> OResult<Option<QueueItem<T>>> {
+        let now = bson_date(Utc::now());
+        let timed_out = Query::try_from(doc! {"state": PENDING, "visible_at": {"$lte": now.clone()}, "attempts": {"$gte": i64::from(self.max_attempts)}})?;
+        let dead = doc! {"$set": {"state": DEAD, "receipt": Bson::Null, "last_error": "Visibility timeout expired on the last attempt"}};
+        self.client.driver().update(self.name.clone(), timed_out, dead, OperationCount::Many, WriteOptions::default()).await?;
+
+        loop {
+            let visible = Query::try_from(doc! {"state": PENDING, "visible_at": {"$lte": now.clone()}})?;
+            let options = Find { limit: Some(CLAIM_CANDIDATES), sort: Some(Sorting::asc("visible_at")), ..Find::many() };
+            let candidates = self.client.driver().find(self.name.clone(), visible, options).await?;
+            if candidates.is_empty() {
+                return Ok(None);
+            }
+
+            for candidate in candidates {
+                // Claims the candidate only if no other worker claimed it since it was read
+                let receipt = Uuid::new_v4().to_string();
+                let query = doc! {"_id": candidate.get("_id").cloned().unwrap_or(Bson::Null), "state": PENDING, "receipt": candidate.get("receipt").cloned().unwrap_or(Bson::Null), "visible_at": {"$lte": now.clone()}};
+                let update = doc! {"$set": {"receipt": &receipt, "visible_at": bson_date(after(self.visibility_timeout)?)}, "$inc": {"attempts": 1_i64}};
+                if let Some(claimed) = self.claim(query, update).await? {
+                    let item = QueueItem::from_document(claimed)?;
+                    if item.receipt.as_deref() == Some(receipt.as_str()) {
+                        return Ok(Some(item));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Acknowledges a processed item, removing it from the queue. Fails with `OrmoxError::NotFound` if its visibility
+    /// timeout expired and another worker claimed it since.
+    pub async fn ack(&self, item: &QueueItem<T>) -> OResult<()> {
+        if self.claim(item.claimed(), doc! {"$set": {"state": DONE}}).await?.is_none() {
+            return Err(OrmoxError::not_found(format!("claim of queue item {}", item.id)));
+        }
+        let query = Query::try_from(doc! {"_id": item.id.to_string(), "state": DONE})?;
+        self.client.driver().delete(self.name.clone(), query, OperationCount::One, WriteOptions::default()).await
+    }
+
+    /// Hands back an item that failed to process: it's retried after the backoff delay, or dead-lettered if it was its
+    /// last attempt. Fails with `OrmoxError::NotFound` if its visibility timeout expired and another worker claimed it
+    /// since.
+    pub async fn fail(&self, item: &QueueItem<T>, error: impl Display) -> OResult<()> {
+        let update = if item.attempts >= self.max_attempts {
+            doc! {"$set": {"state": DEAD, "receipt": Bson::Null, "last_error": error.to_string()}}
+        } else {
+            let visible_at = bson_date(after(self.retry_delay(item.attempts))?);
+            doc! {"$set": {"visible_at": visible_at, "receipt": Bson::Null, "last_error": error.to_string()}}
+        };
+        match self.claim(item.claimed(), update).await? {
+            Some(_) => Ok(()),
+            None => Err(OrmoxError::not_found(format!("claim of queue item {}", item.id)))
+        }
+    }
+
+    /// Extends the visibility timeout of an item still being processed, from now. Fails with `OrmoxError::NotFound` if
+    /// it already expired and another worker claimed the item since.
+    pub async fn extend(&self, item: &QueueItem<T>) -> OResult<()> {
+        let update = doc! {"$set": {"visible_at": bson_date(after(self.visibility_timeout)?)}};
+        match self.claim(item.claimed(), update).await? {
+            Some(_) => Ok(()),
+            None => Err(OrmoxError::not_found(format!("claim of queue item {}", item.id)))
+        }
+    }
+
+    /// Number of items waiting or being processed, dead letters excluded
+    pub async fn len(&self) -> OResult<u64> {
+        self.client.driver().count(self.name.clone(), Query::new().field("state", PENDING), Find::many()).await
+    }
+
+    pub async fn is_empty(&self) -> OResult<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Dead-lettered items, oldest first
+    pub async fn dead_letters(&self, limit: Option<usize>) -> OResult<Vec<QueueItem<T>>> {
+        let options = Find { limit, sort: Some(Sorting::asc("enqueued_at")), ..Find::many() };
+        let dead = self.client.driver().find(self.name.clone(), Query::new().field("state", DEAD), options).await?;
+        dead.into_iter().map(QueueItem::from_document).collect()
+    }
+
+    /// Puts a dead-lettered item back in the queue with a fresh set of attempts
+    pub async fn requeue(&self, id: Uuid) -> OResult<()> {
+        let query = doc! {"_id": id.to_string(), "state": DEAD};
+        let update = doc! {"$set": {"state": PENDING, "attempts": 0_i64, "visible_at": bson_date(Utc::now())}};
+        match self.claim(query, update).await? {
+            Some(_) => Ok(()),
+            None => Err(OrmoxError::not_found(format!("dead-lettered queue item {id}")))
+        }
+    }
+
+    /// Deletes every dead-lettered item
+    pub async fn purge_dead_letters(&self) -> OResult<()> {
+        self.client.driver().delete(self.name.clone(), Query::new().field("state", DEAD), OperationCount::Many, WriteOptions::default()).await
+    }
+}
@@ -0,0 +1,227 @@
+//! Rate limiting shared by every client of a database, counting requests per key & time window in a collection, for
+//! deployments running several instances without a separate store such as Redis.
+
+use std::{fmt::Debug, time::Duration};
+
+use bson::{doc, Bson};
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::{
+    client::{Client, ScheduledJob},
+    core::{
+        driver::{Find, OperationCount, WriteOptions},
+        error::{OResult, OrmoxError},
+        query::Query,
+    },
+};
+
+/// Collection `RateLimiter` counts requests in unless renamed, as `{_id, limiter, key, count, expires_at}` per key &
+/// window
+pub const RATE_LIMITS_COLLECTION: &str = "_ormox_rate_limits";
+
+/// How a `RateLimiter` counts requests against its limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitStrategy {
+    /// Requests in the current window, which start at multiples of the window's length. Cheap, but allows bursts of up
+    /// to twice the limit around window boundaries.
+    #[default]
+    FixedWindow,
+
+    /// Requests in the current window, plus those of the previous window weighted by how much of it still overlaps the
+    /// last window's length. Approximates a window sliding with time, smoothing out the bursts of fixed windows at the
+    /// cost of reading the previous window's count.
+    SlidingWindow
+}
+
+/// Outcome of counting (or checking) a request against a `RateLimiter`, ie for `RateLimit` & `Retry-After` headers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub allowed: bool,
+    pub limit: u64,
+
+    /// Further requests allowed in the current window
+    pub remaining: u64,
+
+    /// End of the current window. Sliding windows may allow requests sooner, as the previous window's weight decays.
+    pub reset_at: DateTime<Utc>
+}
+
+impl RateLimit {
+    /// Time until the current window ends, as of now
+    pub fn retry_after(&self) -> Duration {
+        (self.reset_at - Utc::now()).to_std().unwrap_or_default()
+    }
+}
+
+/// Counts requests per key (ie user or IP address) over time windows, allowing at most `limit` per window. Counters are
+/// kept in a collection and incremented atomically, so every client of the database shares the limits. Requests
+/// over the limit aren't counted.
+///
+/// Expired windows are kept until deleted by `purge`, which `cleanup_every` runs periodically.
+#[derive(Clone)]
+pub struct RateLimiter {
+    client: Client,
+    name: String,
+    collection: String,
+    limit: u64,
+    window: Duration,
+    strategy: RateLimitStrategy
+}
+
+impl RateLimiter {
+    pub fn new(client: Client, name: impl AsRef<str>, limit: u64, window: Duration) -> Self {
+        Self {
+            client,
+            name: name.as_ref().to_string(),
+            collection: RATE_LIMITS_COLLECTION.to_string(),
+            limit,
+            window,
+            strategy: RateLimitStrategy::default()
+        }
+    }
+
+    /// Counts requests in the collection `name` instead of `_ormox_rate_limits`
+    pub fn named(mut self, name: impl AsRef<str>) -> Self {
+        self.collection = name.as_ref().to_string();
+        self
+    }
+
+    pub fn strategy(mut self, strategy: RateLimitStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn window_millis(&self) -> OResult<i64> {
+        match i64::try_from(self.window.as_millis()) {
+            Ok(millis) if millis > 0 => Ok(millis),
+            _ => Err(OrmoxError::compaibility(format!("Invalid rate limit window {:?}", self.window)))
+        }
+    }
+
+    fn counter_id(&self, key: &str, window: i64) -> String {
+        format!("{}:{key}:{window}", self.name)
+    }
+
+    /// Requests counted for `key` in the window numbered `window`
+    async fn count(&self, key: &str, window: i64) -> OResult<u64> {
+        let query = Query::try_from(doc! {"_id": self.counter_id(key, window)})?;
+        let counter = self.client.driver().find(self.collection.clone(), query, Find::one()).await?.into_iter().next();
+        Ok(counter.map_or(0, |counter| stored_count(&counter)))
+    }
+
+    /// Number of the current window, when it ends, and how many requests it allows given the previous window's weight
+    async fn current(&self, key: &str) -> OResult<(i64, DateTime<Utc>, u64)> {
+        let (now, length) = (Utc::now().timestamp_millis(), self.window_millis()?);
+        let window = now.div_euclid(length);
+        let reset_at = Utc.timestamp_millis_opt((window + 1) * length).single().unwrap_or_else(Utc::now);
+        let allowance = match self.strategy {
+            RateLimitStrategy::FixedWindow => self.limit,
+            RateLimitStrategy::SlidingWindow => {
+                let overlap = 1.0 - now.rem_euclid(length) as f64 / length as f64;
+                let previous = self.count(key, window - 1).await? as f64 * overlap;
+                (self.limit as f64 - previous).floor().max(0.0) as u64
+            }
+        };
+        Ok((window, reset_at, allowance))
+    }
+
+    /// Counts a request for `key` if it's within the limit. The counter is only incremented while under the limit, in
+    /// a single atomic update, so concurrent requests from any client can't exceed it.
+    pub async fn hit(&self, key: impl AsRef<str>) -> OResult<RateLimit> {
+        let key = key.as_ref();
+        let (window, reset_at, allowance) = self.current(key).await?;
+        let denied = RateLimit { allowed: false, limit: self.limit, remaining: 0, reset_at };
+        if allowance == 0 {
+            return Ok(denied);
+        }
+
+        let length = self.window_millis()?;
+        let query = Query::try_from(doc! {"_id": self.counter_id(key, window), "count": {"$lt": allowance as i64}})?;
+        let update = doc! {
+            "$inc": {"count": 1_i64},
+            "$setOnInsert": {
+                "limiter": &self.name,
+                "key": key,
+                // Kept for a window after it ends, as the previous window of sliding windows
+                "expires_at": bson::DateTime::from_millis((window + 2) * length)
+            }
+        };
+        let driver = self.client.driver();
+        let counted = match driver.find_one_and_update(self.collection.clone(), query.clone(), update.clone(), true, WriteOptions::default()).await {
+            Ok(counter) => counter,
+            // The counter exists but is full, or another client created it first
+            Err(e) if e.is_duplicate() => driver.find_one_and_update(self.collection.clone(), query, update, false, WriteOptions::default()).await?,
+            Err(e) => return Err(e)
+        };
+
+        Ok(match counted {
+            Some(counter) => RateLimit { allowed: true, limit: self.limit, remaining: allowance.saturating_sub(stored_count(&counter)), reset_at },
+            None => denied
+        })
+    }
+
+    /// Whether a request for `key` would be allowed now, without counting one
+    pub async fn check(&self, key: impl AsRef<str>) -> OResult<RateLimit> {
+        let key = key.as_ref();
+        let (window, reset_at, allowance) = self.current(key).await?;
+        let remaining = allowance.saturating_sub(self.count(key, window).await?);
+        Ok(RateLimit { allowed: remaining > 0, limit: self.limit, remaining, reset_at })
+    }
+
+    /// Forgets the requests counted for `key`
+    pub async fn reset(&self, key: impl AsRef<str>) -> OResult<()> {
+        let query = Query::try_from(doc! {"limiter": &self.name, "key": key.as_ref()})?;
+        self.client.driver().delete(self.collection.clone(), query, OperationCount::Many, WriteOptions::default()).await
+    }
+
+    /// Deletes the counters of expired windows, of every limiter counting in this collection
+    pub async fn purge(&self) -> OResult<()> {
+        let query = Query::try_from(doc! {"expires_at": {"$lte": bson::DateTime::now()}})?;
+        self.client.driver().delete(self.collection.clone(), query, OperationCount::Many, WriteOptions::default()).await
+    }
+
+    /// Runs `purge` every `interval`, as the `"rate_limits:<collection>"` scheduled job
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub fn cleanup_every(&self, interval: Duration) -> ScheduledJob {
+        let limiter = self.clone();
+        self.client.schedule(format!("rate_limits:{}", self.collection), interval, move || {
+            let limiter = limiter.clone();
+            async move { limiter.purge().await }
+        })
+    }
+}
+
+impl Debug for RateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("name", &self.name)
+            .field("collection", &self.collection)
+            .field("limit", &self.limit)
+            .field("window", &self.window)
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+fn stored_count(counter: &bson::Document) -> u64 {
+    match counter.get("count") {
+        Some(Bson::Int64(count)) => (*count).max(0) as u64,
+        Some(Bson::Int32(count)) => (*count).max(0) as u64,
+        Some(Bson::Double(count)) => count.max(0.0) as u64,
+        _ => 0
+    }
+}
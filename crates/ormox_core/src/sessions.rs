@@ -0,0 +1,136 @@
+//! `tower-sessions` integration: `OrmoxStore`, a session store keeping sessions in a collection, so web apps already
+//! using ormox don't need another database for them.
+
+use std::{fmt::{Debug, Display}, time::Duration};
+
+use async_trait::async_trait;
+use bson::doc;
+use time::OffsetDateTime;
+use tower_sessions_core::{
+    session::{Id, Record},
+    session_store::{self, ExpiredDeletion, SessionStore},
+};
+
+use crate::{
+    client::{Client, ScheduledJob},
+    core::{
+        driver::{Find, OperationCount, WriteOptions},
+        error::{OResult, OrmoxError},
+        query::Query,
+    },
+};
+
+/// Collection `OrmoxStore` keeps sessions in unless renamed, as `{_id: <session id>, data, expires_at}`
+pub const SESSIONS_COLLECTION: &str = "_ormox_sessions";
+
+fn backend(error: OrmoxError) -> session_store::Error {
+    session_store::Error::Backend(error.to_string())
+}
+
+fn decode(error: impl Display) -> session_store::Error {
+    session_store::Error::Decode(error.to_string())
+}
+
+fn expires_at(record: &Record) -> bson::DateTime {
+    bson::DateTime::from_millis((record.expiry_date.unix_timestamp_nanos() / 1_000_000) as i64)
+}
+
+/// Session store for `tower-sessions` (and so axum) keeping sessions in a collection. Expired sessions are never
+/// loaded, and are deleted by `delete_expired`, which `cleanup_every` runs periodically.
+#[derive(Clone)]
+pub struct OrmoxStore {
+    client: Client,
+    name: String
+}
+
+impl OrmoxStore {
+    pub fn new(client: Client) -> Self {
+        Self { client, name: SESSIONS_COLLECTION.to_string() }
+    }
+
+    /// Keeps sessions in the collection `name` instead of `_ormox_sessions`
+    pub fn named(mut self, name: impl AsRef<str>) -> Self {
+        self.name = name.as_ref().to_string();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn id_query(&self, id: &Id) -> Result<Query, session_store::Error> {
+        Query::try_from(doc! {"_id": id.to_string()}).map_err(backend)
+    }
+
+    fn document(&self, record: &Record) -> session_store::Result<bson::Document> {
+        let data = bson::to_bson(&record.data).map_err(|e| session_store::Error::Encode(e.to_string()))?;
+        Ok(doc! {"_id": record.id.to_string(), "data": data, "expires_at": expires_at(record)})
+    }
+
+    /// Deletes expired sessions every `interval`, as the `"sessions:<collection>"` scheduled job
+    ///
+    /// # Panics
+    /// If there's no runtime, see `Client::runtime`
+    pub fn cleanup_every(&self, interval: Duration) -> ScheduledJob {
+        let store = self.clone();
+        self.client.schedule(format!("sessions:{}", self.name), interval, move || {
+            let store = store.clone();
+            async move { store.purge().await }
+        })
+    }
+
+    async fn purge(&self) -> OResult<()> {
+        let query = Query::try_from(doc! {"expires_at": {"$lte": bson::DateTime::now()}})?;
+        self.client.driver().delete(self.name.clone(), query, OperationCount::Many, WriteOptions::default()).await
+    }
+}
+
+impl Debug for OrmoxStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OrmoxStore").field("name", &self.name).finish()
+    }
+}
+
+#[async_trait]
+impl SessionStore for OrmoxStore {
+    /// Inserts the session, drawing a new ID while the drawn one is taken
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        loop {
+            let document = self.document(record)?;
+            match self.client.driver().insert(self.name.clone(), vec![document], WriteOptions::default()).await {
+                Ok(_) => return Ok(()),
+                Err(e) if e.is_duplicate() => record.id = Id::default(),
+                Err(e) => return Err(backend(e))
+            }
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let (query, document) = (self.id_query(&record.id)?, self.document(record)?);
+        self.client.driver().upsert(self.name.clone(), query, document, OperationCount::One, WriteOptions::default()).await.map_err(backend)
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let query = Query::try_from(doc! {"_id": id.to_string(), "expires_at": {"$gt": bson::DateTime::now()}}).map_err(backend)?;
+        let Some(stored) = self.client.driver().find(self.name.clone(), query, Find::one()).await.map_err(backend)?.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let data = bson::from_bson(stored.get("data").cloned().unwrap_or_else(|| doc! {}.into())).map_err(decode)?;
+        let expires_at = stored.get_datetime("expires_at").map_err(decode)?;
+        let expiry_date = OffsetDateTime::from_unix_timestamp_nanos(i128::from(expires_at.timestamp_millis()) * 1_000_000).map_err(decode)?;
+        Ok(Some(Record { id: *id, data, expiry_date }))
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        let query = self.id_query(id)?;
+        self.client.driver().delete(self.name.clone(), query, OperationCount::One, WriteOptions::default()).await.map_err(backend)
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for OrmoxStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        self.purge().await.map_err(backend)
+    }
+}
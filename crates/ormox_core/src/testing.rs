@@ -0,0 +1,280 @@
+//! Property testing support: proptest `Arbitrary` implementations for `Query`, `Find` & `Index`, and round-trip
+//! properties checking that queries survive conversions without losing or changing conditions. Driver authors can run
+//! them against their own conversions:
+//!
+//! ```ignore
+//! use ormox::{testing::round_trip, Query};
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn filters_round_trip(query: Query) {
+//!         round_trip(&query, my_driver::to_filter, my_driver::from_filter)?;
+//!     }
+//! }
+//! ```
+//!
+//! Queries from `query()` only use shapes the conversions can tell apart: keys are never repeated, field values are
+//! never objects (those are sub-queries), and custom operators never take arrays of values (those read back as
+//! `$and`-style cases). `query_with_repeats()` also repeats keys, which render merged or moved into `$and` and so parse
+//! back in a different shape; `rendered_round_trip` checks those by their rendered documents instead.
+
+use bson::Bson;
+use proptest::{
+    collection::vec,
+    num::f64::{NEGATIVE, NORMAL, POSITIVE, SUBNORMAL, ZERO},
+    option,
+    prelude::*,
+    test_runner::TestCaseError,
+};
+use serde_json::{Number, Value};
+use uuid::Uuid;
+
+use crate::core::{
+    document::{Index, IndexKind},
+    driver::{Collation, CollationStrength, ErrorPolicy, Find, OperationCount, ReadPreference, Sorting},
+    error::{OResult, OrmoxError},
+    query::{Query, QueryKey, QueryValue},
+    remote::WireDocument,
+};
+
+/// Operators generated as `QueryKey::Operator`, ie ones without a dedicated key
+const OPERATORS: &[&str] = &["$exists", "$size", "$all", "$elemMatch", "$mod", "$nor", "$regex", "$type"];
+
+/// Field names, plain or dotted
+pub fn field() -> impl Strategy<Value = String> {
+    "[a-z_][a-z0-9_]{0,7}(\\.[a-z0-9_]{1,5})?"
+}
+
+/// 64-bit integers & finite doubles
+fn number() -> impl Strategy<Value = Number> {
+    prop_oneof![
+        any::<i64>().prop_map(Number::from),
+        (POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO).prop_map(|n| Number::from_f64(n).unwrap_or(Number::from(0)))
+    ]
+}
+
+fn scalar() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        number().prop_map(Value::Number),
+        ".{0,12}".prop_map(Value::String)
+    ]
+}
+
+fn array() -> impl Strategy<Value = Value> {
+    vec(scalar(), 0..4).prop_map(Value::Array)
+}
+
+/// Values fields are matched against: scalars & arrays of scalars
+pub fn value() -> impl Strategy<Value = Value> {
+    prop_oneof![3 => scalar(), 1 => array()]
+}
+
+/// Up to four conditions, with `inner` for the queries they nest. Conditions on a key already set are dropped unless
+/// `repeats` is set.
+fn conditions(inner: BoxedStrategy<Query>, repeats: bool) -> impl Strategy<Value = Query> {
+    let condition = prop_oneof![
+        (field(), value()).prop_map(|(key, value)| (QueryKey::String(key), QueryValue::Value(value))),
+        (field(), inner.clone()).prop_map(|(key, query)| (QueryKey::String(key), QueryValue::Mapping(query))),
+        (
+            prop_oneof![Just(QueryKey::GreaterThan), Just(QueryKey::GreaterThanEqual), Just(QueryKey::LessThan), Just(QueryKey::LessThanEqual)],
+            number()
+        ).prop_map(|(key, number)| (key, QueryValue::Value(Value::Number(number)))),
+        (prop_oneof![Just(QueryKey::Equals), Just(QueryKey::NotEquals)], value()).prop_map(|(key, value)| (key, QueryValue::Value(value))),
+        (prop_oneof![Just(QueryKey::In), Just(QueryKey::NotIn)], array()).prop_map(|(key, values)| (key, QueryValue::Value(values))),
+        inner.clone().prop_map(|query| (QueryKey::Not, QueryValue::Mapping(query))),
+        (prop_oneof![Just(QueryKey::And), Just(QueryKey::Or)], vec(inner.clone(), 0..3)).prop_map(|(key, cases)| (key, QueryValue::Casematch(cases))),
+        (
+            prop::sample::select(OPERATORS),
+            prop_oneof![
+                scalar().prop_map(QueryValue::Value),
+                inner.clone().prop_map(QueryValue::Mapping),
+                vec(inner.clone(), 1..3).prop_map(QueryValue::Casematch)
+            ]
+        ).prop_map(|(operator, operand)| (QueryKey::Operator(operator.to_string()), operand))
+    ];
+
+    // Keys are picked from a few fields so that conditions repeat them often
+    let repeated = prop_oneof![
+        (prop::sample::select(vec!["a", "b.c"]), value()).prop_map(|(key, value)| (QueryKey::String(key.to_string()), QueryValue::Value(value))),
+        (prop::sample::select(vec!["a", "b.c"]), inner).prop_map(|(key, query)| (QueryKey::String(key.to_string()), QueryValue::Mapping(query)))
+    ];
+    let condition = if repeats { prop_oneof![3 => condition, 2 => repeated].boxed() } else { condition.boxed() };
+
+    // Repeated keys are left out unless asked for, as they render in a different shape (merged, or moved into `$and`)
+    // than they parse back to
+    vec(condition, 0..4).prop_map(move |conditions| {
+        conditions.into_iter().fold(Query::new(), |query, (key, value)| {
+            if !repeats && query.iter().any(|(existing, _)| *existing == key) {
+                query
+            } else {
+                query.push(key, value)
+            }
+        })
+    })
+}
+
+/// Queries nesting up to three levels deep
+pub fn query() -> impl Strategy<Value = Query> {
+    conditions(Just(Query::new()).boxed(), false).prop_recursive(3, 64, 4, |inner| conditions(inner.boxed(), false))
+}
+
+/// Queries nesting up to three levels deep, repeating keys at any level
+pub fn query_with_repeats() -> impl Strategy<Value = Query> {
+    conditions(Just(Query::new()).boxed(), true).prop_recursive(3, 64, 4, |inner| conditions(inner.boxed(), true))
+}
+
+fn collation() -> impl Strategy<Value = Collation> {
+    let strength = prop::sample::select(vec![
+        CollationStrength::Primary,
+        CollationStrength::Secondary,
+        CollationStrength::Tertiary,
+        CollationStrength::Quaternary,
+        CollationStrength::Identical
+    ]);
+    (prop::sample::select(vec!["simple", "en", "fr_CA", "de@collation=phonebook"]), option::of(strength), any::<bool>())
+        .prop_map(|(locale, strength, case_insensitive)| Collation { locale: locale.to_string(), strength, case_insensitive })
+}
+
+impl Arbitrary for Query {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Query>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        query().boxed()
+    }
+}
+
+impl Arbitrary for Find {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Find>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let operation = prop_oneof![Just(OperationCount::One), Just(OperationCount::Many)];
+        let sort = (field(), any::<bool>()).prop_map(|(field, ascending)| if ascending { Sorting::Ascending(field) } else { Sorting::Descending(field) });
+        let read_preference = prop::sample::select(vec![
+            ReadPreference::Primary,
+            ReadPreference::PrimaryPreferred,
+            ReadPreference::Secondary,
+            ReadPreference::SecondaryPreferred,
+            ReadPreference::Nearest
+        ]);
+        let error_policy = prop::sample::select(vec![ErrorPolicy::FailFast, ErrorPolicy::Skip, ErrorPolicy::Collect]);
+        // Projections either include or exclude all of their fields, as MongoDB doesn't allow mixing the two
+        let projection = (vec(field(), 1..4), any::<bool>())
+            .prop_map(|(fields, include)| fields.into_iter().map(|field| (field, Bson::Int32(include as i32))).collect::<bson::Document>());
+
+        (
+            operation,
+            option::of(0usize..1000),
+            option::of(1usize..1000),
+            option::of(sort),
+            option::of(read_preference),
+            option::of(any::<u128>().prop_map(Uuid::from_u128)),
+            error_policy,
+            option::of(projection),
+            option::of(1usize..1000),
+            option::of(collation()),
+            option::of("[a-z_]{1,12}")
+        )
+            .prop_map(|(operation, offset, limit, sort, read_preference, session, error_policy, projection, batch_size, collation, hint)| Find {
+                operation,
+                offset,
+                limit,
+                sort,
+                read_preference,
+                session,
+                error_policy,
+                projection,
+                batch_size,
+                collation,
+                hint
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Index {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Index>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let kind = prop_oneof![Just(IndexKind::BTree), Just(IndexKind::Hashed), Just(IndexKind::Text), Just(IndexKind::Geo)];
+        (vec(field(), 1..4), option::of("[a-z_]{1,12}"), any::<bool>(), option::of(collation()), any::<bool>(), kind, option::of(any::<Query>()))
+            .prop_map(|(fields, name, unique, collation, background, kind, partial_filter)| {
+                let mut index = Index::new_compound(fields);
+                index.name = name;
+                index.unique = unique;
+                index.collation = collation;
+                index.background = background;
+                index.kind = kind;
+                index.partial_filter = partial_filter;
+                index
+            })
+            .boxed()
+    }
+}
+
+/// Whether both queries hold the same conditions, whatever order they were added in
+pub fn equivalent(query: &Query, other: &Query) -> bool {
+    query.equivalent(other)
+}
+
+/// Checks that `query` survives a trip through another representation: `there` converts it (ie to a driver's native
+/// filter), `back` converts that back, and the result must be `equivalent` to `query`
+pub fn round_trip<T>(query: &Query, there: impl FnOnce(Query) -> OResult<T>, back: impl FnOnce(T) -> OResult<Query>) -> Result<(), TestCaseError> {
+    let converted = there(query.clone()).map_err(|e| TestCaseError::fail(format!("Converting {query:?} failed: {e}")))?;
+    let returned = back(converted).map_err(|e| TestCaseError::fail(format!("Converting {query:?} back failed: {e}")))?;
+    prop_assert!(equivalent(query, &returned), "{:?} came back as {:?}", query, returned);
+    Ok(())
+}
+
+/// Query → `bson::Document` → Query, the conversion drivers build their filters from
+pub fn document_round_trip(query: &Query) -> Result<(), TestCaseError> {
+    round_trip(query, TryInto::<bson::Document>::try_into, Query::try_from)
+}
+
+/// Query → Extended JSON → Query, as remote drivers send queries to `ormox_server`
+pub fn wire_round_trip(query: &Query) -> Result<(), TestCaseError> {
+    round_trip(
+        query,
+        |query| serde_json::to_string(&WireDocument::try_from(query)?).map_err(OrmoxError::serialization),
+        |json| Query::try_from(serde_json::from_str::<WireDocument>(&json).map_err(OrmoxError::deserialization)?)
+    )
+}
+
+/// Query → `bson::Document` → Query → `bson::Document`, for queries that repeat keys: the query read back from a
+/// rendered document must render the same document
+pub fn rendered_round_trip(query: &Query) -> Result<(), TestCaseError> {
+    let rendered: bson::Document = query.clone().try_into().map_err(|e| TestCaseError::fail(format!("Rendering {query:?} failed: {e}")))?;
+    let returned = Query::try_from(rendered.clone()).map_err(|e| TestCaseError::fail(format!("Reading {rendered} back failed: {e}")))?;
+    let rerendered: bson::Document = returned.try_into().map_err(|e| TestCaseError::fail(format!("Rendering {rendered} again failed: {e}")))?;
+    prop_assert_eq!(rendered, rerendered);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn queries_survive_documents(query: Query) {
+            document_round_trip(&query)?;
+        }
+
+        #[test]
+        fn queries_survive_the_wire(query: Query) {
+            wire_round_trip(&query)?;
+        }
+
+        #[test]
+        fn repeated_keys_render_stably(query in query_with_repeats()) {
+            rendered_round_trip(&query)?;
+        }
+    }
+}
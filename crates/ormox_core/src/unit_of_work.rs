@@ -0,0 +1,115 @@
+use futures::future::BoxFuture;
+use serde::Serialize;
+
+use crate::{
+    client::{Client, Session},
+    core::{
+        document::Document,
+        driver::DriverCapabilities,
+        error::{OResult, OrmoxError},
+        outbox::OutboxEvent,
+    },
+};
+
+type PendingWrite = Box<dyn FnOnce(Session) -> BoxFuture<'static, OResult<()>> + Send>;
+
+/// Collects writes across any number of collections and commits them together in a single transaction.
+///
+/// Writes are applied in registration order when `commit` is called; nothing is written before then.
+pub struct UnitOfWork {
+    client: Client,
+    pending: Vec<PendingWrite>,
+    atomic: bool
+}
+
+impl UnitOfWork {
+    pub fn new(client: Client) -> Self {
+        Self { client, pending: Vec::new(), atomic: true }
+    }
+
+    /// Allows committing on drivers without transaction support by applying writes one at a time, stopping at the
+    /// first error. Writes applied before the error are kept.
+    pub fn allow_non_atomic(mut self) -> Self {
+        self.atomic = false;
+        self
+    }
+
+    /// Number of pending writes
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Queues inserting a new document
+    pub fn register_new<T: Document + 'static>(&mut self, document: T) -> &mut Self {
+        self.pending.push(Box::new(move |session| Box::pin(async move {
+            session.collection::<T>().insert(vec![document]).await.and(Ok(()))
+        })));
+        self
+    }
+
+    /// Queues saving a modified document. Documents that track changes only write their modified fields.
+    pub fn register_dirty<T: Document + 'static>(&mut self, document: T) -> &mut Self {
+        self.pending.push(Box::new(move |session| Box::pin(async move {
+            session.collection::<T>().save(document).await
+        })));
+        self
+    }
+
+    /// Queues deleting a document
+    pub fn register_deleted<T: Document + 'static>(&mut self, document: T) -> &mut Self {
+        self.pending.push(Box::new(move |session| Box::pin(async move {
+            session.collection::<T>().delete_by_id(document.id()).await
+        })));
+        self
+    }
+
+    /// Queues recording an event in the outbox, so it's only relayed if the other writes commit
+    pub fn register_event(&mut self, topic: impl AsRef<str>, payload: impl Serialize) -> &mut Self {
+        let event = OutboxEvent::new(topic, payload);
+        self.pending.push(Box::new(move |session| Box::pin(async move {
+            session.enqueue_event(event?).await.and(Ok(()))
+        })));
+        self
+    }
+
+    /// Applies all pending writes. With a transactional driver either every write is committed or, on error, none are.
+    pub async fn commit(self) -> OResult<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let transactional = self.client.supports(DriverCapabilities::SESSIONS | DriverCapabilities::TRANSACTIONS);
+        if !transactional && self.atomic {
+            return Err(OrmoxError::unsupported(
+                (DriverCapabilities::SESSIONS | DriverCapabilities::TRANSACTIONS).difference(self.client.capabilities()).names()
+            ));
+        }
+
+        let session = self.client.session().await?;
+        if transactional {
+            session.start_transaction().await?;
+        }
+
+        for write in self.pending {
+            if let Err(e) = write(session.clone()).await {
+                if transactional {
+                    let _ = session.abort_transaction().await;
+                }
+                let _ = session.end().await;
+                return Err(e);
+            }
+        }
+
+        if transactional {
+            if let Err(e) = session.commit_transaction().await {
+                let _ = session.end().await;
+                return Err(e);
+            }
+        }
+        session.end().await
+    }
+}
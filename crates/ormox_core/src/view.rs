@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use bson::{doc, Bson};
+
+use crate::{
+    client::{Client, JobStatus, ScheduledJob},
+    core::{
+        change::{Change, ChangeKind},
+        document::Document,
+        driver::{DriverCapabilities, Find, OperationCount, WriteOptions},
+        error::{OResult, OrmoxError},
+        memory::{get_path, matches, project},
+        query::Query,
+    },
+};
+
+/// How long an incrementally refreshed view waits before watching its source again after the change stream failed
+pub const REWATCH_DELAY: Duration = Duration::from_secs(1);
+
+/// How a view's documents are derived from its source collection
+#[derive(Clone, Debug)]
+pub enum ViewDefinition {
+    /// The source documents matching a query, optionally projected. The projection must keep the documents' ID field,
+    /// which the view shares with its source.
+    Query { query: Query, projection: Option<bson::Document> },
+
+    /// The output of an aggregation pipeline run over the source collection, which must give each document the view's
+    /// ID field. Needs a driver supporting `AGGREGATION`; incremental refreshes rerun the whole pipeline.
+    Aggregation(Vec<bson::Document>)
+}
+
+impl ViewDefinition {
+    pub fn query(query: impl Into<Query>) -> Self {
+        Self::Query { query: query.into(), projection: None }
+    }
+
+    pub fn projected(query: impl Into<Query>, projection: bson::Document) -> Self {
+        Self::Query { query: query.into(), projection: Some(projection) }
+    }
+
+    pub fn aggregation(pipeline: Vec<bson::Document>) -> Self {
+        Self::Aggregation(pipeline)
+    }
+}
+
+/// When a registered view is brought up to date with its source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewRefresh {
+    /// Rebuilt every interval, see `Client::schedule`
+    Scheduled(Duration),
+
+    /// Updated as its source changes, through the watch API. Needs a driver supporting `CHANGE_STREAMS`.
+    Incremental
+}
+
+/// A materialized view: a collection of documents derived from a source collection by a query or aggregation, kept up
+/// to date once registered with `Client::register_view`. Views are read like any other document type, through
+/// `Client::collection`; documents written to them directly are overwritten by the next refresh.
+pub trait View: Document {
+    type Source: Document;
+
+    fn definition() -> ViewDefinition;
+}
+
+/// ID of a stored document of the view `V`, which it's kept up to date by
+fn stored_id<V: View>(document: &bson::Document) -> OResult<Bson> {
+    get_path(document, &V::id_field())
+        .cloned()
+        .ok_or_else(|| OrmoxError::compaibility(format!("Documents of view {} need an ID ({})", V::collection_name(), V::id_field())))
+}
+
+fn id_query<V: View>(id: Bson) -> OResult<Query> {
+    Query::try_from(doc! {V::id_field(): id})
+}
+
+/// Replaces the documents of a view with the given IDs by `documents`, in a transaction where supported. Upserts would
+/// keep fields the new documents no longer have, since drivers turn them into `$set`s.
+async fn replace<V: View>(client: &Client, ids: Vec<Bson>, documents: Vec<bson::Document>) -> OResult<()> {
+    client.collection::<V>().transaction_if(true, |collection| async move {
+        let (driver, view, options) = (collection.driver(), V::collection_name(), collection.write_options());
+        let replaced = Query::try_from(doc! {V::id_field(): {"$in": ids}})?;
+        driver.delete(view.clone(), replaced, OperationCount::Many, options.clone()).await?;
+        if !documents.is_empty() {
+            driver.insert(view, documents, options).await?;
+        }
+        Ok(())
+    }).await
+}
+
+/// Rebuilds a view from its whole source. Current documents replace their stored versions in a transaction where
+/// supported, and stale ones are deleted afterwards, so the view stays readable while it's refreshed (without
+/// transactions, documents are missing while they're replaced).
+pub(crate) async fn refresh<V: View>(client: &Client) -> OResult<()> {
+    let (driver, source, view) = (client.driver(), V::Source::collection_name(), V::collection_name());
+    let documents = match V::definition() {
+        ViewDefinition::Query { query, projection } => {
+            let options = Find { projection: projection.clone(), ..Find::many() };
+            let found = driver.find(source, query, options).await?;
+            // Drivers without projection support return whole documents
+            match projection {
+                Some(projection) => found.into_iter().map(|document| project(document, &projection)).collect(),
+                None => found
+            }
+        },
+        ViewDefinition::Aggregation(pipeline) => {
+            client.require(DriverCapabilities::AGGREGATION)?;
+            driver.aggregate(source, pipeline, Find::many()).await?
+        }
+    };
+
+    let ids = documents.iter().map(stored_id::<V>).collect::<OResult<Vec<_>>>()?;
+    replace::<V>(client, ids.clone(), documents).await?;
+    let stale = Query::try_from(doc! {V::id_field(): {"$nin": ids}})?;
+    driver.delete(view, stale, OperationCount::Many, WriteOptions::default()).await
+}
+
+/// Brings a view up to date with a change to its source. Query views update the changed document only, others are
+/// rebuilt, as are query views on deletes the driver doesn't report the deleted document of.
+pub(crate) async fn apply<V: View>(client: &Client, change: Change) -> OResult<()> {
+    let ViewDefinition::Query { query, projection } = V::definition() else {
+        return refresh::<V>(client).await;
+    };
+    let (driver, source, view) = (client.driver(), V::Source::collection_name(), V::collection_name());
+    let current = match (&change.kind, change.document, &change.key) {
+        (ChangeKind::Insert | ChangeKind::Update | ChangeKind::Replace, Some(document), _) => Some(document),
+        // Drivers that don't look updated documents up report the fields changed only
+        (ChangeKind::Insert | ChangeKind::Update | ChangeKind::Replace, None, Some(key)) => {
+            driver.find(source, Query::try_from(key.clone())?, Find::one()).await?.into_iter().next()
+        },
+        _ => None
+    };
+    let Some(id) = current.as_ref().or(change.previous.as_ref()).and_then(|document| get_path(document, &V::id_field())).cloned() else {
+        return refresh::<V>(client).await;
+    };
+
+    let filter: bson::Document = query.try_into()?;
+    match current {
+        Some(document) if matches(&filter, &document)? => {
+            let document = match projection {
+                Some(projection) => project(document, &projection),
+                None => document
+            };
+            replace::<V>(client, vec![id], vec![document]).await
+        },
+        _ => driver.delete(view, id_query::<V>(id)?, OperationCount::One, WriteOptions::default()).await
+    }
+}
+
+/// A view registered with `Client::register_view`, no longer refreshed once the handle is stopped or dropped
+pub struct ViewHandle(pub(crate) ScheduledJob);
+
+impl ViewHandle {
+    pub fn stop(self) {}
+
+    pub fn is_running(&self) -> bool {
+        self.0.is_running()
+    }
+
+    /// Refreshes of the view, as the `"view:<collection>"` job: incremental views count a run per change applied, and a
+    /// failure each time their change stream fails
+    pub fn status(&self) -> JobStatus {
+        self.0.status()
+    }
+}
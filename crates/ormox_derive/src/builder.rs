@@ -0,0 +1,180 @@
+use darling::FromMeta;
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Generics, Ident, Type, Visibility};
+
+/// Which constructors `ormox_document` generates
+#[derive(FromMeta, Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum Constructor {
+    /// Positional `create(collection, field, ...)`
+    #[default]
+    #[darling(rename = "create")]
+    Create,
+
+    /// `Struct::builder().field(...).build()`
+    #[darling(rename = "builder")]
+    Builder,
+
+    #[darling(rename = "both")]
+    Both
+}
+
+impl Constructor {
+    pub(crate) fn create(&self) -> bool {
+        matches!(self, Constructor::Create | Constructor::Both)
+    }
+
+    pub(crate) fn builder(&self) -> bool {
+        matches!(self, Constructor::Builder | Constructor::Both)
+    }
+}
+
+/// Inner type of `Option<T>` fields, which are optional in the builder
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first() {
+            Some(syn::GenericArgument::Type(inner)) if args.args.len() == 1 => Some(inner),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Generates `<Struct>Builder` & `Struct::builder()`. Required (non-`Option`) fields are tracked in the builder's type
+/// parameters, starting as `()` and becoming the field's type once set, so `build()` only exists once all are set.
+pub(crate) fn document_builder(
+    struct_name: &Ident,
+    vis: &Visibility,
+    generics: &Generics,
+    fields: &[(Ident, Type)],
+    extra_assignments: &TokenStream
+) -> TokenStream {
+    let builder_name = format_ident!("{}Builder", struct_name);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let struct_params: Vec<TokenStream> = generics.params.iter().map(|p| match p {
+        syn::GenericParam::Lifetime(l) => {
+            let lt = &l.lifetime;
+            quote! {#lt}
+        },
+        syn::GenericParam::Type(t) => {
+            let ident = &t.ident;
+            quote! {#ident}
+        },
+        syn::GenericParam::Const(c) => {
+            let ident = &c.ident;
+            quote! {#ident}
+        }
+    }).collect();
+
+    let required: Vec<(&Ident, &Type, Ident)> = fields
+        .iter()
+        .filter(|(_, ty)| option_inner(ty).is_none())
+        .enumerate()
+        .map(|(i, (ident, ty))| (ident, ty, Ident::new(&format!("__Ormox{i}"), Span::call_site())))
+        .collect();
+    let state_params: Vec<&Ident> = required.iter().map(|(_, _, state)| state).collect();
+    let unset: Vec<TokenStream> = required.iter().map(|_| quote! {()}).collect();
+    let all_set: Vec<&Type> = required.iter().map(|(_, ty, _)| *ty).collect();
+
+    // Builder generics: the struct's own, followed by one state parameter per required field
+    let mut builder_generics = generics.clone();
+    for state in &state_params {
+        builder_generics.params.push(syn::parse_quote!(#state));
+    }
+    let (builder_impl_generics, builder_type_generics, builder_where) = builder_generics.split_for_impl();
+
+    let storage = fields.iter().map(|(ident, ty)| match required.iter().find(|(r, _, _)| *r == ident) {
+        Some((_, _, state)) => quote! {#ident: #state},
+        None => quote! {#ident: #ty}
+    });
+    let initial = fields.iter().map(|(ident, _)| match required.iter().any(|(r, _, _)| *r == ident) {
+        true => quote! {#ident: ()},
+        false => quote! {#ident: None}
+    });
+
+    let required_setters = required.iter().enumerate().map(|(index, (ident, ty, _))| {
+        // Every other state parameter stays generic; this one goes from `()` to the field type
+        let mut setter_generics = generics.clone();
+        for (i, state) in state_params.iter().enumerate() {
+            if i != index {
+                setter_generics.params.push(syn::parse_quote!(#state));
+            }
+        }
+        let (setter_impl_generics, _, setter_where) = setter_generics.split_for_impl();
+        let before = state_params.iter().enumerate().map(|(i, s)| if i == index { quote! {()} } else { quote! {#s} });
+        let after = state_params.iter().enumerate().map(|(i, s)| if i == index { quote! {#ty} } else { quote! {#s} });
+        let moved = fields.iter().map(|(f, _)| if f == *ident { quote! {#f: value.into()} } else { quote! {#f: self.#f} });
+
+        quote! {
+            impl #setter_impl_generics #builder_name<#(#struct_params,)* #(#before),*> #setter_where {
+                pub fn #ident(self, value: impl Into<#ty>) -> #builder_name<#(#struct_params,)* #(#after),*> {
+                    #builder_name {
+                        _collection: self._collection,
+                        _document: std::marker::PhantomData,
+                        #(#moved),*
+                    }
+                }
+            }
+        }
+    });
+
+    let optional_setters = fields.iter().filter_map(|(ident, ty)| {
+        let inner = option_inner(ty)?;
+        Some(quote! {
+            pub fn #ident(mut self, value: impl Into<#inner>) -> Self {
+                self.#ident = Some(value.into());
+                self
+            }
+        })
+    });
+
+    let assignments = fields.iter().map(|(ident, _)| quote! {#ident: self.#ident});
+    let doc = format!("Builder for [`{struct_name}`], from `{struct_name}::builder()`. `build()` is available once every required (non-`Option`) field is set.");
+
+    quote! {
+        #[doc = #doc]
+        #[must_use]
+        #vis struct #builder_name #builder_impl_generics #builder_where {
+            _collection: Option<ormox::ormox_core::client::Collection<#struct_name #type_generics>>,
+            _document: std::marker::PhantomData<fn() -> #struct_name #type_generics>,
+            #(#storage),*
+        }
+
+        impl #impl_generics #struct_name #type_generics #where_clause {
+            pub fn builder() -> #builder_name<#(#struct_params,)* #(#unset),*> {
+                #builder_name {
+                    _collection: None,
+                    _document: std::marker::PhantomData,
+                    #(#initial),*
+                }
+            }
+        }
+
+        impl #builder_impl_generics #builder_name #builder_type_generics #builder_where {
+            /// Attaches the built document to a collection
+            pub fn collection(mut self, collection: ormox::ormox_core::client::Collection<#struct_name #type_generics>) -> Self {
+                self._collection = Some(collection);
+                self
+            }
+
+            #(#optional_setters)*
+        }
+
+        #(#required_setters)*
+
+        impl #impl_generics #builder_name<#(#struct_params,)* #(#all_set),*> #where_clause {
+            pub fn build(self) -> #struct_name #type_generics {
+                #struct_name {
+                    _collection: ormox::ormox_core::core::document::Hidden(self._collection),
+                    #extra_assignments
+                    #(#assignments),*
+                }
+            }
+        }
+    }
+}
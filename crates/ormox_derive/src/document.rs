@@ -1,17 +1,89 @@
-use darling::{ast::NestedMeta, FromField, FromMeta};
+use darling::{ast::NestedMeta, util::{Override, PathList}, FromField, FromMeta};
 use proc_macro2::{Span, TokenStream};
-use quote::quote;
-use syn::{punctuated::Punctuated, token::Comma, Ident, Type};
+use quote::{format_ident, quote};
+use syn::{ext::IdentExt, punctuated::Punctuated, token::Comma, Attribute, Generics, Ident, LitStr, Type};
+
+use crate::builder::{document_builder, Constructor};
+use crate::graphql::graphql_object;
+use crate::naming::{collection_name, has_serde_attr, serde_attr, stored_field_name, Casing};
+use crate::paths::{field_consts, field_paths, TypedField};
+use crate::redaction::{redacted_debug, sensitive_fn, take_debug, DebugShape};
+use crate::relations::{counter_caches_fn, holds_ref, references_fn, relation_accessors};
+use crate::scope::{default_scope_fns, Scope};
+#[cfg(feature = "schemars")]
+use crate::validation::schema_rules;
+use crate::validation::{field_checks, nested_check, validate_fields_impl, validate_fn};
+use crate::variants::wrap_enum_document;
 
 #[derive(FromMeta, Debug)]
 pub(crate) struct DocumentMetadata {
-    pub collection: String,
+    /// Collection name; defaults to the struct name, pluralized & cased per `casing`
+    #[darling(default)]
+    pub collection: Option<String>,
+
+    #[darling(default)]
+    pub casing: Casing,
+
+    /// Casing of stored field names, ie `rename_all = "camelCase"`. Passed on to serde. On enums, this is the casing of
+    /// variant names, like serde's.
+    #[darling(default)]
+    pub rename_all: Option<Casing>,
+
+    /// Discriminator field of enum documents. Passed on to serde as `tag`.
+    #[darling(default)]
+    pub tag: Option<String>,
+
+    /// Field holding the variant's fields for adjacently tagged enum documents. Passed on to serde as `content`.
+    #[darling(default)]
+    pub content: Option<String>,
+
+    /// Use this existing field (with its own type) as the document ID, instead of injecting a UUID field
+    #[darling(default)]
+    pub id: Option<String>,
 
     #[darling(default)]
     pub id_field: Option<String>,
 
     #[darling(default)]
-    pub id_alias: Option<String>
+    pub id_alias: Option<String>,
+
+    /// Snapshot loaded state so `save()` only writes changed fields
+    #[darling(default)]
+    pub track_changes: bool,
+
+    /// Fail to load stored documents with keys the type doesn't know, whatever `ClientOptions::decode_mode` is
+    #[darling(default)]
+    pub strict: bool,
+
+    /// Constructors to generate: `create` (positional, default), `builder`, or `both`
+    #[darling(default)]
+    pub constructor: Constructor,
+
+    /// Current schema version, stored in an injected `_schema` field
+    #[darling(default)]
+    pub schema_version: Option<u32>,
+
+    /// Upcasts from each older schema version to the next (`fn(bson::Document) -> OResult<bson::Document>`), in order:
+    /// the first upgrades version 1 to 2
+    #[darling(default)]
+    pub migrations: PathList,
+
+    /// Extra derives for the struct, ie `derive(Debug, PartialEq)`
+    #[darling(default)]
+    pub derive: PathList,
+
+    /// Derive an async-graphql `SimpleObject` for the struct, see `graphql_object`
+    #[darling(default)]
+    pub graphql: bool,
+
+    /// Sort of the collection's reads that don't set one, as a field optionally followed by `asc` or `desc`
+    /// (ie `"created_at desc"`)
+    #[darling(default)]
+    pub default_sort: Option<String>,
+
+    /// Conditions the collection's reads apply on top of their query, see `Scope`
+    #[darling(default)]
+    pub default_scope: Option<Scope>
 }
 
 #[derive(FromField, Debug)]
@@ -28,86 +100,704 @@ pub(crate) struct FieldIndex {
     pub name: Option<String>,
 
     #[darling(default)]
-    pub alias: Option<String>
+    pub alias: Option<String>,
+
+    /// `"btree"` (the default), `"hashed"`, `"text"` or `"geo"`
+    #[darling(default)]
+    pub kind: Option<String>
+}
+
+#[derive(FromField, Debug)]
+#[darling(attributes(ormox))]
+#[allow(dead_code)]
+pub(crate) struct FieldOptions {
+    pub ident: Option<syn::Ident>,
+    pub ty: Type,
+
+    /// Runtime-only field: not stored, and `Default` when loaded or created
+    #[darling(default)]
+    pub skip: bool,
+
+    /// Integer field filled from this `Client::next_sequence` sequence on insert, rather than set by constructors
+    #[darling(default)]
+    pub sequence: Option<String>,
+
+    /// Field stored encrypted, either `"random"` (the default) or `"deterministic"` (queryable by equality)
+    #[darling(default)]
+    pub encrypted: Option<Override<String>>,
+
+    /// Field masked in errors and `Debug` output. Encrypted & hashed fields are always sensitive.
+    #[darling(default)]
+    pub sensitive: bool,
+
+    /// Password field stored as a digest, hashed with this algorithm (only `"argon2"`) on set & insert
+    #[darling(default)]
+    pub hashed: Option<String>,
+
+    /// Module serializing & deserializing the field, like serde's `with`, also encoding the values its typed field
+    /// constant compares it against in queries
+    #[darling(default)]
+    pub with: Option<syn::Path>,
+
+    /// Field holding a document or embedded document, whose typed paths its typed path method returns and whose
+    /// `#[validate(...)]` rules are checked with the document's
+    #[darling(default)]
+    pub nested: bool
+}
+
+/// How a document's ID is stored
+pub(crate) struct DocumentId {
+    /// Whether an existing field is the ID, rather than an injected UUID
+    pub natural: bool,
+    pub field: String,
+    pub alias: String,
+    pub ident: Ident
+}
+
+/// Everything gathered from the named fields of a struct (or enum variant)
+#[derive(Default)]
+pub(crate) struct DocumentFields {
+    pub indexes: Vec<(String, syn::ExprStruct)>,
+    pub creation_fields: Punctuated<syn::FnArg, Comma>,
+    pub creation_assignments: Punctuated<syn::FieldValue, Comma>,
+    pub data_fields: Vec<(Ident, Type)>,
+    /// Fields left out of constructors, set to `Default`
+    pub defaulted_assignments: TokenStream,
+
+    /// `#[validate(...)]` checks, reading fields from local bindings named after them
+    pub validations: TokenStream,
+
+    /// Fields the checks read
+    pub validated: Vec<Ident>,
+
+    /// Collection of the IDs held by `Ref` fields, reading fields from local bindings named after them
+    pub references: TokenStream,
+
+    /// `Ref` fields
+    pub referencing: Vec<Ident>,
+
+    /// `#[ormox(sequence = "...")]` fields & their sequences
+    pub sequences: Vec<(Ident, String)>,
+
+    /// Stored paths of `#[ormox(encrypted)]` fields & their `EncryptionMode` variants
+    pub encrypted: Vec<(String, Ident)>,
+
+    /// `#[ormox(sensitive)]`, encrypted & hashed fields, with their stored paths
+    pub sensitive: Vec<(Ident, String)>,
+
+    /// `#[ormox(hashed = "...")]` fields
+    pub hashed: Vec<Ident>,
+
+    /// Type & stored name of the natural ID field, if any
+    pub natural_id: Option<(Type, String)>,
+
+    /// Stored fields, for typed field constants & paths
+    pub typed: Vec<TypedField>
+}
+
+/// Checks & collects the fields of a struct (or enum variant), then adds the fields managed by the ORM. Stored names of
+/// indexed fields are prefixed with `index_prefix`.
+pub(crate) fn document_fields(
+    fields: &mut syn::FieldsNamed,
+    id: &DocumentId,
+    rename_all: Option<Casing>,
+    index_prefix: &str,
+    track_changes: bool
+) -> Result<DocumentFields, TokenStream> {
+    let mut result = DocumentFields::default();
+    for field in fields.named.iter_mut() {
+        if let Some(ident) = field.ident.clone() {
+            if ident == id.field {
+                if !id.natural {
+                    return Err(quote! {compile_error!("Document ID fields are defined by the ORM; use `id = \"...\"` to use an existing field as the ID.");});
+                }
+                result.natural_id = Some((field.ty.clone(), stored_field_name(&ident, &field.attrs, rename_all)));
+            }
+
+            if matches!(ident.to_string().as_str(), "_collection" | "_loaded" | "_schema") {
+                return Err(quote! {compile_error!("The _collection, _loaded and _schema fields are reserved for the ORM.");});
+            }
+
+            let options = match FieldOptions::from_field(field) {
+                Ok(fo) => fo,
+                Err(e) => return Err(e.write_errors())
+            };
+
+            let mut checks = field_checks(field, &ident, &stored_field_name(&ident, &field.attrs, rename_all))?;
+            if options.nested {
+                checks.extend(nested_check(&ident, &stored_field_name(&ident, &field.attrs, rename_all)));
+            }
+            if !checks.is_empty() {
+                result.validations.extend(checks);
+                result.validated.push(ident.clone());
+            }
+            #[cfg(feature = "schemars")]
+            schema_rules(field)?;
+
+            let stored_path = format!("{index_prefix}{}", stored_field_name(&ident, &field.attrs, rename_all));
+            if options.sensitive || options.encrypted.is_some() || options.hashed.is_some() {
+                result.sensitive.push((ident.clone(), stored_path.clone()));
+            }
+
+            let indexed = field.attrs.iter().any(|a| a.path().segments.last().and_then(|s| Some(s.ident.to_string() == String::from("index"))).or(Some(false)).unwrap());
+            if options.skip {
+                if id.natural && ident == id.field {
+                    return Err(quote! {compile_error!("The ID field can't be skipped.");});
+                }
+                if indexed {
+                    return Err(quote! {compile_error!("Skipped fields are not stored, so they can't be indexed.");});
+                }
+                if options.sequence.is_some() {
+                    return Err(quote! {compile_error!("Skipped fields are not stored, so they can't be filled from a sequence.");});
+                }
+                if options.encrypted.is_some() {
+                    return Err(quote! {compile_error!("Skipped fields are not stored, so they can't be encrypted.");});
+                }
+                if options.hashed.is_some() {
+                    return Err(quote! {compile_error!("Skipped fields are not stored, so they can't be hashed.");});
+                }
+                if options.with.is_some() {
+                    return Err(quote! {compile_error!("Skipped fields are not stored, so they can't be stored with a module.");});
+                }
+                if options.nested {
+                    return Err(quote! {compile_error!("Skipped fields are not stored, so they have no nested paths.");});
+                }
+
+                // Like `_collection`: never serialized, `Default` on load, and not a constructor argument
+                field.attrs.push(syn::parse_quote!{#[serde(skip)]});
+                result.defaulted_assignments.extend(quote! {#ident: Default::default(),});
+                result.creation_assignments.push(syn::parse_quote!{#ident: Default::default()});
+                continue;
+            }
+
+            if indexed {
+                let field_index = match FieldIndex::from_field(field) {
+                    Ok(fi) => fi,
+                    Err(e) => return Err(darling::Error::from(e).write_errors())
+                };
+
+                let alias = format!("{index_prefix}{}", field_index.alias.unwrap_or_else(|| stored_field_name(&ident, &field.attrs, rename_all)));
+                let name = field_index.name.unwrap_or(alias.clone());
+                let unique = field_index.unique;
+                let kind = match field_index.kind.as_deref().unwrap_or("btree") {
+                    "btree" => quote! {BTree},
+                    "hashed" => quote! {Hashed},
+                    "text" => quote! {Text},
+                    "geo" => quote! {Geo},
+                    _ => return Err(quote! {compile_error!("Index kinds are \"btree\", \"hashed\", \"text\" & \"geo\".");})
+                };
+
+                result.indexes.push((alias.clone(), syn::parse_quote!{ormox::Index {fields: vec![String::from(#alias)], name: Some(String::from(#name)), unique: #unique, collation: None, background: false, kind: ormox::IndexKind::#kind, partial_filter: None}}));
+            }
+
+            if let Some(module) = &options.with {
+                if has_serde_attr(&field.attrs, "with") || has_serde_attr(&field.attrs, "serialize_with") || has_serde_attr(&field.attrs, "deserialize_with") {
+                    return Err(quote! {compile_error!("Use either `#[ormox(with = \"...\")]` or serde's `with`, not both.");});
+                }
+                if options.hashed.is_some() {
+                    return Err(quote! {compile_error!("Hashed fields are stored as digests, so they can't be stored with a module.");});
+                }
+
+                let module = quote! {#module}.to_string().replace(' ', "");
+                field.attrs.push(syn::parse_quote!{#[serde(with = #module)]});
+            }
+            if options.nested && (options.with.is_some() || options.encrypted.is_some() || options.hashed.is_some()) {
+                return Err(quote! {compile_error!("Nested fields are queried by the paths of their type, so they can't be stored with a module, encrypted or hashed.");});
+            }
+            result.typed.push(TypedField { ident: ident.clone(), ty: field.ty.clone(), path: stored_path.clone(), with: options.with.clone(), nested: options.nested });
+
+            if let Some(algorithm) = &options.hashed {
+                if !cfg!(feature = "argon2") {
+                    return Err(quote! {compile_error!("Hashed fields need ormox's argon2 feature.");});
+                }
+                if algorithm != "argon2" {
+                    return Err(quote! {compile_error!("The only supported hashing algorithm is \"argon2\".");});
+                }
+                if id.natural && ident == id.field {
+                    return Err(quote! {compile_error!("The ID field can't be hashed.");});
+                }
+                if options.encrypted.is_some() || options.sequence.is_some() {
+                    return Err(quote! {compile_error!("Hashed fields can't also be encrypted or filled from a sequence.");});
+                }
+
+                // Raw passwords are hashed before writes and never serialized
+                field.attrs.push(syn::parse_quote!{
+                    #[serde(default, skip_serializing_if = "ormox::ormox_core::core::password::HashedField::is_raw")]
+                });
+                result.hashed.push(ident.clone());
+            }
+
+            if let Some(encrypted) = options.encrypted {
+                if !cfg!(feature = "encryption") {
+                    return Err(quote! {compile_error!("Encrypted fields need ormox's encryption feature.");});
+                }
+                if id.natural && ident == id.field {
+                    return Err(quote! {compile_error!("The ID field can't be encrypted.");});
+                }
+                let mode = match encrypted.unwrap_or_else(|| String::from("random")).as_str() {
+                    "random" if indexed => {
+                        return Err(quote! {compile_error!("Randomly encrypted fields can't be usefully indexed; use `encrypted = \"deterministic\"`.");});
+                    },
+                    "random" => Ident::new("Random", Span::call_site()),
+                    "deterministic" => Ident::new("Deterministic", Span::call_site()),
+                    _ => return Err(quote! {compile_error!("Encryption modes are \"random\" and \"deterministic\".");})
+                };
+                result.encrypted.push((stored_path, mode));
+            }
+
+            if let Some(sequence) = options.sequence {
+                if id.natural && ident == id.field {
+                    return Err(quote! {compile_error!("The ID field can't be filled from a sequence.");});
+                }
+
+                // Stored, but filled on insert rather than passed to constructors
+                result.defaulted_assignments.extend(quote! {#ident: Default::default(),});
+                result.creation_assignments.push(syn::parse_quote!{#ident: Default::default()});
+                result.sequences.push((ident.clone(), sequence));
+                continue;
+            }
+
+            if holds_ref(&field.ty) {
+                let stored = stored_field_name(&ident, &field.attrs, rename_all);
+                result.references.extend(quote! {
+                    ormox::ormox_core::core::relation::RefField::collect_references(#ident, #stored, &mut __references)?;
+                });
+                result.referencing.push(ident.clone());
+            }
+
+            let ftype = field.ty.clone();
+
+            result.data_fields.push((ident.clone(), ftype.clone()));
+            result.creation_fields.push(syn::parse_quote!{#ident: impl Into<#ftype>});
+            result.creation_assignments.push(syn::parse_quote!{#ident: #ident.into()});
+        }
+    }
+
+    if id.natural {
+        if result.natural_id.is_none() {
+            let message = format!("ID field `{}` not found.", id.field);
+            return Err(quote! {compile_error!(#message);});
+        }
+    } else {
+        let (id_alias, id_ident) = (&id.alias, &id.ident);
+        let omit_default = omit_schema_default();
+        fields.named.push(syn::parse_quote!{
+            #[serde(default = "ormox::ormox_core::uuid::Uuid::new_v4", rename = #id_alias)]
+            #omit_default
+            #id_ident : ormox::ormox_core::uuid::Uuid
+        });
+    }
+
+    fields.named.push(syn::parse_quote!{
+        #[serde(default, skip, bound = "")]
+        _collection: ormox::ormox_core::core::document::Hidden<Option<ormox::ormox_core::client::Collection<Self>>>
+    });
+
+    if track_changes {
+        fields.named.push(syn::parse_quote!{
+            #[serde(default, skip, bound = "")]
+            _loaded: ormox::ormox_core::core::document::Hidden<Option<ormox::ormox_core::bson::Document>>
+        });
+        result.creation_assignments.push(syn::parse_quote!{_loaded: ormox::ormox_core::core::document::Hidden(None)});
+    }
+    Ok(result)
+}
+
+/// `Document::unset_sequences` & `Document::set_sequence` for `#[ormox(sequence = "...")]` fields, matching each
+/// pattern (ie `Self` or `Self::Variant`) with its fields; nothing if there are none
+pub(crate) fn sequence_fns(patterns: &[(TokenStream, Vec<(Ident, String)>)]) -> TokenStream {
+    if patterns.iter().all(|(_, sequences)| sequences.is_empty()) {
+        return quote! {};
+    }
+
+    let unset_arms = patterns.iter().map(|(pattern, sequences)| {
+        let idents = sequences.iter().map(|(ident, _)| ident);
+        let checks = sequences.iter().map(|(ident, sequence)| {
+            let name = ident.to_string();
+            quote! {
+                if ormox::ormox_core::core::sequence::SequenceValue::is_unset(#ident) {
+                    __unset.push((#name, #sequence));
+                }
+            }
+        });
+        quote! {#pattern { #(#idents,)* .. } => { #(#checks)* }}
+    });
+    let set_arms = patterns.iter().map(|(pattern, sequences)| {
+        let idents = sequences.iter().map(|(ident, _)| ident);
+        if sequences.is_empty() {
+            return quote! {#pattern { .. } => {}};
+        }
+        let setters = sequences.iter().map(|(ident, _)| {
+            let name = ident.to_string();
+            quote! {#name => *#ident = ormox::ormox_core::core::sequence::SequenceValue::from_sequence(value)?,}
+        });
+        quote! {#pattern { #(#idents,)* .. } => match field { #(#setters)* _ => {} }}
+    });
+
+    quote! {
+        fn unset_sequences(&self) -> Vec<(&'static str, &'static str)> {
+            let mut __unset = Vec::new();
+            match self {
+                #(#unset_arms),*
+            }
+            __unset
+        }
+
+        fn set_sequence(&mut self, field: &str, value: i64) -> ormox::ormox_core::OResult<()> {
+            match self {
+                #(#set_arms),*
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Keeps the defaults of ORM-managed fields (ie a random ID) out of the generated JSON Schema
+/// `Document::encrypted_fields` listing `#[ormox(encrypted)]` fields as `(stored path, mode)`, or nothing if there are
+/// none
+pub(crate) fn encrypted_fn(encrypted: &[(String, Ident)]) -> TokenStream {
+    if encrypted.is_empty() {
+        return quote! {};
+    }
+
+    let (paths, modes): (Vec<_>, Vec<_>) = encrypted.iter().cloned().unzip();
+    quote! {
+        fn encrypted_fields() -> Vec<(&'static str, ormox::ormox_core::core::encryption::EncryptionMode)> {
+            vec![#((#paths, ormox::ormox_core::core::encryption::EncryptionMode::#modes)),*]
+        }
+    }
+}
+
+/// `Document::decode_mode` for `strict` documents
+pub(crate) fn decode_mode_fn(strict: bool) -> TokenStream {
+    if !strict {
+        return quote! {};
+    }
+
+    quote! {
+        fn decode_mode() -> Option<ormox::DecodeMode> {
+            Some(ormox::DecodeMode::Strict)
+        }
+    }
+}
+
+/// `Document::hash_fields` for `#[ormox(hashed = "...")]` fields, plus `set_<field>` & `verify_<field>` methods
+fn hashed_fns(hashed: &[Ident]) -> (TokenStream, TokenStream) {
+    if hashed.is_empty() {
+        return (quote! {}, quote! {});
+    }
+
+    let methods = hashed.iter().map(|field| {
+        let name = field.unraw().to_string();
+        let (setter, verifier) = (format_ident!("set_{name}"), format_ident!("verify_{name}"));
+        let (setter_doc, verifier_doc) = (
+            LitStr::new(&format!("Hashes `raw` and stores the digest in `{name}`"), Span::call_site()),
+            LitStr::new(&format!("Checks `input` against `{name}`"), Span::call_site())
+        );
+        quote! {
+            #[doc = #setter_doc]
+            pub fn #setter(&mut self, raw: impl AsRef<str>) -> ormox::ormox_core::OResult<()> {
+                let digest = ormox::ormox_core::core::password::hash_password(raw.as_ref())?;
+                ormox::ormox_core::core::password::HashedField::set_digest(&mut self.#field, digest);
+                Ok(())
+            }
+
+            #[doc = #verifier_doc]
+            pub fn #verifier(&self, input: impl AsRef<str>) -> bool {
+                ormox::ormox_core::core::password::HashedField::verify(&self.#field, input.as_ref())
+            }
+        }
+    });
+
+    let hash_fields = quote! {
+        fn hash_fields(&mut self) -> ormox::ormox_core::OResult<()> {
+            #(ormox::ormox_core::core::password::HashedField::hash(&mut self.#hashed)?;)*
+            Ok(())
+        }
+    };
+    (hash_fields, quote! {#(#methods)*})
+}
+
+fn omit_schema_default() -> TokenStream {
+    if cfg!(feature = "schemars") {
+        quote! {#[schemars(skip_serializing_if = "ormox::ormox_core::core::document::omit_schema_default")]}
+    } else {
+        quote! {}
+    }
+}
+
+/// `#[derive(...)]` for the document: serde, `Clone`, `ormox::Document` (and `JsonSchema` / `ToSchema` with the
+/// `schemars` / `utoipa` features), plus any requested with `derive(...)`, minus those the item already derives so
+/// existing `#[derive(...)]` attributes can be kept as-is
+pub(crate) fn document_derives(attrs: &[Attribute], extra: &PathList) -> TokenStream {
+    let mut existing_derives: Vec<String> = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("derive")) {
+        if let Ok(paths) = attr.parse_args_with(Punctuated::<syn::Path, Comma>::parse_terminated) {
+            existing_derives.extend(paths.iter().filter_map(|p| p.segments.last()).map(|s| s.ident.to_string()));
+        }
+    }
+
+    let mut base_derives: Vec<syn::Path> = vec![
+        syn::parse_quote!(ormox::ormox_core::serde::Serialize),
+        syn::parse_quote!(ormox::ormox_core::serde::Deserialize),
+        syn::parse_quote!(Clone),
+        syn::parse_quote!(ormox::Document),
+    ];
+    if cfg!(feature = "schemars") {
+        base_derives.push(syn::parse_quote!(ormox::ormox_core::schemars::JsonSchema));
+    }
+    if cfg!(feature = "utoipa") {
+        // utoipa's derive refers to `utoipa::` paths, so crates using this feature depend on utoipa themselves
+        base_derives.push(syn::parse_quote!(ormox::ormox_core::utoipa::ToSchema));
+    }
+
+    let derives = base_derives.into_iter().chain(extra.iter().cloned()).filter(|path| {
+        !path.segments.last().is_some_and(|s| existing_derives.contains(&s.ident.to_string()) && s.ident != "Document")
+    });
+    let schemars = if cfg!(feature = "schemars") {
+        quote! {#[schemars(crate = "ormox::ormox_core::schemars")]}
+    } else {
+        quote! {}
+    };
+    quote! {
+        #[derive(#(#derives),*)]
+        #schemars
+    }
+}
+
+/// Serde's inferred `T: Deserialize<'de>` bounds clash with the `T: DeserializeOwned` documents need, so generic
+/// documents get explicit bounds unless the item sets its own
+pub(crate) fn serde_bounds(attrs: &[Attribute], generics: &Generics) -> TokenStream {
+    let type_params: Vec<&Ident> = generics.type_params().map(|p| &p.ident).collect();
+    if type_params.is_empty() || has_serde_attr(attrs, "bound") {
+        return quote! {};
+    }
+
+    let serialize = type_params.iter().map(|p| format!("{p}: ormox::ormox_core::serde::Serialize")).collect::<Vec<_>>().join(", ");
+    let deserialize = type_params.iter().map(|p| format!("{p}: ormox::ormox_core::serde::de::DeserializeOwned")).collect::<Vec<_>>().join(", ");
+    quote! {#[serde(bound(serialize = #serialize, deserialize = #deserialize))]}
+}
+
+/// Where clause for the `Document` impl: generic documents only implement it when their parameters allow it
+/// (ie `T: Clone + Send + Sync`)
+pub(crate) fn document_where(generics: &Generics) -> syn::WhereClause {
+    let mut document_where = generics.where_clause.clone().unwrap_or_else(|| syn::parse_quote!(where));
+    document_where.predicates.push(syn::parse_quote!{
+        Self: ormox::ormox_core::serde::Serialize + ormox::ormox_core::serde::de::DeserializeOwned + Clone + Send + Sync
+    });
+    document_where
+}
+
+/// Resolves a casing set either on `ormox_document` (and forwarded to serde) or with an existing serde attribute
+pub(crate) fn forwarded_casing(attrs: &[Attribute], key: &str, arg: Option<Casing>) -> Result<(Option<Casing>, TokenStream), TokenStream> {
+    let existing = serde_attr(attrs, key);
+    if arg.is_some() && existing.is_some() {
+        let message = format!("{key} is set on both ormox_document and serde.");
+        return Err(quote! {compile_error!(#message);});
+    }
+
+    let key = Ident::new(key, Span::call_site());
+    match arg {
+        Some(casing) => {
+            let name = casing.serde_name();
+            Ok((Some(casing), quote! {#[serde(#key = #name)]}))
+        },
+        None => Ok((existing.and_then(|c| Casing::from_string(&c).ok()), quote! {}))
+    }
 }
 
 pub(crate) fn wrap_document(args: TokenStream, input: TokenStream) -> TokenStream {
-    let input = match syn::parse2::<syn::ItemStruct>(input) {
-        Ok(is) => is,
+    let input = match syn::parse2::<syn::Item>(input) {
+        Ok(syn::Item::Struct(is)) => is,
+        Ok(syn::Item::Enum(ie)) => {
+            return match parse_args(args) {
+                Ok(args) => wrap_enum_document(args, ie),
+                Err(e) => e
+            }
+        },
+        Ok(_) => return quote! {compile_error!("This macro only supports structs and enums.");},
         Err(e) => return darling::Error::from(e).write_errors()
     };
-    let attr_args = match NestedMeta::parse_meta_list(args) {
+    let args = match parse_args(args) {
         Ok(v) => v,
-        Err(e) => return darling::Error::from(e).write_errors()
+        Err(e) => return e
     };
-    let args = match DocumentMetadata::from_list(&attr_args) {
+    if args.tag.is_some() || args.content.is_some() {
+        return quote! {compile_error!("tag and content only apply to enum documents.");};
+    }
+
+    let (schema_version, schema_assignment) = match schema_version(&args) {
         Ok(v) => v,
-        Err(e) => return darling::Error::from(e).write_errors()
+        Err(e) => return e
     };
 
     let struct_name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let document_where = document_where(&input.generics);
     let mut original_struct = input.clone();
-    let mut index_objs: Punctuated<syn::ExprStruct, Comma> = Punctuated::new();
-    let mut creation_fields = Punctuated::<syn::FnArg, Comma>::new();
-    let mut creation_assignments = Punctuated::<syn::FieldValue, Comma>::new();
-    let collection = args.collection;
-    let id_field = args.id_field.unwrap_or("_docid".into());
-    let id_alias = args.id_alias.unwrap_or(id_field.clone());
-    let id_ident = Ident::new(&id_field.clone(), Span::call_site());
-
-
-    match original_struct.fields {
-        syn::Fields::Named(ref mut existing) => {
-            for field in existing.named.clone() {
-                if let Some(ident) = field.ident.clone() {
-                    if ident.to_string() == id_field {
-                        return quote! {compile_error!("Document ID fields are defined by the ORM.")};
-                    }
-
-                    if ident.to_string() == "_collection" {
-                        return quote! {compile_error!("The _collection field is reserved for the ORM.")};
-                    }
+    let collection = args.collection.unwrap_or_else(|| collection_name(&struct_name.to_string(), args.casing));
+    if args.id.is_some() && (args.id_field.is_some() || args.id_alias.is_some()) {
+        return quote! {compile_error!("id can't be combined with id_field or id_alias.");};
+    }
+    let natural_id = args.id.is_some();
+    let id_field = args.id.or(args.id_field).unwrap_or("_docid".into());
+    let id = DocumentId {
+        natural: natural_id,
+        alias: args.id_alias.unwrap_or(id_field.clone()),
+        ident: Ident::new(&id_field.clone(), Span::call_site()),
+        field: id_field
+    };
 
-                    if field.attrs.iter().any(|a| a.path().segments.last().and_then(|s| Some(s.ident.to_string() == String::from("index"))).or(Some(false)).unwrap()) {
-                        let field_index = match FieldIndex::from_field(&field) {
-                            Ok(fi) => fi,
-                            Err(e) => return darling::Error::from(e).write_errors()
-                        };
+    // Field casing comes from the macro's `rename_all` (forwarded to serde) or an existing `#[serde(rename_all)]`
+    let (rename_all, rename_all_attr) = match forwarded_casing(&input.attrs, "rename_all", args.rename_all) {
+        Ok(v) => v,
+        Err(e) => return e
+    };
 
-                        let alias = field_index.alias.unwrap_or(field_index.ident.unwrap().to_string());
-                        let name = field_index.name.unwrap_or(alias.clone());
-                        let unique = field_index.unique;
+    let fields = match original_struct.fields {
+        syn::Fields::Named(ref mut existing) => match document_fields(existing, &id, rename_all, "", args.track_changes) {
+            Ok(fields) => fields,
+            Err(e) => return e
+        },
+        syn::Fields::Unnamed(_) => return quote! {compile_error!("This macro only supports fields structs with named fields.");},
+        syn::Fields::Unit => return quote! {compile_error!("This macro does not support unit structs.");}
+    };
+    if args.schema_version.is_some() {
+        if let syn::Fields::Named(ref mut existing) = original_struct.fields {
+            let omit_default = omit_schema_default();
+            existing.named.push(syn::parse_quote!{
+                #[serde(default, rename = "_schema")]
+                #omit_default
+                _schema: u32
+            });
+        }
+    }
+    let graphql = match original_struct.fields {
+        syn::Fields::Named(ref mut existing) if args.graphql => match graphql_object(existing, &input.generics, rename_all) {
+            Ok(graphql) => graphql,
+            Err(e) => return e
+        },
+        _ => quote! {}
+    };
+    let relations = match relation_accessors(&input.attrs, Some(&fields.data_fields), struct_name, &input.generics) {
+        Ok(relations) => relations,
+        Err(e) => return e
+    };
+    let counter_caches = counter_caches_fn(&input.attrs);
+    original_struct.attrs.retain(|a| !a.path().is_ident("relation"));
+    let (id_type, id_alias): (Type, String) = fields.natural_id.clone().unwrap_or_else(|| (syn::parse_quote!(ormox::ormox_core::uuid::Uuid), id.alias.clone()));
+    let id_ident = &id.ident;
+    let index_objs = fields.indexes.iter().map(|(_, index)| index);
+    let creation_fields = &fields.creation_fields;
+    let creation_assignments = &fields.creation_assignments;
 
-                        index_objs.push(syn::parse_quote!{ormox::Index {fields: vec![String::from(#alias)], name: Some(String::from(#name)), unique: #unique}});
-                    }
+    let change_tracking = if args.track_changes {
+        quote! {
+            fn tracks_changes() -> bool {
+                true
+            }
 
-                    let ftype = field.ty.clone();
+            fn loaded_state(&self) -> Option<&ormox::ormox_core::bson::Document> {
+                self._loaded.0.as_ref()
+            }
 
-                    creation_fields.push(syn::parse_quote!{#ident: impl Into<#ftype>});
-                    creation_assignments.push(syn::parse_quote!{#ident: #ident.into()});
-                }
+            fn set_loaded_state(&mut self, state: Option<ormox::ormox_core::bson::Document>) {
+                self._loaded.0 = state;
             }
+        }
+    } else {
+        quote! {}
+    };
 
-            existing.named.push(syn::parse_quote!{
-                #[serde(default = "ormox::ormox_core::uuid::Uuid::new_v4", rename = #id_alias)]
-                #id_ident : ormox::ormox_core::uuid::Uuid
-            });
+    let validated = &fields.validated;
+    let validations = &fields.validations;
+    let validate_fields = validate_fields_impl(struct_name, &input.generics, validated, validations);
+    let validate = validate_fn(!validated.is_empty(), quote! {
+        ormox::ValidateFields::validate_fields(self, &mut __errors, "");
+    });
 
-            existing.named.push(syn::parse_quote!{
-                #[serde(default, skip)]
-                _collection: Option<ormox::ormox_core::client::Collection<Self>>
-            });
-        },
-        syn::Fields::Unnamed(_) => return quote! {compile_error!("This macro only supports fields structs with named fields.")},
-        syn::Fields::Unit => return quote! {compile_error!("This macro does not support unit structs.")}
+    let referencing = &fields.referencing;
+    let references = &fields.references;
+    let references = references_fn(!referencing.is_empty(), quote! {
+        let Self { #(#referencing),*, .. } = self;
+        #references
+    });
+
+    let sequences = sequence_fns(&[(quote! {Self}, fields.sequences.clone())]);
+    let encrypted = encrypted_fn(&fields.encrypted);
+    let decode_mode = decode_mode_fn(args.strict);
+    let default_scope = match default_scope_fns(args.default_scope.as_ref(), args.default_sort.as_deref(), &fields.typed) {
+        Ok(fns) => fns,
+        Err(e) => return e
+    };
+    let sensitive = sensitive_fn(&fields.sensitive.iter().map(|(_, path)| path.clone()).collect::<Vec<_>>());
+    let (hash_fields, password_methods) = hashed_fns(&fields.hashed);
+    let password_methods = (!fields.hashed.is_empty()).then(|| quote! {
+        impl #impl_generics #struct_name #type_generics #where_clause {
+            #password_methods
+        }
+    });
+    let (derive, debug) = take_debug(&mut original_struct.attrs, &args.derive, !fields.sensitive.is_empty());
+    let debug = if debug {
+        let syn::Fields::Named(ref named) = original_struct.fields else { unreachable!() };
+        redacted_debug(struct_name, &input.generics, &[DebugShape {
+            pattern: quote! {Self},
+            name: struct_name.to_string(),
+            fields: named.named.iter().filter_map(|f| f.ident.clone()).collect(),
+            sensitive: fields.sensitive.iter().map(|(ident, _)| ident.clone()).collect()
+        }])
+    } else {
+        quote! {}
+    };
+    let field_consts = field_consts(struct_name, &input.generics, &fields.typed);
+    let field_paths = field_paths(struct_name, &input.vis, &input.generics, &fields.typed);
+    let derives = document_derives(&original_struct.attrs, &derive);
+    let serde_bounds = serde_bounds(&original_struct.attrs, &input.generics);
+
+    let generated_id = if natural_id {
+        quote! {}
+    } else {
+        quote! {#id_ident: ormox::ormox_core::uuid::Uuid::new_v4(),}
+    };
+    let create = if args.constructor.create() {
+        quote! {
+            impl #impl_generics #struct_name #type_generics #where_clause {
+                pub fn create(collection: Option<ormox::Collection<Self>>, #creation_fields) -> Self {
+                    Self {
+                        #generated_id
+                        _collection: ormox::ormox_core::core::document::Hidden(collection.clone()),
+                        #schema_assignment
+                        #creation_assignments
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let builder = if args.constructor.builder() {
+        let mut extra_assignments = generated_id.clone();
+        extra_assignments.extend(schema_assignment.clone());
+        extra_assignments.extend(fields.defaulted_assignments.clone());
+        if args.track_changes {
+            extra_assignments.extend(quote! {_loaded: ormox::ormox_core::core::document::Hidden(None),});
+        }
+        document_builder(struct_name, &input.vis, &input.generics, &fields.data_fields, &extra_assignments)
+    } else {
+        quote! {}
     };
 
     quote! {
-        #[derive(ormox::ormox_core::serde::Serialize, ormox::ormox_core::serde::Deserialize, Clone, ormox::Document)]
+        #derives
+        #graphql
+        #serde_bounds
+        #rename_all_attr
         #original_struct
 
-        impl ormox::Document for #struct_name {
-            fn id(&self) -> ormox::ormox_core::uuid::Uuid {
+        impl #impl_generics ormox::Document for #struct_name #type_generics #document_where {
+            type Id = #id_type;
+
+            fn id(&self) -> Self::Id {
                 self.#id_ident.clone()
             }
 
@@ -120,27 +810,75 @@ pub(crate) fn wrap_document(args: TokenStream, input: TokenStream) -> TokenStrea
             }
 
             fn indexes() -> Vec<ormox::Index> {
-                vec![#index_objs]
+                vec![#(#index_objs),*]
             }
 
             fn attached_collection(&self) -> Option<ormox::Collection<Self>> {
-                self._collection.clone()
+                self._collection.0.clone()
             }
 
             fn attach_collection(&mut self, collection: ormox::Collection<Self>) -> () {
-                self._collection = Some(collection.clone());
+                self._collection.0 = Some(collection.clone());
             }
+
+            #change_tracking
+            #validate
+            #references
+            #counter_caches
+            #sequences
+            #encrypted
+            #sensitive
+            #hash_fields
+            #schema_version
+            #decode_mode
+            #default_scope
         }
 
-        impl #struct_name {
-            pub fn create(collection: Option<ormox::Collection<Self>>, #creation_fields) -> Self {
-                Self {
-                    #id_ident: ormox::ormox_core::uuid::Uuid::new_v4(),
-                    _collection: collection.clone(),
-                    #creation_assignments
-                }
-            }
+        #validate_fields
+        #create
+        #builder
+        #relations
+        #debug
+        #password_methods
+        #field_consts
+        #field_paths
+    }
+}
+
+/// `Document::schema_version` & `Document::upcast` for versioned documents, and the constructors' `_schema` assignment
+fn schema_version(args: &DocumentMetadata) -> Result<(TokenStream, TokenStream), TokenStream> {
+    let Some(version) = args.schema_version else {
+        if !args.migrations.is_empty() {
+            return Err(quote! {compile_error!("migrations need a schema_version.");});
         }
+        return Ok((quote! {}, quote! {}));
+    };
+    if version == 0 {
+        return Err(quote! {compile_error!("Schema versions start at 1.");});
+    }
+    if args.migrations.len() != version as usize - 1 {
+        let message = format!("schema_version = {version} needs {} migrations (one per older version), got {}.", version - 1, args.migrations.len());
+        return Err(quote! {compile_error!(#message);});
     }
+
+    let from = (1..version).map(proc_macro2::Literal::u32_unsuffixed);
+    let migrations = args.migrations.iter();
+    let methods = quote! {
+        fn schema_version() -> Option<u32> {
+            Some(#version)
+        }
+
+        fn upcast(from: u32, data: ormox::ormox_core::bson::Document) -> ormox::ormox_core::OResult<ormox::ormox_core::bson::Document> {
+            match from {
+                #(#from => #migrations(data),)*
+                _ => Err(ormox::ormox_core::OrmoxError::deserialization(format!("No upcast from schema version {from} of {}", Self::collection_name())))
+            }
+        }
+    };
+    Ok((methods, quote! {_schema: #version,}))
 }
 
+fn parse_args(args: TokenStream) -> Result<DocumentMetadata, TokenStream> {
+    let attr_args = NestedMeta::parse_meta_list(args).map_err(|e| darling::Error::from(e).write_errors())?;
+    DocumentMetadata::from_list(&attr_args).map_err(|e| e.write_errors())
+}
@@ -0,0 +1,145 @@
+use darling::{FromField, FromMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Type;
+
+use crate::naming::{has_serde_attr, serde_attr, stored_field_name, Casing};
+use crate::paths::{field_paths, TypedField};
+use crate::validation::{field_checks, nested_check, validate_fields_impl};
+
+#[derive(FromField, Debug)]
+#[darling(attributes(ormox))]
+#[allow(dead_code)]
+pub(crate) struct EmbeddedFieldOptions {
+    pub ident: Option<syn::Ident>,
+    pub ty: Type,
+
+    /// Field holding another embedded document (or a document), like a document's `#[ormox(nested)]` fields
+    #[darling(default)]
+    pub nested: bool
+}
+
+/// Typed field paths, `ormox::ValidateFields` and (with the `schemars` feature) `JsonSchema` for a struct stored inside
+/// documents. Stored names follow the struct's serde attributes; fields stored with serde's `with` are encoded through
+/// its module in queries.
+pub(crate) fn derive_embedded(input: TokenStream) -> TokenStream {
+    let input = match syn::parse2::<syn::DeriveInput>(input) {
+        Ok(di) => di,
+        Err(e) => return darling::Error::from(e).write_errors()
+    };
+    let syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(ref named), .. }) = input.data else {
+        return quote! {compile_error!("EmbeddedDocument only supports structs with named fields.");};
+    };
+
+    let struct_name = &input.ident;
+    let rename_all = serde_attr(&input.attrs, "rename_all").and_then(|c| Casing::from_string(&c).ok());
+    let (mut checks, mut validated, mut typed) = (TokenStream::new(), Vec::new(), Vec::new());
+    for field in named.named.iter() {
+        let options = match EmbeddedFieldOptions::from_field(field) {
+            Ok(options) => options,
+            Err(e) => return e.write_errors()
+        };
+        let Some(ident) = options.ident else { continue };
+        let stored = stored_field_name(&ident, &field.attrs, rename_all);
+
+        let mut field_checks = match field_checks(field, &ident, &stored) {
+            Ok(checks) => checks,
+            Err(e) => return e
+        };
+        if options.nested {
+            field_checks.extend(nested_check(&ident, &stored));
+        }
+        if !field_checks.is_empty() {
+            checks.extend(field_checks);
+            validated.push(ident.clone());
+        }
+
+        if has_serde_attr(&field.attrs, "skip") {
+            if options.nested {
+                return quote! {compile_error!("Skipped fields are not stored, so they have no nested paths.");};
+            }
+            continue;
+        }
+        let with = match serde_attr(&field.attrs, "with").map(|module| syn::parse_str::<syn::Path>(&module)) {
+            Some(Ok(module)) => Some(module),
+            Some(Err(e)) => return e.to_compile_error(),
+            None => None
+        };
+        if options.nested && with.is_some() {
+            return quote! {compile_error!("Nested fields are queried by the paths of their type, so they can't be stored with a module.");};
+        }
+        typed.push(TypedField { ident, ty: options.ty, path: stored, with, nested: options.nested });
+    }
+
+    let paths = field_paths(struct_name, &input.vis, &input.generics, &typed);
+    let validate_fields = validate_fields_impl(struct_name, &input.generics, &validated, &checks);
+    let schema = embedded_schema(&input);
+    quote! {
+        #paths
+        #validate_fields
+        #schema
+    }
+}
+
+/// `JsonSchema` for an embedded document, including its `#[validate(...)]` rules. schemars reads `validate` attributes
+/// with a different `regex` syntax, so rather than deriving `JsonSchema` on the struct, it's derived on a copy whose
+/// rules are rewritten as `#[schemars(...)]` attributes (see `schema_rules`), named like the struct.
+#[cfg(feature = "schemars")]
+fn embedded_schema(input: &syn::DeriveInput) -> TokenStream {
+    let schema_attr = |attr: &syn::Attribute| ["serde", "schemars", "doc"].iter().any(|name| attr.path().is_ident(name));
+    let syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(ref named), .. }) = input.data else { unreachable!() };
+    let mut fields = named.clone();
+    for field in fields.named.iter_mut() {
+        if let Err(e) = crate::validation::schema_rules(field) {
+            return e;
+        }
+        field.attrs.retain(schema_attr);
+        field.vis = syn::Visibility::Inherited;
+    }
+
+    let struct_name = &input.ident;
+    let attrs = input.attrs.iter().filter(|a| schema_attr(a));
+    let type_params = input.generics.type_params().map(|p| p.ident.to_string()).collect::<Vec<_>>();
+    let name = if type_params.is_empty() {
+        struct_name.to_string()
+    } else {
+        format!("{struct_name}_for_{}", type_params.iter().map(|p| format!("{{{p}}}")).collect::<Vec<_>>().join("_and_"))
+    };
+    let copy = syn::Ident::new(&format!("__{struct_name}Schema"), struct_name.span());
+    let generics = &input.generics;
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let mut schema_where = where_clause.cloned().unwrap_or_else(|| syn::parse_quote!(where));
+    for param in generics.type_params() {
+        let param = &param.ident;
+        schema_where.predicates.push(syn::parse_quote_spanned!{param.span()=> #param: ormox::ormox_core::schemars::JsonSchema});
+    }
+
+    quote! {
+        const _: () = {
+            #[derive(ormox::ormox_core::schemars::JsonSchema)]
+            #[schemars(crate = "ormox::ormox_core::schemars", rename = #name)]
+            #(#attrs)*
+            #[allow(dead_code)]
+            struct #copy #generics #where_clause #fields
+
+            impl #impl_generics ormox::ormox_core::schemars::JsonSchema for #struct_name #type_generics #schema_where {
+                fn schema_name() -> String {
+                    <#copy #type_generics as ormox::ormox_core::schemars::JsonSchema>::schema_name()
+                }
+
+                fn schema_id() -> std::borrow::Cow<'static, str> {
+                    <#copy #type_generics as ormox::ormox_core::schemars::JsonSchema>::schema_id()
+                }
+
+                fn json_schema(generator: &mut ormox::ormox_core::schemars::gen::SchemaGenerator) -> ormox::ormox_core::schemars::schema::Schema {
+                    <#copy #type_generics as ormox::ormox_core::schemars::JsonSchema>::json_schema(generator)
+                }
+            }
+        };
+    }
+}
+
+#[cfg(not(feature = "schemars"))]
+fn embedded_schema(_input: &syn::DeriveInput) -> TokenStream {
+    quote! {}
+}
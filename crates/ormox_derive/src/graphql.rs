@@ -0,0 +1,35 @@
+use darling::FromField;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::document::FieldOptions;
+use crate::naming::{stored_field_name, Casing};
+
+/// `#[derive(SimpleObject)]` exposing a struct document as a GraphQL object. Fields keep their stored names, so they
+/// match the paths of `Filter` arguments; ORM-managed, skipped & sensitive (including encrypted & hashed) fields are
+/// left out.
+pub(crate) fn graphql_object(fields: &mut syn::FieldsNamed, generics: &syn::Generics, rename_all: Option<Casing>) -> Result<TokenStream, TokenStream> {
+    if !cfg!(feature = "graphql") {
+        return Err(quote! {compile_error!("GraphQL objects need ormox's graphql feature.");});
+    }
+    if !generics.params.is_empty() {
+        return Err(quote! {compile_error!("Generic documents can't be GraphQL objects.");});
+    }
+
+    for field in fields.named.iter_mut() {
+        let Some(ident) = field.ident.clone() else { continue };
+        let managed = matches!(ident.to_string().as_str(), "_collection" | "_loaded" | "_schema");
+        let options = FieldOptions::from_field(field).map_err(|e| e.write_errors())?;
+        if managed || options.skip || options.sensitive || options.encrypted.is_some() || options.hashed.is_some() {
+            field.attrs.push(syn::parse_quote! {#[graphql(skip)]});
+        } else {
+            let name = stored_field_name(&ident, &field.attrs, rename_all);
+            field.attrs.push(syn::parse_quote! {#[graphql(name = #name)]});
+        }
+    }
+
+    Ok(quote! {
+        #[derive(ormox::ormox_core::async_graphql::SimpleObject)]
+        #[graphql(crate = "ormox::ormox_core::async_graphql")]
+    })
+}
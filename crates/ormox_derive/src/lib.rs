@@ -1,4 +1,15 @@
+mod builder;
 mod document;
+mod embedded;
+mod graphql;
+mod naming;
+mod paths;
+mod projection;
+mod redaction;
+mod relations;
+mod scope;
+mod validation;
+mod variants;
 use quote::quote;
 
 #[proc_macro_attribute]
@@ -6,7 +17,17 @@ pub fn ormox_document(args: proc_macro::TokenStream, input: proc_macro::TokenStr
     document::wrap_document(args.into(), input.into()).into()
 }
 
-#[proc_macro_derive(Document, attributes(index))]
+#[proc_macro_derive(Document, attributes(index, ormox, validate))]
 pub fn derive_document_helper(_input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     quote! {}.into()
-}
\ No newline at end of file
+}
+
+#[proc_macro_derive(EmbeddedDocument, attributes(ormox, validate))]
+pub fn derive_embedded_document(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    embedded::derive_embedded(input.into()).into()
+}
+
+#[proc_macro_derive(Projection, attributes(projection))]
+pub fn derive_projection(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    projection::derive_projection(input.into()).into()
+}
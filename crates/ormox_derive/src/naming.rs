@@ -0,0 +1,214 @@
+use darling::FromMeta;
+use syn::Attribute;
+
+/// Casing used for collection names derived from struct names, and for stored field names with `rename_all`.
+/// Names match serde's `rename_all` values.
+#[derive(FromMeta, Debug, Clone, Copy, Default)]
+pub(crate) enum Casing {
+    #[default]
+    #[darling(rename = "snake_case")]
+    Snake,
+
+    #[darling(rename = "kebab-case")]
+    Kebab,
+
+    #[darling(rename = "camelCase")]
+    Camel,
+
+    #[darling(rename = "PascalCase")]
+    Pascal,
+
+    #[darling(rename = "lowercase")]
+    Lower,
+
+    #[darling(rename = "UPPERCASE")]
+    Upper,
+
+    #[darling(rename = "SCREAMING_SNAKE_CASE")]
+    ScreamingSnake,
+
+    #[darling(rename = "SCREAMING-KEBAB-CASE")]
+    ScreamingKebab
+}
+
+impl Casing {
+    /// The serde `rename_all` value for this casing
+    pub(crate) fn serde_name(&self) -> &'static str {
+        match self {
+            Casing::Snake => "snake_case",
+            Casing::Kebab => "kebab-case",
+            Casing::Camel => "camelCase",
+            Casing::Pascal => "PascalCase",
+            Casing::Lower => "lowercase",
+            Casing::Upper => "UPPERCASE",
+            Casing::ScreamingSnake => "SCREAMING_SNAKE_CASE",
+            Casing::ScreamingKebab => "SCREAMING-KEBAB-CASE"
+        }
+    }
+
+    fn join(&self, words: &[String]) -> String {
+        match self {
+            Casing::Snake => words.join("_"),
+            Casing::Kebab => words.join("-"),
+            Casing::Lower => words.concat(),
+            Casing::Upper => words.concat().to_uppercase(),
+            Casing::ScreamingSnake => words.join("_").to_uppercase(),
+            Casing::ScreamingKebab => words.join("-").to_uppercase(),
+            Casing::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+            Casing::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                .collect()
+        }
+    }
+}
+
+/// Splits a struct name into lowercase words, keeping acronyms together (ie "HTTPRequest" -> ["http", "request"])
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '_' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        let boundary = c.is_uppercase()
+            && !current.is_empty()
+            && (chars[i - 1].is_lowercase()
+                || chars[i - 1].is_ascii_digit()
+                || chars.get(i + 1).is_some_and(|n| n.is_lowercase()));
+        if boundary {
+            words.push(std::mem::take(&mut current));
+        }
+        current.extend(c.to_lowercase());
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn pluralize(word: &str) -> String {
+    let consonant_y = word.ends_with('y') && word.chars().rev().nth(1).is_some_and(|c| !"aeiou".contains(c));
+    if consonant_y {
+        format!("{}ies", &word[..word.len() - 1])
+    } else if ["s", "x", "z", "ch", "sh"].iter().any(|s| word.ends_with(s)) {
+        format!("{word}es")
+    } else {
+        format!("{word}s")
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new()
+    }
+}
+
+/// Default collection name for a struct: its name pluralized & cased (ie `UserProfile` -> `user_profiles`)
+pub(crate) fn collection_name(struct_name: &str, casing: Casing) -> String {
+    let mut words = words(struct_name);
+    if let Some(last) = words.last_mut() {
+        *last = pluralize(last);
+    }
+    casing.join(&words)
+}
+
+/// Stored name of a snake_case field under a `rename_all` casing (ie `created_at` -> `createdAt`). Like serde,
+/// `lowercase` & `UPPERCASE` keep the underscores of field names.
+pub(crate) fn field_name(field: &str, casing: Casing) -> String {
+    match casing {
+        Casing::Lower => field.to_lowercase(),
+        Casing::Upper => field.to_uppercase(),
+        _ => casing.join(&words(field))
+    }
+}
+
+/// Visits each `key` / `key = "value"` / `key(...)` item of the `#[serde(...)]` attributes, with the string value if it has one
+fn visit_serde_attrs(attrs: &[Attribute], mut visit: impl FnMut(&syn::Path, Option<String>)) {
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.input.peek(syn::Token![=]) {
+                match meta.value()?.parse::<syn::Expr>()? {
+                    syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }) => visit(&meta.path, Some(value.value())),
+                    _ => visit(&meta.path, None)
+                }
+            } else {
+                if meta.input.peek(syn::token::Paren) {
+                    meta.input.parse::<proc_macro2::Group>()?;
+                }
+                visit(&meta.path, None);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Stored name of a PascalCase enum variant under a `rename_all` casing, following serde's rules exactly
+/// (ie `BigSquare` -> `big_square`, but `HTTPRequest` -> `h_t_t_p_request`)
+pub(crate) fn variant_name(variant: &str, casing: Casing) -> String {
+    let snake = || {
+        let mut snake = String::new();
+        for (i, c) in variant.char_indices() {
+            if i > 0 && c.is_uppercase() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+
+    match casing {
+        Casing::Pascal => variant.to_string(),
+        Casing::Lower => variant.to_ascii_lowercase(),
+        Casing::Upper => variant.to_ascii_uppercase(),
+        Casing::Camel => variant[..1].to_ascii_lowercase() + &variant[1..],
+        Casing::Snake => snake(),
+        Casing::ScreamingSnake => snake().to_ascii_uppercase(),
+        Casing::Kebab => snake().replace('_', "-"),
+        Casing::ScreamingKebab => snake().replace('_', "-").to_ascii_uppercase()
+    }
+}
+
+/// snake_case version of a type or variant name, for generated identifiers (ie `BigSquare` -> `big_square`)
+pub(crate) fn snake_case(name: &str) -> String {
+    Casing::Snake.join(&words(name))
+}
+
+/// Value of a `#[serde(key = "...")]` attribute (ie `rename`, `rename_all`), if present
+pub(crate) fn serde_attr(attrs: &[Attribute], key: &str) -> Option<String> {
+    let mut found = None;
+    visit_serde_attrs(attrs, |path, value| {
+        if path.is_ident(key) && value.is_some() {
+            found = value;
+        }
+    });
+    found
+}
+
+/// Whether any `#[serde(...)]` attribute sets `key`, in any form
+pub(crate) fn has_serde_attr(attrs: &[Attribute], key: &str) -> bool {
+    let mut found = false;
+    visit_serde_attrs(attrs, |path, _| found |= path.is_ident(key));
+    found
+}
+
+/// Stored name of a field: its `#[serde(rename)]`, else its name under the container's `rename_all` casing
+pub(crate) fn stored_field_name(ident: &syn::Ident, attrs: &[Attribute], rename_all: Option<Casing>) -> String {
+    use syn::ext::IdentExt;
+    serde_attr(attrs, "rename").unwrap_or_else(|| {
+        let name = ident.unraw().to_string();
+        match rename_all {
+            Some(casing) => field_name(&name, casing),
+            None => name
+        }
+    })
+}
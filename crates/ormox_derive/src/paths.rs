@@ -0,0 +1,126 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{ext::IdentExt, Generics, Ident, LitStr, Type, Visibility};
+
+use crate::naming::{field_name, Casing};
+
+/// A stored field, as typed field constants & paths see it
+pub(crate) struct TypedField {
+    pub ident: Ident,
+    pub ty: Type,
+
+    /// Stored name of the field, with the index prefix of enum variants
+    pub path: String,
+
+    /// `#[ormox(with = "...")]` module
+    pub with: Option<syn::Path>,
+
+    /// `#[ormox(nested)]`: the field holds a type with its own typed paths
+    pub nested: bool
+}
+
+impl TypedField {
+    /// `fn(&T) -> OResult<serde_json::Value>` encoding query values like the field is stored
+    fn encoder(&self) -> TokenStream {
+        let ty = &self.ty;
+        match &self.with {
+            Some(module) => quote! {
+                |value: &#ty| ormox::ormox_core::core::field::encode_bson(#module::serialize(value, ormox::ormox_core::bson::Serializer::new()))
+            },
+            None => quote! {ormox::ormox_core::core::field::encode::<#ty>}
+        }
+    }
+}
+
+/// Associated `ormox::Field` constants for the stored fields of a struct document, named after the fields in
+/// SCREAMING_SNAKE_CASE (ie `User::EMAIL`), encoding query values like the fields are stored
+pub(crate) fn field_consts(struct_name: &Ident, generics: &Generics, typed: &[TypedField]) -> TokenStream {
+    if typed.is_empty() {
+        return quote! {};
+    }
+
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let mut field_where = where_clause.cloned().unwrap_or_else(|| syn::parse_quote!(where));
+    let consts = typed.iter().map(|field| {
+        let (ty, path) = (&field.ty, &field.path);
+        let name = format_ident!("{}", field_name(&field.ident.unraw().to_string(), Casing::ScreamingSnake));
+        let doc = LitStr::new(&format!("Stored field `{path}`"), Span::call_site());
+        if field.with.is_none() {
+            field_where.predicates.push(syn::parse_quote!{#ty: ormox::ormox_core::serde::Serialize});
+        }
+        let encode = field.encoder();
+        quote! {
+            #[doc = #doc]
+            pub const #name: ormox::Field<Self, #ty> = ormox::Field::new(#path, #encode);
+        }
+    }).collect::<Vec<_>>();
+
+    quote! {
+        #[allow(dead_code)]
+        impl #impl_generics #struct_name #type_generics #field_where {
+            #(#consts)*
+        }
+    }
+}
+
+/// Typed paths of a struct's fields: a `<Struct>Fields` builder with a method per stored field, returning the field's
+/// `ormox::Field` (or the paths of `#[ormox(nested)]` fields' types), `Struct::FIELDS`, and `ormox::FieldPaths` so the
+/// struct's paths continue those of documents embedding it. Generic structs get none.
+pub(crate) fn field_paths(struct_name: &Ident, vis: &Visibility, generics: &Generics, typed: &[TypedField]) -> TokenStream {
+    if !generics.params.is_empty() {
+        return quote! {};
+    }
+
+    let paths = format_ident!("{}Fields", struct_name.unraw());
+    let paths_doc = LitStr::new(&format!("Typed paths of the fields of `{struct_name}`, see `{struct_name}::FIELDS`"), Span::call_site());
+    let methods = typed.iter().map(|field| {
+        let (ident, ty, path) = (&field.ident, &field.ty, &field.path);
+        if field.nested {
+            let doc = LitStr::new(&format!("Paths of the fields of `{path}`"), Span::call_site());
+            let prefix = format!("{path}.");
+            return quote! {
+                #[doc = #doc]
+                pub fn #ident(&self) -> <#ty as ormox::FieldPaths>::Paths<D> {
+                    <#ty as ormox::FieldPaths>::paths(format!("{}{}", self.prefix, #prefix))
+                }
+            };
+        }
+
+        let doc = LitStr::new(&format!("Stored field `{path}`"), Span::call_site());
+        let encode = field.encoder();
+        quote! {
+            #[doc = #doc]
+            pub fn #ident(&self) -> ormox::Field<D, #ty> {
+                ormox::Field::at(format!("{}{}", self.prefix, #path), #encode)
+            }
+        }
+    });
+
+    quote! {
+        #[doc = #paths_doc]
+        #[allow(dead_code)]
+        #vis struct #paths<D = #struct_name> {
+            prefix: String,
+            _document: std::marker::PhantomData<fn() -> D>
+        }
+
+        #[allow(dead_code)]
+        impl<D> #paths<D> {
+            #(#methods)*
+        }
+
+        #[allow(dead_code)]
+        impl #struct_name {
+            /// Typed paths of the document's fields, for queries on fields of embedded documents in dot notation
+            pub const FIELDS: #paths<Self> = #paths { prefix: String::new(), _document: std::marker::PhantomData };
+        }
+
+        impl ormox::FieldPaths for #struct_name {
+            type Paths<D> = #paths<D>;
+
+            fn paths<D>(prefix: String) -> #paths<D> {
+                #paths { prefix, _document: std::marker::PhantomData }
+            }
+        }
+    }
+}
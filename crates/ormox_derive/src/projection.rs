@@ -0,0 +1,68 @@
+use darling::{ast::Data, FromDeriveInput, FromField, FromMeta};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, Attribute, Path, Type};
+
+use crate::naming::{serde_attr, stored_field_name, Casing};
+
+#[derive(FromDeriveInput, Debug)]
+#[darling(attributes(projection), forward_attrs(serde), supports(struct_named))]
+pub(crate) struct ProjectionInput {
+    pub ident: syn::Ident,
+    pub data: Data<(), ProjectionField>,
+    pub attrs: Vec<Attribute>,
+
+    /// Document this is a projection of
+    pub of: Path
+}
+
+#[derive(FromField, Debug)]
+#[darling(forward_attrs(serde))]
+pub(crate) struct ProjectionField {
+    pub ident: Option<syn::Ident>,
+    pub ty: Type,
+    pub attrs: Vec<Attribute>
+}
+
+pub(crate) fn derive_projection(input: TokenStream) -> TokenStream {
+    let input = match syn::parse2::<syn::DeriveInput>(input) {
+        Ok(di) => di,
+        Err(e) => return darling::Error::from(e).write_errors()
+    };
+    let args = match ProjectionInput::from_derive_input(&input) {
+        Ok(v) => v,
+        Err(e) => return e.write_errors()
+    };
+
+    let struct_name = &args.ident;
+    let parent = &args.of;
+    let rename_all = serde_attr(&args.attrs, "rename_all").and_then(|c| Casing::from_string(&c).ok());
+    let fields = args.data.take_struct().unwrap().fields;
+    let names: Vec<String> = fields.iter().map(|f| stored_field_name(f.ident.as_ref().unwrap(), &f.attrs, rename_all)).collect();
+
+    // Each field must exist on the parent document with the same type, or this fails to compile
+    let checks = fields.iter().map(|field| {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        quote_spanned! {ty.span()=> let _: &#ty = &parent.#ident;}
+    });
+
+    quote! {
+        const _: () = {
+            #[allow(dead_code)]
+            fn check(parent: &#parent) {
+                #(#checks)*
+            }
+        };
+
+        impl ormox::Projection for #struct_name {
+            type Of = #parent;
+
+            fn projection() -> ormox::ormox_core::bson::Document {
+                let mut projection = ormox::ormox_core::bson::Document::new();
+                #(projection.insert(#names, 1);)*
+                projection
+            }
+        }
+    }
+}
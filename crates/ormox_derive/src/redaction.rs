@@ -0,0 +1,92 @@
+use darling::util::PathList;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{ext::IdentExt, punctuated::Punctuated, token::Comma, Attribute, Generics, Ident, LitStr};
+
+/// `Document::sensitive_fields` listing the stored paths of `#[ormox(sensitive)]` & encrypted fields, or nothing if
+/// there are none
+pub(crate) fn sensitive_fn(paths: &[String]) -> TokenStream {
+    if paths.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        fn sensitive_fields() -> Vec<&'static str> {
+            vec![#(#paths),*]
+        }
+    }
+}
+
+/// Documents with sensitive fields get a `Debug` impl masking them rather than the derived one. Takes `Debug` out of
+/// the item's `#[derive(...)]` attributes & the macro's `derive(...)`, returning the remaining `derive(...)` paths and
+/// whether `Debug` was requested.
+pub(crate) fn take_debug(attrs: &mut Vec<Attribute>, derive: &PathList, any_sensitive: bool) -> (PathList, bool) {
+    if !any_sensitive {
+        return (derive.clone(), false);
+    }
+
+    let is_debug = |path: &syn::Path| path.segments.last().is_some_and(|s| s.ident == "Debug");
+    let mut requested = derive.iter().any(is_debug);
+    let mut kept_attrs = Vec::new();
+    for attr in attrs.drain(..) {
+        let paths = match attr.path().is_ident("derive").then(|| attr.parse_args_with(Punctuated::<syn::Path, Comma>::parse_terminated)) {
+            Some(Ok(paths)) if paths.iter().any(is_debug) => paths,
+            _ => {
+                kept_attrs.push(attr);
+                continue;
+            }
+        };
+
+        requested = true;
+        let remaining: Vec<&syn::Path> = paths.iter().filter(|p| !is_debug(p)).collect();
+        if !remaining.is_empty() {
+            kept_attrs.push(syn::parse_quote!(#[derive(#(#remaining),*)]));
+        }
+    }
+    *attrs = kept_attrs;
+    (PathList::new(derive.iter().filter(|p| !is_debug(p)).cloned().collect::<Vec<_>>()), requested)
+}
+
+/// One shape of a document for `redacted_debug`: the pattern binding its fields, the name it's printed with, its
+/// fields in order and those that are sensitive
+pub(crate) struct DebugShape {
+    pub pattern: TokenStream,
+    pub name: String,
+    pub fields: Vec<Ident>,
+    pub sensitive: Vec<Ident>
+}
+
+/// `Debug` printing like the derived impl, except that sensitive fields show as `[REDACTED]`
+pub(crate) fn redacted_debug(document: &Ident, generics: &Generics, shapes: &[DebugShape]) -> TokenStream {
+    let (impl_generics, type_generics, _) = generics.split_for_impl();
+    let mut debug_where = generics.where_clause.clone().unwrap_or_else(|| syn::parse_quote!(where));
+    for param in generics.type_params().map(|p| &p.ident) {
+        debug_where.predicates.push(syn::parse_quote!(#param: std::fmt::Debug));
+    }
+
+    let arms = shapes.iter().map(|shape| {
+        let DebugShape { pattern, name, fields, sensitive } = shape;
+        let entries = fields.iter().map(|field| {
+            let label = LitStr::new(&field.unraw().to_string(), Span::call_site());
+            if sensitive.contains(field) {
+                quote! {.field(#label, &ormox::ormox_core::core::redaction::Redacted)}
+            } else {
+                quote! {.field(#label, #field)}
+            }
+        });
+        let bound = fields.iter().filter(|f| !sensitive.contains(f));
+        quote! {
+            #pattern { #(#bound,)* .. } => f.debug_struct(#name)#(#entries)*.finish()
+        }
+    });
+
+    quote! {
+        impl #impl_generics std::fmt::Debug for #document #type_generics #debug_where {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    #(#arms),*
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use darling::FromMeta;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{Attribute, Generics, Ident, LitStr, Path, Type};
+
+use crate::document::document_where;
+
+use crate::naming::{collection_name, snake_case, Casing};
+/// `#[relation(belongs_to = "Organization", field = "org_id")]`, `#[relation(has_many = "Ticket", foreign_key = "user_id")]`
+/// (optionally with `counter_cache = "tickets_count"`) or `#[relation(many_to_many = "Tag", through = "post_tags")]` on a
+/// document
+#[derive(FromMeta, Debug)]
+pub(crate) struct Relation {
+    /// Document referenced by one of this document's fields
+    #[darling(default)]
+    pub belongs_to: Option<Path>,
+
+    /// Documents referencing this one
+    #[darling(default)]
+    pub has_many: Option<Path>,
+
+    /// Documents linked to this one through a junction collection
+    #[darling(default)]
+    pub many_to_many: Option<Path>,
+
+    /// `many_to_many`: the junction collection
+    #[darling(default)]
+    pub through: Option<String>,
+
+    /// `many_to_many`: junction field holding this document's ID; defaults to `<document>_id`
+    #[darling(default)]
+    pub source_key: Option<String>,
+
+    /// `many_to_many`: junction field holding the related document's ID; defaults to `<related>_id`
+    #[darling(default)]
+    pub target_key: Option<String>,
+
+    /// `belongs_to`: field of this document holding the related document's ID
+    #[darling(default)]
+    pub field: Option<String>,
+
+    /// `has_many`: stored name of the related documents' field holding this document's ID
+    #[darling(default)]
+    pub foreign_key: Option<String>,
+
+    /// `has_many`: stored name of this document's field counting the related documents, see `CounterCache`
+    #[darling(default)]
+    pub counter_cache: Option<String>,
+
+    /// Accessor name; defaults to the related document in snake_case (pluralized for `has_many` & `many_to_many`)
+    #[darling(default)]
+    pub name: Option<String>
+}
+
+/// Whether a field's type is a `Ref`, or an `Option` or `Vec` of them, so `Document::references` includes it
+pub(crate) fn holds_ref(ty: &Type) -> bool {
+    let Type::Path(path) = ty else { return false };
+    let Some(segment) = path.path.segments.last() else { return false };
+    if segment.ident == "Ref" {
+        return true;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if segment.ident == "Option" || segment.ident == "Vec" => match args.args.first() {
+            Some(syn::GenericArgument::Type(inner)) if args.args.len() == 1 => holds_ref(inner),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+/// `Document::references` collecting the IDs of `Ref` fields, or nothing if there are none (keeping the default impl)
+pub(crate) fn references_fn(any_refs: bool, body: TokenStream) -> TokenStream {
+    if !any_refs {
+        return quote! {};
+    }
+
+    quote! {
+        fn references(&self) -> ormox::ormox_core::OResult<Vec<ormox::ormox_core::core::relation::Reference>> {
+            let mut __references = Vec::new();
+            #body
+            Ok(__references)
+        }
+    }
+}
+
+/// `Document::counter_caches` listing the `counter_cache`s of the item's `has_many` relations, or nothing if there are
+/// none (keeping the default impl). Errors in the attributes are reported by `relation_accessors`.
+pub(crate) fn counter_caches_fn(attrs: &[Attribute]) -> TokenStream {
+    let caches = attrs
+        .iter()
+        .filter(|a| a.path().is_ident("relation"))
+        .filter_map(|attr| Relation::from_meta(&attr.meta).ok())
+        .filter_map(|relation| match (relation.has_many, relation.foreign_key, relation.counter_cache) {
+            (Some(target), Some(foreign_key), Some(field)) => Some(quote! {
+                ormox::ormox_core::core::relation::CounterCache::new::<#target>(#foreign_key, #field)
+            }),
+            _ => None
+        })
+        .collect::<Vec<_>>();
+    if caches.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        fn counter_caches() -> Vec<ormox::ormox_core::core::relation::CounterCache> {
+            vec![#(#caches),*]
+        }
+    }
+}
+
+fn type_name(path: &Path) -> String {
+    path.segments.last().map(|s| s.ident.to_string()).unwrap_or_default()
+}
+
+/// Async accessors (& `ManyToMany` impls) for the item's `#[relation(...)]` attributes, which the caller then removes
+/// from the item. `fields` are the document's stored fields, or `None` for enum documents, which don't support
+/// `belongs_to`.
+pub(crate) fn relation_accessors(
+    attrs: &[Attribute],
+    fields: Option<&[(Ident, Type)]>,
+    document: &Ident,
+    generics: &Generics
+) -> Result<TokenStream, TokenStream> {
+    let (impl_generics, type_generics, _) = generics.split_for_impl();
+    let document_where = document_where(generics);
+    let mut accessors = TokenStream::new();
+    let mut impls = TokenStream::new();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("relation")) {
+        let relation = Relation::from_meta(&attr.meta).map_err(|e| e.write_errors())?;
+        if relation.many_to_many.is_none() && (relation.through.is_some() || relation.source_key.is_some() || relation.target_key.is_some()) {
+            return Err(quote! {compile_error!("through, source_key and target_key only apply to many_to_many relations.");});
+        }
+        if relation.has_many.is_none() && relation.counter_cache.is_some() {
+            return Err(quote! {compile_error!("counter_cache only applies to has_many relations.");});
+        }
+
+        let accessor = match (&relation.belongs_to, &relation.has_many, &relation.many_to_many) {
+            (Some(target), None, None) => {
+                if relation.foreign_key.is_some() {
+                    return Err(quote! {compile_error!("foreign_key only applies to has_many relations; belongs_to uses field.");});
+                }
+                let Some(fields) = fields else {
+                    return Err(quote! {compile_error!("Enum documents don't support belongs_to relations.");});
+                };
+                let Some(field) = relation.field else {
+                    return Err(quote! {compile_error!("belongs_to relations need the field holding the related ID, ie `field = \"org_id\"`.");});
+                };
+                if !fields.iter().any(|(ident, _)| ident == &field) {
+                    let message = format!("Relation field `{field}` not found.");
+                    return Err(quote! {compile_error!(#message);});
+                }
+
+                let field = Ident::new(&field, Span::call_site());
+                let name = Ident::new(&relation.name.unwrap_or_else(|| snake_case(&type_name(target))), Span::call_site());
+                let doc = LitStr::new(&format!("The `{}` referenced by `{field}`, if set and it exists", type_name(target)), Span::call_site());
+                quote! {
+                    #[doc = #doc]
+                    pub async fn #name(&self) -> ormox::ormox_core::OResult<Option<#target>> {
+                        ormox::ormox_core::core::relation::belongs_to::<Self, #target>(self, &self.#field).await
+                    }
+                }
+            },
+            (None, Some(target), None) => {
+                if relation.field.is_some() {
+                    return Err(quote! {compile_error!("field only applies to belongs_to relations; has_many uses foreign_key.");});
+                }
+                let Some(foreign_key) = relation.foreign_key else {
+                    return Err(quote! {compile_error!("has_many relations need the related documents' field holding this ID, ie `foreign_key = \"user_id\"`.");});
+                };
+
+                let name = Ident::new(&relation.name.unwrap_or_else(|| collection_name(&type_name(target), Casing::Snake)), Span::call_site());
+                let doc = LitStr::new(&format!("Every `{}` whose `{foreign_key}` is this document's ID", type_name(target)), Span::call_site());
+                quote! {
+                    #[doc = #doc]
+                    pub async fn #name(&self) -> ormox::ormox_core::OResult<Vec<#target>> {
+                        ormox::ormox_core::core::relation::has_many::<Self, #target>(self, #foreign_key).await
+                    }
+                }
+            },
+            (None, None, Some(target)) => {
+                if relation.field.is_some() || relation.foreign_key.is_some() {
+                    return Err(quote! {compile_error!("many_to_many relations use through, source_key and target_key rather than field or foreign_key.");});
+                }
+                let Some(through) = relation.through else {
+                    return Err(quote! {compile_error!("many_to_many relations need a junction collection, ie `through = \"post_tags\"`.");});
+                };
+
+                let source_key = relation.source_key.unwrap_or_else(|| format!("{}_id", snake_case(&document.to_string())));
+                let target_key = relation.target_key.unwrap_or_else(|| format!("{}_id", snake_case(&type_name(target))));
+                impls.extend(quote! {
+                    impl #impl_generics ormox::ManyToMany<#target> for #document #type_generics #document_where {
+                        fn junction() -> String {
+                            String::from(#through)
+                        }
+
+                        fn source_key() -> String {
+                            String::from(#source_key)
+                        }
+
+                        fn target_key() -> String {
+                            String::from(#target_key)
+                        }
+                    }
+                });
+
+                let name = Ident::new(&relation.name.unwrap_or_else(|| collection_name(&type_name(target), Casing::Snake)), Span::call_site());
+                let doc = LitStr::new(&format!("Every `{}` linked to this document through `{through}`", type_name(target)), Span::call_site());
+                quote! {
+                    #[doc = #doc]
+                    pub async fn #name(&self) -> ormox::ormox_core::OResult<Vec<#target>> {
+                        ormox::Document::linked::<#target>(self).await
+                    }
+                }
+            },
+            _ => return Err(quote! {compile_error!("Relations need exactly one of belongs_to, has_many or many_to_many.");})
+        };
+        accessors.extend(accessor);
+    }
+
+    if accessors.is_empty() {
+        return Ok(impls);
+    }
+    Ok(quote! {
+        impl #impl_generics #document #type_generics #document_where {
+            #accessors
+        }
+
+        #impls
+    })
+}
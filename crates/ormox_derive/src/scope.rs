@@ -0,0 +1,106 @@
+use darling::FromMeta;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{ext::IdentExt, punctuated::Punctuated, spanned::Spanned, token::Comma, BinOp, Expr, ExprBinary, Lit, Member};
+
+use crate::paths::TypedField;
+
+/// `default_scope(...)`: comparisons of stored fields to values, which documents must all match (ie
+/// `default_scope(status != "archived", age >= 18)`)
+#[derive(Debug)]
+pub(crate) struct Scope(Vec<ExprBinary>);
+
+impl FromMeta for Scope {
+    fn from_meta(item: &syn::Meta) -> darling::Result<Self> {
+        let syn::Meta::List(list) = item else {
+            return Err(darling::Error::custom("Expected default_scope(<field> <op> <value>, ...)").with_span(item));
+        };
+        let conditions = list.parse_args_with(Punctuated::<Expr, Comma>::parse_terminated)?;
+        conditions.into_iter().map(|condition| match condition {
+            Expr::Binary(binary) => Ok(binary),
+            other => Err(darling::Error::custom("Scope conditions compare a field to a value, ie `status != \"archived\"`").with_span(&other))
+        }).collect::<darling::Result<Vec<_>>>().map(Scope)
+    }
+}
+
+/// Stored path of a field as written in a scope or sort: a field of the document is stored under its stored name, and
+/// anything else (ie `_docid`, or a string literal) as written. `address.city` resolves `address`.
+fn stored_path(written: &str, typed: &[TypedField]) -> String {
+    let (field, rest) = match written.split_once('.') {
+        Some((field, rest)) => (field, Some(rest)),
+        None => (written, None)
+    };
+    let field = typed.iter().find(|f| f.ident.unraw() == field).map_or_else(|| field.to_string(), |f| f.path.clone());
+    match rest {
+        Some(rest) => format!("{field}.{rest}"),
+        None => field
+    }
+}
+
+/// Dotted name of the field a scope condition compares: a field, a path through fields (`address.city`), or a string
+fn written_path(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Path(path) => path.path.get_ident().map(|ident| ident.unraw().to_string()),
+        Expr::Field(field) => {
+            let Member::Named(member) = &field.member else { return None };
+            written_path(&field.base).map(|base| format!("{base}.{}", member.unraw()))
+        },
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Str(name) => Some(name.value()),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// `Document::default_scope` & `Document::default_sort`, or nothing for those the document doesn't declare
+pub(crate) fn default_scope_fns(scope: Option<&Scope>, sort: Option<&str>, typed: &[TypedField]) -> Result<TokenStream, TokenStream> {
+    let mut fns = TokenStream::new();
+    if let Some(Scope(conditions)) = scope {
+        let mut query = quote! {ormox::Query::new()};
+        for condition in conditions {
+            let Some(written) = written_path(&condition.left) else {
+                return Err(syn::Error::new(condition.left.span(), "Scope conditions start with a field, ie `status != \"archived\"`").to_compile_error());
+            };
+            let (path, value) = (stored_path(&written, typed), &condition.right);
+            let compared = match condition.op {
+                BinOp::Eq(_) => {
+                    query.extend(quote! {.field(#path, #value)});
+                    continue;
+                },
+                BinOp::Ne(_) => quote! {not_equals},
+                BinOp::Lt(_) => quote! {less_than},
+                BinOp::Le(_) => quote! {less_than_equal},
+                BinOp::Gt(_) => quote! {greater_than},
+                BinOp::Ge(_) => quote! {greater_than_equal},
+                _ => return Err(syn::Error::new(condition.op.span(), "Scope conditions compare with ==, !=, <, <=, > or >=").to_compile_error())
+            };
+            query.extend(quote! {.subquery(#path, ormox::Query::new().#compared(#value))});
+        }
+        fns.extend(quote! {
+            fn default_scope() -> Option<ormox::Query> {
+                Some(#query)
+            }
+        });
+    }
+
+    if let Some(sort) = sort {
+        let (field, direction) = match sort.split_whitespace().collect::<Vec<_>>()[..] {
+            [field] => (field, "asc"),
+            [field, direction] => (field, direction),
+            _ => return Err(quote! {compile_error!("default_sort is a field, optionally followed by asc or desc, ie \"created_at desc\".");})
+        };
+        let direction = match direction.to_lowercase().as_str() {
+            "asc" => quote! {asc},
+            "desc" => quote! {desc},
+            _ => return Err(quote! {compile_error!("default_sort directions are asc and desc.");})
+        };
+        let path = stored_path(field, typed);
+        fns.extend(quote! {
+            fn default_sort() -> Option<ormox::Sorting> {
+                Some(ormox::Sorting::#direction(#path))
+            }
+        });
+    }
+    Ok(fns)
+}
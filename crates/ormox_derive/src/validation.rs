@@ -0,0 +1,146 @@
+use darling::{FromField, FromMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Expr, Generics, Ident, Type};
+
+#[derive(FromMeta, Debug, Default)]
+pub(crate) struct Bounds {
+    #[darling(default)]
+    pub min: Option<Expr>,
+
+    #[darling(default)]
+    pub max: Option<Expr>
+}
+
+#[derive(FromField, Debug)]
+#[darling(attributes(validate))]
+#[allow(dead_code)]
+pub(crate) struct FieldValidation {
+    pub ident: Option<syn::Ident>,
+    pub ty: Type,
+
+    /// Length of strings (in characters) & collections
+    #[darling(default)]
+    pub length: Option<Bounds>,
+
+    /// Numeric range, inclusive
+    #[darling(default)]
+    pub range: Option<Bounds>,
+
+    #[darling(default)]
+    pub regex: Option<String>,
+
+    #[darling(default)]
+    pub email: bool
+}
+
+fn bound(value: &Option<Expr>) -> TokenStream {
+    match value {
+        Some(value) => quote! {Some(#value)},
+        None => quote! {None}
+    }
+}
+
+/// `#[schemars(...)]` describing a field's `#[validate(...)]` rules in its JSON Schema. Replaces the `validate`
+/// attribute, which schemars would otherwise read with different `regex` syntax.
+#[cfg(feature = "schemars")]
+pub(crate) fn schema_rules(field: &mut syn::Field) -> Result<(), TokenStream> {
+    let rules = FieldValidation::from_field(field).map_err(|e| e.write_errors())?;
+    field.attrs.retain(|a| !a.path().is_ident("validate"));
+
+    let bounds = |name: &str, bounds: &Bounds| {
+        let name = Ident::new(name, proc_macro2::Span::call_site());
+        let min = bounds.min.as_ref().map(|min| quote! {min = #min});
+        let max = bounds.max.as_ref().map(|max| quote! {max = #max});
+        let parts = min.into_iter().chain(max);
+        quote! {#name(#(#parts),*)}
+    };
+    let mut schema = Vec::new();
+    if let Some(length) = &rules.length {
+        schema.push(bounds("length", length));
+    }
+    if let Some(range) = &rules.range {
+        schema.push(bounds("range", range));
+    }
+    if let Some(pattern) = &rules.regex {
+        schema.push(quote! {regex(pattern = #pattern)});
+    }
+    if rules.email {
+        schema.push(quote! {email});
+    }
+    if !schema.is_empty() {
+        field.attrs.push(syn::parse_quote! {#[schemars(#(#schema),*)]});
+    }
+    Ok(())
+}
+
+/// Checks for a field's `#[validate(...)]` rules, reading the field from a local binding named after it and pushing
+/// failures to `__errors`. Empty if the field has no rules.
+pub(crate) fn field_checks(field: &syn::Field, binding: &Ident, stored_name: &str) -> Result<TokenStream, TokenStream> {
+    let rules = FieldValidation::from_field(field).map_err(|e| e.write_errors())?;
+    let mut checks = TokenStream::new();
+    if let Some(length) = &rules.length {
+        let (min, max) = (bound(&length.min), bound(&length.max));
+        checks.extend(quote! {ormox::ormox_core::core::validation::length(&mut __errors, #stored_name, #binding, #min, #max);});
+    }
+
+    if let Some(range) = &rules.range {
+        let (min, max) = (bound(&range.min), bound(&range.max));
+        checks.extend(quote! {ormox::ormox_core::core::validation::range(&mut __errors, #stored_name, #binding, #min, #max);});
+    }
+
+    if let Some(pattern) = &rules.regex {
+        checks.extend(quote! {{
+            static PATTERN: ormox::ormox_core::core::validation::Pattern = ormox::ormox_core::core::validation::Pattern::new();
+            ormox::ormox_core::core::validation::regex(&mut __errors, #stored_name, #binding, &PATTERN, #pattern);
+        }});
+    }
+
+    if rules.email {
+        checks.extend(quote! {ormox::ormox_core::core::validation::email(&mut __errors, #stored_name, #binding);});
+    }
+    Ok(checks)
+}
+
+/// Check of an `#[ormox(nested)]` field's own rules, reading the field from a local binding named after it
+pub(crate) fn nested_check(binding: &Ident, stored_name: &str) -> TokenStream {
+    quote! {ormox::ormox_core::core::validation::nested(&mut __errors, #stored_name, #binding);}
+}
+
+/// `ormox::ValidateFields` for a struct, running the checks of its `validated` fields with their paths under the
+/// prefix, or the default (checking nothing) if no field has rules
+pub(crate) fn validate_fields_impl(struct_name: &Ident, generics: &Generics, validated: &[Ident], checks: &TokenStream) -> TokenStream {
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let validate_fields = (!validated.is_empty()).then(|| quote! {
+        fn validate_fields(&self, errors: &mut Vec<ormox::FieldError>, prefix: &str) {
+            let mut __errors: Vec<ormox::FieldError> = Vec::new();
+            let Self { #(#validated),*, .. } = self;
+            #checks
+            errors.extend(__errors.into_iter().map(|mut error| {
+                error.field.insert_str(0, prefix);
+                error
+            }));
+        }
+    });
+
+    quote! {
+        impl #impl_generics ormox::ValidateFields for #struct_name #type_generics #where_clause {
+            #validate_fields
+        }
+    }
+}
+
+/// `Document::validate` running the collected checks, or nothing if no field has rules (keeping the default impl)
+pub(crate) fn validate_fn(any_rules: bool, body: TokenStream) -> TokenStream {
+    if !any_rules {
+        return quote! {};
+    }
+
+    quote! {
+        fn validate(&self) -> ormox::ormox_core::OResult<()> {
+            let mut __errors: Vec<ormox::FieldError> = Vec::new();
+            #body
+            ormox::ormox_core::core::validation::result::<Self>(__errors)
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use darling::FromMeta;
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Ident, LitStr};
+
+use crate::document::{
+    decode_mode_fn, document_derives, document_fields, document_where, encrypted_fn, forwarded_casing, sequence_fns, serde_bounds,
+    DocumentId, DocumentMetadata
+};
+use crate::naming::{collection_name, serde_attr, snake_case, variant_name, Casing};
+use crate::redaction::{redacted_debug, sensitive_fn, take_debug, DebugShape};
+use crate::relations::{counter_caches_fn, references_fn, relation_accessors};
+use crate::scope::default_scope_fns;
+use crate::validation::validate_fn;
+
+/// Resolves a string serde container attribute set either on `ormox_document` (and forwarded to serde) or directly
+fn forwarded_attr(attrs: &[syn::Attribute], key: &str, arg: Option<String>) -> Result<(Option<String>, TokenStream), TokenStream> {
+    let existing = serde_attr(attrs, key);
+    match (arg, existing) {
+        (Some(_), Some(_)) => {
+            let message = format!("{key} is set on both ormox_document and serde.");
+            Err(quote! {compile_error!(#message);})
+        },
+        (Some(value), None) => {
+            let key = Ident::new(key, Span::call_site());
+            Ok((Some(value.clone()), quote! {#[serde(#key = #value)]}))
+        },
+        (None, existing) => Ok((existing, quote! {}))
+    }
+}
+
+/// `#[ormox_document]` on an enum: a polymorphic collection whose documents are told apart by serde's tag field. Every
+/// variant gets the ORM's fields, a `create_<variant>` constructor, and a marker type in `<enum>_variants` for
+/// `Collection::find_variant`.
+pub(crate) fn wrap_enum_document(args: DocumentMetadata, input: syn::ItemEnum) -> TokenStream {
+    if args.id.is_some() {
+        return quote! {compile_error!("Enum documents can't use an existing field as the ID.");};
+    }
+    if args.schema_version.is_some() || !args.migrations.is_empty() {
+        return quote! {compile_error!("Enum documents don't support schema_version.");};
+    }
+    if args.constructor.builder() {
+        return quote! {compile_error!("Enum documents only support the create constructor.");};
+    }
+    if args.graphql {
+        return quote! {compile_error!("Enum documents can't be GraphQL objects.");};
+    }
+
+    let enum_name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let document_where = document_where(&input.generics);
+    let mut original_enum = input.clone();
+    let collection = args.collection.unwrap_or_else(|| collection_name(&enum_name.to_string(), args.casing));
+    let id_field = args.id_field.unwrap_or("_docid".into());
+    let id = DocumentId {
+        natural: false,
+        alias: args.id_alias.unwrap_or(id_field.clone()),
+        ident: Ident::new(&id_field.clone(), Span::call_site()),
+        field: id_field
+    };
+
+    let (tag, tag_attr) = match forwarded_attr(&input.attrs, "tag", args.tag) {
+        Ok(v) => v,
+        Err(e) => return e
+    };
+    let (content, content_attr) = match forwarded_attr(&input.attrs, "content", args.content) {
+        Ok(v) => v,
+        Err(e) => return e
+    };
+    let Some(tag) = tag else {
+        return quote! {compile_error!("Enum documents must be internally (`tag = \"...\"`) or adjacently (`tag = \"...\", content = \"...\"`) tagged.");};
+    };
+
+    // Like serde, `rename_all` renames variants; fields follow `rename_all_fields` or the variant's own `rename_all`
+    let (variant_casing, rename_all_attr) = match forwarded_casing(&input.attrs, "rename_all", args.rename_all) {
+        Ok(v) => v,
+        Err(e) => return e
+    };
+    let fields_casing = serde_attr(&input.attrs, "rename_all_fields").and_then(|c| Casing::from_string(&c).ok());
+    let prefix = content.as_ref().map(|c| format!("{c}.")).unwrap_or_default();
+    let id_path = format!("{prefix}{}", id.alias);
+    let id_ident = &id.ident;
+
+    let mut variants: Vec<Ident> = Vec::new();
+    let mut index_names: Vec<String> = Vec::new();
+    let mut index_objs: Vec<syn::ExprStruct> = Vec::new();
+    let mut constructors: Vec<TokenStream> = Vec::new();
+    let mut markers: Vec<TokenStream> = Vec::new();
+    let mut validation_arms: Vec<TokenStream> = Vec::new();
+    let mut any_rules = false;
+    let mut reference_arms: Vec<TokenStream> = Vec::new();
+    let mut any_refs = false;
+    let mut sequences: Vec<(TokenStream, Vec<(Ident, String)>)> = Vec::new();
+    let mut encrypted: Vec<(String, Ident)> = Vec::new();
+    let mut sensitive: Vec<String> = Vec::new();
+    let mut debug_shapes: Vec<DebugShape> = Vec::new();
+    for variant in original_enum.variants.iter_mut() {
+        let variant_ident = variant.ident.clone();
+        let syn::Fields::Named(ref mut named) = variant.fields else {
+            return quote! {compile_error!("Enum document variants must have named fields.");};
+        };
+        let casing = serde_attr(&variant.attrs, "rename_all").and_then(|c| Casing::from_string(&c).ok()).or(fields_casing);
+        let fields = match document_fields(named, &id, casing, &prefix, args.track_changes) {
+            Ok(fields) => fields,
+            Err(e) => return e
+        };
+
+        if !fields.hashed.is_empty() {
+            return quote! {compile_error!("Enum documents don't support hashed fields.");};
+        }
+
+        let (validated, validations) = (&fields.validated, &fields.validations);
+        any_rules |= !validated.is_empty();
+        validation_arms.push(quote! {
+            Self::#variant_ident { #(#validated,)* .. } => {
+                #validations
+            }
+        });
+
+        let (referencing, references) = (&fields.referencing, &fields.references);
+        any_refs |= !referencing.is_empty();
+        reference_arms.push(quote! {
+            Self::#variant_ident { #(#referencing,)* .. } => {
+                #references
+            }
+        });
+
+        sequences.push((quote! {Self::#variant_ident}, fields.sequences.clone()));
+
+        // Like indexes, the same field may be encrypted or sensitive in several variants
+        for (path, mode) in fields.encrypted {
+            if !encrypted.iter().any(|(existing, _)| existing == &path) {
+                encrypted.push((path, mode));
+            }
+        }
+        for (_, path) in &fields.sensitive {
+            if !sensitive.contains(path) {
+                sensitive.push(path.clone());
+            }
+        }
+        debug_shapes.push(DebugShape {
+            pattern: quote! {Self::#variant_ident},
+            name: variant_ident.to_string(),
+            fields: named.named.iter().filter_map(|f| f.ident.clone()).collect(),
+            sensitive: fields.sensitive.iter().map(|(ident, _)| ident.clone()).collect()
+        });
+
+        // The same field may be indexed in several variants
+        for (name, index) in fields.indexes {
+            if !index_names.contains(&name) {
+                index_names.push(name);
+                index_objs.push(index);
+            }
+        }
+
+        let constructor = format_ident!("create_{}", snake_case(&variant_ident.to_string()));
+        let creation_fields = &fields.creation_fields;
+        let creation_assignments = &fields.creation_assignments;
+        constructors.push(quote! {
+            pub fn #constructor(collection: Option<ormox::Collection<Self>>, #creation_fields) -> Self {
+                Self::#variant_ident {
+                    #id_ident: ormox::ormox_core::uuid::Uuid::new_v4(),
+                    _collection: ormox::ormox_core::core::document::Hidden(collection.clone()),
+                    #creation_assignments
+                }
+            }
+        });
+
+        let value = serde_attr(&variant.attrs, "rename").unwrap_or_else(|| match variant_casing {
+            Some(casing) => variant_name(&variant_ident.to_string(), casing),
+            None => variant_ident.to_string()
+        });
+        let doc = LitStr::new(&format!("Marker for [`{enum_name}::{variant_ident}`](super::{enum_name}::{variant_ident}), for `Collection::find_variant`"), Span::call_site());
+        markers.push(quote! {
+            #[doc = #doc]
+            pub struct #variant_ident #impl_generics (std::marker::PhantomData<fn() -> super::#enum_name #type_generics>) #where_clause;
+
+            impl #impl_generics ormox::Variant for #variant_ident #type_generics where super::#enum_name #type_generics: ormox::Document {
+                type Of = super::#enum_name #type_generics;
+
+                fn tag_field() -> String {
+                    String::from(#tag)
+                }
+
+                fn tag() -> String {
+                    String::from(#value)
+                }
+            }
+        });
+        variants.push(variant_ident);
+    }
+
+    let change_tracking = if args.track_changes {
+        quote! {
+            fn tracks_changes() -> bool {
+                true
+            }
+
+            fn loaded_state(&self) -> Option<&ormox::ormox_core::bson::Document> {
+                match self {
+                    #(Self::#variants { _loaded, .. })|* => _loaded.0.as_ref()
+                }
+            }
+
+            fn set_loaded_state(&mut self, state: Option<ormox::ormox_core::bson::Document>) {
+                match self {
+                    #(Self::#variants { _loaded, .. })|* => _loaded.0 = state
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let validate = validate_fn(any_rules, quote! {
+        match self {
+            #(#validation_arms),*
+        }
+    });
+
+    let references = references_fn(any_refs, quote! {
+        match self {
+            #(#reference_arms),*
+        }
+    });
+
+    let sequences = sequence_fns(&sequences);
+    let encrypted = encrypted_fn(&encrypted);
+    let decode_mode = decode_mode_fn(args.strict);
+    // Variants' fields may share names with different stored paths, so enum scopes & sorts name stored paths
+    let default_scope = match default_scope_fns(args.default_scope.as_ref(), args.default_sort.as_deref(), &[]) {
+        Ok(fns) => fns,
+        Err(e) => return e
+    };
+    let (derive, debug) = take_debug(&mut original_enum.attrs, &args.derive, !sensitive.is_empty());
+    let debug = if debug { redacted_debug(enum_name, &input.generics, &debug_shapes) } else { quote! {} };
+    let sensitive = sensitive_fn(&sensitive);
+
+    let relations = match relation_accessors(&input.attrs, None, enum_name, &input.generics) {
+        Ok(relations) => relations,
+        Err(e) => return e
+    };
+    let counter_caches = counter_caches_fn(&input.attrs);
+    original_enum.attrs.retain(|a| !a.path().is_ident("relation"));
+
+    let derives = document_derives(&original_enum.attrs, &derive);
+    let serde_bounds = serde_bounds(&original_enum.attrs, &input.generics);
+    let vis = &input.vis;
+    let module = format_ident!("{}_variants", snake_case(&enum_name.to_string()));
+    let module_doc = LitStr::new(&format!("Variant markers of [`{enum_name}`](super::{enum_name}), for `Collection::find_variant`"), Span::call_site());
+
+    quote! {
+        #derives
+        #serde_bounds
+        #tag_attr
+        #content_attr
+        #rename_all_attr
+        #original_enum
+
+        impl #impl_generics ormox::Document for #enum_name #type_generics #document_where {
+            type Id = ormox::ormox_core::uuid::Uuid;
+
+            fn id(&self) -> Self::Id {
+                match self {
+                    #(Self::#variants { #id_ident, .. })|* => #id_ident.clone()
+                }
+            }
+
+            fn id_field() -> String {
+                String::from(#id_path)
+            }
+
+            fn collection_name() -> String {
+                String::from(#collection)
+            }
+
+            fn indexes() -> Vec<ormox::Index> {
+                vec![#(#index_objs),*]
+            }
+
+            fn attached_collection(&self) -> Option<ormox::Collection<Self>> {
+                match self {
+                    #(Self::#variants { _collection, .. })|* => _collection.0.clone()
+                }
+            }
+
+            fn attach_collection(&mut self, collection: ormox::Collection<Self>) -> () {
+                match self {
+                    #(Self::#variants { _collection, .. })|* => _collection.0 = Some(collection.clone())
+                }
+            }
+
+            #change_tracking
+            #validate
+            #references
+            #counter_caches
+            #sequences
+            #encrypted
+            #sensitive
+            #decode_mode
+            #default_scope
+        }
+
+        impl #impl_generics #enum_name #type_generics #where_clause {
+            #(#constructors)*
+        }
+
+        #relations
+        #debug
+
+        #[doc = #module_doc]
+        #vis mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#markers)*
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! Serves any `DatabaseDriver` over HTTP/JSON, so several services (and non-Rust tools) can share one database, ie an
+//! embedded PoloDB instance. Rust clients connect with `ormox_driver_http`.
+//!
+//! Request & response bodies are the JSON types of `ormox_core::core::remote`, with documents as canonical Extended
+//! JSON. Failed requests return the `OrmoxError` as JSON, with a status code matching its `ErrorKind`. Requests with an
+//! `ormox-context` header (sent by the HTTP driver) run in that `OperationContext`.
+//!
+//! | Route | Operation |
+//! |---|---|
+//! | `GET /` | `ServerInfo` |
+//! | `GET /collections` | `collections` |
+//! | `POST /collections/{collection}/insert` | `insert` |
+//! | `POST /collections/{collection}/update` | `update` |
+//! | `POST /collections/{collection}/upsert` | `upsert` |
+//! | `POST /collections/{collection}/delete` | `delete` |
+//! | `POST /collections/{collection}/find` | `find` |
+//! | `POST /collections/{collection}/all` | `all` |
+//! | `POST /collections/{collection}/count` | `count` |
+//! | `POST /collections/{collection}/find_with_count` | `find_with_count` |
+//! | `POST /collections/{collection}/aggregate` | `aggregate` |
+//! | `POST /collections/{collection}/find_one_and_update` | `find_one_and_update` |
+//! | `POST /collections/{collection}/explain` | `explain` |
+//! | `GET /collections/{collection}/stats` | `collection_stats` |
+//! | `GET /collections/{collection}/indexes` | `index_stats` |
+//! | `POST /collections/{collection}/indexes` | `create_index` |
+//! | `DELETE /collections/{collection}/indexes/{name}` | `drop_index` |
+//! | `POST /sessions` | `start_session` |
+//! | `DELETE /sessions/{session}` | `end_session` |
+//! | `POST /sessions/{session}/{start,commit,abort}_transaction` | `start_transaction`, etc |
+//! | `POST /maintain` | `maintain` |
+//! | `GET /ping` | `ping` |
+//! | `GET /health` | `health`, as a `HealthReport` |
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use ormox_core::{
+    axum::status_code,
+    core::{
+        context,
+        remote::{
+            parse_context_header, unwire, wire, AggregateRequest, CountedDocuments, DeleteRequest,
+            FindOneAndUpdateRequest, FindRequest, InsertRequest, ServerInfo, WireDocument,
+            WriteRequest, CONTEXT_HEADER,
+        },
+    },
+    Client, CollectionStats, DatabaseDriver, HealthReport, Index, IndexStats, OrmoxError, Query,
+    QueryPlan,
+};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct Server {
+    driver: Arc<dyn DatabaseDriver + Send + Sync>,
+    token: Option<Arc<str>>,
+}
+
+impl Server {
+    pub fn new(driver: impl DatabaseDriver + Send + Sync + 'static) -> Self {
+        Self {
+            driver: Arc::new(driver),
+            token: None,
+        }
+    }
+
+    /// Serves the driver of an existing client
+    pub fn from_client(client: &Client) -> Self {
+        Self {
+            driver: client.driver(),
+            token: None,
+        }
+    }
+
+    /// Requires every request to carry `Authorization: Bearer <token>`
+    pub fn with_token(mut self, token: impl AsRef<str>) -> Self {
+        self.token = Some(Arc::from(token.as_ref()));
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/", get(info))
+            .route("/collections", get(collections))
+            .route("/collections/{collection}/insert", post(insert))
+            .route("/collections/{collection}/update", post(update))
+            .route("/collections/{collection}/upsert", post(upsert))
+            .route("/collections/{collection}/delete", post(remove))
+            .route("/collections/{collection}/find", post(find))
+            .route("/collections/{collection}/all", post(all))
+            .route("/collections/{collection}/count", post(count))
+            .route(
+                "/collections/{collection}/find_with_count",
+                post(find_with_count),
+            )
+            .route("/collections/{collection}/aggregate", post(aggregate))
+            .route(
+                "/collections/{collection}/find_one_and_update",
+                post(find_one_and_update),
+            )
+            .route("/collections/{collection}/explain", post(explain))
+            .route("/collections/{collection}/stats", get(stats))
+            .route(
+                "/collections/{collection}/indexes",
+                get(index_stats).post(create_index),
+            )
+            .route(
+                "/collections/{collection}/indexes/{name}",
+                delete(drop_index),
+            )
+            .route("/sessions", post(start_session))
+            .route("/sessions/{session}", delete(end_session))
+            .route(
+                "/sessions/{session}/start_transaction",
+                post(start_transaction),
+            )
+            .route(
+                "/sessions/{session}/commit_transaction",
+                post(commit_transaction),
+            )
+            .route(
+                "/sessions/{session}/abort_transaction",
+                post(abort_transaction),
+            )
+            .route("/maintain", post(maintain))
+            .route("/ping", get(ping))
+            .route("/health", get(health))
+            .layer(middleware::from_fn(contextualize))
+            .layer(middleware::from_fn_with_state(self.clone(), authorize))
+            .with_state(self)
+    }
+
+    /// Listens on `address` until the process exits
+    pub async fn serve(self, address: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        axum::serve(listener, self.router()).await
+    }
+}
+
+/// An `OrmoxError` response
+struct ApiError(StatusCode, OrmoxError);
+
+impl From<OrmoxError> for ApiError {
+    fn from(error: OrmoxError) -> Self {
+        Self(status_code(&error), error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(self.1)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+async fn authorize(State(server): State<Server>, request: Request, next: Next) -> Response {
+    let Some(token) = &server.token else {
+        return next.run(request).await;
+    };
+
+    let provided = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if provided.is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes())) {
+        next.run(request).await
+    } else {
+        let error = OrmoxError::Driver {
+            driver_name: String::from("ormox_server"),
+            error: String::from("Unauthorized"),
+        };
+        ApiError(StatusCode::UNAUTHORIZED, error).into_response()
+    }
+}
+
+async fn contextualize(request: Request, next: Next) -> Response {
+    let context = request
+        .headers()
+        .get(CONTEXT_HEADER)
+        .and_then(|value| parse_context_header(value.as_bytes()));
+    context::scope(context, next.run(request)).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn info(State(server): State<Server>) -> ApiResult<ServerInfo> {
+    Ok(Json(ServerInfo {
+        driver_name: server.driver.driver_name(),
+        capabilities: server.driver.capabilities(),
+    }))
+}
+
+async fn collections(State(server): State<Server>) -> ApiResult<Vec<String>> {
+    Ok(Json(server.driver.collections().await?))
+}
+
+async fn insert(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<InsertRequest>,
+) -> ApiResult<Vec<Uuid>> {
+    Ok(Json(
+        server
+            .driver
+            .insert(collection, unwire(request.documents), request.options)
+            .await?,
+    ))
+}
+
+async fn update(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<WriteRequest>,
+) -> ApiResult<()> {
+    let WriteRequest {
+        query,
+        document: WireDocument(update),
+        count,
+        options,
+    } = request;
+    Ok(Json(
+        server
+            .driver
+            .update(collection, Query::try_from(query)?, update, count, options)
+            .await?,
+    ))
+}
+
+async fn upsert(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<WriteRequest>,
+) -> ApiResult<()> {
+    let WriteRequest {
+        query,
+        document: WireDocument(document),
+        count,
+        options,
+    } = request;
+    Ok(Json(
+        server
+            .driver
+            .upsert(
+                collection,
+                Query::try_from(query)?,
+                document,
+                count,
+                options,
+            )
+            .await?,
+    ))
+}
+
+async fn remove(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<DeleteRequest>,
+) -> ApiResult<()> {
+    Ok(Json(
+        server
+            .driver
+            .delete(
+                collection,
+                Query::try_from(request.query)?,
+                request.count,
+                request.options,
+            )
+            .await?,
+    ))
+}
+
+async fn find(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<FindRequest>,
+) -> ApiResult<Vec<WireDocument>> {
+    Ok(Json(wire(
+        server
+            .driver
+            .find(collection, Query::try_from(request.query)?, request.options)
+            .await?,
+    )))
+}
+
+async fn all(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<FindRequest>,
+) -> ApiResult<Vec<WireDocument>> {
+    Ok(Json(wire(
+        server.driver.all(collection, request.options).await?,
+    )))
+}
+
+async fn count(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<FindRequest>,
+) -> ApiResult<u64> {
+    Ok(Json(
+        server
+            .driver
+            .count(collection, Query::try_from(request.query)?, request.options)
+            .await?,
+    ))
+}
+
+async fn find_with_count(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<FindRequest>,
+) -> ApiResult<CountedDocuments> {
+    let (documents, count) = server
+        .driver
+        .find_with_count(collection, Query::try_from(request.query)?, request.options)
+        .await?;
+    Ok(Json(CountedDocuments {
+        documents: wire(documents),
+        count,
+    }))
+}
+
+async fn aggregate(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<AggregateRequest>,
+) -> ApiResult<Vec<WireDocument>> {
+    Ok(Json(wire(
+        server
+            .driver
+            .aggregate(collection, unwire(request.pipeline), request.options)
+            .await?,
+    )))
+}
+
+async fn find_one_and_update(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<FindOneAndUpdateRequest>,
+) -> ApiResult<Option<WireDocument>> {
+    let FindOneAndUpdateRequest {
+        query,
+        update: WireDocument(update),
+        upsert,
+        options,
+    } = request;
+    Ok(Json(
+        server
+            .driver
+            .find_one_and_update(collection, Query::try_from(query)?, update, upsert, options)
+            .await?
+            .map(WireDocument),
+    ))
+}
+
+async fn explain(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(request): Json<FindRequest>,
+) -> ApiResult<QueryPlan> {
+    Ok(Json(
+        server
+            .driver
+            .explain(collection, Query::try_from(request.query)?, request.options)
+            .await?,
+    ))
+}
+
+async fn stats(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+) -> ApiResult<CollectionStats> {
+    Ok(Json(server.driver.collection_stats(collection).await?))
+}
+
+async fn index_stats(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+) -> ApiResult<Vec<IndexStats>> {
+    Ok(Json(server.driver.index_stats(collection).await?))
+}
+
+async fn create_index(
+    State(server): State<Server>,
+    Path(collection): Path<String>,
+    Json(index): Json<Index>,
+) -> ApiResult<()> {
+    Ok(Json(server.driver.create_index(collection, index).await?))
+}
+
+async fn drop_index(
+    State(server): State<Server>,
+    Path((collection, name)): Path<(String, String)>,
+) -> ApiResult<()> {
+    Ok(Json(server.driver.drop_index(collection, name).await?))
+}
+
+async fn start_session(State(server): State<Server>) -> ApiResult<Uuid> {
+    Ok(Json(server.driver.start_session().await?))
+}
+
+async fn end_session(State(server): State<Server>, Path(session): Path<Uuid>) -> ApiResult<()> {
+    Ok(Json(server.driver.end_session(session).await?))
+}
+
+async fn start_transaction(
+    State(server): State<Server>,
+    Path(session): Path<Uuid>,
+) -> ApiResult<()> {
+    Ok(Json(server.driver.start_transaction(session).await?))
+}
+
+async fn commit_transaction(
+    State(server): State<Server>,
+    Path(session): Path<Uuid>,
+) -> ApiResult<()> {
+    Ok(Json(server.driver.commit_transaction(session).await?))
+}
+
+async fn abort_transaction(
+    State(server): State<Server>,
+    Path(session): Path<Uuid>,
+) -> ApiResult<()> {
+    Ok(Json(server.driver.abort_transaction(session).await?))
+}
+
+async fn maintain(State(server): State<Server>) -> ApiResult<()> {
+    Ok(Json(server.driver.maintain().await?))
+}
+
+async fn ping(State(server): State<Server>) -> ApiResult<()> {
+    Ok(Json(server.driver.ping().await?))
+}
+
+async fn health(State(server): State<Server>) -> ApiResult<HealthReport> {
+    Ok(Json(server.driver.health().await))
+}
@@ -1,8 +1,9 @@
 use std::error::Error;
 
-use ormox::{drivers::PoloDriver, ormox_core::bson::doc, ormox_document, Client, Document};
+use ormox::{drivers::PoloDriver, ormox_core::bson::doc, ormox_document, Client, Document, Projection, Query};
+use serde::Deserialize;
 
-#[ormox_document(collection = "test", id_field = "id", id_alias = "_id")]
+#[ormox_document(collection = "test", id_field = "id", id_alias = "_id", constructor = "builder")]
 pub struct User {
     #[index]
     pub name: String,
@@ -12,15 +13,23 @@ pub struct User {
     pub nickname: Option<String>
 }
 
+#[derive(Projection, Deserialize, Debug)]
+#[projection(of = User)]
+pub struct UserSummary {
+    pub name: String,
+    pub age: i64
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let client = Client::create_global(PoloDriver::new("test.db")?);
-    let user = User::create(None, "Test User", 27, None);
+    let user = User::builder().name("Test User").age(27).build();
     user.save().await?;
     for d in client.collection::<User>().all(None).await? {
         println!("{:?}", d.id());
     }
     println!("{:?}", client.collection::<User>().get(user.id().to_string()).await?.name);
+    println!("{:?}", client.collection::<User>().find_as::<UserSummary>(Query::new(), None).await?);
 
     Ok(())
 }